@@ -3,7 +3,7 @@
 use std::fmt;
 use std::ops::Deref;
 
-use pos::{CharPos, Located, Span};
+use pos::{Located, Location, Span, located};
 use symbol::Symbol;
 use types::{self, Alias, AliasData, Kind, Type, TypeEnv, TypeVariable};
 
@@ -183,18 +183,26 @@ pub type LPattern<Id> = Located<Pattern<Id>>;
 
 #[derive(Clone, PartialEq, Debug)]
 pub enum Pattern<Id: AstId> {
-    Constructor(Id, Vec<Id>),
+    Constructor(Id, Vec<LPattern<Id>>),
     Record {
         id: Id,
         types: Vec<(Id::Untyped, Option<Id::Untyped>)>,
-        fields: Vec<(Id::Untyped, Option<Id::Untyped>)>,
+        fields: Vec<(Id::Untyped, Option<LPattern<Id>>)>,
+    },
+    Tuple {
+        // Field to store the type of the tuple since type_of returns a borrowed reference
+        typ: Id,
+        elems: Vec<LPattern<Id>>,
     },
     Identifier(Id),
+    Literal(LiteralEnum),
 }
 
 #[derive(Clone, PartialEq, Debug)]
 pub struct Alternative<Id: AstId> {
     pub pattern: LPattern<Id>,
+    /// Condition which must be `True` for the alternative to match (`| x if x > 0 -> ...`)
+    pub guard: Option<LExpr<Id>>,
     pub expression: LExpr<Id>,
 }
 
@@ -209,13 +217,56 @@ pub struct Array<Id: AstId> {
 pub struct Lambda<Id: AstId> {
     // Field to store the type of the array since type_of returns a borrowed reference
     pub id: Id,
-    pub arguments: Vec<Id>,
+    pub arguments: Vec<Located<Id>>,
     pub body: Box<LExpr<Id>>,
 }
 
+/// A `do id = bound` binding followed by the expression `body`. The typechecker desugars it into
+/// `monad.flat_map (\id -> body) bound` where `monad` is the `Monad` instance which is in scope
+#[derive(Clone, PartialEq, Debug)]
+pub struct Do<Id: AstId> {
+    pub id: Id,
+    pub bound: Box<LExpr<Id>>,
+    pub body: Box<LExpr<Id>>,
+    /// Identifier for the `flat_map` field of the monad
+    pub flat_map_id: Id,
+    /// Identifier for the lambda which `body` is wrapped in when desugaring
+    pub lambda_id: Id,
+}
+
+impl<Id> Do<Id>
+    where Id: AstId
+{
+    /// Returns the span of the binding line, `do id = bound`, of a `do` expression which starts at
+    /// `location`
+    pub fn bind_span(&self, location: Location) -> Span {
+        Span {
+            start: location,
+            end: self.bound.span.end,
+        }
+    }
+
+    /// Desugars the `do` expression into `monad.flat_map (\id -> body) bound`. Every expression
+    /// created is given `span` so that errors refer to the binding line
+    pub fn desugar(self, span: Span, monad: LExpr<Id>) -> Expr<Id> {
+        let Do { id, bound, body, flat_map_id, lambda_id } = self;
+        let flat_map = located(span, Expr::FieldAccess(Box::new(monad), flat_map_id));
+        let lambda = located(span,
+                             Expr::Lambda(Lambda {
+                                 id: lambda_id,
+                                 arguments: vec![located(span, id)],
+                                 body: body,
+                             }));
+        Expr::Call(Box::new(flat_map), vec![lambda, *bound])
+    }
+}
+
 /// Expression which contains a location
 pub type LExpr<Id> = Located<Expr<Id>>;
 
+/// Type annotation which contains a location
+pub type LType<Id> = Located<AstType<Id>>;
+
 #[derive(Clone, PartialEq, Debug)]
 pub enum Expr<Id: AstId> {
     Identifier(Id),
@@ -232,10 +283,29 @@ pub enum Expr<Id: AstId> {
         types: Vec<(Id::Untyped, Option<AstType<Id::Untyped>>)>,
         exprs: Vec<(Id::Untyped, Option<LExpr<Id>>)>,
     },
+    /// A copy of the record `base` where each field in `fields` is replaced by its new value.
+    /// Fields which `base` does not have are added but only if `base` is a closed record.
+    /// `{ base | x = 1, y = 2 }`
+    RecordUpdate {
+        typ: Id,
+        base: Box<LExpr<Id>>,
+        fields: Vec<(Id::Untyped, LExpr<Id>)>,
+    },
     Lambda(Lambda<Id>),
+    Do(Do<Id>),
     Tuple(Vec<LExpr<Id>>),
     Type(Vec<TypeBinding<Id::Untyped>>, Box<LExpr<Id>>),
     Block(Vec<LExpr<Id>>),
+    /// An expression with a type annotation, `expr : Type`. The annotated expression has exactly
+    /// the annotated type, record fields which the annotation does not mention are hidden and
+    /// types declared as `type T` in the annotation are made abstract
+    Annotated(Box<LExpr<Id>>, LType<Id::Untyped>),
+    /// Placeholder for an expression which could not be parsed. The parser has already reported
+    /// an error for it so it should just be skipped by later passes
+    Error(Id),
+    /// A typed hole (`_` or `_name`). Typechecking a hole reports the type that is expected in
+    /// its place
+    Hole(Id),
 }
 
 #[derive(Clone, PartialEq, Debug)]
@@ -243,110 +313,54 @@ pub struct TypeBinding<Id> {
     pub comment: Option<String>,
     pub name: Id,
     pub alias: Alias<Id, AstType<Id>>,
+    /// The names of the instance records which should be generated for the type
+    /// (`type T = ... deriving (Eq, Show)`)
+    pub deriving: Vec<Id>,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum Associativity {
+    Left,
+    Right,
+}
+
+/// The precedence and associativity of an infix operator, declared through `infixl` or `infixr`
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct Fixity {
+    pub associativity: Associativity,
+    pub precedence: i32,
+}
+
+impl fmt::Display for Fixity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let keyword = match self.associativity {
+            Associativity::Left => "infixl",
+            Associativity::Right => "infixr",
+        };
+        write!(f, "{} {}", keyword, self.precedence)
+    }
 }
 
 #[derive(Clone, PartialEq, Debug)]
 pub struct Binding<Id: AstId> {
     pub comment: Option<String>,
+    /// If true the binding is an implicit instance (`let implicit eq_Int = ...`) which the
+    /// typechecker may use to fill in implicit arguments
+    pub implicit: bool,
+    /// The fixity declared for the operator bound by this binding (`infixl 4 <|>`)
+    pub fixity: Option<Fixity>,
     pub name: LPattern<Id>,
-    pub typ: Option<AstType<Id::Untyped>>,
-    pub arguments: Vec<Id>,
+    pub typ: Option<LType<Id::Untyped>>,
+    pub arguments: Vec<Located<Id>>,
     pub expression: LExpr<Id>,
 }
 
-impl<Id> LExpr<Id>
-    where Id: AstId
-{
-    /// Returns the an approximation of the span of the expression
-    pub fn span<'a>(&self, env: &(DisplayEnv<Ident = Id> + 'a)) -> Span {
-        use self::Expr::*;
-        let end = match self.value {
-            Identifier(ref id) => self.location.line_offset(CharPos(env.string(id).len())),
-            Literal(ref lit) => {
-                use self::LiteralEnum::*;
-                match *lit {
-                    Integer(i) => self.location.line_offset(CharPos::from(format!("{}", i).len())),
-                    Byte(i) => self.location.line_offset(CharPos::from(format!("{}", i).len() + 1)),
-                    Float(f) => self.location.line_offset(CharPos::from(format!("{}", f).len())),
-                    String(ref s) => self.location.line_offset(CharPos::from(s.len() + 2)),
-                    Char(_) => self.location.line_offset(CharPos(3)),
-                }
-            }
-            Call(ref func, ref args) => {
-                args.last()
-                    .map_or_else(|| func.span(env).end, |e| e.span(env).end)
-            }
-            IfElse(_, ref if_true, ref if_false) => {
-                if_false.as_ref()
-                    .map_or_else(|| if_true.span(env).end, |e| e.span(env).end)
-            }
-            Match(_, ref alts) => {
-                alts.last()
-                    .map_or(self.location, |alt| alt.expression.span(env).end)
-            }
-            BinOp(_, _, ref r) => r.span(env).end,
-            Let(_, ref expr) |
-            Type(_, ref expr) => expr.span(env).end,
-            FieldAccess(ref expr, ref id) => {
-                let base = expr.span(env).end;
-                base.line_offset(CharPos::from(1 + env.string(id).len()))
-            }
-            Array(ref array) => {
-                array.expressions
-                    .last()
-                    .map_or(self.location, |expr| expr.span(env).end)
-                    .line_offset(CharPos(1))
-            }
-            Record { ref exprs, .. } => {
-                exprs.last()
-                    .and_then(|tup| tup.1.as_ref().map(|expr| expr.span(env).end))
-                    .unwrap_or(self.location)
-                    .line_offset(CharPos(2))
-            }
-            Lambda(ref lambda) => lambda.body.span(env).end,
-            Tuple(ref args) => {
-                args.last()
-                    .map_or(self.location, |expr| expr.span(env).end)
-                    .line_offset(CharPos(2))
-            }
-            Block(ref exprs) => exprs.last().expect("Expr in block").span(env).end,
-        };
-        Span {
-            start: self.location,
-            end: end,
-        }
-    }
-}
-impl<Id> LPattern<Id>
-    where Id: AstId + AsRef<str>,
-          Id::Untyped: AsRef<str>
-{
-    /// Returns the an approximation of the span of the expression
+impl<Id: AstId> Binding<Id> {
+    /// Returns the span from the start of the bound pattern to the end of the bound expression
     pub fn span(&self) -> Span {
-        // FIXME Use exact spans instead of approximations
-        let end = match self.value {
-            Pattern::Constructor(ref id, ref args) => {
-                let offset = args.iter().fold(0, |acc, arg| acc + arg.as_ref().len() + " ".len());
-                self.location.line_offset(CharPos::from(id.as_ref().len() + offset))
-            }
-            Pattern::Record { ref fields, ref types, .. } => {
-                let field_offset = fields.iter().fold(0, |acc, t| {
-                    acc + t.0.as_ref().len() +
-                    t.1.as_ref().map(|id| id.as_ref().len()).unwrap_or(0) +
-                    ",".len()
-                });
-                let type_offset = types.iter().fold(0, |acc, t| {
-                    acc + t.0.as_ref().len() +
-                    t.1.as_ref().map(|id| id.as_ref().len()).unwrap_or(0) +
-                    ",".len()
-                });
-                self.location.line_offset(CharPos::from(field_offset + type_offset + "{".len()))
-            }
-            Pattern::Identifier(ref id) => self.location.line_offset(CharPos::from(id.as_ref().len())),
-        };
         Span {
-            start: self.location,
-            end: end,
+            start: self.name.span.start,
+            end: self.expression.span.end,
         }
     }
 }
@@ -400,6 +414,9 @@ pub fn walk_mut_expr<V: ?Sized + MutVisitor>(v: &mut V, e: &mut LExpr<V::T>) {
             v.visit_expr(&mut **expr);
             for alt in alts.iter_mut() {
                 v.visit_pattern(&mut alt.pattern);
+                if let Some(ref mut guard) = alt.guard {
+                    v.visit_expr(guard);
+                }
                 v.visit_expr(&mut alt.expression);
             }
         }
@@ -417,6 +434,13 @@ pub fn walk_mut_expr<V: ?Sized + MutVisitor>(v: &mut V, e: &mut LExpr<V::T>) {
                 }
             }
         }
+        Expr::RecordUpdate { ref mut typ, ref mut base, ref mut fields } => {
+            v.visit_identifier(typ);
+            v.visit_expr(base);
+            for field in fields {
+                v.visit_expr(&mut field.1);
+            }
+        }
         Expr::Tuple(ref mut exprs) => {
             for expr in exprs {
                 v.visit_expr(expr);
@@ -426,8 +450,16 @@ pub fn walk_mut_expr<V: ?Sized + MutVisitor>(v: &mut V, e: &mut LExpr<V::T>) {
             v.visit_identifier(&mut lambda.id);
             v.visit_expr(&mut *lambda.body);
         }
-        Expr::Type(_, ref mut expr) => v.visit_expr(&mut *expr),
-        Expr::Identifier(ref mut id) => v.visit_identifier(id),
+        Expr::Do(ref mut do_expr) => {
+            v.visit_identifier(&mut do_expr.id);
+            v.visit_expr(&mut do_expr.bound);
+            v.visit_expr(&mut do_expr.body);
+        }
+        Expr::Type(_, ref mut expr) |
+        Expr::Annotated(ref mut expr, _) => v.visit_expr(&mut *expr),
+        Expr::Identifier(ref mut id) |
+        Expr::Error(ref mut id) |
+        Expr::Hole(ref mut id) => v.visit_identifier(id),
         Expr::Literal(..) => (),
         Expr::Block(ref mut exprs) => {
             for expr in exprs {
@@ -442,14 +474,26 @@ pub fn walk_mut_pattern<V: ?Sized + MutVisitor>(v: &mut V, p: &mut Pattern<V::T>
     match *p {
         Pattern::Constructor(ref mut id, ref mut args) => {
             v.visit_identifier(id);
-            for a in args {
-                v.visit_identifier(a);
+            for arg in args {
+                v.visit_pattern(arg);
             }
         }
-        Pattern::Record { ref mut id, .. } => {
+        Pattern::Record { ref mut id, ref mut fields, .. } => {
             v.visit_identifier(id);
+            for field in fields {
+                if let Some(ref mut pattern) = field.1 {
+                    v.visit_pattern(pattern);
+                }
+            }
+        }
+        Pattern::Tuple { ref mut typ, ref mut elems } => {
+            v.visit_identifier(typ);
+            for elem in elems {
+                v.visit_pattern(elem);
+            }
         }
         Pattern::Identifier(ref mut id) => v.visit_identifier(id),
+        Pattern::Literal(_) => (),
     }
 }
 
@@ -477,20 +521,26 @@ impl<Id> Typed for Expr<Id>
     fn env_type_of(&self, env: &TypeEnv) -> AstType<Symbol> {
         match *self {
             Expr::Identifier(ref id) |
-            Expr::FieldAccess(_, ref id) => id.env_type_of(env),
-            Expr::Literal(ref lit) => {
-                match *lit {
-                    LiteralEnum::Integer(_) => Type::int(),
-                    LiteralEnum::Float(_) => Type::float(),
-                    LiteralEnum::Byte(_) => Type::byte(),
-                    LiteralEnum::String(_) => Type::string(),
-                    LiteralEnum::Char(_) => Type::char(),
-                }
-            }
+            Expr::FieldAccess(_, ref id) |
+            Expr::Error(ref id) |
+            Expr::Hole(ref id) => id.env_type_of(env),
+            Expr::Literal(ref lit) => lit.env_type_of(env),
             Expr::IfElse(_, ref arm, _) => arm.env_type_of(env),
             Expr::Tuple(ref exprs) => {
-                assert!(exprs.is_empty());
-                Type::unit()
+                if exprs.is_empty() {
+                    Type::unit()
+                } else {
+                    let fields = exprs.iter()
+                        .enumerate()
+                        .map(|(i, expr)| {
+                            types::Field {
+                                name: Symbol::new(&tuple_field_name(i)),
+                                typ: expr.env_type_of(env),
+                            }
+                        })
+                        .collect();
+                    Type::record(vec![], fields)
+                }
             }
             Expr::BinOp(_, ref op, _) => {
                 if let Type::App(_, ref args) = *op.env_type_of(env) {
@@ -508,8 +558,25 @@ impl<Id> Typed for Expr<Id>
             Expr::Match(_, ref alts) => alts[0].expression.env_type_of(env),
             Expr::Array(ref a) => a.id.env_type_of(env),
             Expr::Lambda(ref lambda) => lambda.id.env_type_of(env),
-            Expr::Record { ref typ, .. } => typ.env_type_of(env),
+            Expr::Do(ref do_expr) => do_expr.body.env_type_of(env),
+            Expr::Record { ref typ, .. } |
+            Expr::RecordUpdate { ref typ, .. } => typ.env_type_of(env),
             Expr::Block(ref exprs) => exprs.last().expect("Expr in block").env_type_of(env),
+            Expr::Annotated(_, ref typ) => typ.value.clone(),
+        }
+    }
+}
+
+impl Typed for LiteralEnum {
+    type Id = Symbol;
+
+    fn env_type_of(&self, _: &TypeEnv) -> AstType<Symbol> {
+        match *self {
+            LiteralEnum::Integer(_) => Type::int(),
+            LiteralEnum::Float(_) => Type::float(),
+            LiteralEnum::Byte(_) => Type::byte(),
+            LiteralEnum::String(_) => Type::string(),
+            LiteralEnum::Char(_) => Type::char(),
         }
     }
 }
@@ -530,7 +597,9 @@ impl Typed for Pattern<TcIdent<Symbol>> {
         match *self {
             Pattern::Identifier(ref name) => name.env_type_of(env),
             Pattern::Record { ref id, .. } => id.env_type_of(env),
+            Pattern::Tuple { ref typ, .. } => typ.env_type_of(env),
             Pattern::Constructor(ref id, ref args) => get_return_type(env, &id.typ, args.len()),
+            Pattern::Literal(ref lit) => lit.env_type_of(env),
         }
     }
 }
@@ -540,12 +609,18 @@ impl Typed for Binding<TcIdent<Symbol>> {
 
     fn env_type_of(&self, env: &TypeEnv) -> AstType<Symbol> {
         match self.typ {
-            Some(ref typ) => typ.clone(),
+            Some(ref typ) => typ.value.clone(),
             None => self.name.env_type_of(env),
         }
     }
 }
 
+/// Returns the name of the field which stores the `index`th element of a tuple. Tuples are
+/// represented as records with the fields `_0`, `_1`, ...
+pub fn tuple_field_name(index: usize) -> String {
+    format!("_{}", index)
+}
+
 fn get_return_type(env: &TypeEnv,
                    alias_type: &AstType<Symbol>,
                    arg_count: usize)
@@ -553,7 +628,7 @@ fn get_return_type(env: &TypeEnv,
     if arg_count == 0 {
         alias_type.clone()
     } else {
-        match alias_type.as_function() {
+        match alias_type.as_function().or_else(|| alias_type.as_implicit_function()) {
             Some((_, ret)) => get_return_type(env, ret, arg_count - 1),
             None => {
                 match alias_type.as_alias() {
@@ -0,0 +1,280 @@
+//! Structured representation of the errors and warnings reported by the compiler. Unlike the
+//! `Display` output of the errors a `Diagnostic` keeps the positions and messages separate so
+//! that tools such as editor integrations do not need to parse them out of a string.
+use std::fmt;
+
+use pos::{Location, Span, Spanned};
+
+/// How severe a diagnostic is
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum Severity {
+    Error,
+    Warning,
+    Note,
+}
+
+impl Severity {
+    pub fn name(self) -> &'static str {
+        match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+            Severity::Note => "note",
+        }
+    }
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// A secondary location which is relevant to a diagnostic
+#[derive(Clone, Debug, PartialEq)]
+pub struct Label {
+    pub span: Span,
+    pub message: String,
+}
+
+/// An error or warning together with all the information needed to display it
+#[derive(Clone, Debug, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// A stable identifier for the kind of diagnostic (`type_mismatch`, `unused_binding`, ...)
+    pub code: String,
+    pub message: String,
+    /// The file which the diagnostic was found in, if it is known
+    pub file: Option<String>,
+    /// The location which the diagnostic is about, if it has one
+    pub span: Option<Span>,
+    pub labels: Vec<Label>,
+    /// Additional information which does not refer to a specific location
+    pub notes: Vec<String>,
+}
+
+impl Diagnostic {
+    pub fn new<C, M>(severity: Severity, code: C, message: M) -> Diagnostic
+        where C: Into<String>,
+              M: Into<String>
+    {
+        Diagnostic {
+            severity: severity,
+            code: code.into(),
+            message: message.into(),
+            file: None,
+            span: None,
+            labels: Vec::new(),
+            notes: Vec::new(),
+        }
+    }
+
+    pub fn error<C, M>(code: C, message: M) -> Diagnostic
+        where C: Into<String>,
+              M: Into<String>
+    {
+        Diagnostic::new(Severity::Error, code, message)
+    }
+
+    pub fn warning<C, M>(code: C, message: M) -> Diagnostic
+        where C: Into<String>,
+              M: Into<String>
+    {
+        Diagnostic::new(Severity::Warning, code, message)
+    }
+
+    pub fn with_span(mut self, span: Span) -> Diagnostic {
+        self.span = Some(span);
+        self
+    }
+
+    pub fn with_label<M: Into<String>>(mut self, span: Span, message: M) -> Diagnostic {
+        self.labels.push(Label {
+            span: span,
+            message: message.into(),
+        });
+        self
+    }
+
+    pub fn with_note<M: Into<String>>(mut self, note: M) -> Diagnostic {
+        self.notes.push(note.into());
+        self
+    }
+
+    pub fn in_file<F: Into<String>>(mut self, file: F) -> Diagnostic {
+        self.file = Some(file.into());
+        self
+    }
+
+    /// Serializes the diagnostic as a JSON object
+    ///
+    /// ```json
+    /// { "severity": "error", "code": "undefined_variable", "message": "...",
+    ///   "file": "test", "span": { "start": { "line": 1, "column": 1 }, "end": ... },
+    ///   "labels": [{ "span": ..., "message": "..." }], "notes": ["..."] }
+    /// ```
+    ///
+    /// `file` and `span` are `null` if they are not known.
+    pub fn to_json(&self) -> String {
+        let mut out = String::new();
+        out.push_str("{\"severity\":");
+        push_json_string(&mut out, self.severity.name());
+        out.push_str(",\"code\":");
+        push_json_string(&mut out, &self.code);
+        out.push_str(",\"message\":");
+        push_json_string(&mut out, &self.message);
+        out.push_str(",\"file\":");
+        match self.file {
+            Some(ref file) => push_json_string(&mut out, file),
+            None => out.push_str("null"),
+        }
+        out.push_str(",\"span\":");
+        match self.span {
+            Some(span) => push_json_span(&mut out, span),
+            None => out.push_str("null"),
+        }
+        out.push_str(",\"labels\":[");
+        for (i, label) in self.labels.iter().enumerate() {
+            if i != 0 {
+                out.push(',');
+            }
+            out.push_str("{\"span\":");
+            push_json_span(&mut out, label.span);
+            out.push_str(",\"message\":");
+            push_json_string(&mut out, &label.message);
+            out.push('}');
+        }
+        out.push_str("],\"notes\":[");
+        for (i, note) in self.notes.iter().enumerate() {
+            if i != 0 {
+                out.push(',');
+            }
+            push_json_string(&mut out, note);
+        }
+        out.push_str("]}");
+        out
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        try!(write!(f, "{}[{}]: ", self.severity, self.code));
+        if let Some(ref file) = self.file {
+            try!(write!(f, "{}:", file));
+        }
+        if let Some(ref span) = self.span {
+            try!(write!(f, "{}: ", span.start));
+        }
+        try!(write!(f, "{}", self.message));
+        for label in &self.labels {
+            try!(write!(f, "\n{}: {}", label.span.start, label.message));
+        }
+        for note in &self.notes {
+            try!(write!(f, "\nnote: {}", note));
+        }
+        Ok(())
+    }
+}
+
+/// Trait for errors which can be converted into a `Diagnostic`
+pub trait ToDiagnostic {
+    fn to_diagnostic(&self) -> Diagnostic;
+
+    /// Converts `self` into a diagnostic about the code at `span`. Errors which want to label
+    /// the code they were found at can override this.
+    fn to_diagnostic_at(&self, span: Span) -> Diagnostic {
+        self.to_diagnostic().with_span(span)
+    }
+}
+
+impl<T: ToDiagnostic> ToDiagnostic for Spanned<T> {
+    fn to_diagnostic(&self) -> Diagnostic {
+        self.value.to_diagnostic_at(self.span)
+    }
+}
+
+/// Serializes `diagnostics` as a JSON array of the objects created by `Diagnostic::to_json`
+pub fn to_json(diagnostics: &[Diagnostic]) -> String {
+    let mut out = String::from("[");
+    for (i, diagnostic) in diagnostics.iter().enumerate() {
+        if i != 0 {
+            out.push(',');
+        }
+        out.push_str(&diagnostic.to_json());
+    }
+    out.push(']');
+    out
+}
+
+fn push_json_span(out: &mut String, span: Span) {
+    out.push_str("{\"start\":");
+    push_json_location(out, span.start);
+    out.push_str(",\"end\":");
+    push_json_location(out, span.end);
+    out.push('}');
+}
+
+fn push_json_location(out: &mut String, location: Location) {
+    out.push_str(&format!("{{\"line\":{},\"column\":{}}}",
+                          location.line,
+                          location.column.to_usize()));
+}
+
+fn push_json_string(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pos::{BytePos, CharPos, Location, Span};
+
+    fn location(line: u32, column: usize) -> Location {
+        Location {
+            line: line,
+            column: CharPos(column),
+            absolute: BytePos(0),
+        }
+    }
+
+    #[test]
+    fn diagnostic_to_json() {
+        let span = Span {
+            start: location(1, 5),
+            end: location(1, 8),
+        };
+        let diagnostic = Diagnostic::error("undefined_variable", "Undefined variable `x`")
+            .in_file("test")
+            .with_span(span)
+            .with_label(span, "used \"here\"")
+            .with_note("line\nbreak");
+        assert_eq!(diagnostic.to_json(),
+                   "{\"severity\":\"error\",\"code\":\"undefined_variable\",\
+                    \"message\":\"Undefined variable `x`\",\"file\":\"test\",\
+                    \"span\":{\"start\":{\"line\":1,\"column\":5},\
+                    \"end\":{\"line\":1,\"column\":8}},\
+                    \"labels\":[{\"span\":{\"start\":{\"line\":1,\"column\":5},\
+                    \"end\":{\"line\":1,\"column\":8}},\"message\":\"used \\\"here\\\"\"}],\
+                    \"notes\":[\"line\\nbreak\"]}");
+    }
+
+    #[test]
+    fn diagnostic_without_span_to_json() {
+        let diagnostic = Diagnostic::warning("unused_binding", "Unused binding `x`");
+        assert_eq!(to_json(&[diagnostic]),
+                   "[{\"severity\":\"warning\",\"code\":\"unused_binding\",\
+                    \"message\":\"Unused binding `x`\",\"file\":null,\"span\":null,\
+                    \"labels\":[],\"notes\":[]}]");
+    }
+}
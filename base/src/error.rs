@@ -5,7 +5,9 @@ use std::any::Any;
 use std::error::Error as StdError;
 use std::fmt;
 
+use diagnostic::{Diagnostic, ToDiagnostic};
 use pos::Spanned;
+use snippet;
 
 /// An error type which can represent multiple errors.
 #[derive(Debug, PartialEq)]
@@ -46,65 +48,58 @@ impl<T: fmt::Display + fmt::Debug + Any> StdError for Errors<T> {
 }
 
 
-#[derive(Debug)]
-struct SourceContext<E> {
-    context: String,
-    error: Spanned<E>,
-}
-
-fn extract_context<E>(lines: &[&str], error: Spanned<E>) -> SourceContext<E> {
-    SourceContext {
-        context: lines.get((error.span.start.line - 1) as usize)
-            .cloned()
-            .unwrap_or("N/A")
-            .to_string(),
-        error: error,
-    }
-}
-
 /// Error type which contains information of which file and where in the file the error occured
 #[derive(Debug)]
 pub struct InFile<E> {
     file: String,
-    error: Errors<SourceContext<E>>,
+    source: String,
+    error: Errors<Spanned<E>>,
 }
 
 impl<E> InFile<E> {
     /// Creates a new `InFile` error which states that the error occured in `file` using the file
     /// contents in `contents` to provide a context to the span.
     pub fn new(file: String, contents: &str, error: Errors<Spanned<E>>) -> InFile<E> {
-        let lines: Vec<_> = contents.lines().collect();
         InFile {
             file: file,
-            error: Errors {
-                errors: error.errors
-                    .into_iter()
-                    .map(|error| extract_context(&lines, error))
-                    .collect(),
-            },
+            source: contents.to_string(),
+            error: error,
         }
     }
     pub fn errors(self) -> Errors<Spanned<E>> {
-        Errors { errors: self.error.errors.into_iter().map(|err| err.error).collect() }
+        self.error
+    }
+
+    /// Returns a diagnostic for each of the errors
+    pub fn diagnostics(&self) -> Vec<Diagnostic>
+        where E: ToDiagnostic
+    {
+        self.error
+            .errors
+            .iter()
+            .map(|err| err.to_diagnostic().in_file(self.file.clone()))
+            .collect()
+    }
+
+    /// Renders each error together with its labels and the source code they refer to. If
+    /// `colors` is true the output is coloured with ANSI escape codes.
+    pub fn render(&self, colors: bool) -> String
+        where E: ToDiagnostic
+    {
+        self.diagnostics()
+            .iter()
+            .map(|diagnostic| snippet::render(diagnostic, Some(&self.source), colors))
+            .collect()
     }
 }
 
 impl<E: fmt::Display> fmt::Display for InFile<E> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let lines: Vec<_> = self.source.lines().collect();
         for error in &self.error.errors {
-            try!(write!(f, "{}:{}\n{}\n", self.file, error.error, error.context));
-            for _ in 1..error.error.span.start.column.to_usize() {
-                try!(write!(f, " "));
-            }
-            try!(write!(f, "^"));
-            for _ in error.error.span.start.column.to_usize()..(error.error
-                .span
-                .end
-                .column
-                .to_usize() - 1) {
-                try!(write!(f, "~"));
-            }
-            try!(writeln!(f, ""));
+            try!(write!(f, "{}:{}\n", self.file, error));
+            let gutter = snippet::gutter_width(Some(error.span));
+            try!(snippet::write_span(f, &lines, error.span, gutter, '^', "", None));
         }
         Ok(())
     }
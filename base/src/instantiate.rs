@@ -211,13 +211,58 @@ impl Instantiator {
 pub fn instantiate<F>(typ: TcType, mut f: F) -> TcType
     where F: FnMut(&Generic<Symbol>) -> Option<TcType>
 {
+    instantiate_(typ, &mut f)
+}
+
+fn instantiate_(typ: TcType, f: &mut FnMut(&Generic<Symbol>) -> Option<TcType>) -> TcType {
     walk_move_type_no_recurse(typ,
                               &mut |typ| {
-                                  match *typ {
-                                      Type::Generic(ref x) => f(x),
-                                      _ => None,
-                                  }
-                              })
+        match *typ {
+            Type::Generic(ref x) => f(x),
+            // The variables bound by the `forall` shadow any outer variables with the same name
+            Type::Forall(ref params, ref body) => {
+                let new_body = instantiate_(body.clone(), &mut |gen| {
+                    if params.iter().any(|param| param.id == gen.id) {
+                        None
+                    } else {
+                        f(gen)
+                    }
+                });
+                Some(Type::forall(params.clone(), new_body))
+            }
+            _ => None,
+        }
+    })
+}
+
+/// Replaces the variables bound by a `forall` with new rigid type variables which are only equal
+/// to themselves. The names of the new variables are created by `new_skolem` and added to
+/// `skolems`.
+///
+/// Checking that `forall a . a -> a` is an instance of some type is done by checking that the
+/// type is an instance of `a@1 -> a@1` where `a@1` is a variable which can't be unified with any
+/// type except itself.
+pub fn skolemize<F>(params: &[Generic<Symbol>],
+                    typ: &TcType,
+                    skolems: &mut Vec<Symbol>,
+                    mut new_skolem: F)
+                    -> TcType
+    where F: FnMut(&Symbol) -> Symbol
+{
+    let start = skolems.len();
+    skolems.extend(params.iter().map(|param| new_skolem(&param.id)));
+    let new_params = &skolems[start..];
+    instantiate(typ.clone(), |gen| {
+        params.iter()
+            .zip(new_params)
+            .find(|&(param, _)| param.id == gen.id)
+            .map(|(param, skolem)| {
+                Type::generic(Generic {
+                    kind: param.kind.clone(),
+                    id: skolem.clone(),
+                })
+            })
+    })
 }
 
 /// Walks through a type replacing some types
@@ -245,8 +290,7 @@ fn walk_move_type2<F, I, T>(typ: &Type<I, T>, f: &mut F) -> Option<T>
                     let new_args = walk_move_types(args.iter(), |t| walk_move_type2(t, f));
                     merge(id, walk_move_type2(id, f), args, new_args, Type::app)
                 }
-                Type::Record { ref types, ref fields } => {
-                    let new_types = None;
+                Type::Record { ref types, ref fields, ref rest } => {
                     let new_fields = walk_move_types(fields.iter(), |field| {
                         walk_move_type2(&field.typ, f).map(|typ| {
                             types::Field {
@@ -255,13 +299,23 @@ fn walk_move_type2<F, I, T>(typ: &Type<I, T>, f: &mut F) -> Option<T>
                             }
                         })
                     });
-                    merge(types, new_types, fields, new_fields, Type::record)
+                    let new_rest = rest.as_ref()
+                        .and_then(|rest| walk_move_type2(rest, f))
+                        .map(Some);
+                    merge(fields,
+                          new_fields,
+                          rest,
+                          new_rest,
+                          |fields, rest| types::record_with_rest(types.clone(), fields, rest))
                 }
                 Type::Variants(ref variants) => {
                     walk_move_types(variants.iter(),
                                     |v| walk_move_type2(&v.1, f).map(|t| (v.0.clone(), t)))
                         .map(Type::variants)
                 }
+                Type::Forall(ref params, ref typ) => {
+                    walk_move_type2(typ, f).map(|typ| Type::forall(params.clone(), typ))
+                }
                 Type::Builtin(_) |
                 Type::Variable(_) |
                 Type::Generic(_) |
@@ -8,6 +8,7 @@ extern crate quick_error;
 extern crate pretty;
 
 pub mod ast;
+pub mod diagnostic;
 pub mod error;
 pub mod fixed;
 pub mod fnv;
@@ -15,5 +16,7 @@ pub mod instantiate;
 pub mod metadata;
 pub mod pos;
 pub mod scoped_map;
+pub mod snippet;
+pub mod source;
 pub mod symbol;
 pub mod types;
@@ -1,5 +1,6 @@
 use std::collections::BTreeMap;
 
+use ast::Fixity;
 use symbol::Symbol;
 
 pub trait MetadataEnv {
@@ -29,11 +30,17 @@ impl<T: MetadataEnv, U: MetadataEnv> MetadataEnv for (T, U) {
 #[derive(Clone, Debug, Default, Eq, PartialEq)]
 pub struct Metadata {
     pub comment: Option<String>,
+    /// The fixity of the operator if one was declared with `infixl` or `infixr`
+    pub fixity: Option<Fixity>,
+    /// True if the value was bound with `let implicit` and can be used to resolve implicit
+    /// arguments in the modules which import it
+    pub implicit: bool,
     pub module: BTreeMap<String, Metadata>,
 }
 
 impl Metadata {
     pub fn has_data(&self) -> bool {
-        self.comment.is_some() || !self.module.is_empty()
+        self.comment.is_some() || self.fixity.is_some() || self.implicit ||
+        !self.module.is_empty()
     }
 }
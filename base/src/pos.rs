@@ -4,7 +4,8 @@
 
 use std::cmp::Ordering;
 use std::fmt;
-use std::ops::{Add, AddAssign, Deref, Sub, SubAssign};
+use std::hash::{Hash, Hasher};
+use std::ops::{Add, AddAssign, Deref, DerefMut, Sub, SubAssign};
 
 /// A byte offset in a source string
 #[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Debug)]
@@ -106,8 +107,9 @@ impl fmt::Display for CharPos {
     }
 }
 
-/// A location in a source file
-#[derive(Copy, Clone, Eq, PartialEq, Debug, Hash, Ord, PartialOrd)]
+/// A location in a source file. Locations are compared by their line and column only as the
+/// byte offset is not known for locations which are not created by the parser.
+#[derive(Copy, Clone, Debug)]
 pub struct Location {
     pub line: u32,
     pub column: CharPos,
@@ -121,6 +123,33 @@ impl Location {
     }
 }
 
+impl PartialEq for Location {
+    fn eq(&self, other: &Location) -> bool {
+        self.line == other.line && self.column == other.column
+    }
+}
+
+impl Eq for Location {}
+
+impl PartialOrd for Location {
+    fn partial_cmp(&self, other: &Location) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Location {
+    fn cmp(&self, other: &Location) -> Ordering {
+        (self.line, self.column).cmp(&(other.line, other.column))
+    }
+}
+
+impl Hash for Location {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.line.hash(state);
+        self.column.hash(state);
+    }
+}
+
 impl fmt::Display for Location {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "Line: {}, Column: {}", self.line, self.column)
@@ -165,9 +194,10 @@ impl<T: fmt::Display> fmt::Display for Spanned<T> {
     }
 }
 
+/// A value together with the span of the source code it was parsed from
 #[derive(Clone, Debug)]
 pub struct Located<T> {
-    pub location: Location,
+    pub span: Span,
     pub value: T,
 }
 
@@ -184,15 +214,28 @@ impl<T> Deref for Located<T> {
     }
 }
 
+impl<T> DerefMut for Located<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.value
+    }
+}
+
 impl<T: fmt::Display> fmt::Display for Located<T> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}: {}", self.location, self.value)
+        write!(f, "{}: {}", self.span.start, self.value)
     }
 }
 
-pub fn located<T>(location: Location, value: T) -> Located<T> {
+pub fn located<T>(span: Span, value: T) -> Located<T> {
     Located {
-        location: location,
+        span: span,
         value: value,
     }
 }
+
+pub fn span(start: Location, end: Location) -> Span {
+    Span {
+        start: start,
+        end: end,
+    }
+}
@@ -0,0 +1,240 @@
+//! Rendering of the source code which errors and diagnostics refer to. Each span is shown as the
+//! lines it covers, each followed by a line which underlines the part of it that the span covers.
+//!
+//! ```text
+//! error[type_mismatch]: test:Line: 3, Column: 11: Expected `Int` but found `String`
+//!   |
+//! 2 | let f x : Int -> Int = x
+//!   |     - Expected `Int` because of this
+//! 3 | let y = f "abc"
+//!   |           ^^^^^ Found `String`
+//! ```
+use std::cmp;
+use std::fmt::{self, Write};
+
+use diagnostic::{Diagnostic, Severity};
+use pos::Span;
+
+/// Spans covering more lines than this only show the first and last lines
+const MAX_SPAN_LINES: u32 = 4;
+
+const RESET: &'static str = "\x1b[0m";
+const BOLD: &'static str = "\x1b[1m";
+const LABEL_COLOR: &'static str = "\x1b[1;34m";
+
+fn severity_color(severity: Severity) -> &'static str {
+    match severity {
+        Severity::Error => "\x1b[1;31m",
+        Severity::Warning => "\x1b[1;33m",
+        Severity::Note => "\x1b[1;32m",
+    }
+}
+
+/// Returns the number of columns needed to display the line numbers of all spans in `spans`
+pub fn gutter_width<I>(spans: I) -> usize
+    where I: IntoIterator<Item = Span>
+{
+    spans.into_iter()
+        .map(|span| cmp::max(span.start.line, span.end.line).to_string().len())
+        .max()
+        .unwrap_or(1)
+}
+
+/// Writes the lines of `lines` which `span` covers, each followed by a line which underlines the
+/// covered part with `marker`. `message` is written after the last underline and the underlines
+/// and message are written with `color` if it is not `None`.
+pub fn write_span<W>(out: &mut W,
+                     lines: &[&str],
+                     span: Span,
+                     gutter: usize,
+                     marker: char,
+                     message: &str,
+                     color: Option<&str>)
+                     -> fmt::Result
+    where W: Write
+{
+    let first = span.start.line;
+    let last = cmp::max(first, span.end.line);
+    for line in first..(last + 1) {
+        if last - first >= MAX_SPAN_LINES && line > first + 1 && line < last {
+            if line == first + 2 {
+                try!(writeln!(out, "{:>width$}", "...", width = gutter + 2));
+            }
+            continue;
+        }
+        let text = lines.get(line as usize - 1).cloned().unwrap_or("");
+        let len = text.chars().count() + 1;
+        // Lines in the middle of the span are underlined from their first non-whitespace
+        // character
+        let start = if line == first {
+            span.start.column.to_usize()
+        } else {
+            text.chars().take_while(|c| c.is_whitespace()).count() + 1
+        };
+        let end = if line == span.end.line {
+            span.end.column.to_usize()
+        } else {
+            len
+        };
+        let start = cmp::min(cmp::max(start, 1), len);
+        let end = cmp::max(cmp::min(end, len), start + 1);
+
+        try!(writeln!(out, "{:>width$} | {}", line, text, width = gutter));
+        try!(write!(out, "{:width$} | ", "", width = gutter));
+        // Keep any tabs so that the underline lines up with the text
+        for c in text.chars().take(start - 1) {
+            try!(out.write_char(if c == '\t' { '\t' } else { ' ' }));
+        }
+        if let Some(color) = color {
+            try!(out.write_str(color));
+        }
+        for _ in start..end {
+            try!(out.write_char(marker));
+        }
+        if line == last && !message.is_empty() {
+            try!(write!(out, " {}", message));
+        }
+        if color.is_some() {
+            try!(out.write_str(RESET));
+        }
+        try!(writeln!(out, ""));
+    }
+    Ok(())
+}
+
+/// Renders `diagnostic` together with the lines of `source` which it refers to. The primary span
+/// is underlined with `^` and each label with `-`. If the source is not available only the
+/// message and notes are rendered. If `colors` is true the output is coloured with ANSI escape
+/// codes.
+pub fn render(diagnostic: &Diagnostic, source: Option<&str>, colors: bool) -> String {
+    let mut out = String::new();
+    write_diagnostic(&mut out, diagnostic, source, colors).unwrap();
+    out
+}
+
+fn write_diagnostic<W>(out: &mut W,
+                       diagnostic: &Diagnostic,
+                       source: Option<&str>,
+                       colors: bool)
+                       -> fmt::Result
+    where W: Write
+{
+    let style = |color: &'static str| if colors { Some(color) } else { None };
+    let severity_color = style(severity_color(diagnostic.severity));
+
+    if let Some(color) = severity_color {
+        try!(out.write_str(color));
+    }
+    try!(write!(out, "{}[{}]", diagnostic.severity, diagnostic.code));
+    if colors {
+        try!(write!(out, "{}{}", RESET, BOLD));
+    }
+    try!(write!(out, ": "));
+    if let Some(ref file) = diagnostic.file {
+        try!(write!(out, "{}:", file));
+    }
+    if let Some(ref span) = diagnostic.span {
+        try!(write!(out, "{}: ", span.start));
+    }
+    try!(write!(out, "{}", diagnostic.message));
+    if colors {
+        try!(out.write_str(RESET));
+    }
+    try!(writeln!(out, ""));
+
+    let gutter = gutter_width(diagnostic.span
+        .into_iter()
+        .chain(diagnostic.labels.iter().map(|label| label.span)));
+    let lines: Vec<_> = match source {
+        Some(source) => source.lines().collect(),
+        None => {
+            for note in &diagnostic.notes {
+                try!(writeln!(out, "{:width$} = note: {}", "", note, width = gutter));
+            }
+            return Ok(());
+        }
+    };
+    if diagnostic.span.is_some() || !diagnostic.labels.is_empty() {
+        try!(writeln!(out, "{:width$} |", "", width = gutter));
+    }
+    // A label on the primary span is shown on the underline of the primary span
+    let primary_message = diagnostic.labels
+        .iter()
+        .find(|label| Some(label.span) == diagnostic.span)
+        .map_or("", |label| &label.message[..]);
+    let mut spans: Vec<_> = diagnostic.span
+        .into_iter()
+        .map(|span| (span, '^', primary_message, severity_color))
+        .chain(diagnostic.labels
+            .iter()
+            .filter(|label| Some(label.span) != diagnostic.span)
+            .map(|label| (label.span, '-', &label.message[..], style(LABEL_COLOR))))
+        .collect();
+    // Show the spans in the order they appear in the source
+    spans.sort_by_key(|&(span, _, _, _)| span.start);
+    for (span, marker, message, color) in spans {
+        try!(write_span(out, &lines, span, gutter, marker, message, color));
+    }
+    for note in &diagnostic.notes {
+        try!(writeln!(out, "{:width$} = note: {}", "", note, width = gutter));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use diagnostic::Diagnostic;
+    use pos::{BytePos, CharPos, Location, Span};
+
+    fn span(start: (u32, usize), end: (u32, usize)) -> Span {
+        let location = |(line, column)| {
+            Location {
+                line: line,
+                column: CharPos(column),
+                absolute: BytePos(0),
+            }
+        };
+        Span {
+            start: location(start),
+            end: location(end),
+        }
+    }
+
+    #[test]
+    fn render_labels() {
+        let source = "let f x : Int -> Int = x\nlet y = f \"abc\"";
+        let diagnostic = Diagnostic::error("type_mismatch", "Expected `Int` but found `String`")
+            .with_span(span((2, 11), (2, 16)))
+            .with_label(span((2, 11), (2, 16)), "Found `String`")
+            .with_label(span((1, 5), (1, 6)), "Expected `Int` because of this")
+            .with_note("Types do not match");
+        assert_eq!(render(&diagnostic, Some(source), false),
+                   "error[type_mismatch]: Line: 2, Column: 11: Expected `Int` but found \
+                    `String`
+  |
+1 | let f x : Int -> Int = x
+  |     - Expected `Int` because of this
+2 | let y = f \"abc\"
+  |           ^^^^^ Found `String`
+  = note: Types do not match
+");
+    }
+
+    #[test]
+    fn render_multi_line_span() {
+        let source = "let x = (1\n    #Int+\n    2)\nx";
+        let diagnostic = Diagnostic::warning("unused_binding", "Unused binding")
+            .with_span(span((1, 9), (3, 7)));
+        assert_eq!(render(&diagnostic, Some(source), false),
+                   "warning[unused_binding]: Line: 1, Column: 9: Unused binding
+  |
+1 | let x = (1
+  |         ^^
+2 |     #Int+
+  |     ^^^^^
+3 |     2)
+  |     ^^
+");
+    }
+}
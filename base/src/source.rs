@@ -0,0 +1,203 @@
+//! Conversion between the byte offsets stored in spans and the lines and columns which are shown
+//! to users or exchanged with editors through the language server protocol.
+use pos::{BytePos, CharPos, Location, Span};
+
+/// How the columns of a `Position` are counted
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum Encoding {
+    /// Columns are counted in UTF-8 code units (bytes)
+    Utf8,
+    /// Columns are counted in UTF-16 code units, the default of the language server protocol
+    Utf16,
+}
+
+/// A zero based line and column as used by the language server protocol
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash, Ord, PartialOrd)]
+pub struct Position {
+    pub line: u32,
+    pub character: u32,
+}
+
+/// Maps byte offsets in a source string to lines and columns and back
+#[derive(Clone, Debug)]
+pub struct SourceMap {
+    source: String,
+    /// The byte offset which each line starts at
+    lines: Vec<BytePos>,
+}
+
+impl SourceMap {
+    pub fn new(source: &str) -> SourceMap {
+        let mut lines = vec![BytePos(0)];
+        lines.extend(source.char_indices()
+            .filter(|&(_, c)| c == '\n')
+            .map(|(i, _)| BytePos::from(i + 1)));
+        SourceMap {
+            source: source.to_string(),
+            lines: lines,
+        }
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// Returns the number of lines in the source
+    pub fn line_count(&self) -> usize {
+        self.lines.len()
+    }
+
+    /// Returns the text of `line` (counted from 1) without its line ending
+    pub fn line(&self, line: u32) -> Option<&str> {
+        self.line_range(line).map(|(start, end)| {
+            self.source[start..end].trim_right_matches(|c| c == '\n' || c == '\r')
+        })
+    }
+
+    /// Returns the byte range of `line` (counted from 1) including its line ending
+    fn line_range(&self, line: u32) -> Option<(usize, usize)> {
+        if line == 0 {
+            return None;
+        }
+        self.lines.get(line as usize - 1).map(|start| {
+            let end = self.lines
+                .get(line as usize)
+                .map_or(self.source.len(), |end| end.to_usize());
+            (start.to_usize(), end)
+        })
+    }
+
+    /// Returns the line and column (both counted from 1, the column in characters) of the byte
+    /// offset `pos`
+    pub fn location(&self, pos: BytePos) -> Option<Location> {
+        if pos.to_usize() > self.source.len() || !self.source.is_char_boundary(pos.to_usize()) {
+            return None;
+        }
+        let line = match self.lines.binary_search(&pos) {
+            Ok(line) => line,
+            Err(line) => line - 1,
+        };
+        let start = self.lines[line].to_usize();
+        Some(Location {
+            line: line as u32 + 1,
+            column: CharPos(self.source[start..pos.to_usize()].chars().count() + 1),
+            absolute: pos,
+        })
+    }
+
+    /// Returns the byte offset of the character at `column` on `line` (both counted from 1)
+    pub fn byte_pos(&self, line: u32, column: CharPos) -> Option<BytePos> {
+        if column.to_usize() == 0 {
+            return None;
+        }
+        self.line_range(line).and_then(|(start, end)| {
+            let mut chars = self.source[start..end].char_indices().map(|(i, _)| start + i);
+            // The column just past the last character of the source is the end of the source
+            chars.nth(column.to_usize() - 1)
+                .or_else(|| if end == self.source.len() { Some(end) } else { None })
+                .map(BytePos::from)
+        })
+    }
+
+    /// Returns the span between the byte offsets `start` and `end`
+    pub fn span(&self, start: BytePos, end: BytePos) -> Option<Span> {
+        self.location(start).and_then(|start| {
+            self.location(end).map(|end| {
+                Span {
+                    start: start,
+                    end: end,
+                }
+            })
+        })
+    }
+
+    /// Converts the byte offset `pos` into a position whose column is counted in code units of
+    /// `encoding`
+    pub fn position(&self, pos: BytePos, encoding: Encoding) -> Option<Position> {
+        self.location(pos).map(|location| {
+            let start = self.lines[location.line as usize - 1].to_usize();
+            let prefix = &self.source[start..pos.to_usize()];
+            let character = match encoding {
+                Encoding::Utf8 => prefix.len(),
+                Encoding::Utf16 => prefix.chars().map(|c| c.len_utf16()).sum(),
+            };
+            Position {
+                line: location.line - 1,
+                character: character as u32,
+            }
+        })
+    }
+
+    /// Converts `position`, whose column is counted in code units of `encoding`, into a byte
+    /// offset. Returns `None` if the position is outside of the source or points into the middle
+    /// of a character.
+    pub fn from_position(&self, position: Position, encoding: Encoding) -> Option<BytePos> {
+        self.line_range(position.line + 1).and_then(|(start, end)| {
+            let mut units = 0;
+            for (i, c) in self.source[start..end].char_indices() {
+                if units == position.character as usize {
+                    return Some(BytePos::from(start + i));
+                }
+                units += match encoding {
+                    Encoding::Utf8 => c.len_utf8(),
+                    Encoding::Utf16 => c.len_utf16(),
+                };
+            }
+            if units == position.character as usize && end == self.source.len() {
+                Some(BytePos::from(end))
+            } else {
+                None
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pos::{BytePos, CharPos};
+
+    #[test]
+    fn location_and_byte_pos() {
+        let map = SourceMap::new("let x = 1\nlet åäö = \"ö\"\nx");
+        let location = map.location(BytePos(18)).unwrap();
+        assert_eq!((location.line, location.column), (2, CharPos(7)));
+        assert_eq!(map.byte_pos(2, CharPos(7)), Some(BytePos(18)));
+        assert_eq!(map.byte_pos(3, CharPos(1)), Some(BytePos(28)));
+        assert_eq!(map.byte_pos(3, CharPos(2)), Some(BytePos(29)));
+        assert_eq!(map.line(2), Some("let åäö = \"ö\""));
+        // In the middle of `å`
+        assert_eq!(map.location(BytePos(15)), None);
+    }
+
+    #[test]
+    fn utf8_and_utf16_positions() {
+        let map = SourceMap::new("x\n\"å𝄞\" y");
+        // `y` comes after `"`, `å` (2 bytes, 1 UTF-16 unit), `𝄞` (4 bytes, 2 UTF-16 units), `"` and
+        // a space
+        let y = BytePos(11);
+        assert_eq!(map.position(y, Encoding::Utf8),
+                   Some(Position {
+                       line: 1,
+                       character: 9,
+                   }));
+        assert_eq!(map.position(y, Encoding::Utf16),
+                   Some(Position {
+                       line: 1,
+                       character: 6,
+                   }));
+        assert_eq!(map.from_position(Position {
+                                         line: 1,
+                                         character: 6,
+                                     },
+                                     Encoding::Utf16),
+                   Some(y));
+        // Inside the surrogate pair of `𝄞`
+        assert_eq!(map.from_position(Position {
+                                         line: 1,
+                                         character: 3,
+                                     },
+                                     Encoding::Utf16),
+                   None);
+    }
+}
@@ -10,6 +10,7 @@ use pretty::{DocAllocator, Arena, DocBuilder};
 
 use ast;
 use ast::{AstType, DisplayEnv};
+use metadata::MetadataEnv;
 use symbol::{Symbol, SymbolRef};
 
 pub type TcType = ast::AstType<Symbol>;
@@ -55,15 +56,21 @@ impl<'a, T: ?Sized + TypeEnv> TypeEnv for &'a T {
 }
 
 /// Trait which is a `TypeEnv` which also provides access to the type representation of some
-/// primitive types
-pub trait PrimitiveEnv: TypeEnv {
+/// primitive types and to the metadata of the global values
+pub trait PrimitiveEnv: TypeEnv + MetadataEnv {
     fn get_bool(&self) -> &TcType;
+    /// Returns true if values of the type `id` are mutable (such as `Ref a`). The type variables
+    /// of mutable values are never generalized
+    fn is_mutable_type(&self, id: &SymbolRef) -> bool;
 }
 
 impl<'a, T: ?Sized + PrimitiveEnv> PrimitiveEnv for &'a T {
     fn get_bool(&self) -> &TcType {
         (**self).get_bool()
     }
+    fn is_mutable_type(&self, id: &SymbolRef) -> bool {
+        (**self).is_mutable_type(id)
+    }
 }
 
 pub fn instantiate<F>(typ: TcType, mut f: F) -> TcType
@@ -106,10 +113,17 @@ pub enum Type<Id, T = AstType<Id>> {
         types: Vec<Field<Id, Alias<Id, T>>>,
         /// The fields of this record type
         fields: Vec<Field<Id, T>>,
+        /// The type of the remaining fields of an open record (the `r` in `{ x : Int | r }`) or
+        /// `None` if the record is closed and contains exactly `fields`
+        rest: Option<T>,
     },
     /// An identifier type. Anything which is not a builting type.
     Id(Id),
     Alias(AliasData<Id, T>),
+    /// A type which is polymorphic in the variables it binds, `forall a b . a -> b -> a`.
+    /// Unlike the generics of a signature which are quantified at the top level this can appear
+    /// anywhere in a type such as a record field or function argument.
+    Forall(Vec<Generic<Id>>, T),
 }
 
 impl<Id, T> Type<Id, T>
@@ -227,6 +241,12 @@ pub enum BuiltinType {
     Array,
     /// Type constructor for functions, `(->) a b : Type -> Type -> Type`
     Function,
+    /// Type constructor for functions taking an implicit argument, `[a] -> b`. The argument is
+    /// filled in by the typechecker from the implicit instances which are in scope
+    ImplicitFunction,
+    /// Type given to expressions which failed to typecheck. It unifies with every other type so
+    /// that a single error does not cause more errors to be reported
+    Error,
 }
 
 impl BuiltinType {
@@ -263,6 +283,8 @@ impl BuiltinType {
             BuiltinType::Unit => "()",
             BuiltinType::Array => "Array",
             BuiltinType::Function => "->",
+            BuiltinType::ImplicitFunction => "[->]",
+            BuiltinType::Error => "!",
         }
     }
 }
@@ -439,6 +461,35 @@ impl<Id, T> Type<Id, T>
         T::from(Type::Record {
             types: types,
             fields: fields,
+            rest: None,
+        })
+    }
+
+    /// Creates an open record type which contains `fields` as well as any fields which `rest`
+    /// represents. If `rest` is itself a record its fields are merged into the returned record.
+    pub fn poly_record(types: Vec<Field<Id, Alias<Id, T>>>,
+                       fields: Vec<Field<Id, T>>,
+                       rest: T)
+                       -> T
+        where T: Deref<Target = Type<Id, T>> + Clone,
+              Id: Clone
+    {
+        let (types, fields, rest) = match *rest {
+            Type::Record { types: ref rest_types,
+                           fields: ref rest_fields,
+                           rest: ref rest_rest } => {
+                let mut types = types;
+                types.extend(rest_types.iter().cloned());
+                let mut fields = fields;
+                fields.extend(rest_fields.iter().cloned());
+                (types, fields, rest_rest.clone())
+            }
+            _ => (types, fields, Some(rest.clone())),
+        };
+        T::from(Type::Record {
+            types: types,
+            fields: fields,
+            rest: rest,
         })
     }
 
@@ -452,6 +503,17 @@ impl<Id, T> Type<Id, T>
                   |body, arg| Type::app(function.clone(), vec![arg, body]))
     }
 
+    /// Creates a function type where each argument in `args` is implicit
+    pub fn implicit_function(args: Vec<T>, ret: T) -> T
+        where T: Clone
+    {
+        let function: T = Type::builtin(BuiltinType::ImplicitFunction);
+        args.into_iter()
+            .rev()
+            .fold(ret,
+                  |body, arg| Type::app(function.clone(), vec![arg, body]))
+    }
+
     pub fn generic(typ: Generic<Id>) -> T {
         T::from(Type::Generic(typ))
     }
@@ -464,6 +526,14 @@ impl<Id, T> Type<Id, T>
         T::from(Type::Variable(typ))
     }
 
+    pub fn forall(params: Vec<Generic<Id>>, typ: T) -> T {
+        if params.is_empty() {
+            typ
+        } else {
+            T::from(Type::Forall(params, typ))
+        }
+    }
+
     pub fn alias(name: Id, args: Vec<Generic<Id>>, typ: T) -> T {
         T::from(Type::Alias(AliasData {
             name: name,
@@ -499,6 +569,10 @@ impl<Id, T> Type<Id, T>
     pub fn unit() -> T {
         Type::builtin(BuiltinType::Unit)
     }
+
+    pub fn error() -> T {
+        Type::builtin(BuiltinType::Error)
+    }
 }
 
 impl<Id, T> Type<Id, T>
@@ -515,6 +589,26 @@ impl<Id, T> Type<Id, T>
         None
     }
 
+    /// Returns true if `self` is the type given to expressions which failed to typecheck
+    pub fn is_error(&self) -> bool {
+        match *self {
+            Type::Builtin(BuiltinType::Error) => true,
+            _ => false,
+        }
+    }
+
+    /// Returns the implicit argument and the return type if `self` is `[a] -> b`
+    pub fn as_implicit_function(&self) -> Option<(&T, &T)> {
+        if let Type::App(ref app, ref args) = *self {
+            if args.len() == 2 {
+                if let Type::Builtin(BuiltinType::ImplicitFunction) = **app {
+                    return Some((&args[0], &args[1]));
+                }
+            }
+        }
+        None
+    }
+
     pub fn as_alias(&self) -> Option<(&Id, &[T])> {
         match *self {
             Type::App(ref id, ref args) => {
@@ -747,6 +841,17 @@ impl<'a, I, T, E> DisplayType<'a, I, T, E>
 
                         enclose(p, Prec::Function, arena, doc)
                     }
+                    None if self.typ.as_implicit_function().is_some() => {
+                        let (arg, ret) = self.typ.as_implicit_function().unwrap();
+                        let doc = chain![arena;
+                                         "[",
+                                         top(self.env, arg).pretty(arena).group(),
+                                         "] ->",
+                                         arena.newline(),
+                                         top(self.env, ret).pretty(arena)];
+
+                        enclose(p, Prec::Function, arena, doc)
+                    }
                     None => {
                         let mut doc = dt(self.env, Prec::Top, t).pretty(arena);
                         for arg in args {
@@ -777,7 +882,7 @@ impl<'a, I, T, E> DisplayType<'a, I, T, E>
                 enclose(p, Prec::Constructor, arena, doc).group()
             }
             Type::Builtin(ref t) => arena.text(t.to_str()),
-            Type::Record { ref types, ref fields } => {
+            Type::Record { ref types, ref fields, ref rest } => {
                 let mut doc = arena.text("{");
                 if !types.is_empty() {
                     for (i, field) in types.iter().enumerate() {
@@ -824,8 +929,12 @@ impl<'a, I, T, E> DisplayType<'a, I, T, E>
                         doc = doc.append(arena.newline()).append(f);
                     }
                 }
+                if let Some(ref rest) = *rest {
+                    doc = doc.append(arena.newline())
+                        .append(chain![arena; "| ", top(self.env, rest).pretty(arena)].group());
+                }
                 doc = doc.nest(4);
-                if !types.is_empty() || !fields.is_empty() {
+                if !types.is_empty() || !fields.is_empty() || rest.is_some() {
                     doc = doc.append(arena.newline());
                 }
                 doc.append("}")
@@ -833,6 +942,18 @@ impl<'a, I, T, E> DisplayType<'a, I, T, E>
             }
             Type::Id(ref id) => arena.text(self.env.string(id)),
             Type::Alias(ref alias) => arena.text(self.env.string(&alias.name)),
+            Type::Forall(ref params, ref typ) => {
+                let mut doc = arena.text("forall");
+                for param in params {
+                    doc = doc.append(" ").append(param.id.as_ref());
+                }
+                let doc = chain![arena;
+                                 doc,
+                                 " .",
+                                 arena.newline(),
+                                 top(self.env, typ).pretty(arena)];
+                enclose(p, Prec::Function, arena, doc).group()
+            }
         }
     }
 }
@@ -877,7 +998,7 @@ pub fn walk_type_<I, T, F: ?Sized>(typ: &T, f: &mut F)
                 f.walk(a);
             }
         }
-        Type::Record { ref types, ref fields } => {
+        Type::Record { ref types, ref fields, ref rest } => {
             for field in types {
                 if let Some(ref typ) = field.typ.typ {
                     f.walk(typ);
@@ -886,12 +1007,16 @@ pub fn walk_type_<I, T, F: ?Sized>(typ: &T, f: &mut F)
             for field in fields {
                 f.walk(&field.typ);
             }
+            if let Some(ref rest) = *rest {
+                f.walk(rest);
+            }
         }
         Type::Variants(ref variants) => {
             for variant in variants {
                 f.walk(&variant.1);
             }
         }
+        Type::Forall(_, ref typ) => f.walk(typ),
         Type::Builtin(_) |
         Type::Variable(_) |
         Type::Generic(_) |
@@ -1022,7 +1147,7 @@ pub fn walk_move_type_opt<F: ?Sized, I, T>(typ: &Type<I, T>, f: &mut F) -> Optio
             let new_args = walk_move_types(args, |t| f.visit(t));
             merge(id, f.visit(id), args, new_args, Type::app)
         }
-        Type::Record { ref types, ref fields } => {
+        Type::Record { ref types, ref fields, ref rest } => {
             let new_fields = walk_move_types(fields, |field| {
                 f.visit(&field.typ).map(|typ| {
                     Field {
@@ -1031,12 +1156,20 @@ pub fn walk_move_type_opt<F: ?Sized, I, T>(typ: &Type<I, T>, f: &mut F) -> Optio
                     }
                 })
             });
-            new_fields.map(|fields| Type::record(types.clone(), fields))
+            let new_rest = rest.as_ref().and_then(|rest| f.visit(rest)).map(Some);
+            merge(fields,
+                  new_fields,
+                  rest,
+                  new_rest,
+                  |fields, rest| record_with_rest(types.clone(), fields, rest))
         }
         Type::Variants(ref variants) => {
             walk_move_types(variants, |v| f.visit(&v.1).map(|t| (v.0.clone(), t)))
                 .map(Type::variants)
         }
+        Type::Forall(ref params, ref typ) => {
+            f.visit(typ).map(|typ| Type::forall(params.clone(), typ))
+        }
         Type::Builtin(_) |
         Type::Variable(_) |
         Type::Generic(_) |
@@ -1045,6 +1178,20 @@ pub fn walk_move_type_opt<F: ?Sized, I, T>(typ: &Type<I, T>, f: &mut F) -> Optio
     }
 }
 
+/// Creates a closed record if `rest` is `None` and an open record otherwise
+pub fn record_with_rest<I, T>(types: Vec<Field<I, Alias<I, T>>>,
+                              fields: Vec<Field<I, T>>,
+                              rest: Option<T>)
+                              -> T
+    where T: Deref<Target = Type<I, T>> + From<Type<I, T>> + Clone,
+          I: Clone
+{
+    match rest {
+        Some(rest) => Type::poly_record(types, fields, rest),
+        None => Type::record(types, fields),
+    }
+}
+
 pub fn walk_move_types<'a, I, F, T>(types: I, mut f: F) -> Option<Vec<T>>
     where I: IntoIterator<Item = &'a T>,
           F: FnMut(&'a T) -> Option<T>,
@@ -17,13 +17,17 @@ use gluon::compiler_pipeline::*;
 fn typecheck_prelude(b: &mut ::test::Bencher) {
     let vm = new_vm();
     let mut compiler = Compiler::new();
-    let MacroValue(expr) = {
+    let MacroValue { expr, implicit_prelude_lets } = {
         let mut text = String::new();
         File::open("std/prelude.glu").unwrap().read_to_string(&mut text).unwrap();
         text.expand_macro(&mut compiler, &vm, "std.prelude").unwrap_or_else(|err| panic!("{}", err))
     };
     b.iter(|| {
-        let result = MacroValue(expr.clone()).typecheck(&mut compiler, &vm, "<top>", "");
+        let value = MacroValue {
+            expr: expr.clone(),
+            implicit_prelude_lets: implicit_prelude_lets,
+        };
+        let result = value.typecheck(&mut compiler, &vm, "<top>", "");
         if let Err(ref err) = result {
             println!("{}", err);
             assert!(false);
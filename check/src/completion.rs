@@ -3,7 +3,7 @@
 use std::iter::once;
 use std::cmp::Ordering;
 
-use base::ast::{DisplayEnv, Expr, LExpr, LPattern, Pattern, TcIdent, Typed};
+use base::ast::{Expr, LExpr, LPattern, Pattern, TcIdent, Typed};
 use base::instantiate;
 use base::pos::{Location, Span};
 use base::scoped_map::ScopedMap;
@@ -55,33 +55,55 @@ impl<E: TypeEnv> OnFound for Suggest<E> {
             Pattern::Record { ref id, fields: ref field_ids, .. } => {
                 let unaliased = instantiate::remove_aliases(&self.env, id.typ.clone());
                 if let Type::Record { ref fields, .. } = *unaliased {
-                    for (field, field_type) in field_ids.iter().zip(fields) {
-                        let f = field.1.as_ref().unwrap_or(&field.0).clone();
-                        self.stack.insert(f, field_type.typ.clone());
+                    for field in field_ids {
+                        match field.1 {
+                            Some(ref pattern) => self.on_pattern(pattern),
+                            None => {
+                                let field_type = fields.iter()
+                                    .find(|field_type| field_type.name.name_eq(&field.0));
+                                if let Some(field_type) = field_type {
+                                    self.stack.insert(field.0.clone(), field_type.typ.clone());
+                                }
+                            }
+                        }
                     }
                 }
             }
             Pattern::Identifier(ref id) => {
                 self.stack.insert(id.name.clone(), id.typ.clone());
             }
+            Pattern::Tuple { elems: ref args, .. } |
             Pattern::Constructor(_, ref args) => {
                 for arg in args {
-                    self.stack.insert(arg.name.clone(), arg.typ.clone());
+                    self.on_pattern(arg);
                 }
             }
+            Pattern::Literal(_) => (),
         }
     }
 
     fn expr(&mut self, expr: &LExpr<TcIdent<Symbol>>) {
-        if let Expr::Identifier(ref ident) = expr.value {
-            for (k, typ) in self.stack.iter() {
-                if k.declared_name().starts_with(ident.name.declared_name()) {
+        match expr.value {
+            Expr::Identifier(ref ident) => {
+                for (k, typ) in self.stack.iter() {
+                    if k.declared_name().starts_with(ident.name.declared_name()) {
+                        self.result.push(TcIdent {
+                            name: k.clone(),
+                            typ: typ.clone(),
+                        });
+                    }
+                }
+            }
+            // Any binding in scope could be used in place of a hole
+            Expr::Hole(_) => {
+                for (k, typ) in self.stack.iter() {
                     self.result.push(TcIdent {
                         name: k.clone(),
                         typ: typ.clone(),
                     });
                 }
             }
+            _ => (),
         }
     }
 
@@ -103,13 +125,12 @@ impl<E: TypeEnv> OnFound for Suggest<E> {
     }
 }
 
-struct FindVisitor<'a, F> {
-    env: &'a (DisplayEnv<Ident = TcIdent<Symbol>> + 'a),
+struct FindVisitor<F> {
     location: Location,
     on_found: F,
 }
 
-impl<'a, F> FindVisitor<'a, F> {
+impl<F> FindVisitor<F> {
     fn select_spanned<'e, I, S, T>(&self, iter: I, mut span: S) -> (bool, Option<&'e T>)
         where I: IntoIterator<Item = &'e T>,
               S: FnMut(&T) -> Span
@@ -138,13 +159,13 @@ impl<'a, F> FindVisitor<'a, F> {
     }
 }
 
-impl<'a, F> FindVisitor<'a, F>
+impl<F> FindVisitor<F>
     where F: OnFound
 {
     fn visit_one<'e, I>(&mut self, iter: I)
         where I: IntoIterator<Item = &'e LExpr<TcIdent<Symbol>>>
     {
-        let (_, expr) = self.select_spanned(iter, |e| e.span(self.env));
+        let (_, expr) = self.select_spanned(iter, |e| e.span);
         self.visit_expr(expr.unwrap());
     }
 
@@ -156,7 +177,7 @@ impl<'a, F> FindVisitor<'a, F>
         use base::ast::Expr::*;
 
         match current.value {
-            Identifier(_) | Literal(_) => self.on_found.expr(current),
+            Identifier(_) | Literal(_) | Error(_) | Hole(_) => self.on_found.expr(current),
             Call(ref func, ref args) => {
                 self.visit_one(once(&**func).chain(args));
             }
@@ -166,11 +187,12 @@ impl<'a, F> FindVisitor<'a, F>
                     .map(|x| &***x))
             }
             Match(ref expr, ref alts) => {
-                self.visit_one(once(&**expr).chain(alts.iter().map(|alt| &alt.expression)))
+                let alt_exprs = alts.iter()
+                    .flat_map(|alt| alt.guard.iter().chain(once(&alt.expression)));
+                self.visit_one(once(&**expr).chain(alt_exprs))
             }
             BinOp(ref l, ref op, ref r) => {
-                match (l.span(self.env).containment(&self.location),
-                       r.span(self.env).containment(&self.location)) {
+                match (l.span.containment(&self.location), r.span.containment(&self.location)) {
                     (Ordering::Greater, Ordering::Less) => self.on_found.ident(current, op),
                     (_, Ordering::Greater) |
                     (_, Ordering::Equal) => self.visit_expr(r),
@@ -181,7 +203,7 @@ impl<'a, F> FindVisitor<'a, F>
                 for bind in bindings {
                     self.visit_pattern(&bind.name);
                 }
-                match self.select_spanned(bindings, |b| b.expression.span(self.env)) {
+                match self.select_spanned(bindings, |b| b.expression.span) {
                     (false, Some(bind)) => {
                         for arg in &bind.arguments {
                             self.on_found.on_ident(arg);
@@ -191,9 +213,10 @@ impl<'a, F> FindVisitor<'a, F>
                     _ => self.visit_expr(expr),
                 }
             }
-            Type(_, ref expr) => self.visit_expr(expr),
+            Type(_, ref expr) |
+            Annotated(ref expr, _) => self.visit_expr(expr),
             FieldAccess(ref expr, ref id) => {
-                if expr.span(self.env).containment(&self.location) <= Ordering::Equal {
+                if expr.span.containment(&self.location) <= Ordering::Equal {
                     self.visit_expr(expr);
                 } else {
                     self.on_found.ident(current, id);
@@ -202,7 +225,13 @@ impl<'a, F> FindVisitor<'a, F>
             Array(ref array) => self.visit_one(&array.expressions),
             Record { ref exprs, .. } => {
                 let exprs = exprs.iter().filter_map(|tup| tup.1.as_ref());
-                if let (_, Some(expr)) = self.select_spanned(exprs, |e| e.span(self.env)) {
+                if let (_, Some(expr)) = self.select_spanned(exprs, |e| e.span) {
+                    self.visit_expr(expr);
+                }
+            }
+            RecordUpdate { ref base, ref fields, .. } => {
+                let exprs = Some(&**base).into_iter().chain(fields.iter().map(|field| &field.1));
+                if let (_, Some(expr)) = self.select_spanned(exprs, |e| e.span) {
                     self.visit_expr(expr);
                 }
             }
@@ -212,22 +241,23 @@ impl<'a, F> FindVisitor<'a, F>
                 }
                 self.visit_expr(&lambda.body)
             }
+            Do(ref do_expr) => {
+                self.on_found.on_ident(&do_expr.id);
+                self.visit_one([&do_expr.bound, &do_expr.body].iter().map(|x| &***x))
+            }
             Tuple(ref args) => self.visit_one(args),
             Block(ref exprs) => self.visit_one(exprs),
         };
     }
 }
 
-pub fn find<D, T>(env: &D,
-                  typ_env: &T,
-                  expr: &LExpr<TcIdent<Symbol>>,
-                  location: Location)
-                  -> Result<TcType, ()>
-    where D: DisplayEnv<Ident = TcIdent<Symbol>>,
-          T: TypeEnv
+pub fn find<T>(typ_env: &T,
+               expr: &LExpr<TcIdent<Symbol>>,
+               location: Location)
+               -> Result<TcType, ()>
+    where T: TypeEnv
 {
     let mut visitor = FindVisitor {
-        env: env,
         location: location,
         on_found: GetType {
             env: typ_env,
@@ -238,16 +268,13 @@ pub fn find<D, T>(env: &D,
     visitor.on_found.typ.ok_or(())
 }
 
-pub fn suggest<D, T>(env: &D,
-                     typ_env: &T,
-                     expr: &LExpr<TcIdent<Symbol>>,
-                     location: Location)
-                     -> Vec<TcIdent<Symbol>>
-    where D: DisplayEnv<Ident = TcIdent<Symbol>>,
-          T: TypeEnv
+pub fn suggest<T>(typ_env: &T,
+                  expr: &LExpr<TcIdent<Symbol>>,
+                  location: Location)
+                  -> Vec<TcIdent<Symbol>>
+    where T: TypeEnv
 {
     let mut visitor = FindVisitor {
-        env: env,
         location: location,
         on_found: Suggest {
             env: typ_env,
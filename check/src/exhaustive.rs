@@ -0,0 +1,325 @@
+//! Module which checks that `match` expressions are exhaustive and that they do not contain any
+//! unreachable alternatives.
+//!
+//! The patterns of each alternative are first translated into the simplified `Pattern` type which
+//! only distinguishes between wildcards and constructors (variants, records, tuples and literals
+//! are all treated as constructors). The checks are then done using the "usefulness" algorithm
+//! described in "Warnings for pattern matching" by Luc Maranget.
+use std::fmt;
+
+use base::ast::LiteralEnum;
+use base::symbol::{Name, Symbol};
+
+/// The maximum number of patterns which `missing_patterns` returns. Enumerating every missing
+/// pattern of a nested type can take exponential time so only a few of them are reported.
+pub const MAX_MISSING_PATTERNS: usize = 3;
+
+/// A simplified pattern
+#[derive(Clone, Debug, PartialEq)]
+pub enum Pattern {
+    /// Pattern which matches any value (an identifier pattern)
+    Wildcard,
+    /// Pattern which matches the values created by `Constructor` with arguments matching the
+    /// argument patterns
+    Constructor(Constructor, Vec<Pattern>),
+}
+
+/// The constructor of a value
+#[derive(Clone, Debug, PartialEq)]
+pub enum Constructor {
+    /// A variant of an enumeration.
+    Variant {
+        name: Symbol,
+        /// The name and number of arguments of every variant of the enumeration
+        variants: Vec<(Symbol, usize)>,
+    },
+    /// A record with the fields in `Vec`. Records only have a single constructor
+    Record(Vec<Symbol>),
+    /// A tuple with `usize` elements. Tuples only have a single constructor
+    Tuple(usize),
+    /// A literal value. Literals are assumed to have an infinite amount of constructors
+    Literal(LiteralEnum),
+}
+
+impl Constructor {
+    /// Returns the number of arguments the constructor takes
+    fn arity(&self) -> usize {
+        match *self {
+            Constructor::Variant { ref name, ref variants } => {
+                variants.iter()
+                    .find(|variant| variant.0 == *name)
+                    .map_or(0, |variant| variant.1)
+            }
+            Constructor::Record(ref fields) => fields.len(),
+            Constructor::Tuple(len) => len,
+            Constructor::Literal(_) => 0,
+        }
+    }
+
+    /// Checks if `self` and `other` construct the same values
+    fn same(&self, other: &Constructor) -> bool {
+        match (self, other) {
+            (&Constructor::Variant { name: ref l, .. },
+             &Constructor::Variant { name: ref r, .. }) => l == r,
+            (&Constructor::Literal(ref l), &Constructor::Literal(ref r)) => l == r,
+            (&Constructor::Record(_), &Constructor::Record(_)) |
+            (&Constructor::Tuple(_), &Constructor::Tuple(_)) => true,
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fn fmt_arg(arg: &Pattern, f: &mut fmt::Formatter) -> fmt::Result {
+            match *arg {
+                Pattern::Constructor(Constructor::Variant { .. }, ref args) if !args.is_empty() => {
+                    write!(f, "({})", arg)
+                }
+                _ => write!(f, "{}", arg),
+            }
+        }
+        match *self {
+            Pattern::Wildcard => write!(f, "_"),
+            Pattern::Constructor(ref ctor, ref args) => {
+                match *ctor {
+                    Constructor::Variant { ref name, .. } => {
+                        try!(write!(f, "{}", Name::new(name.declared_name()).name()));
+                        for arg in args {
+                            try!(write!(f, " "));
+                            try!(fmt_arg(arg, f));
+                        }
+                        Ok(())
+                    }
+                    Constructor::Record(ref fields) => {
+                        try!(write!(f, "{{"));
+                        for (i, (field, arg)) in fields.iter().zip(args).enumerate() {
+                            if i != 0 {
+                                try!(write!(f, ","));
+                            }
+                            try!(write!(f, " {} = {}", field.declared_name(), arg));
+                        }
+                        write!(f, " }}")
+                    }
+                    Constructor::Tuple(_) => {
+                        try!(write!(f, "("));
+                        for (i, arg) in args.iter().enumerate() {
+                            if i != 0 {
+                                try!(write!(f, ", "));
+                            }
+                            try!(write!(f, "{}", arg));
+                        }
+                        write!(f, ")")
+                    }
+                    Constructor::Literal(ref lit) => {
+                        match *lit {
+                            LiteralEnum::Byte(b) => write!(f, "{}b", b),
+                            LiteralEnum::Integer(i) => write!(f, "{}", i),
+                            LiteralEnum::Float(x) => write!(f, "{:?}", x),
+                            LiteralEnum::String(ref s) => write!(f, "{:?}", s),
+                            LiteralEnum::Char(c) => write!(f, "{:?}", c),
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Returns the rows of `matrix` which starts with a pattern that matches `ctor`, with the first
+/// pattern replaced by the arguments of the constructor
+fn specialize(matrix: &[Vec<Pattern>], ctor: &Constructor) -> Vec<Vec<Pattern>> {
+    let arity = ctor.arity();
+    matrix.iter()
+        .filter_map(|row| {
+            let mut new_row = match row[0] {
+                Pattern::Wildcard => vec![Pattern::Wildcard; arity],
+                Pattern::Constructor(ref row_ctor, ref args) => {
+                    if !row_ctor.same(ctor) {
+                        return None;
+                    }
+                    args.clone()
+                }
+            };
+            new_row.extend(row[1..].iter().cloned());
+            Some(new_row)
+        })
+        .collect()
+}
+
+/// Returns the rows of `matrix` which starts with a wildcard, with the wildcard removed
+fn default_matrix(matrix: &[Vec<Pattern>]) -> Vec<Vec<Pattern>> {
+    matrix.iter()
+        .filter(|row| row[0] == Pattern::Wildcard)
+        .map(|row| row[1..].to_owned())
+        .collect()
+}
+
+/// Returns the constructors used in the first column of `matrix`
+fn head_constructors(matrix: &[Vec<Pattern>]) -> Vec<&Constructor> {
+    let mut ctors: Vec<&Constructor> = Vec::new();
+    for row in matrix {
+        if let Pattern::Constructor(ref ctor, _) = row[0] {
+            if !ctors.iter().any(|c| c.same(ctor)) {
+                ctors.push(ctor);
+            }
+        }
+    }
+    ctors
+}
+
+/// Returns every constructor of the type that `ctor` constructs or `None` if the type has an
+/// infinite number of constructors
+fn all_constructors(ctor: &Constructor) -> Option<Vec<Constructor>> {
+    match *ctor {
+        Constructor::Variant { ref variants, .. } => {
+            Some(variants.iter()
+                .map(|variant| {
+                    Constructor::Variant {
+                        name: variant.0.clone(),
+                        variants: variants.clone(),
+                    }
+                })
+                .collect())
+        }
+        Constructor::Record(_) |
+        Constructor::Tuple(_) => Some(vec![ctor.clone()]),
+        Constructor::Literal(_) => None,
+    }
+}
+
+/// Returns all constructors which have the same type as the constructors in `used` if `used`
+/// contains every constructor of that type
+fn complete_signature(used: &[&Constructor]) -> Option<Vec<Constructor>> {
+    used.first()
+        .and_then(|ctor| all_constructors(ctor))
+        .and_then(|all| {
+            if all.iter().all(|c| used.iter().any(|u| u.same(c))) {
+                Some(all)
+            } else {
+                None
+            }
+        })
+}
+
+/// Returns true if there exists a value which matches `row` but none of the rows in `matrix`
+fn is_useful(matrix: &[Vec<Pattern>], row: &[Pattern]) -> bool {
+    if row.is_empty() {
+        return matrix.is_empty();
+    }
+    match row[0] {
+        Pattern::Constructor(ref ctor, ref args) => {
+            let mut new_row = args.clone();
+            new_row.extend(row[1..].iter().cloned());
+            is_useful(&specialize(matrix, ctor), &new_row)
+        }
+        Pattern::Wildcard => {
+            let complete = complete_signature(&head_constructors(matrix));
+            match complete {
+                Some(ctors) => {
+                    ctors.iter().any(|ctor| {
+                        let mut new_row = vec![Pattern::Wildcard; ctor.arity()];
+                        new_row.extend(row[1..].iter().cloned());
+                        is_useful(&specialize(matrix, ctor), &new_row)
+                    })
+                }
+                None => is_useful(&default_matrix(matrix), &row[1..]),
+            }
+        }
+    }
+}
+
+/// Returns at most `limit` rows of `columns` patterns which are not matched by any row in `matrix`
+fn missing_rows(matrix: &[Vec<Pattern>], columns: usize, limit: usize) -> Vec<Vec<Pattern>> {
+    if limit == 0 {
+        return vec![];
+    }
+    if columns == 0 {
+        return if matrix.is_empty() {
+            vec![vec![]]
+        } else {
+            vec![]
+        };
+    }
+    let used = head_constructors(matrix);
+    let mut missing = Vec::new();
+    match used.first().and_then(|ctor| all_constructors(ctor)) {
+        Some(ctors) => {
+            let mut default_rows = None;
+            for ctor in ctors {
+                if missing.len() >= limit {
+                    break;
+                }
+                let remaining = limit - missing.len();
+                if used.iter().any(|u| u.same(&ctor)) {
+                    let arity = ctor.arity();
+                    let rows = missing_rows(&specialize(matrix, &ctor),
+                                            arity + columns - 1,
+                                            remaining);
+                    for mut row in rows {
+                        let rest = row.split_off(arity);
+                        let mut new_row = vec![Pattern::Constructor(ctor.clone(), row)];
+                        new_row.extend(rest);
+                        missing.push(new_row);
+                    }
+                } else {
+                    // Values created by an unused constructor are only matched by the rows which
+                    // start with a wildcard
+                    if default_rows.is_none() {
+                        default_rows =
+                            Some(missing_rows(&default_matrix(matrix), columns - 1, limit));
+                    }
+                    let pattern = Pattern::Constructor(ctor.clone(),
+                                                       vec![Pattern::Wildcard; ctor.arity()]);
+                    for row in default_rows.as_ref().unwrap().iter().take(remaining) {
+                        let mut new_row = vec![pattern.clone()];
+                        new_row.extend(row.iter().cloned());
+                        missing.push(new_row);
+                    }
+                }
+            }
+        }
+        // Either no constructors are used or the type has an infinite amount of constructors so
+        // a wildcard is used to represent the values which are not matched
+        None => {
+            for row in missing_rows(&default_matrix(matrix), columns - 1, limit) {
+                let mut new_row = vec![Pattern::Wildcard];
+                new_row.extend(row);
+                missing.push(new_row);
+            }
+        }
+    }
+    missing
+}
+
+/// Returns patterns matching the values which are not matched by any of the patterns in
+/// `patterns`. If the returned `Vec` is empty `patterns` are exhaustive. At most `limit` patterns
+/// are returned.
+pub fn missing_patterns(patterns: &[Pattern], limit: usize) -> Vec<Pattern> {
+    let matrix = patterns.iter()
+        .map(|pattern| vec![pattern.clone()])
+        .collect::<Vec<_>>();
+    missing_rows(&matrix, 1, limit)
+        .into_iter()
+        .map(|mut row| row.pop().expect("Single column"))
+        .collect()
+}
+
+/// Returns the indexes of the patterns in `patterns` which can never be matched since the
+/// patterns before them already match every value they match. `guarded[i]` is true if the
+/// alternative of `patterns[i]` has a guard, such patterns can still be unreachable but they do
+/// not make any of the patterns after them unreachable.
+pub fn unreachable_patterns(patterns: &[Pattern], guarded: &[bool]) -> Vec<usize> {
+    let mut matrix = Vec::new();
+    let mut unreachable = Vec::new();
+    for (i, (pattern, &guarded)) in patterns.iter().zip(guarded).enumerate() {
+        let row = vec![pattern.clone()];
+        if !is_useful(&matrix, &row) {
+            unreachable.push(i);
+        }
+        if !guarded {
+            matrix.push(row);
+        }
+    }
+    unreachable
+}
@@ -86,6 +86,15 @@ impl<'a> KindCheck<'a> {
         self.variables.extend(variables.iter().cloned());
     }
 
+    /// Returns the kind of a type parameter. Parameters which were annotated with a kind keep
+    /// that kind while all other parameters get a fresh kind variable to be inferred.
+    pub fn parameter_kind(&mut self, param: &Generic<Symbol>) -> RcKind {
+        match *param.kind {
+            Kind::Variable(_) => self.subs.new_var(),
+            _ => param.kind.clone(),
+        }
+    }
+
     pub fn type_kind(&self) -> RcKind {
         self.type_kind.clone()
     }
@@ -106,6 +115,7 @@ impl<'a> KindCheck<'a> {
             .or_else(|| {
                 self.locals
                     .iter()
+                    .rev()
                     .find(|t| t.0 == *id)
                     .map(|t| t.1.clone())
             })
@@ -143,9 +153,10 @@ impl<'a> KindCheck<'a> {
     fn builtin_kind(&self, typ: BuiltinType) -> RcKind {
         match typ {
             BuiltinType::String | BuiltinType::Byte | BuiltinType::Char | BuiltinType::Int |
-            BuiltinType::Float | BuiltinType::Unit => self.type_kind(),
+            BuiltinType::Float | BuiltinType::Unit | BuiltinType::Error => self.type_kind(),
             BuiltinType::Array => self.function1_kind(),
-            BuiltinType::Function => self.function2_kind(),
+            BuiltinType::Function |
+            BuiltinType::ImplicitFunction => self.function2_kind(),
         }
     }
 
@@ -190,7 +201,7 @@ impl<'a> KindCheck<'a> {
                     .collect());
                 Ok((self.type_kind(), Type::variants(variants)))
             }
-            Type::Record { ref types, ref fields } => {
+            Type::Record { ref types, ref fields, ref rest } => {
                 let fields = try!(fields.iter()
                     .map(|field| {
                         let (kind, typ) = try!(self.kindcheck(&field.typ));
@@ -202,10 +213,42 @@ impl<'a> KindCheck<'a> {
                         })
                     })
                     .collect());
-                Ok((self.type_kind(), Type::record(types.clone(), fields)))
+                // The row variable of an open record has the same kind as the record itself
+                let rest = match *rest {
+                    Some(ref rest) => {
+                        let (kind, rest) = try!(self.kindcheck(rest));
+                        let type_kind = self.type_kind();
+                        try!(self.unify(&type_kind, kind));
+                        Some(rest)
+                    }
+                    None => None,
+                };
+                Ok((self.type_kind(), types::record_with_rest(types.clone(), fields, rest)))
             }
             Type::Id(ref id) => self.find(id).map(|kind| (kind, typ.clone())),
             Type::Alias(ref alias) => self.find(&alias.name).map(|kind| (kind, typ.clone())),
+            Type::Forall(ref params, ref body) => {
+                // The bound variables are only in scope in the body and shadow any outer variables
+                let locals = self.locals.len();
+                for param in params {
+                    let kind = self.parameter_kind(param);
+                    self.locals.push((param.id.clone(), kind));
+                }
+                let result = self.kindcheck(body);
+                let params = self.locals
+                    .drain(locals..locals + params.len())
+                    .map(|(id, kind)| {
+                        Generic {
+                            id: id,
+                            kind: kind,
+                        }
+                    })
+                    .collect();
+                let (kind, body) = try!(result);
+                let type_kind = self.type_kind();
+                try!(self.unify(&type_kind, kind));
+                Ok((self.type_kind(), Type::forall(params, body)))
+            }
         }
     }
 
@@ -237,6 +280,10 @@ impl<'a> KindCheck<'a> {
                     }))
                 }
                 Type::Generic(ref var) => Some(Type::generic(self.finalize_generic(var))),
+                Type::Forall(ref params, ref body) => {
+                    let params = params.iter().map(|param| self.finalize_generic(param)).collect();
+                    Some(Type::forall(params, body.clone()))
+                }
                 _ => None,
             }
         })
@@ -2,7 +2,8 @@
 //! program. This currently consits of three larger parts, typechecking, kindchecking and renaming.
 //! If an AST passes the checks in `Typecheck::typecheck_expr` (which runs all of theses checks
 //! the expression is expected to compile succesfully (if it does not it should be considered an
-//! internal compiler error.
+//! internal compiler error. The only exception are expressions containing holes (`_`) which
+//! typecheck but are reported through `Typecheck::holes` instead of being compiled.
 
 #[macro_use]
 extern crate log;
@@ -18,8 +19,10 @@ pub mod unify;
 pub mod kindcheck;
 mod substitution;
 mod rename;
+mod exhaustive;
 pub mod completion;
 pub mod metadata;
+pub mod lint;
 
 #[cfg(test)]
 mod tests {
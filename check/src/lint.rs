@@ -0,0 +1,308 @@
+//! Warnings about code which typechecks but which is likely to be a mistake. Warnings are computed
+//! from a typechecked (and renamed) expression so each use of a binding refers to exactly the
+//! binding it resolved to.
+use std::collections::HashSet;
+use std::fmt;
+
+use base::ast::{self, Expr, LExpr, LPattern, MutVisitor, Pattern};
+use base::diagnostic::{Diagnostic, ToDiagnostic};
+use base::pos::{Span, Spanned};
+use base::symbol::Symbol;
+use base::types::{TcIdent, TcType};
+
+use rename::original_name;
+
+/// Identifies the kind of a warning
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum WarningCode {
+    /// A `let` binding which is never used
+    UnusedBinding,
+    /// A variable bound by a pattern or a function argument which is never used
+    UnusedVariable,
+    /// A binding which hides an earlier binding with the same name and type
+    ShadowedBinding,
+    /// A field destructured from an `import` which is never used
+    UnusedImport,
+    /// A `match` expression which does not handle every value of the matched type
+    NonExhaustiveMatch,
+    /// An alternative which can't be reached since the alternatives before it already match every
+    /// value that it matches
+    UnreachableAlternative,
+}
+
+impl WarningCode {
+    /// Returns the name of the code (`unused_binding`, `shadowed_binding`, ...)
+    pub fn name(self) -> &'static str {
+        match self {
+            WarningCode::UnusedBinding => "unused_binding",
+            WarningCode::UnusedVariable => "unused_variable",
+            WarningCode::ShadowedBinding => "shadowed_binding",
+            WarningCode::UnusedImport => "unused_import",
+            WarningCode::NonExhaustiveMatch => "non_exhaustive_match",
+            WarningCode::UnreachableAlternative => "unreachable_alternative",
+        }
+    }
+
+    /// Returns the code with the name `name`
+    pub fn from_name(name: &str) -> Option<WarningCode> {
+        match name {
+            "unused_binding" => Some(WarningCode::UnusedBinding),
+            "unused_variable" => Some(WarningCode::UnusedVariable),
+            "shadowed_binding" => Some(WarningCode::ShadowedBinding),
+            "unused_import" => Some(WarningCode::UnusedImport),
+            "non_exhaustive_match" => Some(WarningCode::NonExhaustiveMatch),
+            "unreachable_alternative" => Some(WarningCode::UnreachableAlternative),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for WarningCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// How a warning is reported
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum WarningLevel {
+    /// The warning is not reported
+    Allow,
+    /// The warning is reported but does not stop compilation
+    Warn,
+    /// The warning is reported as an error
+    Deny,
+}
+
+/// A warning about `name`, which is the binding that the warning refers to or, for warnings
+/// about `match` expressions, a pattern
+#[derive(Clone, Debug, PartialEq)]
+pub struct Warning<I> {
+    pub code: WarningCode,
+    pub name: I,
+}
+
+impl<I: fmt::Display> Warning<I> {
+    /// Returns the message describing the warning
+    pub fn message(&self) -> String {
+        match self.code {
+            WarningCode::UnusedBinding => format!("Unused binding `{}`", self.name),
+            WarningCode::UnusedVariable => format!("Unused variable `{}`", self.name),
+            WarningCode::ShadowedBinding => {
+                format!("`{}` shadows an earlier binding of the same type", self.name)
+            }
+            WarningCode::UnusedImport => format!("Unused import `{}`", self.name),
+            WarningCode::NonExhaustiveMatch => {
+                format!("Non-exhaustive `match` expression, the pattern `{}` is not matched",
+                        self.name)
+            }
+            WarningCode::UnreachableAlternative => {
+                format!("Unreachable alternative `{}`, the alternatives before it already match \
+                         every value that it matches",
+                        self.name)
+            }
+        }
+    }
+}
+
+impl<I: fmt::Display> fmt::Display for Warning<I> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} [{}]", self.message(), self.code)
+    }
+}
+
+impl<I: fmt::Display> ToDiagnostic for Warning<I> {
+    fn to_diagnostic(&self) -> Diagnostic {
+        Diagnostic::warning(self.code.name(), self.message())
+    }
+}
+
+/// Returns the warnings for the typechecked expression `expr`. Bindings whose names start with
+/// `_` are never warned about.
+pub fn lint(expr: &mut LExpr<TcIdent>) -> Vec<Spanned<Warning<Symbol>>> {
+    struct FindUsed {
+        used: HashSet<Symbol>,
+    }
+
+    impl MutVisitor for FindUsed {
+        type T = TcIdent;
+
+        fn visit_expr(&mut self, expr: &mut LExpr<TcIdent>) {
+            match expr.value {
+                Expr::Identifier(ref id) |
+                Expr::BinOp(_, ref id, _) => {
+                    self.used.insert(id.name.clone());
+                }
+                _ => (),
+            }
+            ast::walk_mut_expr(self, expr);
+        }
+    }
+
+    let mut find_used = FindUsed { used: HashSet::new() };
+    find_used.visit_expr(expr);
+
+    let mut linter = Linter {
+        used: find_used.used,
+        scope: Vec::new(),
+        derived: Vec::new(),
+        warnings: Vec::new(),
+    };
+    linter.visit_expr(expr);
+    linter.warnings
+}
+
+struct Linter {
+    used: HashSet<Symbol>,
+    /// The names and types of the bindings which are currently in scope
+    scope: Vec<(String, TcType)>,
+    /// The names of the instances generated by `deriving` clauses
+    derived: Vec<String>,
+    warnings: Vec<Spanned<Warning<Symbol>>>,
+}
+
+impl Linter {
+    fn binding(&mut self, id: &TcIdent, span: Span, code: WarningCode) {
+        let name = original_name(id.name.as_ref()).to_owned();
+        if name.starts_with('_') {
+            return;
+        }
+        // Bindings with the same name but different types are overloads which can both be used
+        if self.scope.iter().any(|&(ref other, ref typ)| *other == name && *typ == id.typ) {
+            self.warn(span, WarningCode::ShadowedBinding, &name);
+        }
+        if !self.used.contains(&id.name) {
+            self.warn(span, code, &name);
+        }
+        self.scope.push((name, id.typ.clone()));
+    }
+
+    fn pattern(&mut self, pattern: &LPattern<TcIdent>, code: WarningCode) {
+        match pattern.value {
+            Pattern::Identifier(ref id) => self.binding(id, pattern.span, code),
+            Pattern::Constructor(_, ref args) => {
+                for arg in args {
+                    self.pattern(arg, code);
+                }
+            }
+            Pattern::Record { ref fields, .. } => {
+                for field in fields {
+                    if let Some(ref field) = field.1 {
+                        self.pattern(field, code);
+                    }
+                }
+            }
+            Pattern::Tuple { ref elems, .. } => {
+                for elem in elems {
+                    self.pattern(elem, code);
+                }
+            }
+            Pattern::Literal(_) => (),
+        }
+    }
+
+    fn let_binding(&mut self, bind: &ast::Binding<TcIdent>) {
+        let code = match bind.name.value {
+            Pattern::Identifier(_) => WarningCode::UnusedBinding,
+            // `import` is expanded into the global which holds the module
+            _ if is_global(&bind.expression) => WarningCode::UnusedImport,
+            _ => WarningCode::UnusedVariable,
+        };
+        self.pattern(&bind.name, code);
+    }
+
+    fn is_derived(&self, bind: &ast::Binding<TcIdent>) -> bool {
+        match bind.name.value {
+            Pattern::Identifier(ref id) => {
+                let name = original_name(id.name.as_ref());
+                self.derived.iter().any(|derived| derived == name)
+            }
+            _ => false,
+        }
+    }
+
+    fn warn(&mut self, span: Span, code: WarningCode, name: &str) {
+        self.warnings.push(Spanned {
+            span: span,
+            value: Warning {
+                code: code,
+                name: Symbol::new(name),
+            },
+        });
+    }
+}
+
+impl MutVisitor for Linter {
+    type T = TcIdent;
+
+    fn visit_expr(&mut self, expr: &mut LExpr<TcIdent>) {
+        let scope = self.scope.len();
+        match expr.value {
+            Expr::Let(ref mut bindings, ref mut body) => {
+                let is_recursive = bindings.iter().all(|bind| !bind.arguments.is_empty());
+                for bind in bindings.iter_mut() {
+                    // The code generated for `deriving` is not linted
+                    if self.is_derived(bind) {
+                        continue;
+                    }
+                    if !is_recursive {
+                        self.visit_expr(&mut bind.expression);
+                    }
+                    self.let_binding(bind);
+                }
+                if is_recursive {
+                    for bind in bindings.iter_mut() {
+                        if self.is_derived(bind) {
+                            continue;
+                        }
+                        let arguments = self.scope.len();
+                        for arg in &bind.arguments {
+                            self.binding(arg, arg.span, WarningCode::UnusedVariable);
+                        }
+                        self.visit_expr(&mut bind.expression);
+                        self.scope.truncate(arguments);
+                    }
+                }
+                self.visit_expr(body);
+            }
+            Expr::Lambda(ref mut lambda) => {
+                for arg in &lambda.arguments {
+                    self.binding(arg, arg.span, WarningCode::UnusedVariable);
+                }
+                self.visit_expr(&mut lambda.body);
+            }
+            Expr::Match(ref mut matched, ref mut alts) => {
+                self.visit_expr(matched);
+                for alt in alts {
+                    let alt_scope = self.scope.len();
+                    self.pattern(&alt.pattern, WarningCode::UnusedVariable);
+                    if let Some(ref mut guard) = alt.guard {
+                        self.visit_expr(guard);
+                    }
+                    self.visit_expr(&mut alt.expression);
+                    self.scope.truncate(alt_scope);
+                }
+            }
+            Expr::Type(ref bindings, ref mut body) => {
+                for bind in bindings {
+                    for instance in &bind.deriving {
+                        self.derived.push(format!("{}_{}",
+                                                  instance.as_ref().to_lowercase(),
+                                                  bind.name));
+                    }
+                }
+                self.visit_expr(body);
+            }
+            _ => ast::walk_mut_expr(self, expr),
+        }
+        self.scope.truncate(scope);
+    }
+}
+
+/// Returns true if `expr` refers to a global instead of a local binding
+fn is_global(expr: &LExpr<TcIdent>) -> bool {
+    match expr.value {
+        Expr::Identifier(ref id) => original_name(id.name.as_ref()) == id.name.as_ref(),
+        _ => false,
+    }
+}
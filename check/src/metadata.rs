@@ -5,7 +5,7 @@ use base::ast::MutVisitor;
 use base::metadata::{Metadata, MetadataEnv};
 use base::scoped_map::ScopedMap;
 use base::symbol::{Name, Symbol};
-use base::types::TcIdent;
+use base::types::{TcIdent, Type};
 
 struct Environment<'b> {
     env: &'b MetadataEnv,
@@ -21,14 +21,20 @@ pub fn metadata(env: &MetadataEnv, expr: &mut ast::LExpr<TcIdent>) -> Metadata {
         fn new_binding(&mut self, metadata: Metadata, bind: &mut ast::Binding<TcIdent>) {
             match bind.name.value {
                 ast::Pattern::Identifier(ref mut id) => {
-                    let metadata = bind.comment
+                    let mut metadata = bind.comment
                         .as_ref()
                         .map_or(metadata, |comment| {
                             Metadata {
                                 comment: Some(comment.clone()),
+                                fixity: None,
+                                implicit: false,
                                 module: BTreeMap::new(),
                             }
                         });
+                    if bind.fixity.is_some() {
+                        metadata.fixity = bind.fixity;
+                    }
+                    metadata.implicit = bind.implicit;
                     self.stack_var(id.name.clone(), metadata);
                 }
                 _ => self.new_pattern(metadata, &mut bind.name),
@@ -38,7 +44,15 @@ pub fn metadata(env: &MetadataEnv, expr: &mut ast::LExpr<TcIdent>) -> Metadata {
         fn new_pattern(&mut self, mut metadata: Metadata, pattern: &mut ast::LPattern<TcIdent>) {
             match pattern.value {
                 ast::Pattern::Record { ref mut fields, ref mut types, .. } => {
-                    for field in fields.iter_mut().chain(types) {
+                    for field in fields {
+                        if let Some(m) = metadata.module.remove(field.0.as_ref()) {
+                            match field.1 {
+                                Some(ref mut pattern) => self.new_pattern(m, pattern),
+                                None => self.stack_var(field.0.clone(), m),
+                            }
+                        }
+                    }
+                    for field in types {
                         if let Some(m) = metadata.module.remove(field.0.as_ref()) {
                             let id = field.1.as_ref().unwrap_or_else(|| &field.0).clone();
                             self.stack_var(id, m);
@@ -48,7 +62,9 @@ pub fn metadata(env: &MetadataEnv, expr: &mut ast::LExpr<TcIdent>) -> Metadata {
                 ast::Pattern::Identifier(ref mut id) => {
                     self.stack_var(id.name.clone(), metadata);
                 }
-                ast::Pattern::Constructor(..) => (),
+                ast::Pattern::Tuple { .. } |
+                ast::Pattern::Constructor(..) |
+                ast::Pattern::Literal(_) => (),
             }
         }
 
@@ -99,6 +115,8 @@ pub fn metadata(env: &MetadataEnv, expr: &mut ast::LExpr<TcIdent>) -> Metadata {
                     }
                     Metadata {
                         comment: None,
+                        fixity: None,
+                        implicit: false,
                         module: module,
                     }
                 }
@@ -130,6 +148,8 @@ pub fn metadata(env: &MetadataEnv, expr: &mut ast::LExpr<TcIdent>) -> Metadata {
                         let maybe_metadata = bind.comment.as_ref().map(|comment| {
                             Metadata {
                                 comment: Some(comment.clone()),
+                                fixity: None,
+                                implicit: false,
                                 module: BTreeMap::new(),
                             }
                         });
@@ -141,6 +161,20 @@ pub fn metadata(env: &MetadataEnv, expr: &mut ast::LExpr<TcIdent>) -> Metadata {
                     self.env.stack.exit_scope();
                     result
                 }
+                ast::Expr::Annotated(ref mut expr, ref typ) => {
+                    let mut metadata = self.metadata_expr(expr);
+                    // Fields which are hidden by the annotation should not have any metadata
+                    if let Type::Record { ref types, ref fields, .. } = *typ.value {
+                        metadata.module = metadata.module
+                            .into_iter()
+                            .filter(|&(ref name, _)| {
+                                fields.iter().any(|field| field.name.as_ref() == &name[..]) ||
+                                types.iter().any(|field| field.name.as_ref() == &name[..])
+                            })
+                            .collect();
+                    }
+                    metadata
+                }
                 _ => {
                     ast::walk_mut_expr(self, expr);
                     Metadata::default()
@@ -1,7 +1,8 @@
 use std::fmt;
 
 use base::ast::{self, DisplayEnv, Expr, LExpr, MutVisitor, Typed};
-use base::pos::{self, Location, Spanned};
+use base::diagnostic::{Diagnostic, ToDiagnostic};
+use base::pos::{self, CharPos, Location, Span, Spanned};
 use base::error::Errors;
 use base::fnv::FnvMap;
 use base::scoped_map::ScopedMap;
@@ -42,6 +43,32 @@ impl fmt::Display for RenameError {
     }
 }
 
+impl ToDiagnostic for RenameError {
+    fn to_diagnostic(&self) -> Diagnostic {
+        match *self {
+            RenameError::NoMatchingType { ref symbol, ref expected, ref possible_types } => {
+                let message = format!("Could not resolve a binding for `{}` with type `{}`",
+                                      symbol,
+                                      expected);
+                let diagnostic = Diagnostic::error("no_matching_overload", message);
+                possible_types.iter().fold(diagnostic, |diagnostic, &(ref location, ref typ)| {
+                    let message = format!("`{}` has the type `{}`", symbol, typ);
+                    match *location {
+                        Some(location) => {
+                            let span = Span {
+                                start: location,
+                                end: location.line_offset(CharPos::from(symbol.len())),
+                            };
+                            diagnostic.with_label(span, message)
+                        }
+                        None => diagnostic.with_note(format!("The global {}", message)),
+                    }
+                })
+            }
+        }
+    }
+}
+
 struct Environment<'b> {
     env: &'b TypeEnv,
     stack: ScopedMap<Symbol, (Symbol, Location, TcType)>,
@@ -70,6 +97,16 @@ impl<'a> TypeEnv for Environment<'a> {
     }
 }
 
+/// Returns the name which a binding of `name` at `location` is renamed to
+fn unique_name(name: &str, location: Location) -> String {
+    format!("{}:{}", name, location)
+}
+
+/// Returns the name that a binding had before it was given a unique name by `rename`
+pub fn original_name(name: &str) -> &str {
+    name.find(":Line: ").map_or(name, |i| &name[..i])
+}
+
 pub fn rename(symbols: &mut SymbolModule,
               env: &TypeEnv,
               expr: &mut LExpr<TcIdent>)
@@ -101,8 +138,22 @@ pub fn rename(symbols: &mut SymbolModule,
                             .expect("ICE: Existing field")
                             .typ
                             .clone();
-                        let id = field.1.as_ref().unwrap_or_else(|| &field.0).clone();
-                        field.1 = Some(self.stack_var(id, pattern.location, field_type));
+                        match field.1 {
+                            Some(ref mut field_pattern) => {
+                                self.new_pattern(&field_type, field_pattern);
+                            }
+                            None => {
+                                let id = field.0.clone();
+                                let new_id = self.stack_var(id,
+                                                            pattern.span.start,
+                                                            field_type.clone());
+                                let field_pattern = ast::Pattern::Identifier(TcIdent {
+                                    name: new_id,
+                                    typ: field_type,
+                                });
+                                field.1 = Some(pos::located(pattern.span, field_pattern));
+                            }
+                        }
                     }
                     let record_type = instantiate::remove_aliases(&self.env, typ.clone()).clone();
                     let imported_types = match *record_type {
@@ -113,31 +164,33 @@ pub fn rename(symbols: &mut SymbolModule,
                         let field_type = imported_types.iter()
                             .find(|field| field.name.name_eq(name))
                             .expect("field_type");
-                        self.stack_type(name.clone(), pattern.location, &field_type.typ);
+                        self.stack_type(name.clone(), pattern.span.start, &field_type.typ);
+                    }
+                }
+                ast::Pattern::Tuple { ref mut elems, .. } => {
+                    let field_types = self.find_fields(typ).unwrap_or_else(Vec::new);
+                    for (field, elem) in field_types.iter().zip(elems) {
+                        self.new_pattern(&field.typ, elem);
                     }
                 }
                 ast::Pattern::Identifier(ref mut id) => {
                     let new_name =
-                        self.stack_var(id.name.clone(), pattern.location, id.typ.clone());
+                        self.stack_var(id.name.clone(), pattern.span.start, id.typ.clone());
                     id.name = new_name;
                 }
                 ast::Pattern::Constructor(ref mut id, ref mut args) => {
-                    let typ = self.env
-                        .find_type(id.id())
-                        .expect("ICE: Expected constructor")
-                        .clone();
-                    for (arg_type, arg) in types::arg_iter(&typ).zip(args) {
-                        arg.name =
-                            self.stack_var(arg.name.clone(), pattern.location, arg_type.clone());
+                    for (arg_type, arg) in types::arg_iter(&id.typ).zip(args) {
+                        self.new_pattern(arg_type, arg);
                     }
                 }
+                ast::Pattern::Literal(_) => (),
             }
         }
 
         fn stack_var(&mut self, id: Symbol, location: Location, typ: TcType) -> Symbol {
             let old_id = id.clone();
             let name = self.symbols.string(&id).to_owned();
-            let new_id = self.symbols.symbol(format!("{}:{}", name, location));
+            let new_id = self.symbols.symbol(unique_name(&name, location));
             debug!("Rename binding `{}` = `{}` `{}`",
                    self.symbols.string(&old_id),
                    self.symbols.string(&new_id),
@@ -214,7 +267,7 @@ pub fn rename(symbols: &mut SymbolModule,
                             None => {
                                 if let Some(new_id) = try!(self.rename(id, &field.typ)) {
                                     debug!("Rename record field {} = {}", id, new_id);
-                                    *maybe_expr = Some(pos::located(expr.location,
+                                    *maybe_expr = Some(pos::located(expr.span,
                                                                     Expr::Identifier(TcIdent {
                                                                         name: new_id,
                                                                         typ: field.typ.clone(),
@@ -241,6 +294,9 @@ pub fn rename(symbols: &mut SymbolModule,
                         self.env.stack.enter_scope();
                         let typ = expr.env_type_of(&self.env);
                         self.new_pattern(&typ, &mut alt.pattern);
+                        if let Some(ref mut guard) = alt.guard {
+                            self.visit_expr(guard);
+                        }
                         self.visit_expr(&mut alt.expression);
                         self.env.stack.exit_scope();
                         self.env.stack_types.exit_scope();
@@ -263,7 +319,7 @@ pub fn rename(symbols: &mut SymbolModule,
                             for (typ, arg) in types::arg_iter(&bind.env_type_of(&self.env))
                                 .zip(&mut bind.arguments) {
                                 arg.name =
-                                    self.stack_var(arg.name.clone(), expr.location, typ.clone());
+                                    self.stack_var(arg.name.clone(), expr.span.start, typ.clone());
                             }
                             self.visit_expr(&mut bind.expression);
                             self.env.stack.exit_scope();
@@ -276,7 +332,7 @@ pub fn rename(symbols: &mut SymbolModule,
                 Expr::Lambda(ref mut lambda) => {
                     self.env.stack.enter_scope();
                     for (typ, arg) in types::arg_iter(&lambda.id.typ).zip(&mut lambda.arguments) {
-                        arg.name = self.stack_var(arg.name.clone(), expr.location, typ.clone());
+                        arg.name = self.stack_var(arg.name.clone(), expr.span.start, typ.clone());
                     }
                     self.visit_expr(&mut lambda.body);
                     self.env.stack.exit_scope();
@@ -284,7 +340,7 @@ pub fn rename(symbols: &mut SymbolModule,
                 Expr::Type(ref bindings, ref mut body) => {
                     self.env.stack_types.enter_scope();
                     for bind in bindings {
-                        self.stack_type(bind.name.clone(), expr.location, &bind.alias);
+                        self.stack_type(bind.name.clone(), expr.span.start, &bind.alias);
                     }
                     self.visit_expr(body);
                     self.env.stack_types.exit_scope();
@@ -301,7 +357,7 @@ pub fn rename(symbols: &mut SymbolModule,
         fn visit_expr(&mut self, expr: &mut LExpr<Self::T>) {
             if let Err(err) = self.rename_expr(expr) {
                 self.errors.error(Spanned {
-                    span: expr.span(&ast::TcIdentEnvWrapper(&self.symbols)),
+                    span: expr.span,
                     value: err,
                 });
             }
@@ -374,4 +430,20 @@ impl<'a> Unifier<State<'a>, TcType> for Equivalent {
             }
         }
     }
+
+    fn new_var(unifier: &mut UnifierState<State<'a>, Self>) -> TcType {
+        // A fresh variable is only needed when two records have fields which the other record
+        // lacks in which case the records can't be equivalent
+        unifier.unifier.equiv = false;
+        Type::variable(types::TypeVariable::new(0))
+    }
+
+    fn real(_unifier: &UnifierState<State<'a>, Self>, typ: &TcType) -> TcType {
+        typ.clone()
+    }
+
+    fn new_skolem(_unifier: &mut UnifierState<State<'a>, Self>, param: &Symbol) -> Symbol {
+        // Skolems only need to be distinct from each other as they are never displayed
+        Symbol::new(param.as_ref())
+    }
 }
@@ -1,4 +1,4 @@
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::default::Default;
 use std::fmt;
 
@@ -22,6 +22,8 @@ pub struct Substitution<T> {
     /// stored here. As the type stored will never changed we use a `FixedMap` lets `real` return
     /// `&T` from this map safely.
     types: FixedMap<u32, T>,
+    /// The number of skolems which have been created, used to give each skolem a distinct name
+    skolems: Cell<usize>,
 }
 
 impl<T: Substitutable> Default for Substitution<T> {
@@ -175,6 +177,7 @@ impl<T: Substitutable> Substitution<T> {
             union: RefCell::new(QuickFindUf::new(1)),
             variables: variables,
             types: FixedMap::new(),
+            skolems: Cell::new(0),
         }
     }
 
@@ -246,6 +249,22 @@ impl<T: Substitutable> Substitution<T> {
         union.get_mut(other as usize).level = level;
     }
 
+    /// Creates the name of a new skolem for the variable `param` (see `instantiate::skolemize`).
+    /// The name can't be written in source code and is distinct from the names of all other
+    /// skolems created by this substitution
+    pub fn new_skolem(&self, param: &Symbol) -> Symbol {
+        let id = self.skolems.get();
+        self.skolems.set(id + 1);
+        Symbol::new(&format!("{}'{}", param, id))
+    }
+
+    /// Lowers the level of `var` to `level` unless it already has a lower level
+    pub fn restrict_level(&self, var: u32, level: u32) {
+        let level = ::std::cmp::min(self.get_level(var), level);
+        let mut union = self.union.borrow_mut();
+        union.get_mut(var as usize).level = level;
+    }
+
     pub fn get_level(&self, mut var: u32) -> u32 {
         if let Some(v) = self.find_type_for_var(var) {
             var = v.get_var().map_or(var, |v| v.get_id());
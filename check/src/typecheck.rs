@@ -1,19 +1,24 @@
 //! The main typechecking interface which is responsible for typechecking expressions, patterns,
 //! etc. Only checks which need to be aware of expressions are handled here the actual unifying and
 //! checking of types are done in the `unify_type` and `kindcheck` modules.
+use std::collections::HashMap;
 use std::fmt;
 use std::mem;
 
 use base::scoped_map::ScopedMap;
 use base::ast::{self, Typed, DisplayEnv, MutVisitor};
+use base::diagnostic::{Diagnostic, ToDiagnostic};
 use base::error::Errors;
 use base::instantiate::{self, Instantiator};
-use base::pos::{Span, Spanned};
+use base::metadata::{Metadata, MetadataEnv};
+use base::pos::{self, Span, Spanned};
 use base::symbol::{Symbol, SymbolRef, SymbolModule, Symbols};
 use base::types::{self, RcKind, Type, Generic, Kind};
 use base::types::{KindEnv, TypeEnv, PrimitiveEnv, TcIdent, Alias, AliasData, TcType, TypeVariable};
+use exhaustive;
 use kindcheck::{self, KindCheck};
-use substitution::Substitution;
+use lint::{Warning, WarningCode};
+use substitution::{Substitution, Substitutable};
 use unify::Error as UnifyError;
 use unify;
 use unify_type;
@@ -36,8 +41,9 @@ pub enum TypeError<I> {
     UndefinedField(ast::AstType<I>, I),
     /// Constructor type was found in a pattern but did not have the expected number of arguments
     PatternError(ast::AstType<I>, usize),
-    /// Errors found when trying to unify two types
-    Unification(ast::AstType<I>, ast::AstType<I>, Vec<unify_type::Error<I>>),
+    /// Errors found when trying to unify two types. Contains the location of the code which
+    /// required the expected type if it is known
+    Unification(ast::AstType<I>, ast::AstType<I>, Vec<unify_type::Error<I>>, Option<Span>),
     /// Error were found when trying to unify the kinds of two types
     KindError(kindcheck::Error<I>),
     /// Errors found during renaming (overload resolution)
@@ -52,6 +58,19 @@ pub enum TypeError<I> {
     },
     /// Found a case expression without any alternatives
     EmptyCase,
+    /// No implicit instance in scope has the type of an implicit argument
+    MissingImplicit(ast::AstType<I>),
+    /// More than one implicit instance in scope has the type of an implicit argument
+    AmbiguousImplicit(ast::AstType<I>, Vec<I>),
+    /// The type of the expression contains type variables of a mutable value which can't be
+    /// generalized
+    UngeneralizableType(ast::AstType<I>),
+    /// A variable bound by a `forall` was unified with a type variable from outside the
+    /// expression which had to be polymorphic
+    EscapingTypeVariable(I),
+    /// The pattern of a `let` binding does not match every value of its type. Contains a pattern
+    /// which is not matched
+    RefutablePattern(I),
 }
 
 impl<I> From<kindcheck::Error<I>> for TypeError<I>
@@ -81,7 +100,7 @@ impl<I: fmt::Display + AsRef<str>> fmt::Display for TypeError<I> {
             UndefinedField(ref typ, ref field) => {
                 write!(f, "Type `{}` does not have the field `{}`", typ, field)
             }
-            Unification(ref expected, ref actual, ref errors) => {
+            Unification(ref expected, ref actual, ref errors, _) => {
                 try!(writeln!(f,
                               "Expected the following types to be equal\nExpected: {}\nFound: \
                                {}\n{} errors were found during unification:",
@@ -120,10 +139,136 @@ impl<I: fmt::Display + AsRef<str>> fmt::Display for TypeError<I> {
                 Ok(())
             }
             EmptyCase => write!(f, "`case` expression with no alternatives"),
+            MissingImplicit(ref typ) => {
+                write!(f,
+                       "No implicit instance of type `{}` could be found in scope",
+                       typ)
+            }
+            AmbiguousImplicit(ref typ, ref candidates) => {
+                try!(write!(f,
+                            "Multiple implicit instances of type `{}` are in scope: ",
+                            typ));
+                try!(write!(f, "`{}`", candidates[0]));
+                for candidate in &candidates[1..] {
+                    try!(write!(f, ", `{}`", candidate));
+                }
+                Ok(())
+            }
+            UngeneralizableType(ref typ) => {
+                write!(f,
+                       "The type `{}` contains type variables of a mutable value which can't be \
+                        generalized. Add a type annotation to give the variables a concrete type",
+                       typ)
+            }
+            EscapingTypeVariable(ref id) => {
+                write!(f,
+                       "The type variable `{}` would escape its scope. The expression must work \
+                        for any type `{}` but it depends on a type from outside the expression",
+                       id,
+                       id)
+            }
+            RefutablePattern(ref pattern) => {
+                write!(f,
+                       "The pattern of a `let` binding must match every value but `{}` is not \
+                        matched. Use a `match` expression instead",
+                       pattern)
+            }
         }
     }
 }
 
+impl<I> TypeError<I> {
+    /// Returns the stable code which identifies the kind of the error
+    pub fn code(&self) -> &'static str {
+        match *self {
+            UndefinedVariable(_) => "undefined_variable",
+            NotAFunction(_) => "not_a_function",
+            UndefinedType(_) => "undefined_type",
+            UndefinedField(..) => "undefined_field",
+            PatternError(..) => "pattern_arguments",
+            Unification(..) => "type_mismatch",
+            KindError(UnifyError::TypeMismatch(..)) => "kind_mismatch",
+            KindError(UnifyError::Occurs(..)) => "kind_occurs",
+            KindError(UnifyError::Other(_)) => "undefined_type",
+            Rename(_) => "no_matching_overload",
+            DuplicateTypeDefinition(_) => "duplicate_type",
+            InvalidFieldAccess(_) => "invalid_field_access",
+            UndefinedRecord { .. } => "undefined_record",
+            EmptyCase => "empty_match",
+            MissingImplicit(_) => "missing_implicit",
+            AmbiguousImplicit(..) => "ambiguous_implicit",
+            UngeneralizableType(_) => "ungeneralizable_type",
+            EscapingTypeVariable(_) => "escaping_type_variable",
+            RefutablePattern(_) => "refutable_pattern",
+        }
+    }
+}
+
+impl<I: fmt::Display + AsRef<str>> ToDiagnostic for TypeError<I> {
+    fn to_diagnostic(&self) -> Diagnostic {
+        match *self {
+            Unification(ref expected, ref actual, ref errors, _) => {
+                let message = format!("Expected `{}` but found `{}`", expected, actual);
+                errors.iter().fold(Diagnostic::error(self.code(), message),
+                                   |diagnostic, error| diagnostic.with_note(error.to_string()))
+            }
+            Rename(ref err) => err.to_diagnostic(),
+            _ => Diagnostic::error(self.code(), self.to_string()),
+        }
+    }
+
+    fn to_diagnostic_at(&self, span: Span) -> Diagnostic {
+        let diagnostic = self.to_diagnostic().with_span(span);
+        match *self {
+            Unification(ref expected, ref actual, _, origin) => {
+                let diagnostic = diagnostic.with_label(span, format!("Found `{}`", actual));
+                match origin {
+                    Some(origin) => {
+                        diagnostic.with_label(origin,
+                                              format!("Expected `{}` because of this", expected))
+                    }
+                    None => diagnostic,
+                }
+            }
+            _ => diagnostic,
+        }
+    }
+}
+
+/// A typed hole (`_` or `_name`) which was found while typechecking
+#[derive(Clone, Debug, PartialEq)]
+pub struct Hole<I> {
+    /// The name of the hole
+    pub name: I,
+    /// The type that is expected in place of the hole
+    pub typ: ast::AstType<I>,
+    /// The bindings in scope at the hole whose types unify with `typ`
+    pub candidates: Vec<(I, ast::AstType<I>)>,
+}
+
+impl<I: fmt::Display + AsRef<str>> ToDiagnostic for Hole<I> {
+    fn to_diagnostic(&self) -> Diagnostic {
+        let message = format!("Found hole `{}` of type `{}`", self.name, self.typ);
+        self.candidates.iter().fold(Diagnostic::error("typed_hole", message),
+                                    |diagnostic, &(ref name, ref typ)| {
+            diagnostic.with_note(format!("`{} : {}` could fill the hole", name, typ))
+        })
+    }
+}
+
+impl<I: fmt::Display + AsRef<str>> fmt::Display for Hole<I> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        try!(write!(f, "Found hole `{}` of type `{}`", self.name, self.typ));
+        if !self.candidates.is_empty() {
+            try!(write!(f, "\nBindings in scope which could fill the hole:"));
+            for &(ref name, ref typ) in &self.candidates {
+                try!(write!(f, "\n    {} : {}", name, typ));
+            }
+        }
+        Ok(())
+    }
+}
+
 type TcResult<T> = Result<T, TypeError<Symbol>>;
 
 struct Environment<'a> {
@@ -184,10 +329,20 @@ impl<'a> TypeEnv for Environment<'a> {
     }
 }
 
+impl<'a> MetadataEnv for Environment<'a> {
+    fn get_metadata(&self, id: &Symbol) -> Option<&Metadata> {
+        self.environment.get_metadata(id)
+    }
+}
+
 impl<'a> PrimitiveEnv for Environment<'a> {
     fn get_bool(&self) -> &TcType {
         self.environment.get_bool()
     }
+
+    fn is_mutable_type(&self, id: &SymbolRef) -> bool {
+        self.environment.is_mutable_type(id)
+    }
 }
 
 /// Type returned from the main typecheck function to make sure that nested `type` and `let`
@@ -199,6 +354,86 @@ enum TailCall {
     TailCall,
 }
 
+/// An implicit argument which has been inserted into the expression but which has not yet been
+/// resolved to an implicit instance
+struct ImplicitConstraint {
+    span: Span,
+    /// The name of the placeholder identifier which is replaced once the argument is resolved
+    placeholder: Symbol,
+    typ: TcType,
+    /// The implicit instances which were in scope where the argument was inserted
+    candidates: Vec<ImplicitInstance>,
+}
+
+/// An implicit instance which is in scope, either as a variable or as a field of a module which
+/// has been imported
+#[derive(Clone)]
+struct ImplicitInstance {
+    /// The variable which the instance is reached through, followed by the fields which are
+    /// accessed to reach the instance. The type of the instance is the last type in the path
+    path: Vec<(Symbol, TcType)>,
+    /// The global name of instances which were declared in another module. The same global
+    /// instance may be in scope through several paths
+    global: Option<String>,
+}
+
+impl ImplicitInstance {
+    fn local(name: Symbol, typ: TcType) -> ImplicitInstance {
+        ImplicitInstance {
+            path: vec![(name, typ)],
+            global: None,
+        }
+    }
+
+    fn typ(&self) -> &TcType {
+        &self.path.last().expect("ICE: Empty implicit instance path").1
+    }
+
+    fn name(&self) -> String {
+        let names = self.path.iter().map(|&(ref name, _)| name.to_string()).collect::<Vec<_>>();
+        names.join(".")
+    }
+
+    /// Creates the expression which accesses the instance, giving the instance itself the type
+    /// `typ`
+    fn to_expr(&self, span: Span, typ: TcType) -> ast::LExpr<TcIdent> {
+        let last = self.path.len() - 1;
+        let ident = |i: usize| {
+            let (ref name, ref path_type) = self.path[i];
+            TcIdent {
+                name: name.clone(),
+                typ: if i == last { typ.clone() } else { path_type.clone() },
+            }
+        };
+        let mut expr = pos::located(span, ast::Expr::Identifier(ident(0)));
+        for i in 1..self.path.len() {
+            expr = pos::located(span, ast::Expr::FieldAccess(Box::new(expr), ident(i)));
+        }
+        expr
+    }
+}
+
+/// The result of checking if an implicit instance can be used as an implicit argument
+#[derive(Copy, Clone, PartialEq)]
+enum InstanceMatch {
+    Exact,
+    /// The instance matches if some of the type variables in the argument are unified
+    Possible,
+    Mismatch,
+}
+
+impl InstanceMatch {
+    fn and(self, other: InstanceMatch) -> InstanceMatch {
+        match (self, other) {
+            (InstanceMatch::Mismatch, _) |
+            (_, InstanceMatch::Mismatch) => InstanceMatch::Mismatch,
+            (InstanceMatch::Possible, _) |
+            (_, InstanceMatch::Possible) => InstanceMatch::Possible,
+            _ => InstanceMatch::Exact,
+        }
+    }
+}
+
 /// Struct which provides methods to typecheck expressions.
 pub struct Typecheck<'a> {
     environment: Environment<'a>,
@@ -211,6 +446,22 @@ pub struct Typecheck<'a> {
     errors: Errors<Spanned<TypeError<Symbol>>>,
     /// Type variables `let test: a -> b` (`a` and `b`)
     type_variables: ScopedMap<Symbol, TcType>,
+    /// Bindings declared with `let implicit`, implicit lambda arguments and the implicit instances
+    /// of imported modules which are in scope, keyed by the variable they are reached through
+    implicit_instances: ScopedMap<Symbol, Vec<ImplicitInstance>>,
+    /// Implicit arguments which are waiting to be resolved
+    implicit_constraints: Vec<ImplicitConstraint>,
+    /// Number of placeholders which have been created, used to give each placeholder an unique
+    /// name
+    implicit_placeholders: usize,
+    /// The holes found in the last typechecked expression
+    holes: Vec<Spanned<Hole<Symbol>>>,
+    /// The non-exhaustive matches and unreachable alternatives found in the last typechecked
+    /// expression
+    warnings: Vec<Spanned<Warning<Symbol>>>,
+    /// The types returned by the calls in the bindings which are currently being typechecked,
+    /// excluding calls inside syntactic values (see `restrict_mutable_types`)
+    call_types: Vec<TcType>,
 }
 
 /// Error returned when unsuccessfully typechecking an expression
@@ -235,15 +486,25 @@ impl<'a> Typecheck<'a> {
             inst: Instantiator::new(),
             errors: Errors::new(),
             type_variables: ScopedMap::new(),
+            implicit_instances: ScopedMap::new(),
+            implicit_constraints: Vec::new(),
+            implicit_placeholders: 0,
+            holes: Vec::new(),
+            warnings: Vec::new(),
+            call_types: Vec::new(),
         }
     }
 
+    /// Records `error` and returns the error type which should be used as the type of the
+    /// expression that failed to typecheck
     fn error(&mut self, span: Span, error: TypeError<Symbol>) -> TcType {
-        self.errors.error(Spanned {
-            span: span,
-            value: error,
-        });
-        self.subs.new_var()
+        if !is_caused_by_error_type(&error) {
+            self.errors.error(Spanned {
+                span: span,
+                value: error,
+            });
+        }
+        Type::error()
     }
 
     fn bool(&self) -> TcType {
@@ -267,6 +528,7 @@ impl<'a> Typecheck<'a> {
             .map(|typ| {
                 let typ = subs.set_type(typ.clone());
                 let typ = inst.instantiate(&typ, |_| subs.new_var());
+                let typ = instantiate_forall(subs, typ);
                 debug!("Find {} : {}",
                        symbols.string(id),
                        types::display_type(symbols, &typ));
@@ -317,12 +579,14 @@ impl<'a> Typecheck<'a> {
         self.environment.stack.enter_scope();
         self.environment.stack_types.enter_scope();
         self.original_symbols.enter_scope();
+        self.implicit_instances.enter_scope();
     }
 
     fn exit_scope(&mut self) {
         self.environment.stack.exit_scope();
         self.environment.stack_types.exit_scope();
         self.original_symbols.exit_scope();
+        self.implicit_instances.exit_scope();
     }
 
     /// Generalizing updates all variables which are above `level` into "generic variables". A
@@ -382,18 +646,37 @@ impl<'a> Typecheck<'a> {
         }
         self.subs.clear();
         self.environment.stack.clear();
+        self.implicit_instances.clear();
+        self.implicit_constraints.clear();
+        self.holes.clear();
+        self.warnings.clear();
+        self.call_types.clear();
 
         let mut typ = self.typecheck(expr);
         if let Some(expected) = expected_type {
-            let span = expr.span(&ast::TcIdentEnvWrapper(&self.symbols));
+            let span = expr.span;
             let expected = self.instantiate(expected);
             typ = self.unify_span(span, &expected, typ)
         }
+        // Resolve the implicit arguments which were not inside any `let` binding
+        self.resolve_implicits(0, Some(&mut *expr));
+        self.restrict_mutable_types(0, 0);
         typ = self.finish_type(0, &typ).unwrap_or(typ);
         typ = types::walk_move_type(typ, &mut unroll_app);
+        // Any variables which are left could only be instantiated once so they can't be part of
+        // the type of the whole expression
+        let mut ungeneralizable = false;
+        types::walk_type(&typ, |typ: &TcType| if let Type::Variable(_) = **typ {
+            ungeneralizable = true;
+        });
+        if ungeneralizable {
+            let span = expr.span;
+            self.error(span, UngeneralizableType(typ.clone()));
+        }
         // Only the 'tail' expression need to be generalized at this point as all bindings
         // will have already been generalized
         self.generalize_variables(0, tail_expr(expr));
+        self.find_holes(expr);
         if self.errors.has_errors() {
             Err(mem::replace(&mut self.errors, Errors::new()))
         } else {
@@ -415,6 +698,63 @@ impl<'a> Typecheck<'a> {
         }
     }
 
+    /// Returns the holes (`_` or `_name`) found in the last typechecked expression. Holes are not
+    /// errors but an expression containing holes can't be compiled
+    pub fn holes(&self) -> &[Spanned<Hole<Symbol>>] {
+        &self.holes
+    }
+
+    /// Returns the warnings about the `match` expressions in the last typechecked expression.
+    /// Unlike the warnings in the `lint` module these can only be found while typechecking
+    pub fn warnings(&self) -> &[Spanned<Warning<Symbol>>] {
+        &self.warnings
+    }
+
+    /// Records the type of each hole in `expr` together with the bindings in scope of the hole
+    /// which could be used in its place
+    fn find_holes(&mut self, expr: &mut ast::LExpr<TcIdent>) {
+        struct FindHoles {
+            holes: Vec<ast::LExpr<TcIdent>>,
+        }
+        impl MutVisitor for FindHoles {
+            type T = TcIdent;
+
+            fn visit_expr(&mut self, expr: &mut ast::LExpr<TcIdent>) {
+                match expr.value {
+                    ast::Expr::Hole(_) => self.holes.push(expr.clone()),
+                    _ => ast::walk_mut_expr(self, expr),
+                }
+            }
+        }
+        let mut visitor = FindHoles { holes: Vec::new() };
+        visitor.visit_expr(expr);
+        for hole in visitor.holes {
+            let id = match hole.value {
+                ast::Expr::Hole(ref id) => id,
+                _ => unreachable!(),
+            };
+            let typ = self.subs.set_type(id.typ.clone());
+            let mut candidates: Vec<_> =
+                ::completion::suggest(&self.environment, expr, hole.span.start)
+                    .into_iter()
+                    .filter(|candidate| {
+                        self.match_instance(&mut HashMap::new(), &candidate.typ, &typ) !=
+                        InstanceMatch::Mismatch
+                    })
+                    .map(|candidate| (candidate.name, self.subs.set_type(candidate.typ)))
+                    .collect();
+            candidates.sort_by(|l, r| l.0.declared_name().cmp(r.0.declared_name()));
+            self.holes.push(Spanned {
+                span: hole.span,
+                value: Hole {
+                    name: id.name.clone(),
+                    typ: typ,
+                    candidates: candidates,
+                },
+            });
+        }
+    }
+
     /// Main typechecking function. Returns the type of the expression if typechecking was
     /// successful
     fn typecheck(&mut self, mut expr: &mut ast::LExpr<TcIdent>) -> TcType {
@@ -425,7 +765,11 @@ impl<'a> Typecheck<'a> {
         let mut scope_count = 0;
         let returned_type;
         loop {
-            match self.typecheck_(expr) {
+            let result = match expr.value {
+                ast::Expr::Do(_) => Ok(TailCall::Type(self.typecheck_do(expr))),
+                _ => self.typecheck_(expr),
+            };
+            match result {
                 Ok(tailcall) => {
                     match tailcall {
                         TailCall::TailCall => {
@@ -438,17 +782,14 @@ impl<'a> Typecheck<'a> {
                             scope_count += 1;
                         }
                         TailCall::Type(typ) => {
-                            returned_type = typ;
+                            returned_type = self.insert_implicit_arguments(expr, typ);
                             break;
                         }
                     }
                 }
                 Err(err) => {
-                    returned_type = self.subs.new_var();
-                    self.errors.error(Spanned {
-                        span: expr.span(&ast::TcIdentEnvWrapper(&self.symbols)),
-                        value: err,
-                    });
+                    let span = expr.span;
+                    returned_type = self.error(span, err);
                     break;
                 }
             }
@@ -462,6 +803,9 @@ impl<'a> Typecheck<'a> {
     fn typecheck_(&mut self,
                   expr: &mut ast::LExpr<TcIdent>)
                   -> Result<TailCall, TypeError<Symbol>> {
+        if let Some(id) = self.identifier_hole(expr) {
+            expr.value = ast::Expr::Hole(id);
+        }
         match expr.value {
             ast::Expr::Identifier(ref mut id) => {
                 if let Some(new) = self.original_symbols.get(&id.name) {
@@ -481,24 +825,49 @@ impl<'a> Typecheck<'a> {
             }
             ast::Expr::Call(ref mut func, ref mut args) => {
                 let mut func_type = self.typecheck(&mut **func);
-                for arg in args.iter_mut() {
+                let func_span = func.span;
+                let mut args = args.iter_mut();
+                while let Some(arg) = args.next() {
                     let f = Type::function(vec![self.subs.new_var()], self.subs.new_var());
-                    func_type = try!(self.unify(&f, func_type));
+                    func_type = match self.unify(&f, func_type) {
+                        Ok(func_type) => func_type,
+                        Err(err) => {
+                            let span = expr.span;
+                            self.error(span, err);
+                            // Still typecheck the remaining arguments so that any errors in them
+                            // are reported as well
+                            self.typecheck(arg);
+                            for arg in args.by_ref() {
+                                self.typecheck(arg);
+                            }
+                            // The error type unifies with anything so no further errors are
+                            // reported for the result of this call
+                            return Ok(TailCall::Type(Type::error()));
+                        }
+                    };
                     func_type = match func_type.as_function() {
                         Some((arg_ty, ret_ty)) => {
+                            // An argument of a higher-rank type such as `forall a . a -> a` must
+                            // itself be polymorphic
+                            let level = self.subs.var_id();
+                            let mut skolems = Vec::new();
+                            let arg_ty = skolemize(&self.subs, arg_ty, &mut skolems)
+                                .unwrap_or_else(|| arg_ty.clone());
                             let actual = self.typecheck(arg);
-                            let span = arg.span(&ast::TcIdentEnvWrapper(&self.symbols));
-                            self.unify_span(span, arg_ty, actual);
+                            let span = arg.span;
+                            self.unify_span_origin(span, func_span, &arg_ty, actual);
+                            self.check_escaping_variables(span, level, &skolems);
                             ret_ty.clone()
                         }
                         None => return Err(NotAFunction(func_type.clone())),
                     };
                 }
+                self.call_types.push(func_type.clone());
                 Ok(TailCall::Type(func_type))
             }
             ast::Expr::IfElse(ref mut pred, ref mut if_true, ref mut if_false) => {
                 let pred_type = self.typecheck(&mut **pred);
-                let span = pred.span(&ast::TcIdentEnvWrapper(&self.symbols));
+                let span = pred.span;
                 let bool_type = self.bool();
                 self.unify_span(span, &bool_type, pred_type);
                 let true_type = self.typecheck(&mut **if_true);
@@ -535,14 +904,21 @@ impl<'a> Typecheck<'a> {
                         }
                         _ => {
                             op.typ = try!(self.find(op.id()));
+                            // Any implicit arguments of the operator are inserted by
+                            // `insert_implicit_arguments`
+                            let mut op_type = op.typ.clone();
+                            while let Some(ret) = op_type.as_implicit_function()
+                                .map(|(_, ret)| ret.clone()) {
+                                op_type = ret;
+                            }
                             let func_type = Type::function(vec![lhs_type, rhs_type],
                                                            self.subs.new_var());
-                            let ret = try!(self.unify(&op.typ, func_type))
+                            let ret = try!(self.unify(&op_type, func_type))
                                 .as_function()
                                 .and_then(|(_, ret)| ret.as_function())
                                 .map(|(_, ret)| ret.clone())
                                 .expect("ICE: unify binop");
-
+                            self.call_types.push(ret.clone());
                             Ok(ret)
                         }
                     }
@@ -550,74 +926,85 @@ impl<'a> Typecheck<'a> {
                 result.map(TailCall::Type)
             }
             ast::Expr::Tuple(ref mut exprs) => {
-                assert!(exprs.len() == 0);
-                Ok(TailCall::Type(Type::unit()))
+                let elem_types = exprs.iter_mut().map(|expr| self.typecheck(expr)).collect();
+                Ok(TailCall::Type(self.tuple_type(elem_types)))
             }
-            ast::Expr::Match(ref mut expr, ref mut alts) => {
-                let typ = self.typecheck(&mut **expr);
+            ast::Expr::Match(ref mut scrutinee, ref mut alts) => {
+                let typ = self.typecheck(&mut **scrutinee);
                 let mut expected_alt_type = None;
+                let error_count = self.errors.errors.len();
 
                 for alt in alts.iter_mut() {
                     self.enter_scope();
                     self.typecheck_pattern(&mut alt.pattern, typ.clone());
-                    let mut alt_type = self.typecheck(&mut alt.expression);
+                    if let Some(ref mut guard) = alt.guard {
+                        let guard_type = self.typecheck(guard);
+                        let span = guard.span;
+                        let bool_type = self.bool();
+                        self.unify_span(span, &bool_type, guard_type);
+                    }
+                    let alt_type = self.typecheck(&mut alt.expression);
                     self.exit_scope();
-                    // All alternatives must unify to the same type
-                    if let Some(ref expected) = expected_alt_type {
-                        alt_type = try!(self.unify(expected, alt_type));
+                    // All alternatives must unify to the same type. If an alternative fails to
+                    // unify the type of the earlier alternatives is kept so that every
+                    // mismatched alternative gets reported
+                    let span = alt.expression.span;
+                    match expected_alt_type {
+                        Some((ref expected, origin)) => {
+                            self.unify_span_origin(span, origin, expected, alt_type);
+                        }
+                        None => expected_alt_type = Some((alt_type, span)),
+                    }
+                }
+                // The types of the patterns may not be correct if any errors were found so avoid
+                // reporting unhelpful errors in that case
+                if self.errors.errors.len() == error_count {
+                    if let Some(alt) = alts.last() {
+                        let span = Span {
+                            start: expr.span.start,
+                            end: alt.expression.span.end,
+                        };
+                        self.check_match_patterns(span, alts);
                     }
-                    expected_alt_type = Some(alt_type);
                 }
-                expected_alt_type.ok_or(EmptyCase)
+                expected_alt_type.map(|(typ, _)| typ)
+                    .ok_or(EmptyCase)
                     .map(TailCall::Type)
             }
             ast::Expr::Let(ref mut bindings, _) => {
-                try!(self.typecheck_bindings(bindings));
+                self.typecheck_bindings(bindings);
                 Ok(TailCall::TailCall)
             }
             ast::Expr::FieldAccess(ref mut expr, ref mut field_access) => {
-                let mut typ = self.typecheck(&mut **expr);
+                let typ = self.typecheck(&mut **expr);
                 debug!("FieldAccess {} . {:?}",
                        types::display_type(&self.symbols, &typ),
                        self.symbols.string(&field_access.name));
-                self.subs.make_real(&mut typ);
-                if let Type::Variable(_) = *typ {
-                    // Attempt to find a record with `field_access` since inferring to a record
-                    // with only `field_access` as the field is probably useless
-                    let (record_type, _) = try!(self.find_record(&[field_access.name.clone()])
-                        .map(|t| (t.0.clone(), t.1.clone())));
-                    let record_type = self.instantiate(&record_type);
-                    typ = try!(self.unify(&record_type, typ));
-                }
-                let record = self.remove_aliases(typ.clone());
-                match *record {
-                    Type::Record { ref fields, .. } => {
-                        let field_type = fields.iter()
-                            .find(|field| field.name.name_eq(field_access.id()))
-                            .map(|field| field.typ.clone());
-                        field_access.typ = match field_type {
-                            Some(typ) => self.instantiate(&typ),
-                            None => {
-                                return Err(UndefinedField(typ.clone(), field_access.name.clone()))
-                            }
-                        };
-                        Ok(TailCall::Type(field_access.typ.clone()))
-                    }
-                    _ => Err(InvalidFieldAccess(record.clone())),
-                }
+                let field_type = try!(self.find_field_type(&typ, &field_access.name));
+                let field_type = self.instantiate(&field_type);
+                field_access.typ = instantiate_forall(&self.subs, field_type);
+                Ok(TailCall::Type(field_access.typ.clone()))
             }
             ast::Expr::Array(ref mut a) => {
                 let mut expected_type = self.subs.new_var();
+                let mut origin = None;
                 for expr in &mut a.expressions {
                     let typ = self.typecheck(expr);
-                    let span = expr.span(&ast::TcIdentEnvWrapper(&self.symbols));
-                    expected_type = self.unify_span(span, &expected_type, typ);
+                    let span = expr.span;
+                    // The type of the first element is the type expected of the rest
+                    expected_type = match origin {
+                        Some(origin) => self.unify_span_origin(span, origin, &expected_type, typ),
+                        None => {
+                            origin = Some(span);
+                            self.unify_span(span, &expected_type, typ)
+                        }
+                    };
                 }
                 a.id.typ = Type::array(expected_type);
                 Ok(TailCall::Type(a.id.typ.clone()))
             }
             ast::Expr::Lambda(ref mut lambda) => {
-                let loc = format!("lambda:{}", expr.location);
+                let loc = format!("lambda:{}", expr.span.start);
                 lambda.id.name = self.symbols.symbol(loc);
                 let function_type = self.subs.new_var();
                 let typ =
@@ -625,10 +1012,23 @@ impl<'a> Typecheck<'a> {
                 lambda.id.typ = typ.clone();
                 Ok(TailCall::Type(typ))
             }
+            ast::Expr::Do(_) => panic!("ICE: `do` expressions are handled by `typecheck_do`"),
             ast::Expr::Type(ref mut bindings, ref expr) => {
-                try!(self.typecheck_type_bindings(bindings, expr));
+                self.typecheck_type_bindings(bindings, expr);
                 Ok(TailCall::TailCall)
             }
+            ast::Expr::Annotated(ref mut expr, ref mut typ) => {
+                let span = expr.span;
+                typ.value = self.refresh_symbols_in_type(typ.value.clone());
+                self.kindcheck_signature(span, typ);
+                self.type_variables.enter_scope();
+                self.instantiate_signature(typ);
+                let level = self.subs.var_id();
+                let actual = self.typecheck(expr);
+                typ.value = self.seal_signature(span, level, typ, actual);
+                self.type_variables.exit_scope();
+                Ok(TailCall::Type(typ.value.clone()))
+            }
             ast::Expr::Record { typ: ref mut id, ref mut types, exprs: ref mut fields } => {
                 let types = try!(types.iter_mut()
                     .map(|&mut (ref mut symbol, ref mut typ)| {
@@ -674,6 +1074,55 @@ impl<'a> Typecheck<'a> {
                 id.typ = id_type.clone();
                 Ok(TailCall::Type(id_type.clone()))
             }
+            ast::Expr::RecordUpdate { ref mut typ, ref mut base, ref mut fields } => {
+                let base_type = self.typecheck(base);
+                let record = self.remove_aliases(self.subs.set_type(base_type.clone()));
+                // Only closed records can be extended with new fields. An open record may already
+                // contain the field and the layout of its values is not known
+                let (types, mut record_fields, closed) = match *record {
+                    Type::Record { ref types, ref fields, ref rest } => {
+                        (types.clone(), fields.clone(), rest.is_none())
+                    }
+                    Type::Variable(_) => (Vec::new(), Vec::new(), false),
+                    _ => return Err(InvalidFieldAccess(record.clone())),
+                };
+                let known_fields = record_fields.len();
+                for field in fields.iter_mut() {
+                    let span = field.1.span;
+                    let field_type = self.typecheck(&mut field.1);
+                    let expected = record_fields.iter()
+                        .find(|record_field| record_field.name.name_eq(&field.0))
+                        .map(|record_field| record_field.typ.clone());
+                    match expected {
+                        Some(expected) => {
+                            self.unify_span(span, &expected, field_type);
+                        }
+                        None if closed => {
+                            record_fields.push(types::Field {
+                                name: field.0.clone(),
+                                typ: field_type,
+                            })
+                        }
+                        None => {
+                            // The field must already exist in the rest of the open record
+                            match self.find_field_type(&base_type, &field.0) {
+                                Ok(expected) => {
+                                    self.unify_span(span, &expected, field_type);
+                                }
+                                Err(err) => {
+                                    self.error(span, err);
+                                }
+                            }
+                        }
+                    }
+                }
+                typ.typ = if record_fields.len() == known_fields {
+                    base_type
+                } else {
+                    Type::record(types, record_fields)
+                };
+                Ok(TailCall::Type(typ.typ.clone()))
+            }
             ast::Expr::Block(ref mut exprs) => {
                 let (last, exprs) = exprs.split_last_mut().expect("Expr in block");
                 for expr in exprs {
@@ -681,12 +1130,76 @@ impl<'a> Typecheck<'a> {
                 }
                 Ok(TailCall::Type(self.typecheck(last)))
             }
+            ast::Expr::Error(ref mut id) => {
+                // The parser has already reported the error so just give the expression a type
+                // which unifies with anything
+                id.typ = self.subs.new_var();
+                Ok(TailCall::Type(id.typ.clone()))
+            }
+            ast::Expr::Hole(ref mut id) => {
+                // Holes are reported in `find_holes` once the types of all bindings are known
+                id.typ = self.subs.new_var();
+                Ok(TailCall::Type(id.typ.clone()))
+            }
         }
     }
 
+    /// Returns the identifier of `expr` if it is a named hole. An identifier starting with `_`,
+    /// such as `_x`, is a hole unless it refers to a binding which is in scope.
+    fn identifier_hole(&self, expr: &ast::LExpr<TcIdent>) -> Option<TcIdent> {
+        match expr.value {
+            ast::Expr::Identifier(ref id) if id.name.declared_name().starts_with('_') => {
+                let name = self.original_symbols.get(&id.name).unwrap_or(&id.name);
+                if self.environment.find_type(name).is_none() {
+                    Some(id.clone())
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the type of the field `field` in the record type `record`.
+    ///
+    /// If the type of `record` is not known yet it is inferred to be an open record containing
+    /// `field`. Likewise, an open record which does not contain `field` is extended with it.
+    fn find_field_type(&mut self, record: &TcType, field: &Symbol) -> TcResult<TcType> {
+        let record = self.remove_aliases(self.subs.set_type(record.clone()));
+        match *record {
+            Type::Variable(_) => self.extend_record(&record, field),
+            Type::Record { ref fields, ref rest, .. } => {
+                let found = fields.iter()
+                    .find(|record_field| record_field.name.name_eq(field))
+                    .map(|record_field| record_field.typ.clone());
+                match (found, rest.as_ref()) {
+                    (Some(typ), _) => Ok(typ),
+                    (None, Some(rest)) if rest.get_var().is_some() => {
+                        self.extend_record(rest, field)
+                    }
+                    (None, _) => Err(UndefinedField(record.clone(), field.clone())),
+                }
+            }
+            _ => Err(InvalidFieldAccess(record.clone())),
+        }
+    }
+
+    /// Unifies the unknown row `rest` with a row containing `field` and returns the type of the
+    /// field
+    fn extend_record(&mut self, rest: &TcType, field: &Symbol) -> TcResult<TcType> {
+        let field_type = self.subs.new_var();
+        let fields = vec![types::Field {
+                              name: field.clone(),
+                              typ: field_type.clone(),
+                          }];
+        let row = Type::poly_record(vec![], fields, self.subs.new_var());
+        try!(self.unify(rest, row));
+        Ok(field_type)
+    }
+
     fn typecheck_lambda(&mut self,
                         function_type: TcType,
-                        arguments: &mut [TcIdent],
+                        arguments: &mut [pos::Located<TcIdent>],
                         body: &mut ast::LExpr<TcIdent>)
                         -> TcType {
         self.enter_scope();
@@ -694,22 +1207,461 @@ impl<'a> Typecheck<'a> {
         {
             let mut iter1 = function_arg_iter(self, function_type);
             let mut iter2 = arguments.iter_mut();
-            while let (Some(arg_type), Some(arg)) = (iter1.next(), iter2.next()) {
+            while let (Some((arg_type, implicit)), Some(arg)) = (iter1.next(), iter2.next()) {
                 arg.typ = arg_type;
-                arg_types.push(arg.typ.clone());
+                arg_types.push((arg.typ.clone(), implicit));
                 iter1.tc.stack_var(arg.name.clone(), arg.typ.clone());
+                // Implicit arguments can be used to resolve implicit arguments in the body
+                if implicit {
+                    let instance = ImplicitInstance::local(arg.name.clone(), arg.typ.clone());
+                    iter1.tc.implicit_instances.insert(arg.name.clone(), vec![instance]);
+                }
             }
         }
         let body_type = self.typecheck(body);
         self.exit_scope();
-        Type::function(arg_types, body_type)
+        arg_types.into_iter()
+            .rev()
+            .fold(body_type, |ret, (arg, implicit)| {
+                if implicit {
+                    Type::implicit_function(vec![arg], ret)
+                } else {
+                    Type::function(vec![arg], ret)
+                }
+            })
+    }
+
+    /// Applies placeholder arguments to an identifier, field access or operator whose type takes
+    /// implicit arguments. The placeholders are replaced by implicit instances in
+    /// `resolve_implicits` once the types of the arguments are known
+    fn insert_implicit_arguments(&mut self,
+                                 expr: &mut ast::LExpr<TcIdent>,
+                                 typ: TcType)
+                                 -> TcType {
+        let span = expr.span;
+        let is_operator = match expr.value {
+            ast::Expr::Identifier(_) |
+            ast::Expr::FieldAccess(..) if typ.as_implicit_function().is_some() => false,
+            ast::Expr::BinOp(_, ref op, _) if op.typ.as_implicit_function().is_some() => true,
+            _ => return typ,
+        };
+        let func = mem::replace(&mut expr.value, ast::Expr::Tuple(Vec::new()));
+        if !is_operator {
+            let (args, typ) = self.placeholder_arguments(span, typ);
+            expr.value = ast::Expr::Call(Box::new(pos::located(span, func)), args);
+            return typ;
+        }
+        // `l op r` is rewritten to `op implicit_args.. l r` as the operator takes implicit
+        // arguments
+        let (lhs, op, rhs) = match func {
+            ast::Expr::BinOp(lhs, op, rhs) => (lhs, op, rhs),
+            _ => panic!("ICE: Expected a binary operator"),
+        };
+        let (args, _) = self.placeholder_arguments(span, op.typ.clone());
+        let op = pos::located(span, ast::Expr::Identifier(op));
+        let op = pos::located(span, ast::Expr::Call(Box::new(op), args));
+        expr.value = ast::Expr::Call(Box::new(op), vec![*lhs, *rhs]);
+        typ
+    }
+
+    /// Creates placeholders for each of the implicit arguments of `typ`, returning the
+    /// placeholders and the type which remains once they have been applied
+    fn placeholder_arguments(&mut self,
+                             span: Span,
+                             mut typ: TcType)
+                             -> (Vec<ast::LExpr<TcIdent>>, TcType) {
+        let mut args = Vec::new();
+        while let Some((arg, ret)) = typ.as_implicit_function()
+            .map(|(arg, ret)| (arg.clone(), ret.clone())) {
+            args.push(self.implicit_placeholder(span, arg));
+            typ = ret;
+        }
+        (args, typ)
+    }
+
+    /// Creates a placeholder expression for an implicit argument of type `typ` which is resolved
+    /// using the implicit instances which are currently in scope
+    fn implicit_placeholder(&mut self, span: Span, typ: TcType) -> ast::LExpr<TcIdent> {
+        let name = format!("implicit:{}", self.implicit_placeholders);
+        self.implicit_placeholders += 1;
+        let placeholder = self.symbols.symbol(name);
+        let candidates = self.implicit_instances
+            .iter()
+            .flat_map(|(_, instances)| instances.iter().cloned())
+            .collect();
+        self.implicit_constraints.push(ImplicitConstraint {
+            span: span,
+            placeholder: placeholder.clone(),
+            typ: typ.clone(),
+            candidates: candidates,
+        });
+        pos::located(span,
+                     ast::Expr::Identifier(TcIdent {
+                         name: placeholder,
+                         typ: typ,
+                     }))
+    }
+
+    /// Typechecks `do id = bound in body` and replaces it with `monad.flat_map (\id -> body)
+    /// bound` where `monad` is the implicit `Monad` instance which is in scope, either declared
+    /// locally or through an imported module. All errors are reported at the binding line.
+    fn typecheck_do(&mut self, expr: &mut ast::LExpr<TcIdent>) -> TcType {
+        let mut do_expr = match mem::replace(&mut expr.value, ast::Expr::Tuple(Vec::new())) {
+            ast::Expr::Do(do_expr) => do_expr,
+            _ => panic!("ICE: Expected a `do` expression"),
+        };
+        let location = expr.span.start;
+        let span = do_expr.bind_span(location);
+
+        let monad_type = self.subs.new_var();
+        let bound_arg = self.subs.new_var();
+        let body_arg = self.subs.new_var();
+        let bound_type = Type::app(monad_type.clone(), vec![bound_arg.clone()]);
+        let body_type = Type::app(monad_type.clone(), vec![body_arg]);
+
+        let actual = self.typecheck(&mut do_expr.bound);
+        self.unify_span(span, &bound_type, actual);
+
+        self.enter_scope();
+        do_expr.id.typ = bound_arg.clone();
+        self.stack_var(do_expr.id.name.clone(), bound_arg.clone());
+        let actual = self.typecheck(&mut do_expr.body);
+        self.exit_scope();
+        let body_type = self.unify_span(span, &body_type, actual);
+
+        let lambda_type = Type::function(vec![bound_arg], body_type.clone());
+        let flat_map_type = Type::function(vec![lambda_type.clone(), bound_type],
+                                           body_type.clone());
+        do_expr.lambda_id = TcIdent {
+            name: self.symbols.symbol(format!("lambda:{}", location)),
+            typ: lambda_type,
+        };
+        do_expr.flat_map_id.typ = flat_map_type.clone();
+
+        // A `Monad` type which is in scope is used if there is one, otherwise the type is found
+        // through the prelude so that `do` can be used without importing `Monad`
+        let monad_symbol = self.symbols.symbol("Monad");
+        let monad_alias = self.find_type_info(&monad_symbol)
+            .or_else(|err| {
+                self.find_type_info(&Symbol::new("std.prelude.Monad")).map_err(|_| err)
+            })
+            .map(|alias| alias.as_ref().clone());
+        let monad = match monad_alias {
+            Ok(monad_alias) => {
+                let monad = Type::app(monad_alias, vec![monad_type]);
+                match self.find_field_type(&monad, &do_expr.flat_map_id.name) {
+                    Ok(field_type) => {
+                        let field_type = self.instantiate(&field_type);
+                        let field_type = instantiate_forall(&self.subs, field_type);
+                        self.unify_span(span, &flat_map_type, field_type);
+                    }
+                    Err(err) => {
+                        self.error(span, err);
+                    }
+                }
+                self.implicit_placeholder(span, monad)
+            }
+            Err(err) => {
+                // The expression will not be compiled since an error has been reported so any
+                // expression can be used in place of the monad
+                self.error(span, err);
+                pos::located(span, ast::Expr::Tuple(Vec::new()))
+            }
+        };
+        expr.value = do_expr.desugar(span, monad);
+        body_type
+    }
+
+    /// Resolves the implicit arguments which were inserted after the first `start` arguments and
+    /// replaces their placeholders in `exprs`
+    fn resolve_implicits<'e, I>(&mut self, start: usize, exprs: I)
+        where I: IntoIterator<Item = &'e mut ast::LExpr<TcIdent>>
+    {
+        struct ReplaceVisitor {
+            resolved: HashMap<Symbol, ast::LExpr<TcIdent>>,
+        }
+        impl MutVisitor for ReplaceVisitor {
+            type T = TcIdent;
+
+            fn visit_expr(&mut self, expr: &mut ast::LExpr<TcIdent>) {
+                let replacement = match expr.value {
+                    ast::Expr::Identifier(ref id) => self.resolved.remove(&id.name),
+                    _ => None,
+                };
+                match replacement {
+                    Some(replacement) => *expr = replacement,
+                    None => ast::walk_mut_expr(self, expr),
+                }
+            }
+        }
+        if self.implicit_constraints.len() <= start {
+            return;
+        }
+        let mut resolved = HashMap::new();
+        for constraint in self.implicit_constraints.split_off(start) {
+            match self.resolve_implicit(constraint.span, &constraint.typ, &constraint.candidates) {
+                Ok(expr) => {
+                    resolved.insert(constraint.placeholder, expr);
+                }
+                Err(err) => {
+                    self.error(constraint.span, err);
+                }
+            }
+        }
+        let mut visitor = ReplaceVisitor { resolved: resolved };
+        for expr in exprs {
+            visitor.visit_expr(expr);
+        }
+    }
+
+    /// Selects the implicit instance in `candidates` which has the type `typ`. If the selected
+    /// instance itself takes implicit arguments those are resolved as well
+    fn resolve_implicit(&mut self,
+                        span: Span,
+                        typ: &TcType,
+                        candidates: &[ImplicitInstance])
+                        -> TcResult<ast::LExpr<TcIdent>> {
+        let typ = self.subs.set_type(typ.clone());
+        // Instances which are declared in this module are preferred over instances which are
+        // imported so that an imported instance can be replaced locally
+        let mut exact = (Vec::new(), Vec::new());
+        let mut possible = (Vec::new(), Vec::new());
+        for candidate in candidates {
+            let mut instance = candidate.typ();
+            while let Some((_, ret)) = instance.as_implicit_function() {
+                instance = ret;
+            }
+            let matches = match self.match_instance(&mut HashMap::new(), instance, &typ) {
+                InstanceMatch::Exact => &mut exact,
+                InstanceMatch::Possible => &mut possible,
+                InstanceMatch::Mismatch => continue,
+            };
+            match candidate.global {
+                Some(ref global) => {
+                    // The same global instance may be reachable through several modules
+                    let is_duplicate = matches.1
+                        .iter()
+                        .any(|c: &&ImplicitInstance| c.global.as_ref() == Some(global));
+                    if !is_duplicate {
+                        matches.1.push(candidate);
+                    }
+                }
+                None => matches.0.push(candidate),
+            }
+        }
+        // Instances which need type variables to be unified are only used if there are no
+        // instances which match the type exactly
+        let matches = vec![exact.0, exact.1, possible.0, possible.1]
+            .into_iter()
+            .find(|matches| !matches.is_empty())
+            .unwrap_or_else(Vec::new);
+        let candidate = match matches.len() {
+            0 => return Err(MissingImplicit(typ)),
+            1 => matches[0],
+            _ => {
+                let mut names = matches.iter().map(|m| m.name()).collect::<Vec<_>>();
+                names.sort();
+                let names = names.iter().map(|name| Symbol::new(name)).collect();
+                return Err(AmbiguousImplicit(typ, names));
+            }
+        };
+        let candidate_type = self.instantiate(candidate.typ());
+        let mut instance = candidate_type.clone();
+        let mut arg_types = Vec::new();
+        while let Some((arg, ret)) = instance.as_implicit_function()
+            .map(|(arg, ret)| (arg.clone(), ret.clone())) {
+            arg_types.push(arg);
+            instance = ret;
+        }
+        try!(self.unify(&typ, instance));
+        let id = candidate.to_expr(span, candidate_type);
+        if arg_types.is_empty() {
+            return Ok(id);
+        }
+        let args = try!(arg_types.iter()
+            .map(|arg| self.resolve_implicit(span, arg, candidates))
+            .collect::<TcResult<Vec<_>>>());
+        Ok(pos::located(span, ast::Expr::Call(Box::new(id), args)))
+    }
+
+    /// Checks if an implicit instance of type `instance` can be used where a value of type `typ`
+    /// is expected. Generic variables in `instance` are bound in `generics` as they are matched.
+    fn match_instance(&self,
+                      generics: &mut HashMap<Symbol, TcType>,
+                      instance: &TcType,
+                      typ: &TcType)
+                      -> InstanceMatch {
+        let instance = self.subs.set_type(instance.clone());
+        let typ = self.subs.set_type(typ.clone());
+        match (&*instance, &*typ) {
+            (&Type::Generic(ref gen), _) => {
+                match generics.get(&gen.id).cloned() {
+                    Some(bound) => self.match_instance(&mut HashMap::new(), &bound, &typ),
+                    None => {
+                        generics.insert(gen.id.clone(), typ.clone());
+                        InstanceMatch::Exact
+                    }
+                }
+            }
+            (&Type::Variable(ref l), &Type::Variable(ref r)) if l.id == r.id => {
+                InstanceMatch::Exact
+            }
+            (&Type::Variable(_), _) |
+            (_, &Type::Variable(_)) => InstanceMatch::Possible,
+            (&Type::App(ref l, ref l_args), &Type::App(ref r, ref r_args))
+                if l_args.len() == r_args.len() => {
+                let mut result = self.match_instance(generics, l, r);
+                for (l, r) in l_args.iter().zip(r_args) {
+                    result = result.and(self.match_instance(generics, l, r));
+                }
+                result
+            }
+            (&Type::Record { fields: ref l_fields, rest: ref l_rest, .. },
+             &Type::Record { fields: ref r_fields, rest: ref r_rest, .. })
+                if l_fields.len() == r_fields.len() &&
+                   l_rest.is_some() == r_rest.is_some() => {
+                let mut result = InstanceMatch::Exact;
+                for l_field in l_fields {
+                    match r_fields.iter().find(|r_field| r_field.name.name_eq(&l_field.name)) {
+                        Some(r_field) => {
+                            result = result.and(self.match_instance(generics,
+                                                                    &l_field.typ,
+                                                                    &r_field.typ))
+                        }
+                        None => return InstanceMatch::Mismatch,
+                    }
+                }
+                if let (&Some(ref l), &Some(ref r)) = (l_rest, r_rest) {
+                    result = result.and(self.match_instance(generics, l, r));
+                }
+                result
+            }
+            _ if instance == typ => InstanceMatch::Exact,
+            _ => {
+                // Aliases are only expanded if the types can't be compared otherwise so that
+                // `List a` is matched against `List Int` before `List` is expanded
+                let l = instantiate::maybe_remove_alias(&self.environment, &instance)
+                    .unwrap_or(None);
+                let r = instantiate::maybe_remove_alias(&self.environment, &typ).unwrap_or(None);
+                if l.is_some() || r.is_some() {
+                    let l = l.unwrap_or(instance.clone());
+                    let r = r.unwrap_or(typ.clone());
+                    self.match_instance(generics, &l, &r)
+                } else {
+                    InstanceMatch::Mismatch
+                }
+            }
+        }
+    }
+
+    /// Checks that the alternatives of a match expression handles every possible value and that
+    /// each alternative can be reached
+    fn check_match_patterns(&mut self, span: Span, alts: &[ast::Alternative<TcIdent>]) {
+        let patterns = alts.iter()
+            .map(|alt| self.exhaustive_pattern(&alt.pattern))
+            .collect::<Vec<_>>();
+        let guarded = alts.iter().map(|alt| alt.guard.is_some()).collect::<Vec<_>>();
+        for index in exhaustive::unreachable_patterns(&patterns, &guarded) {
+            let pattern = patterns[index].to_string();
+            self.warn(alts[index].pattern.span, WarningCode::UnreachableAlternative, &pattern);
+        }
+        // Alternatives with a guard may not match any values so they can't make the match
+        // exhaustive
+        let unguarded = patterns.iter()
+            .zip(&guarded)
+            .filter(|&(_, &guarded)| !guarded)
+            .map(|(pattern, _)| pattern.clone())
+            .collect::<Vec<_>>();
+        // Look for one more pattern than is reported to know if any patterns were left out
+        let missing = exhaustive::missing_patterns(&unguarded,
+                                                   exhaustive::MAX_MISSING_PATTERNS + 1);
+        for pattern in missing.iter().take(exhaustive::MAX_MISSING_PATTERNS) {
+            self.warn(span, WarningCode::NonExhaustiveMatch, &pattern.to_string());
+        }
+        if missing.len() > exhaustive::MAX_MISSING_PATTERNS {
+            self.warn(span, WarningCode::NonExhaustiveMatch, "…");
+        }
+    }
+
+    /// Checks that the pattern of a `let` binding matches every value as there is no alternative
+    /// to fall back to if it does not match
+    fn check_let_pattern(&mut self, pattern: &ast::LPattern<TcIdent>) {
+        let exhaustive_pattern = self.exhaustive_pattern(pattern);
+        let missing = exhaustive::missing_patterns(&[exhaustive_pattern], 1);
+        if let Some(missing) = missing.first() {
+            self.error(pattern.span, RefutablePattern(Symbol::new(&missing.to_string())));
+        }
+    }
+
+    fn warn(&mut self, span: Span, code: WarningCode, name: &str) {
+        self.warnings.push(Spanned {
+            span: span,
+            value: Warning {
+                code: code,
+                name: Symbol::new(name),
+            },
+        });
+    }
+
+    /// Translates `pattern` into the simplified form used by the `exhaustive` module
+    fn exhaustive_pattern(&self, pattern: &ast::LPattern<TcIdent>) -> exhaustive::Pattern {
+        use exhaustive::{Constructor, Pattern};
+        match pattern.value {
+            ast::Pattern::Identifier(_) => Pattern::Wildcard,
+            ast::Pattern::Constructor(ref id, ref args) => {
+                let ctor_type = self.subs.set_type(id.typ.clone());
+                let return_type = (0..args.len()).fold(ctor_type, |typ, _| {
+                    typ.as_function().map_or_else(|| typ.clone(), |(_, ret)| ret.clone())
+                });
+                let variants = match *self.remove_aliases(return_type) {
+                    Type::Variants(ref variants) => {
+                        variants.iter()
+                            .map(|variant| (variant.0.clone(), types::arg_iter(&variant.1).count()))
+                            .collect()
+                    }
+                    _ => vec![(id.name.clone(), args.len())],
+                };
+                let ctor = Constructor::Variant {
+                    name: id.name.clone(),
+                    variants: variants,
+                };
+                let args = args.iter().map(|arg| self.exhaustive_pattern(arg)).collect();
+                Pattern::Constructor(ctor, args)
+            }
+            ast::Pattern::Record { ref id, ref fields, .. } => {
+                let record_type = self.remove_aliases(self.subs.set_type(id.typ.clone()));
+                let field_names = match *record_type {
+                    Type::Record { fields: ref record_fields, .. } => {
+                        record_fields.iter().map(|field| field.name.clone()).collect()
+                    }
+                    _ => fields.iter().map(|field| field.0.clone()).collect::<Vec<_>>(),
+                };
+                // Fields which are not mentioned in the pattern can have any value
+                let args = field_names.iter()
+                    .map(|name| {
+                        fields.iter()
+                            .find(|field| field.0.name_eq(name))
+                            .and_then(|field| field.1.as_ref())
+                            .map_or(Pattern::Wildcard, |field| self.exhaustive_pattern(field))
+                    })
+                    .collect();
+                Pattern::Constructor(Constructor::Record(field_names), args)
+            }
+            ast::Pattern::Tuple { ref elems, .. } => {
+                let args = elems.iter().map(|elem| self.exhaustive_pattern(elem)).collect();
+                Pattern::Constructor(Constructor::Tuple(elems.len()), args)
+            }
+            ast::Pattern::Literal(ref lit) => {
+                Pattern::Constructor(Constructor::Literal(lit.clone()), vec![])
+            }
+        }
     }
 
     fn typecheck_pattern(&mut self,
                          pattern: &mut ast::LPattern<TcIdent>,
                          match_type: TcType)
                          -> TcType {
-        let span = pattern.span();
+        let span = pattern.span;
         match pattern.value {
             ast::Pattern::Constructor(ref mut id, ref mut args) => {
                 if let Some(new) = self.original_symbols.get(&id.name) {
@@ -717,36 +1669,39 @@ impl<'a> Typecheck<'a> {
                 }
                 // Find the enum constructor and return the types for its arguments
                 let ctor_type = self.find_at(span, &id.name);
-                let return_type = match self.typecheck_pattern_rec(args, ctor_type) {
-                    Ok(return_type) => return_type,
-                    Err(err) => self.error(span, err),
+                id.typ = ctor_type.clone();
+                let (arg_types, return_type) = match self.pattern_arg_types(args.len(), ctor_type) {
+                    Ok(types) => types,
+                    Err(err) => {
+                        let typ = self.error(span, err);
+                        (Vec::new(), typ)
+                    }
                 };
-                self.unify_span(span, &match_type, return_type)
+                // Unify the return type first so that the argument types are known when the
+                // nested patterns are checked
+                let return_type = self.unify_span(span, &match_type, return_type);
+                for (arg, arg_type) in args.iter_mut().zip(arg_types) {
+                    let arg_type = self.subs.set_type(arg_type);
+                    self.typecheck_pattern(arg, arg_type);
+                }
+                return_type
             }
-            ast::Pattern::Record { ref mut id, types: ref mut associated_types, ref fields } => {
+            ast::Pattern::Record { ref mut id,
+                                   types: ref mut associated_types,
+                                   ref mut fields } => {
+                let match_type = self.subs.set_type(match_type);
                 id.typ = match_type.clone();
                 let mut match_type = self.remove_alias(match_type);
                 let mut types = Vec::new();
                 let new_type = match *match_type {
-                    Type::Record { fields: ref expected_fields, .. } => {
-                        for pattern_field in fields {
-                            let found_field = expected_fields.iter()
-                                .find(|expected_field| {
-                                    pattern_field.0
-                                        .name_eq(&expected_field.name)
-                                });
-                            let expected_field = match found_field {
-                                Some(expected) => expected,
-                                None => {
-                                    self.error(span,
-                                               UndefinedField(match_type.clone(),
-                                                              pattern_field.0.clone()));
-                                    continue;
-                                }
-                            };
-                            let var = self.subs.new_var();
-                            types.push(var.clone());
-                            self.unify_span(span, &var, expected_field.typ.clone());
+                    Type::Record { .. } => {
+                        for pattern_field in fields.iter() {
+                            let field_type =
+                                match self.find_field_type(&match_type, &pattern_field.0) {
+                                    Ok(typ) => typ,
+                                    Err(err) => self.error(span, err),
+                                };
+                            types.push(field_type);
                         }
                         None
                     }
@@ -765,7 +1720,9 @@ impl<'a> Typecheck<'a> {
                                         }
                                     })
                                     .collect();
-                                let t = Type::record(Vec::new(), fields);
+                                // No record type with these fields exist so the value can be
+                                // any record which has (at least) the fields of the pattern
+                                let t = Type::poly_record(Vec::new(), fields, self.subs.new_var());
                                 (t.clone(), t)
                             }
                         };
@@ -774,7 +1731,12 @@ impl<'a> Typecheck<'a> {
                         self.unify_span(span, &match_type, typ);
                         match *actual_type {
                             Type::Record { fields: ref record_types, .. } => {
-                                types.extend(record_types.iter().map(|f| f.typ.clone()));
+                                types.extend(fields.iter().map(|field| {
+                                    record_types.iter()
+                                        .find(|record_field| record_field.name.name_eq(field))
+                                        .map(|record_field| record_field.typ.clone())
+                                        .unwrap_or_else(|| self.subs.new_var())
+                                }));
                             }
                             _ => {
                                 panic!("Expected record found {}",
@@ -785,12 +1747,14 @@ impl<'a> Typecheck<'a> {
                     }
                 };
                 match_type = new_type.unwrap_or(match_type);
-                for (field, field_type) in fields.iter().zip(types) {
-                    let name = match field.1 {
-                        Some(ref bind_name) => bind_name,
-                        None => &field.0,
-                    };
-                    self.stack_var(name.clone(), field_type);
+                for (field, field_type) in fields.iter_mut().zip(types) {
+                    match field.1 {
+                        Some(ref mut pattern) => {
+                            let field_type = self.subs.set_type(field_type);
+                            self.typecheck_pattern(pattern, field_type);
+                        }
+                        None => self.stack_var(field.0.clone(), field_type),
+                    }
                 }
                 match *match_type {
                     Type::Record { ref types, .. } => {
@@ -821,39 +1785,92 @@ impl<'a> Typecheck<'a> {
                 }
                 match_type
             }
+            ast::Pattern::Tuple { ref mut typ, ref mut elems } => {
+                let elem_types: Vec<_> = elems.iter().map(|_| self.subs.new_var()).collect();
+                let tuple_type = self.tuple_type(elem_types.clone());
+                typ.typ = self.unify_span(span, &match_type, tuple_type);
+                for (elem, elem_type) in elems.iter_mut().zip(elem_types) {
+                    let elem_type = self.subs.set_type(elem_type);
+                    self.typecheck_pattern(elem, elem_type);
+                }
+                typ.typ.clone()
+            }
             ast::Pattern::Identifier(ref mut id) => {
                 self.stack_var(id.id().clone(), match_type.clone());
                 id.typ = match_type.clone();
                 match_type
             }
+            ast::Pattern::Literal(ref lit) => {
+                let literal_type = lit.env_type_of(&self.environment);
+                self.unify_span(span, &match_type, literal_type)
+            }
         }
     }
 
-    fn typecheck_pattern_rec(&mut self, args: &[TcIdent], typ: TcType) -> TcResult<TcType> {
-        if args.len() == 0 {
-            return Ok(typ);
+    /// Splits the type of a constructor into the types of its `count` arguments and its return
+    /// type
+    fn pattern_arg_types(&mut self,
+                         count: usize,
+                         mut typ: TcType)
+                         -> TcResult<(Vec<TcType>, TcType)> {
+        let mut arg_types = Vec::with_capacity(count);
+        for i in 0..count {
+            typ = match typ.as_function() {
+                Some((arg, ret)) => {
+                    arg_types.push(arg.clone());
+                    ret.clone()
+                }
+                None => return Err(PatternError(typ.clone(), count - i)),
+            };
         }
-        match typ.as_function() {
-            Some((arg, ret)) => {
-                self.stack_var(args[0].id().clone(), arg.clone());
-                self.typecheck_pattern_rec(&args[1..], ret.clone())
-            }
-            None => Err(PatternError(typ.clone(), args.len())),
+        Ok((arg_types, typ))
+    }
+
+    /// Constructs the type of a tuple with elements of type `elems`. Tuples are represented as
+    /// records with the fields `_0`, `_1`, ... with the empty tuple being the unit type.
+    fn tuple_type(&mut self, elems: Vec<TcType>) -> TcType {
+        if elems.is_empty() {
+            return Type::unit();
         }
+        let fields = elems.into_iter()
+            .enumerate()
+            .map(|(i, typ)| {
+                types::Field {
+                    name: self.symbols.symbol(ast::tuple_field_name(i)),
+                    typ: typ,
+                }
+            })
+            .collect();
+        Type::record(vec![], fields)
     }
 
-    fn typecheck_bindings(&mut self, bindings: &mut [ast::Binding<TcIdent>]) -> TcResult<()> {
+    fn typecheck_bindings(&mut self, bindings: &mut [ast::Binding<TcIdent>]) {
+        // The generics of a signature are always quantified so a `forall` at the top of a
+        // signature can just be removed
+        for bind in bindings.iter_mut() {
+            if let Some(ref mut typ) = bind.typ {
+                loop {
+                    let body = match *typ.value {
+                        Type::Forall(_, ref body) => body.clone(),
+                        _ => break,
+                    };
+                    typ.value = body;
+                }
+            }
+        }
         self.enter_scope();
         self.type_variables.enter_scope();
         let level = self.subs.var_id();
+        let implicit_start = self.implicit_constraints.len();
+        let call_start = self.call_types.len();
         let is_recursive = bindings.iter().all(|bind| !bind.arguments.is_empty());
         // When the definitions are allowed to be mutually recursive
         if is_recursive {
             for bind in bindings.iter_mut() {
                 let typ = match bind.typ {
                     Some(ref mut type_decl) => {
-                        *type_decl = self.refresh_symbols_in_type(type_decl.clone());
-                        try!(self.kindcheck(type_decl));
+                        type_decl.value = self.refresh_symbols_in_type(type_decl.value.clone());
+                        self.kindcheck_signature(bind.name.span, type_decl);
                         self.instantiate_signature(type_decl)
                     }
                     None => self.subs.new_var(),
@@ -869,14 +1886,15 @@ impl<'a> Typecheck<'a> {
         let mut types = Vec::new();
         for bind in bindings.iter_mut() {
             self.type_variables.enter_scope();
+            let bind_call_start = self.call_types.len();
 
             // Functions which are declared as `let f x = ...` are allowed to be self
             // recursive
             let mut typ = if bind.arguments.is_empty() {
                 if let Some(ref mut type_decl) = bind.typ {
                     self.instantiate_signature(type_decl);
-                    *type_decl = self.refresh_symbols_in_type(type_decl.clone());
-                    try!(self.kindcheck(type_decl));
+                    type_decl.value = self.refresh_symbols_in_type(type_decl.value.clone());
+                    self.kindcheck_signature(bind.name.span, type_decl);
                 }
                 self.typecheck(&mut bind.expression)
             } else {
@@ -890,9 +1908,18 @@ impl<'a> Typecheck<'a> {
                    bind.name,
                    types::display_type(&self.symbols, &typ));
             if let Some(ref type_decl) = bind.typ {
-                typ = self.merge_signature(bind.name.span(), level, type_decl, typ);
+                typ = self.merge_signature(bind.name.span, level, type_decl, typ);
             }
             if !is_recursive {
+                // Implicit arguments must be resolved before generalizing as resolving them may
+                // refine the type
+                self.resolve_implicits(implicit_start, Some(&mut bind.expression));
+                if is_syntactic_value(bind) {
+                    // Calls inside a function body create new values each time it is called
+                    self.call_types.truncate(bind_call_start);
+                } else {
+                    self.restrict_mutable_types(bind_call_start, level.saturating_sub(1));
+                }
                 // Merge the type declaration and the actual type
                 self.generalize_variables(level, &mut bind.expression);
                 self.typecheck_pattern(&mut bind.name, typ);
@@ -906,24 +1933,134 @@ impl<'a> Typecheck<'a> {
                 // Merge the variable we bound to the name and the type inferred
                 // in the expression
                 let bound_typ = bind.env_type_of(&self.environment);
-                self.unify_span(bind.name.span(), &bound_typ, found_typ);
+                self.unify_span(bind.name.span, &bound_typ, found_typ);
             }
+            self.resolve_implicits(implicit_start,
+                                   bindings.iter_mut().map(|bind| &mut bind.expression));
+            // Recursive bindings are all functions so any calls in them are in a function body
+            self.call_types.truncate(call_start);
         }
         // Once all variables inside the let has been unified we can quantify them
         debug!("Generalize {}", level);
         for bind in bindings {
             self.generalize_variables(level, &mut bind.expression);
             self.finish_binding(level, bind);
+            self.check_let_pattern(&bind.name);
+            if bind.implicit {
+                if let ast::Pattern::Identifier(ref id) = bind.name.value {
+                    let instance = ImplicitInstance::local(id.name.clone(), id.typ.clone());
+                    self.implicit_instances.insert(id.name.clone(), vec![instance]);
+                }
+            }
+            // Bring the implicit instances of imported modules into scope
+            let global = match bind.expression.value {
+                ast::Expr::Identifier(ref id) => {
+                    if self.environment.stack.get(&id.name).is_none() {
+                        Some(id.name.clone())
+                    } else {
+                        None
+                    }
+                }
+                _ => None,
+            };
+            if let Some(global) = global {
+                let env: &'a (PrimitiveEnv + 'a) = self.environment.environment;
+                if let Some(metadata) = env.get_metadata(&global) {
+                    let global = String::from(global.as_ref());
+                    self.insert_imported_instances(&bind.name, global, metadata);
+                }
+            }
         }
         debug!("Typecheck `in`");
         self.type_variables.exit_scope();
-        Ok(())
+    }
+
+    /// Inserts the implicit instances which are bound by `pattern` when it is bound to the global
+    /// value `global`, described by `metadata`
+    fn insert_imported_instances(&mut self,
+                                 pattern: &ast::LPattern<TcIdent>,
+                                 global: String,
+                                 metadata: &Metadata) {
+        match pattern.value {
+            ast::Pattern::Identifier(ref id) => {
+                let mut instances = Vec::new();
+                self.module_instances(&mut instances,
+                                      vec![(id.name.clone(), id.typ.clone())],
+                                      global,
+                                      metadata);
+                if !instances.is_empty() {
+                    self.implicit_instances.insert(id.name.clone(), instances);
+                }
+            }
+            ast::Pattern::Record { ref fields, .. } => {
+                for &(ref field, ref field_pattern) in fields {
+                    let field_metadata = match metadata.module.get(field.as_ref()) {
+                        Some(field_metadata) => field_metadata,
+                        None => continue,
+                    };
+                    let global = format!("{}.{}", global, field);
+                    match *field_pattern {
+                        Some(ref field_pattern) => {
+                            self.insert_imported_instances(field_pattern, global, field_metadata)
+                        }
+                        None => {
+                            let typ = match self.environment.stack.get(field) {
+                                Some(typ) => typ.clone(),
+                                None => continue,
+                            };
+                            let mut instances = Vec::new();
+                            self.module_instances(&mut instances,
+                                                  vec![(field.clone(), typ)],
+                                                  global,
+                                                  field_metadata);
+                            if !instances.is_empty() {
+                                self.implicit_instances.insert(field.clone(), instances);
+                            }
+                        }
+                    }
+                }
+            }
+            ast::Pattern::Tuple { .. } |
+            ast::Pattern::Constructor(..) |
+            ast::Pattern::Literal(_) => (),
+        }
+    }
+
+    /// Collects the implicit instances which can be reached through `path`, including the value
+    /// at `path` itself if it was declared as `implicit`
+    fn module_instances(&self,
+                        instances: &mut Vec<ImplicitInstance>,
+                        path: Vec<(Symbol, TcType)>,
+                        global: String,
+                        metadata: &Metadata) {
+        if metadata.implicit {
+            instances.push(ImplicitInstance {
+                path: path.clone(),
+                global: Some(global.clone()),
+            });
+        }
+        if metadata.module.is_empty() {
+            return;
+        }
+        let typ = self.remove_aliases(path.last().expect("ICE: Empty path").1.clone());
+        if let Type::Record { ref fields, .. } = *typ {
+            for field in fields {
+                if let Some(field_metadata) = metadata.module.get(field.name.as_ref()) {
+                    let mut field_path = path.clone();
+                    field_path.push((field.name.clone(), field.typ.clone()));
+                    self.module_instances(instances,
+                                          field_path,
+                                          format!("{}.{}", global, field.name),
+                                          field_metadata);
+                }
+            }
+        }
     }
 
     fn typecheck_type_bindings(&mut self,
                                bindings: &mut [ast::TypeBinding<Symbol>],
-                               expr: &ast::LExpr<TcIdent>)
-                               -> TcResult<()> {
+                               expr: &ast::LExpr<TcIdent>) {
+        let span = expr.span;
         self.enter_scope();
         // Rename the types so they get a name which is distinct from types from other
         // modules
@@ -941,6 +2078,7 @@ impl<'a> Typecheck<'a> {
                 .expect("Expected binding to have an aliased type");
             *typ = self.refresh_symbols_in_type(typ.clone());
         }
+        let mut kind_error = None;
         {
             let subs = Substitution::new();
             let mut check = KindCheck::new(&self.environment, &self.symbols, subs);
@@ -954,7 +2092,7 @@ impl<'a> Typecheck<'a> {
                 let mut id_kind = check.type_kind();
                 let alias = Alias::make_mut(&mut bind.alias);
                 for gen in alias.args.iter_mut().rev() {
-                    gen.kind = check.subs.new_var();
+                    gen.kind = check.parameter_kind(gen);
                     id_kind = Kind::function(gen.kind.clone(), id_kind);
                 }
                 check.add_local(alias.name.clone(), id_kind);
@@ -967,7 +2105,12 @@ impl<'a> Typecheck<'a> {
                     .typ
                     .as_mut()
                     .expect("Expected binding to have an aliased type");
-                try!(check.kindcheck_type(typ));
+                if let Err(err) = check.kindcheck_type(typ) {
+                    // The types may be mutually recursive so any errors in the remaining
+                    // bindings are likely caused by this error
+                    kind_error = Some(err);
+                    break;
+                }
             }
 
             // All kinds are now inferred so replace the kinds store in the AST
@@ -982,18 +2125,18 @@ impl<'a> Typecheck<'a> {
             }
         }
 
+        if let Some(err) = kind_error {
+            self.error(span, err.into());
+        }
+
         // Finally insert the declared types into the global scope
         for bind in bindings {
             if self.environment.stack_types.get(&bind.name).is_some() {
-                self.errors.error(Spanned {
-                    span: expr.span(&ast::TcIdentEnvWrapper(&self.symbols)),
-                    value: DuplicateTypeDefinition(bind.name.clone()),
-                });
+                self.error(span, DuplicateTypeDefinition(bind.name.clone()));
             } else {
                 self.stack_type(bind.name.clone(), &bind.alias);
             }
         }
-        Ok(())
     }
 
     fn kindcheck(&self, typ: &mut TcType) -> TcResult<()> {
@@ -1003,8 +2146,24 @@ impl<'a> Typecheck<'a> {
         Ok(())
     }
 
+    /// Kindchecks the type signature of a binding. If the signature is not well formed the error
+    /// is reported and the signature is replaced by the error type
+    fn kindcheck_signature(&mut self, span: Span, typ: &mut TcType) {
+        if let Err(err) = self.kindcheck(typ) {
+            *typ = self.error(span, err);
+        }
+    }
+
     fn finish_binding(&mut self, level: u32, bind: &mut ast::Binding<TcIdent>) {
-        match bind.name.value {
+        debug!("{:?}: {}",
+               bind.name,
+               types::display_type(&self.symbols,
+                                   &bind.expression.env_type_of(&self.environment)));
+        self.finish_pattern(level, &mut bind.name);
+    }
+
+    fn finish_pattern(&mut self, level: u32, pattern: &mut ast::LPattern<TcIdent>) {
+        match pattern.value {
             ast::Pattern::Identifier(ref mut id) => {
                 if let Some(typ) = self.finish_type(level, &id.typ) {
                     id.typ = typ;
@@ -1015,29 +2174,34 @@ impl<'a> Typecheck<'a> {
                 self.intersect_type(level, &id.name, &id.typ);
             }
             ast::Pattern::Record { ref mut id, ref mut fields, .. } => {
-                debug!("{{ .. }}: {}",
-                       types::display_type(&self.symbols,
-                                           &bind.expression
-                                               .env_type_of(&self.environment)));
                 if let Some(typ) = self.finish_type(level, &id.typ) {
                     id.typ = typ;
                 }
                 let record_type = self.remove_alias(id.typ.clone());
                 with_pattern_types(fields, &record_type, |field_name, binding, field_type| {
-                    let field_name = binding.as_ref().unwrap_or(field_name);
-                    self.intersect_type(level, field_name, field_type);
+                    match *binding {
+                        Some(ref mut pattern) => self.finish_pattern(level, pattern),
+                        None => self.intersect_type(level, field_name, field_type),
+                    }
                 });
             }
-            ast::Pattern::Constructor(ref id, ref arguments) => {
-                debug!("{}: {}",
-                       self.symbols.string(&id.name),
-                       types::display_type(&self.symbols,
-                                           &bind.expression
-                                               .env_type_of(&self.environment)));
-                for arg in arguments {
-                    self.intersect_type(level, &arg.name, &arg.typ);
+            ast::Pattern::Tuple { ref mut typ, ref mut elems } => {
+                if let Some(new_type) = self.finish_type(level, &typ.typ) {
+                    typ.typ = new_type;
+                }
+                for elem in elems {
+                    self.finish_pattern(level, elem);
                 }
             }
+            ast::Pattern::Constructor(ref mut id, ref mut args) => {
+                if let Some(typ) = self.finish_type(level, &id.typ) {
+                    id.typ = typ;
+                }
+                for arg in args {
+                    self.finish_pattern(level, arg);
+                }
+            }
+            ast::Pattern::Literal(_) => (),
         }
     }
 
@@ -1062,6 +2226,61 @@ impl<'a> Typecheck<'a> {
             .unwrap_or(typ)
     }
 
+    /// Lowers the type variables of the mutable values (such as `Ref a`) returned from the calls
+    /// in `call_types[start..]` to `level` so that they are not generalized by any binding at a
+    /// higher level. If `let r = ref None` were generalized to `Ref (Option a)` the same reference
+    /// could be written and read at different types.
+    ///
+    /// Only the results of calls need to be checked as those are the only expressions which can
+    /// create new mutable values. Variables which do not belong to a mutable value are left as is
+    /// which lets functions such as `\r -> load r` remain polymorphic. The call types are kept so
+    /// that an enclosing binding which is not a syntactic value restricts them as well.
+    fn restrict_mutable_types(&mut self, start: usize, level: u32) {
+        struct RestrictMutable<'a, 'b: 'a> {
+            tc: &'a Typecheck<'b>,
+            level: u32,
+            /// Aliases which are currently being expanded, used to stop recursive aliases from
+            /// being expanded forever
+            expanded: Vec<Symbol>,
+        }
+        impl<'a, 'b> types::Walker<TcType> for RestrictMutable<'a, 'b> {
+            fn walk(&mut self, typ: &TcType) {
+                let typ = self.tc.subs.real(typ).clone();
+                if let Some((id, _)) = typ.as_alias() {
+                    if self.tc.environment.is_mutable_type(id) {
+                        restrict_variables(&self.tc.subs, self.level, &typ);
+                        return;
+                    }
+                    if !self.expanded.contains(id) {
+                        if let Ok(Some(expanded)) =
+                               instantiate::maybe_remove_alias(&self.tc.environment, &typ) {
+                            self.expanded.push(id.clone());
+                            self.walk(&expanded);
+                            self.expanded.pop();
+                        }
+                    }
+                }
+                types::walk_type_(&typ, self);
+            }
+        }
+        fn restrict_variables(subs: &Substitution<TcType>, level: u32, typ: &TcType) {
+            types::walk_type(typ, |typ: &TcType| if let Type::Variable(ref var) = **typ {
+                match subs.find_type_for_var(var.id) {
+                    Some(typ) => restrict_variables(subs, level, typ),
+                    None => subs.restrict_level(var.id, level),
+                }
+            });
+        }
+        let mut visitor = RestrictMutable {
+            tc: self,
+            level: level,
+            expanded: Vec::new(),
+        };
+        for typ in &self.call_types[start..] {
+            visitor.walk(typ);
+        }
+    }
+
     /// Generate a generic variable name which is not used in the current scope
     fn next_variable(&mut self, level: u32, s: &mut String) {
         for c in b'a'..(b'z' + 1) {
@@ -1127,7 +2346,9 @@ impl<'a> Typecheck<'a> {
                     self.subs.insert(var.id, gen.clone());
                     Some(gen)
                 }
-                Type::Record { ref types, ref fields, .. } => {
+                Type::Record { ref types, ref fields, ref rest } => {
+                    let new_rest = rest.as_ref()
+                        .and_then(|rest| self.finish_type_(level, generic, i, rest));
                     let new_fields = types::walk_move_types(fields, |field| {
                         // Make a new name base for any unbound variables in the record field
                         // Gives { id : a0 -> a0, const : b0 -> b1 -> b1 }
@@ -1139,7 +2360,13 @@ impl<'a> Typecheck<'a> {
                             }
                         })
                     });
-                    new_fields.map(|fields| Type::record(types.clone(), fields))
+                    types::merge(fields,
+                                 new_fields,
+                                 rest,
+                                 new_rest.map(Some),
+                                 |fields, rest| {
+                                     types::record_with_rest(types.clone(), fields, rest)
+                                 })
                         .or_else(|| replacement.clone())
                 }
                 _ => {
@@ -1179,9 +2406,12 @@ impl<'a> Typecheck<'a> {
                         })
                         .cloned()
                         .map(|new_id| {
-                            Type::alias(new_id,
-                                        alias.args.clone(),
-                                        alias.typ.clone().expect("Type"))
+                            Alias::from(AliasData {
+                                    name: new_id,
+                                    args: alias.args.clone(),
+                                    typ: alias.typ.clone(),
+                                })
+                                .into_type()
                         })
                 }
                 Type::Id(ref id) => {
@@ -1231,39 +2461,214 @@ impl<'a> Typecheck<'a> {
                        expected: &TcType,
                        mut actual: TcType)
                        -> TcType {
+        let mut skolems = Vec::new();
+        let skolemized = skolemize(&self.subs, expected, &mut skolems);
         let state = unify_type::State::new(&self.environment);
         match unify_type::merge_signature(&self.subs,
                                           &mut self.type_variables,
                                           level,
                                           state,
-                                          expected,
+                                          skolemized.as_ref().unwrap_or(expected),
                                           &actual) {
-            Ok(typ) => self.subs.set_type(typ),
+            Ok(typ) => {
+                if skolemized.is_none() {
+                    self.subs.set_type(typ)
+                } else {
+                    self.check_escaping_variables(span, level, &skolems);
+                    // The skolemized type is only used for checking the binding, the binding
+                    // itself keeps its polymorphic type
+                    self.subs.set_type(expected.clone())
+                }
+            }
             Err(errors) => {
                 let mut expected = expected.clone();
                 expected = self.subs.set_type(expected);
                 actual = self.subs.set_type(actual);
-                let err =
-                    TypeError::Unification(expected, actual, apply_subs(&self.subs, errors.errors));
-                self.errors.error(Spanned {
-                    span: span,
-                    value: err,
-                });
-                self.subs.new_var()
+                let errors = apply_subs(&self.subs, errors.errors);
+                self.error(span, TypeError::Unification(expected, actual, errors, None))
             }
         }
     }
 
+    /// Checks that `actual` matches the type annotation `expected` and returns the type that the
+    /// annotated expression has outside of the annotation.
+    ///
+    /// If `expected` is a record type, only the fields and types it mentions are visible on the
+    /// returned type. Types declared as `type T` are replaced by a new abstract type so that code
+    /// outside of the annotation can't depend on what `T` is defined as.
+    fn seal_signature(&mut self,
+                      span: Span,
+                      level: u32,
+                      expected: &TcType,
+                      actual: TcType)
+                      -> TcType {
+        let (types, fields) = match **expected {
+            Type::Record { ref types, ref fields, rest: None } => (types, fields),
+            _ => return self.merge_signature(span, level, expected, actual),
+        };
+        let actual_types = match *self.remove_aliases(self.subs.set_type(actual.clone())) {
+            Type::Record { ref types, .. } => types.clone(),
+            _ => Vec::new(),
+        };
+        let mut abstract_types = Vec::new();
+        let mut new_types = Vec::new();
+        for field in types {
+            let alias = actual_types.iter()
+                .find(|actual_field| actual_field.name.name_eq(&field.name))
+                .map(|actual_field| actual_field.typ.clone());
+            let alias = match alias {
+                Some(alias) => alias,
+                None => {
+                    self.error(span, UndefinedField(actual.clone(), field.name.clone()));
+                    continue;
+                }
+            };
+            let alias = match field.typ.typ {
+                None => {
+                    let name = String::from(self.symbols.string(&alias.name));
+                    let abstract_alias = Alias::from(AliasData {
+                        name: self.symbols.scoped_symbol(&name),
+                        args: alias.args.clone(),
+                        typ: None,
+                    });
+                    // The fields of the signature may refer to the type through the name it has
+                    // in the signature or the name it has in the sealed record
+                    abstract_types.push((field.typ.name.clone(), abstract_alias.clone()));
+                    abstract_types.push((alias.name.clone(), abstract_alias.clone()));
+                    abstract_alias
+                }
+                Some(ref expected) => {
+                    self.check_signature_alias(span, &field.typ, expected, &alias);
+                    alias
+                }
+            };
+            new_types.push(types::Field {
+                name: field.name.clone(),
+                typ: alias,
+            });
+        }
+        let mut new_fields = Vec::new();
+        for field in fields {
+            match self.find_field_type(&actual, &field.name) {
+                Ok(actual_field) => {
+                    self.merge_signature(span, level, &field.typ, actual_field);
+                }
+                Err(err) => {
+                    self.error(span, err);
+                }
+            }
+            let typ = types::walk_move_type(field.typ.clone(), &mut |typ: &Type<Symbol>| {
+                let name = match *typ {
+                    Type::Alias(ref alias) => &alias.name,
+                    Type::Id(ref id) => id,
+                    _ => return None,
+                };
+                abstract_types.iter()
+                    .find(|&&(ref id, _)| id == name)
+                    .map(|&(_, ref alias)| alias.as_ref().clone())
+            });
+            new_fields.push(types::Field {
+                name: field.name.clone(),
+                typ: typ,
+            });
+        }
+        Type::record(new_types, new_fields)
+    }
+
+    /// Checks that the definition `expected` which the signature gives for the type
+    /// `expected_alias` is the same as the definition of `actual`, the type in the sealed record
+    fn check_signature_alias(&mut self,
+                             span: Span,
+                             expected_alias: &Alias<Symbol, TcType>,
+                             expected: &TcType,
+                             actual: &Alias<Symbol, TcType>) {
+        // `{ T }` refers to the `T` which is in scope and is therefore the type in the record
+        let is_same_type = match **expected {
+            Type::Alias(ref alias) => alias.name == actual.name,
+            Type::Id(ref id) => *id == actual.name,
+            _ => false,
+        };
+        if is_same_type {
+            return;
+        }
+        let actual_typ = match actual.typ {
+            Some(ref typ) if actual.args.len() == expected_alias.args.len() => typ.clone(),
+            _ => {
+                let expected_alias = expected_alias.clone().into_type();
+                let actual = actual.clone().into_type();
+                self.error(span, Unification(expected_alias, actual, vec![], None));
+                return;
+            }
+        };
+        // Refer to the arguments of the type by the names they have in the signature
+        let actual_typ = types::instantiate(actual_typ, |generic| {
+            actual.args
+                .iter()
+                .position(|arg| arg.id == generic.id)
+                .map(|i| Type::generic(expected_alias.args[i].clone()))
+        });
+        self.unify_span(span, expected, actual_typ);
+    }
+
+    /// Reports an error if any of the type variables which were created before `level` (and are
+    /// therefore defined outside of the expression being checked) were unified with one of the
+    /// rigid variables in `skolems`. If that were allowed `\x -> y` could be passed where
+    /// `forall a . a -> a` is expected which would let `y` be used as a value of any type.
+    fn check_escaping_variables(&mut self, span: Span, level: u32, skolems: &[Symbol]) {
+        fn find_skolem(subs: &Substitution<TcType>,
+                       skolems: &[Symbol],
+                       typ: &TcType,
+                       found: &mut Option<Symbol>) {
+            types::walk_type(typ, |typ: &TcType| match **typ {
+                Type::Generic(ref gen) if skolems.contains(&gen.id) => {
+                    *found = Some(gen.id.clone())
+                }
+                Type::Variable(ref var) => {
+                    if let Some(typ) = subs.find_type_for_var(var.id) {
+                        find_skolem(subs, skolems, typ, found)
+                    }
+                }
+                _ => (),
+            });
+        }
+        if skolems.is_empty() {
+            return;
+        }
+        let mut escaped = None;
+        for var in 1..level + 1 {
+            if let Some(typ) = self.subs.find_type_for_var(var) {
+                find_skolem(&self.subs, skolems, typ, &mut escaped);
+            }
+            if escaped.is_some() {
+                break;
+            }
+        }
+        if let Some(id) = escaped {
+            self.error(span, EscapingTypeVariable(id));
+        }
+    }
+
     fn unify_span(&mut self, span: Span, expected: &TcType, actual: TcType) -> TcType {
         match self.unify(expected, actual) {
             Ok(typ) => typ,
-            Err(err) => {
-                self.errors.error(Spanned {
-                    span: span,
-                    value: err,
-                });
-                self.subs.new_var()
+            Err(err) => self.error(span, err),
+        }
+    }
+
+    /// Unifies `actual` with `expected` where `expected` is the type required by the code at
+    /// `origin`. Any error is reported at `span` with a label pointing to `origin`
+    fn unify_span_origin(&mut self,
+                         span: Span,
+                         origin: Span,
+                         expected: &TcType,
+                         actual: TcType)
+                         -> TcType {
+        match self.unify(expected, actual) {
+            Ok(typ) => typ,
+            Err(Unification(expected, actual, errors, _)) => {
+                self.error(span, Unification(expected, actual, errors, Some(origin)))
             }
+            Err(err) => self.error(span, err),
         }
     }
 
@@ -1282,7 +2687,8 @@ impl<'a> Typecheck<'a> {
                        errors,
                        types::display_type(&self.symbols, &expected),
                        types::display_type(&self.symbols, &actual));
-                Err(TypeError::Unification(expected, actual, apply_subs(&self.subs, errors.errors)))
+                let errors = apply_subs(&self.subs, errors.errors);
+                Err(TypeError::Unification(expected, actual, errors, None))
             }
         }
     }
@@ -1313,8 +2719,24 @@ impl<'a> Typecheck<'a> {
     }
 }
 
-fn with_pattern_types<F>(fields: &[(Symbol, Option<Symbol>)], typ: &TcType, mut f: F)
-    where F: FnMut(&Symbol, &Option<Symbol>, &TcType)
+/// Returns true if `bind` binds a syntactic value (a function, an identifier or a literal).
+/// Evaluating a syntactic value can't create a mutable value so its type can always be generalized
+fn is_syntactic_value(bind: &ast::Binding<TcIdent>) -> bool {
+    if !bind.arguments.is_empty() {
+        return true;
+    }
+    match bind.expression.value {
+        ast::Expr::Lambda(_) |
+        ast::Expr::Identifier(_) |
+        ast::Expr::Literal(_) => true,
+        _ => false,
+    }
+}
+
+fn with_pattern_types<F>(fields: &mut [(Symbol, Option<ast::LPattern<TcIdent>>)],
+                         typ: &TcType,
+                         mut f: F)
+    where F: FnMut(&Symbol, &mut Option<ast::LPattern<TcIdent>>, &TcType)
 {
     if let Type::Record { fields: ref field_types, .. } = **typ {
         for field in fields {
@@ -1322,12 +2744,95 @@ fn with_pattern_types<F>(fields: &[(Symbol, Option<Symbol>)], typ: &TcType, mut
             // the error itself will already have been reported
             if let Some(associated_type) = field_types.iter()
                 .find(|type_field| type_field.name.name_eq(&field.0)) {
-                f(&field.0, &field.1, &associated_type.typ);
+                f(&field.0, &mut field.1, &associated_type.typ);
             }
         }
     }
 }
 
+/// Instantiates the variables bound by a `forall` at the top of `typ` with fresh type variables
+fn instantiate_forall(subs: &Substitution<TcType>, typ: TcType) -> TcType {
+    let new_type = match **subs.real(&typ) {
+        Type::Forall(ref params, ref body) => {
+            let vars: Vec<TcType> = params.iter()
+                .map(|param| {
+                    let id = subs.new_var().get_var().expect("Variable").id;
+                    Type::variable(TypeVariable {
+                        kind: param.kind.clone(),
+                        id: id,
+                    })
+                })
+                .collect();
+            Some(instantiate::instantiate(body.clone(), |gen| {
+                params.iter()
+                    .zip(&vars)
+                    .find(|&(param, _)| param.id == gen.id)
+                    .map(|(_, var)| var.clone())
+            }))
+        }
+        _ => None,
+    };
+    match new_type {
+        Some(typ) => instantiate_forall(subs, typ),
+        None => typ,
+    }
+}
+
+/// Replaces the variables bound by a `forall` in `typ` with rigid variables (added to
+/// `skolems`). Only the positions which a value of type `typ` must provide are replaced, that is
+/// the type itself, the fields of records and the results of functions. A `forall` in a function
+/// argument is instead something that the value may rely on.
+///
+/// Returns `None` if `typ` does not contain any `forall` in those positions.
+fn skolemize(subs: &Substitution<TcType>,
+             typ: &TcType,
+             skolems: &mut Vec<Symbol>)
+             -> Option<TcType> {
+    match **typ {
+        Type::Forall(ref params, ref body) => {
+            let body =
+                instantiate::skolemize(params, body, skolems, |param| subs.new_skolem(param));
+            Some(skolemize(subs, &body, skolems).unwrap_or(body))
+        }
+        Type::Record { ref types, ref fields, ref rest } => {
+            types::walk_move_types(fields, |field| {
+                    skolemize(subs, &field.typ, skolems).map(|typ| {
+                        types::Field {
+                            name: field.name.clone(),
+                            typ: typ,
+                        }
+                    })
+                })
+                .map(|fields| types::record_with_rest(types.clone(), fields, rest.clone()))
+        }
+        _ => {
+            typ.as_function().and_then(|(arg, ret)| {
+                skolemize(subs, ret, skolems).map(|ret| Type::function(vec![arg.clone()], ret))
+            })
+        }
+    }
+}
+
+/// Returns true if `error` is only a consequence of an earlier error (an expression having the
+/// error type) and should therefore not be reported
+fn is_caused_by_error_type(error: &TypeError<Symbol>) -> bool {
+    match *error {
+        NotAFunction(ref typ) |
+        UndefinedField(ref typ, _) |
+        InvalidFieldAccess(ref typ) |
+        PatternError(ref typ, _) => typ.is_error(),
+        MissingImplicit(ref typ) |
+        AmbiguousImplicit(ref typ, _) => {
+            let mut contains_error = false;
+            types::walk_type(typ, |typ: &TcType| if typ.is_error() {
+                contains_error = true
+            });
+            contains_error
+        }
+        _ => false,
+    }
+}
+
 fn apply_subs(subs: &Substitution<TcType>,
               error: Vec<unify_type::Error<Symbol>>)
               -> Vec<unify_type::Error<Symbol>> {
@@ -1378,27 +2883,34 @@ fn get_alias_app<'a>(env: &'a TypeEnv,
     }
 }
 
+/// Iterates over the argument types of a function type. Each argument is paired with a flag
+/// which is true if the argument is implicit
 struct FunctionArgIter<'a, 'b: 'a> {
     tc: &'a mut Typecheck<'b>,
     typ: TcType,
 }
 
 impl<'a, 'b> Iterator for FunctionArgIter<'a, 'b> {
-    type Item = TcType;
-    fn next(&mut self) -> Option<TcType> {
+    type Item = (TcType, bool);
+    fn next(&mut self) -> Option<(TcType, bool)> {
         loop {
             let (arg, new) = match self.typ.as_function() {
-                Some((arg, ret)) => (Some(arg.clone()), ret.clone()),
+                Some((arg, ret)) => (Some((arg.clone(), false)), ret.clone()),
                 None => {
-                    match get_alias_app(&self.tc.environment, &self.typ) {
-                        Some((alias, args)) => {
-                            match self.tc.type_of_alias(alias, args) {
-                                Ok(Some(typ)) => (None, typ.clone()),
-                                Ok(None) => return None,
-                                Err(_) => return Some(self.tc.subs.new_var()),
+                    match self.typ.as_implicit_function() {
+                        Some((arg, ret)) => (Some((arg.clone(), true)), ret.clone()),
+                        None => {
+                            match get_alias_app(&self.tc.environment, &self.typ) {
+                                Some((alias, args)) => {
+                                    match self.tc.type_of_alias(alias, args) {
+                                        Ok(Some(typ)) => (None, typ.clone()),
+                                        Ok(None) => return None,
+                                        Err(_) => return Some((self.tc.subs.new_var(), false)),
+                                    }
+                                }
+                                None => return Some((self.tc.subs.new_var(), false)),
                             }
                         }
-                        None => return Some(self.tc.subs.new_var()),
                     }
                 }
             };
@@ -3,6 +3,7 @@ use std::hash::Hash;
 
 use base::error::Errors;
 use base::fnv::FnvMap;
+use base::symbol::Symbol;
 use substitution::{Substitution, Substitutable, Variable};
 
 #[derive(Debug, PartialEq)]
@@ -49,6 +50,20 @@ impl<S, U> UnifierState<S, U> {
     {
         Unifier::try_match(self, l, r)
     }
+
+    pub fn new_var<Type>(&mut self) -> Type
+        where U: Unifier<S, Type>,
+              Type: Unifiable<S>
+    {
+        Unifier::new_var(self)
+    }
+
+    pub fn real<Type>(&self, typ: &Type) -> Type
+        where U: Unifier<S, Type>,
+              Type: Unifiable<S>
+    {
+        Unifier::real(self, typ)
+    }
 }
 
 /// A `Unifier` is a type which implements a unifying strategy between two values.
@@ -59,6 +74,14 @@ pub trait Unifier<S, Type>: Sized
     fn report_error(unifier: &mut UnifierState<S, Self>, error: Error<Type, Type::Error>);
     /// Attempt to unify `l` and `r` using the strategy of `Self`.
     fn try_match(unifier: &mut UnifierState<S, Self>, l: &Type, r: &Type) -> Option<Type>;
+    /// Creates a new type variable which can be used when unifying requires an unknown type (such
+    /// as the remaining fields of two open records).
+    fn new_var(unifier: &mut UnifierState<S, Self>) -> Type;
+    /// Returns the type that `typ` has been unified with if `typ` is a type variable
+    fn real(unifier: &UnifierState<S, Self>, typ: &Type) -> Type;
+    /// Creates the name of a new skolem for the variable `param` which is used when checking
+    /// that a type is at least as polymorphic as another type
+    fn new_skolem(unifier: &mut UnifierState<S, Self>, param: &Symbol) -> Symbol;
 }
 
 /// A type which can be unified by checking for equivalence between the top level of
@@ -159,6 +182,18 @@ impl<'e, S, T> Unifier<S, T> for Unify<'e, T, T::Error>
             }
         }
     }
+
+    fn new_var(unifier: &mut UnifierState<S, Self>) -> T {
+        unifier.unifier.subs.new_var()
+    }
+
+    fn real(unifier: &UnifierState<S, Self>, typ: &T) -> T {
+        unifier.unifier.subs.real(typ).clone()
+    }
+
+    fn new_skolem(unifier: &mut UnifierState<S, Self>, param: &Symbol) -> Symbol {
+        unifier.unifier.subs.new_skolem(param)
+    }
 }
 
 /// Calculates the intersection between two types. The intersection between two types is the most
@@ -214,6 +249,18 @@ impl<'m, S, T> Unifier<S, T> for Intersect<'m, T>
             }
         }
     }
+
+    fn new_var(unifier: &mut UnifierState<S, Self>) -> T {
+        unifier.unifier.subs.new_var()
+    }
+
+    fn real(unifier: &UnifierState<S, Self>, typ: &T) -> T {
+        unifier.unifier.subs.real(typ).clone()
+    }
+
+    fn new_skolem(unifier: &mut UnifierState<S, Self>, param: &Symbol) -> Symbol {
+        unifier.unifier.subs.new_skolem(param)
+    }
 }
 
 #[cfg(test)]
@@ -115,6 +115,13 @@ impl<'a> Unifiable<State<'a>> for TcType {
                     -> Result<Option<Self>, Error<Symbol>>
         where U: Unifier<State<'a>, Self>
     {
+        // The error type matches anything as the error which caused it has already been reported
+        if self.is_error() {
+            return Ok(Some(other.clone()));
+        }
+        if other.is_error() {
+            return Ok(None);
+        }
         let reduced_aliases = unifier.state.reduced_aliases.len();
         debug!("{:?} <=> {:?}", self, other);
         let (l_temp, r_temp);
@@ -178,9 +185,9 @@ fn do_zip_match<'a, U>(self_: &TcType,
                 }
             }
         }
-        (&Type::Record { fields: ref l_args, types: ref l_types },
-         &Type::Record { fields: ref r_args, types: ref r_types }) if l_args.len() == r_args.len() &&
-                                                                     l_types == r_types => {
+        (&Type::Record { fields: ref l_args, types: ref l_types, rest: None },
+         &Type::Record { fields: ref r_args, types: ref r_types, rest: None })
+            if l_args.len() == r_args.len() && l_types == r_types => {
             let args = walk_move_types(l_args.iter().zip(r_args.iter()), |l, r| {
                 let opt_type = if !l.name.name_eq(&r.name) {
 
@@ -199,10 +206,62 @@ fn do_zip_match<'a, U>(self_: &TcType,
             });
             Ok(args.map(|args| Type::record(l_types.clone(), args)))
         }
+        (&Type::Record { fields: ref l_args, types: ref l_types, rest: ref l_rest },
+         &Type::Record { fields: ref r_args, types: ref r_types, rest: ref r_rest })
+            if (l_rest.is_some() || r_rest.is_some()) && l_types == r_types => {
+            try!(zip_rows(self_, l_args, l_rest, other, r_args, r_rest, unifier));
+            Ok(None)
+        }
         (&Type::Id(ref id), &Type::Alias(ref alias)) if *id == alias.name => {
             Ok(Some(other.clone()))
         }
         (&Type::Alias(ref alias), &Type::Id(ref id)) if *id == alias.name => Ok(None),
+        (&Type::Forall(ref l_params, ref l_typ), &Type::Forall(ref r_params, ref r_typ))
+            if l_params.len() == r_params.len() => {
+            // Two polymorphic types are equal if their bodies are equal once the bound variables
+            // of both types are replaced by the same rigid variables
+            let mut skolems = Vec::new();
+            let l_typ = instantiate::skolemize(l_params,
+                                               l_typ,
+                                               &mut skolems,
+                                               |param| U::new_skolem(unifier, param));
+            let r_typ = instantiate::instantiate(r_typ.clone(), |gen| {
+                r_params.iter()
+                    .zip(l_params.iter().zip(&skolems))
+                    .find(|&(param, _)| param.id == gen.id)
+                    .map(|(_, (l_param, skolem))| {
+                        Type::generic(types::Generic {
+                            kind: l_param.kind.clone(),
+                            id: skolem.clone(),
+                        })
+                    })
+            });
+            unifier.try_match(&l_typ, &r_typ);
+            Ok(None)
+        }
+        (&Type::Forall(ref params, ref typ), _) => {
+            // `other` must be at least as polymorphic as `self` which is only the case if it can
+            // be unified with `self` where the bound variables can't be unified with any type
+            let mut skolems = Vec::new();
+            let typ = instantiate::skolemize(params,
+                                             typ,
+                                             &mut skolems,
+                                             |param| U::new_skolem(unifier, param));
+            unifier.try_match(&typ, other);
+            Ok(None)
+        }
+        (_, &Type::Forall(ref params, ref typ)) => {
+            // A polymorphic value can be used at any instance of its type
+            let vars: Vec<TcType> = params.iter().map(|_| unifier.new_var()).collect();
+            let typ = instantiate::instantiate(typ.clone(), |gen| {
+                params.iter()
+                    .zip(&vars)
+                    .find(|&(param, _)| param.id == gen.id)
+                    .map(|(_, var)| var.clone())
+            });
+            unifier.try_match(self_, &typ);
+            Ok(None)
+        }
         _ => {
             if self_ == other {
                 // Successful unification
@@ -214,6 +273,86 @@ fn do_zip_match<'a, U>(self_: &TcType,
     }
 }
 
+/// Unifies the fields of two records where at least one of the records is open. Fields which
+/// exist in both records are unified with each other while the fields which only exist in one of
+/// the records are unified with the rest of the other record.
+fn zip_rows<'a, U>(l: &TcType,
+                   l_fields: &[types::Field<Symbol, TcType>],
+                   l_rest: &Option<TcType>,
+                   r: &TcType,
+                   r_fields: &[types::Field<Symbol, TcType>],
+                   r_rest: &Option<TcType>,
+                   unifier: &mut UnifierState<'a, U>)
+                   -> Result<(), Error<Symbol>>
+    where U: Unifier<State<'a>, TcType>
+{
+    fn missing_from(fields: &[types::Field<Symbol, TcType>],
+                    other: &[types::Field<Symbol, TcType>])
+                    -> Vec<types::Field<Symbol, TcType>> {
+        fields.iter()
+            .filter(|field| !other.iter().any(|o| o.name.name_eq(&field.name)))
+            .cloned()
+            .collect()
+    }
+    let l_only = missing_from(l_fields, r_fields);
+    let r_only = missing_from(r_fields, l_fields);
+    // A closed record can't be extended with the fields it is missing
+    if (l_rest.is_none() && !r_only.is_empty()) || (r_rest.is_none() && !l_only.is_empty()) {
+        return Err(UnifyError::TypeMismatch(l.clone(), r.clone()));
+    }
+    for l_field in l_fields {
+        if let Some(r_field) = r_fields.iter().find(|r| r.name.name_eq(&l_field.name)) {
+            unifier.try_match(&l_field.typ, &r_field.typ);
+        }
+    }
+    match (l_rest.as_ref(), r_rest.as_ref()) {
+        (Some(l_rest), None) => {
+            unifier.try_match(l_rest, &Type::record(vec![], r_only));
+        }
+        (None, Some(r_rest)) => {
+            unifier.try_match(&Type::record(vec![], l_only), r_rest);
+        }
+        (Some(l_rest), Some(r_rest)) => {
+            if l_only.is_empty() && r_only.is_empty() {
+                unifier.try_match(l_rest, r_rest);
+            } else if l_only.is_empty() {
+                unifier.try_match(l_rest, &Type::poly_record(vec![], r_only, r_rest.clone()));
+            } else if r_only.is_empty() {
+                unifier.try_match(&Type::poly_record(vec![], l_only, l_rest.clone()), r_rest);
+            } else {
+                // If both rows end in the same variable neither of them can contain the fields
+                // that only the other record has. Unifying them with a shared rest would only
+                // recreate the same situation with a new variable.
+                if row_tail(unifier, l_rest) == row_tail(unifier, r_rest) {
+                    return Err(UnifyError::TypeMismatch(l.clone(), r.clone()));
+                }
+                // Both records have fields that the other lacks so both of the rests are unified
+                // with a record containing those fields and a new, shared rest
+                let rest: TcType = unifier.new_var();
+                unifier.try_match(l_rest, &Type::poly_record(vec![], r_only, rest.clone()));
+                unifier.try_match(&Type::poly_record(vec![], l_only, rest), r_rest);
+            }
+        }
+        (None, None) => unreachable!("zip_rows called with two closed records"),
+    }
+    Ok(())
+}
+
+/// Returns the type which ends the row `rest`, following the rests of any records which the
+/// variables of the row have been unified with
+fn row_tail<'a, U>(unifier: &UnifierState<'a, U>, rest: &TcType) -> TcType
+    where U: Unifier<State<'a>, TcType>
+{
+    let mut rest = unifier.real(rest);
+    loop {
+        let next = match *rest {
+            Type::Record { rest: Some(ref next), .. } => unifier.real(next),
+            _ => return rest,
+        };
+        rest = next;
+    }
+}
+
 /// Attempt to unify two alias types.
 /// To find a possible successful unification we walk through the alias expansions of `l` to find
 /// an expansion which has `r_id` in the spine of the expanded type
@@ -485,6 +624,18 @@ impl<'a, 'e> Unifier<State<'a>, TcType> for Merge<'e> {
             }
         }
     }
+
+    fn new_var(unifier: &mut UnifierState<Self>) -> TcType {
+        unifier.unifier.subs.new_var()
+    }
+
+    fn real(unifier: &UnifierState<Self>, typ: &TcType) -> TcType {
+        unifier.unifier.subs.real(typ).clone()
+    }
+
+    fn new_skolem(unifier: &mut UnifierState<Self>, param: &Symbol) -> Symbol {
+        unifier.unifier.subs.new_skolem(param)
+    }
 }
 
 #[cfg(test)]
@@ -4,7 +4,6 @@ extern crate gluon_base as base;
 extern crate gluon_parser as parser;
 extern crate gluon_check as check;
 
-use base::ast;
 use base::pos::{BytePos, CharPos, Location};
 use base::types::{Type, TcType};
 use check::completion;
@@ -13,22 +12,20 @@ mod support;
 use support::{MockEnv, typ};
 
 fn find_type(s: &str, location: Location) -> Result<TcType, ()> {
-    let env = ast::EmptyEnv::new();
     let typ_env = MockEnv::new();
 
     let (mut expr, result) = support::typecheck_expr(s);
     assert!(result.is_ok(), "{}", result.unwrap_err());
 
-    completion::find(&env, &typ_env, &mut expr, location)
+    completion::find(&typ_env, &mut expr, location)
 }
 
 fn suggest(s: &str, location: Location) -> Result<Vec<String>, ()> {
-    let env = ast::EmptyEnv::new();
     let typ_env = MockEnv::new();
 
     let (mut expr, _result) = support::typecheck_partial_expr(s);
     let mut vec: Vec<String> = {
-        completion::suggest(&env, &typ_env, &mut expr, location)
+        completion::suggest(&typ_env, &mut expr, location)
             .into_iter()
             .map(|ident| ident.name.declared_name().to_string())
             .collect()
@@ -40,14 +37,12 @@ fn suggest(s: &str, location: Location) -> Result<Vec<String>, ()> {
 
 #[test]
 fn identifier() {
-    let env = ast::EmptyEnv::new();
     let typ_env = MockEnv::new();
 
     let (mut expr, result) = support::typecheck_expr("let abc = 1 in abc");
     assert!(result.is_ok(), "{}", result.unwrap_err());
 
-    let result = completion::find(&env,
-                                  &typ_env,
+    let result = completion::find(&typ_env,
                                   &mut expr,
                                   Location {
                                       line: 1,
@@ -57,8 +52,7 @@ fn identifier() {
     let expected = Ok(typ("Int"));
     assert_eq!(result, expected);
 
-    let result = completion::find(&env,
-                                  &typ_env,
+    let result = completion::find(&typ_env,
                                   &mut expr,
                                   Location {
                                       line: 1,
@@ -68,8 +62,7 @@ fn identifier() {
     let expected = Ok(typ("Int"));
     assert_eq!(result, expected);
 
-    let result = completion::find(&env,
-                                  &typ_env,
+    let result = completion::find(&typ_env,
                                   &mut expr,
                                   Location {
                                       line: 1,
@@ -79,8 +72,7 @@ fn identifier() {
     let expected = Ok(typ("Int"));
     assert_eq!(result, expected);
 
-    let result = completion::find(&env,
-                                  &typ_env,
+    let result = completion::find(&typ_env,
                                   &mut expr,
                                   Location {
                                       line: 1,
@@ -139,7 +131,6 @@ let f x = f x
 
 #[test]
 fn binop() {
-    let env = ast::EmptyEnv::new();
     let typ_env = MockEnv::new();
 
     let (mut expr, result) = support::typecheck_expr(r#"
@@ -151,8 +142,7 @@ let (++) l r =
 "#);
     assert!(result.is_ok(), "{}", result.unwrap_err());
 
-    let result = completion::find(&env,
-                                  &typ_env,
+    let result = completion::find(&typ_env,
                                   &mut expr,
                                   Location {
                                       line: 6,
@@ -162,8 +152,7 @@ let (++) l r =
     let expected = Ok(Type::function(vec![typ("Int"), typ("Float")], typ("Int")));
     assert_eq!(result, expected);
 
-    let result = completion::find(&env,
-                                  &typ_env,
+    let result = completion::find(&typ_env,
                                   &mut expr,
                                   Location {
                                       line: 6,
@@ -173,8 +162,7 @@ let (++) l r =
     let expected = Ok(typ("Int"));
     assert_eq!(result, expected);
 
-    let result = completion::find(&env,
-                                  &typ_env,
+    let result = completion::find(&typ_env,
                                   &mut expr,
                                   Location {
                                       line: 6,
@@ -320,3 +308,21 @@ record.aa
 
     assert_eq!(result, expected);
 }
+
+#[test]
+fn suggest_after_syntax_error() {
+    let result = suggest(r#"
+let aa = 1
+let ab = 1 +
+let ac = ""
+a
+"#,
+                         Location {
+                             line: 5,
+                             column: CharPos(2),
+                             absolute: BytePos(0),
+                         });
+    let expected = Ok(vec!["aa".into(), "ab".into(), "ac".into()]);
+
+    assert_eq!(result, expected);
+}
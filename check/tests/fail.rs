@@ -52,7 +52,7 @@ macro_rules! assert_unify_err {
             Err(err) => {
                 for err in err.errors.iter() {
                     match *err {
-                        Spanned { value: Unification(_, _, ref errors), .. } => {
+                        Spanned { value: Unification(_, _, ref errors, _), .. } => {
                             let mut iter = errors.iter();
                             $(
                             match iter.next() {
@@ -132,6 +132,42 @@ in 1
     assert_err!(result, KindError(TypeMismatch(..)));
 }
 
+#[test]
+fn record_update_field_type_mismatch() {
+    let _ = env_logger::init();
+    let text = r#"
+let r = { x = 1 }
+{ r | x = "" }
+"#;
+    let result = support::typecheck(text);
+
+    assert_err!(result, Unification(..));
+}
+
+#[test]
+fn record_extension_of_polymorphic_record_with_existing_field() {
+    let _ = env_logger::init();
+    let text = r#"
+let add_x r = { r | x = 1 }
+add_x { x = "", y = 2 }
+"#;
+    let result = support::typecheck(text);
+
+    assert_err!(result, Unification(..));
+}
+
+#[test]
+fn open_records_with_the_same_rest_and_different_fields() {
+    let _ = env_logger::init();
+    let text = r#"
+let f x y : { a : Int | r } -> { b : Int | r } -> Int = 1
+\r -> f r r
+"#;
+    let result = support::typecheck(text);
+
+    assert_err!(result, Unification(..));
+}
+
 #[test]
 fn unpack_field_which_does_not_exist() {
     let _ = env_logger::init();
@@ -214,6 +250,88 @@ else
     assert_unify_err!(result, TypeMismatch(..));
 }
 
+#[test]
+fn literal_pattern_mismatch() {
+    let _ = env_logger::init();
+    let text = r#"
+match 1 with
+| "a" -> 1
+| x -> 2
+"#;
+    let result = support::typecheck(text);
+
+    assert_unify_err!(result, TypeMismatch(..));
+}
+
+#[test]
+fn constructor_pattern_too_many_arguments() {
+    let _ = env_logger::init();
+    let text = r#"
+type Test = | A Int in
+match A 1 with
+| A x y -> x
+"#;
+    let result = support::typecheck(text);
+
+    assert_err!(result, PatternError(..));
+}
+
+#[test]
+fn guard_must_be_bool() {
+    let _ = env_logger::init();
+    let text = r#"
+match 1 with
+| x if 1 -> x
+| _ -> 0
+"#;
+    let result = support::typecheck(text);
+
+    assert_unify_err!(result, TypeMismatch(..));
+}
+
+#[test]
+fn missing_implicit_instance() {
+    let _ = env_logger::init();
+    let text = r#"
+type Eq a = { eq: a -> a -> Int }
+let implicit eq_Int : Eq Int = { eq = \l r -> 1 }
+let equal e : [Eq a] -> a -> a -> Int = e.eq
+equal "a" "b"
+"#;
+    let result = support::typecheck(text);
+
+    assert_err!(result, MissingImplicit(..));
+}
+
+#[test]
+fn ambiguous_implicit_instance() {
+    let _ = env_logger::init();
+    let text = r#"
+type Eq a = { eq: a -> a -> Int }
+let implicit eq_Int : Eq Int = { eq = \l r -> 1 }
+let implicit eq_Int2 : Eq Int = { eq = \l r -> 2 }
+let equal e : [Eq a] -> a -> a -> Int = e.eq
+equal 1 2
+"#;
+    let result = support::typecheck(text);
+
+    assert_err!(result, AmbiguousImplicit(..));
+}
+
+#[test]
+fn do_without_monad_instance() {
+    let _ = env_logger::init();
+    let text = r#"
+type Option a = | None | Some a
+type Monad m = { flat_map : (a -> m b) -> m a -> m b }
+do x = Some 1
+Some x
+"#;
+    let result = support::typecheck(text);
+
+    assert_err!(result, MissingImplicit(..));
+}
+
 #[test]
 fn arguments_need_to_be_instantiated_before_any_access() {
     let _ = env_logger::init();
@@ -308,3 +426,229 @@ make
     let result = support::typecheck(text);
     assert!(result.is_err());
 }
+
+#[test]
+fn report_multiple_independent_errors() {
+    let _ = ::env_logger::init();
+    let text = r#"
+let x : Int = "a"
+let y : String = 1
+let z = x #Int+ y
+x
+"#;
+    let result = support::typecheck(text);
+
+    assert_err!(result, Unification(..), Unification(..), Unification(..));
+}
+
+#[test]
+fn no_follow_on_errors_from_error_type() {
+    let _ = ::env_logger::init();
+    let text = r#"
+let x = "" 1
+let y = x.field
+let z = x 1
+match x with
+| { field } -> field
+"#;
+    let result = support::typecheck(text);
+
+    assert_err!(result, Unification(..));
+}
+
+#[test]
+fn report_errors_in_each_alternative() {
+    let _ = ::env_logger::init();
+    let text = r#"
+match 1 with
+| 1 -> 1
+| 2 -> ""
+| _ -> 3.0
+"#;
+    let result = support::typecheck(text);
+
+    assert_err!(result, Unification(..), Unification(..));
+}
+
+#[test]
+fn reference_is_not_generalized() {
+    let _ = ::env_logger::init();
+    let text = r#"
+let r = ref (\x -> x)
+let a : Ref (Int -> Int) = r
+let b : Ref (String -> String) = r
+()
+"#;
+    let result = support::typecheck(text);
+
+    assert_err!(result, Unification(..));
+}
+
+#[test]
+fn reference_captured_in_closure_is_not_generalized() {
+    let _ = ::env_logger::init();
+    let text = r#"
+let get =
+    let r = ref (\x -> x)
+    \_ -> r
+let a : Ref (Int -> Int) = get ()
+let b : Ref (String -> String) = get ()
+()
+"#;
+    let result = support::typecheck(text);
+
+    assert_err!(result, Unification(..));
+}
+
+#[test]
+fn reference_with_variables_can_not_escape_the_expression() {
+    let _ = ::env_logger::init();
+    let text = r#"
+ref (\x -> x)
+"#;
+    let result = support::typecheck(text);
+
+    assert_err!(result, UngeneralizableType(..));
+}
+
+#[test]
+fn forall_argument_is_not_polymorphic_enough() {
+    let _ = ::env_logger::init();
+    let text = r#"
+let f r : { id : forall a . a -> a } -> Int = r.id 1
+f { id = \x -> x #Int+ 1 }
+"#;
+    let result = support::typecheck(text);
+
+    assert_err!(result, Unification(..));
+}
+
+#[test]
+fn forall_type_variable_can_not_escape() {
+    let _ = ::env_logger::init();
+    let text = r#"
+let f g : (forall a . a -> a) -> () = ()
+let h y = f (\x -> y)
+h
+"#;
+    let result = support::typecheck(text);
+
+    assert_err!(result, EscapingTypeVariable(..));
+}
+
+#[test]
+fn abstract_type_hides_its_definition() {
+    let _ = ::env_logger::init();
+    let text = r#"
+let m =
+    type T = Int
+    let make x : Int -> T = x
+    { T, make } : { type T, make : Int -> T }
+m.make 1 #Int+ 1
+"#;
+    let result = support::typecheck(text);
+
+    assert_err!(result, Unification(..));
+}
+
+#[test]
+fn annotation_requires_the_types_it_mentions() {
+    let _ = ::env_logger::init();
+    let text = r#"
+{ x = 1 } : { type T, x : Int }
+"#;
+    let result = support::typecheck(text);
+
+    assert_err!(result, UndefinedField(..));
+}
+
+#[test]
+fn annotation_type_definition_must_match_the_record() {
+    let _ = ::env_logger::init();
+    let text = r#"
+type T = Int
+{ T } : { type T = String }
+"#;
+    let result = support::typecheck(text);
+
+    assert_err!(result, Unification(..));
+}
+
+#[test]
+fn annotation_requires_the_fields_it_mentions() {
+    let _ = ::env_logger::init();
+    let text = r#"
+{ x = 1 } : { x : Int, y : Int }
+"#;
+    let result = support::typecheck(text);
+
+    assert_err!(result, UndefinedField(..));
+}
+
+#[test]
+fn kind_annotation_mismatch() {
+    let _ = env_logger::init();
+    let text = r#"
+type Test (a : Type) = a Int
+in 1
+"#;
+    let result = support::typecheck(text);
+
+    assert_err!(result, KindError(TypeMismatch(..)));
+}
+
+#[test]
+fn argument_mismatch_points_to_the_function() {
+    let _ = env_logger::init();
+    let text = r#"
+let f x : Int -> Int = x
+f "abc"
+"#;
+    let err = support::typecheck(text).unwrap_err();
+
+    assert_eq!(err.errors.len(), 1);
+    match err.errors[0].value {
+        check::typecheck::TypeError::Unification(_, _, _, Some(origin)) => {
+            assert_eq!((origin.start.line, origin.start.column.to_usize()), (3, 1));
+            assert_eq!(err.errors[0].span.start.column.to_usize(), 3);
+        }
+        ref err => panic!("Unexpected error {}", err),
+    }
+}
+
+#[test]
+fn refutable_constructor_pattern_in_let() {
+    let _ = env_logger::init();
+    let text = r#"
+type Option a = | None | Some a
+let Some x = None
+x
+"#;
+    let result = support::typecheck(text);
+
+    assert_err!(result, RefutablePattern(..));
+}
+
+#[test]
+fn refutable_literal_pattern_in_let() {
+    let _ = env_logger::init();
+    let text = r#"
+let 1 = 2
+()
+"#;
+    let result = support::typecheck(text);
+
+    assert_err!(result, RefutablePattern(..));
+}
+
+#[test]
+fn calling_an_expression_of_the_error_type_does_not_report_another_error() {
+    let _ = env_logger::init();
+    let text = r#"
+let f x : Int -> Int = x
+(f 1 "") 2
+"#;
+    let result = support::typecheck(text);
+
+    assert_err!(result, Unification(..));
+}
@@ -0,0 +1,215 @@
+extern crate env_logger;
+
+extern crate gluon_base as base;
+extern crate gluon_parser as parser;
+extern crate gluon_check as check;
+
+use check::lint::{lint, WarningCode};
+
+mod support;
+
+fn warnings(text: &str) -> Vec<(WarningCode, String)> {
+    let (mut expr, result, typecheck_warnings) = support::typecheck_expr_warnings(text);
+
+    assert!(result.is_ok(), "{}", result.unwrap_err());
+
+    typecheck_warnings.into_iter()
+        .chain(lint(&mut expr))
+        .map(|warning| (warning.value.code, String::from(warning.value.name.as_ref())))
+        .collect()
+}
+
+#[test]
+fn unused_let_binding() {
+    let _ = env_logger::init();
+
+    let text = r#"
+let x = 1
+let y = 2
+y
+"#;
+    assert_eq!(warnings(text),
+               vec![(WarningCode::UnusedBinding, "x".to_string())]);
+}
+
+#[test]
+fn unused_function_argument() {
+    let _ = env_logger::init();
+
+    let text = r#"
+let f x y = x
+f 1 (\z -> 2)
+"#;
+    assert_eq!(warnings(text),
+               vec![(WarningCode::UnusedVariable, "y".to_string()),
+                    (WarningCode::UnusedVariable, "z".to_string())]);
+}
+
+#[test]
+fn unused_function_argument_span() {
+    let _ = env_logger::init();
+
+    let text = r#"
+let f x y = x
+f 1 (\x z -> x)
+"#;
+    let (mut expr, result, _) = support::typecheck_expr_warnings(text);
+    assert!(result.is_ok(), "{}", result.unwrap_err());
+
+    let locations: Vec<_> = lint(&mut expr)
+        .into_iter()
+        .map(|warning| (warning.span.start.line, warning.span.start.column.to_usize()))
+        .collect();
+    assert_eq!(locations, vec![(2, 9), (3, 9)]);
+}
+
+#[test]
+fn unused_pattern_variable_span_covers_the_name() {
+    let _ = env_logger::init();
+
+    let text = r#"
+type Option a = | None | Some a
+let (first, second) = (1, 2)
+match Some second with
+| Some value -> 0
+| None -> second
+"#;
+    let (mut expr, result, _) = support::typecheck_expr_warnings(text);
+    assert!(result.is_ok(), "{}", result.unwrap_err());
+
+    let spans: Vec<_> = lint(&mut expr)
+        .into_iter()
+        .map(|warning| {
+            (warning.span.start.line,
+             warning.span.start.column.to_usize(),
+             warning.span.end.column.to_usize())
+        })
+        .collect();
+    assert_eq!(spans, vec![(3, 6, 11), (5, 8, 13)]);
+}
+
+#[test]
+fn unused_pattern_variable() {
+    let _ = env_logger::init();
+
+    let text = r#"
+type Test = | A Int Int
+let { x, y } = { x = 1, y = 2 }
+match A x 3 with
+| A a b -> a
+"#;
+    assert_eq!(warnings(text),
+               vec![(WarningCode::UnusedVariable, "y".to_string()),
+                    (WarningCode::UnusedVariable, "b".to_string())]);
+}
+
+#[test]
+fn underscore_prefix_silences_warnings() {
+    let _ = env_logger::init();
+
+    let text = r#"
+let _x = 1
+let f _y = 2
+f 3
+"#;
+    assert_eq!(warnings(text), vec![]);
+}
+
+#[test]
+fn shadowed_binding() {
+    let _ = env_logger::init();
+
+    let text = r#"
+let x = 1
+let x = x
+x
+"#;
+    assert_eq!(warnings(text),
+               vec![(WarningCode::ShadowedBinding, "x".to_string())]);
+}
+
+#[test]
+fn overloaded_binding_is_not_shadowed() {
+    let _ = env_logger::init();
+
+    let text = r#"
+let x = 1
+let x = ""
+let y : Int = x
+let z : String = x
+{ y, z }
+"#;
+    assert_eq!(warnings(text), vec![]);
+}
+
+#[test]
+fn non_exhaustive_match() {
+    let _ = env_logger::init();
+    let text = r#"
+type Option a = | None | Some a in
+type List a = | Nil | Cons a (List a) in
+match Nil with
+| Cons (Some x) Nil -> x
+| Nil -> 0
+"#;
+    assert_eq!(warnings(text),
+               vec![(WarningCode::NonExhaustiveMatch, "Cons None _".to_string()),
+                    (WarningCode::NonExhaustiveMatch, "Cons (Some _) (Cons _ _)".to_string())]);
+}
+
+#[test]
+fn non_exhaustive_match_reports_a_limited_number_of_patterns() {
+    let _ = env_logger::init();
+    let text = r#"
+type B = | F | T in
+match (T, T, T, T, T, T, T, T, T, T, T, T) with
+| (T, T, T, T, T, T, T, T, T, T, T, T) -> 0
+"#;
+    assert_eq!(warnings(text),
+               vec![(WarningCode::NonExhaustiveMatch,
+                     "(F, _, _, _, _, _, _, _, _, _, _, _)".to_string()),
+                    (WarningCode::NonExhaustiveMatch,
+                     "(T, F, _, _, _, _, _, _, _, _, _, _)".to_string()),
+                    (WarningCode::NonExhaustiveMatch,
+                     "(T, T, F, _, _, _, _, _, _, _, _, _)".to_string()),
+                    (WarningCode::NonExhaustiveMatch, "…".to_string())]);
+}
+
+#[test]
+fn non_exhaustive_literal_match() {
+    let _ = env_logger::init();
+    let text = r#"
+match 1 with
+| 0 -> 1
+| 1 -> 2
+"#;
+    assert_eq!(warnings(text),
+               vec![(WarningCode::NonExhaustiveMatch, "_".to_string())]);
+}
+
+#[test]
+fn guarded_alternative_is_not_exhaustive() {
+    let _ = env_logger::init();
+    let text = r#"
+type Option a = | None | Some a in
+match Some 1 with
+| Some x if False -> x
+| None -> 0
+"#;
+    assert_eq!(warnings(text),
+               vec![(WarningCode::NonExhaustiveMatch, "Some _".to_string())]);
+}
+
+#[test]
+fn unreachable_alternative() {
+    let _ = env_logger::init();
+    let text = r#"
+type Option a = | None | Some a in
+match Some 1 with
+| Some x -> x
+| None -> 0
+| Some 1 -> 1
+"#;
+    assert_eq!(warnings(text),
+               vec![(WarningCode::UnreachableAlternative, "Some 1".to_string())]);
+}
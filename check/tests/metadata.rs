@@ -4,6 +4,7 @@ extern crate gluon_base as base;
 extern crate gluon_parser as parser;
 extern crate gluon_check as check;
 
+use base::ast::{Associativity, Fixity};
 use base::metadata::Metadata;
 use check::metadata::metadata;
 
@@ -26,6 +27,8 @@ id
     assert_eq!(metadata,
                Metadata {
                    comment: Some("The identity function".into()),
+                   fixity: None,
+                   implicit: false,
                    module: Default::default(),
                });
 }
@@ -47,6 +50,8 @@ let id x = x
     assert_eq!(metadata.module.get("id"),
                Some(&Metadata {
                    comment: Some("The identity function".into()),
+                   fixity: None,
+                   implicit: false,
                    module: Default::default(),
                }));
 }
@@ -68,6 +73,56 @@ type Test = Int
     assert_eq!(metadata.module.get("Test"),
                Some(&Metadata {
                    comment: Some("A test type".into()),
+                   fixity: None,
+                   implicit: false,
+                   module: Default::default(),
+               }));
+}
+
+#[test]
+fn propagate_metadata_fixity() {
+    let _ = env_logger::init();
+
+    let text = r#"
+infixr 2 <|>
+let (<|>) l r = l
+{ (<|>) }
+"#;
+    let (mut expr, result) = support::typecheck_expr(text);
+
+    assert!(result.is_ok(), "{}", result.unwrap_err());
+
+    let metadata = metadata(&(), &mut expr);
+    assert_eq!(metadata.module.get("<|>"),
+               Some(&Metadata {
+                   comment: None,
+                   fixity: Some(Fixity {
+                       associativity: Associativity::Right,
+                       precedence: 2,
+                   }),
+                   implicit: false,
+                   module: Default::default(),
+               }));
+}
+
+#[test]
+fn propagate_metadata_implicit() {
+    let _ = env_logger::init();
+
+    let text = r#"
+let implicit x = 1
+{ x }
+"#;
+    let (mut expr, result) = support::typecheck_expr(text);
+
+    assert!(result.is_ok(), "{}", result.unwrap_err());
+
+    let metadata = metadata(&(), &mut expr);
+    assert_eq!(metadata.module.get("x"),
+               Some(&Metadata {
+                   comment: None,
+                   fixity: None,
+                   implicit: true,
                    module: Default::default(),
                }));
 }
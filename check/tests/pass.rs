@@ -476,6 +476,53 @@ in y
     assert_eq!(result, expected);
 }
 
+#[test]
+fn nested_pattern() {
+    let _ = env_logger::init();
+
+    let text = r#"
+type Option a = | None | Some a in
+type Pair a b = | Pair a b in
+match Some (Pair 1 "") with
+| Some (Pair x "") -> x
+| Some (Pair x y) -> 0
+| None -> 0
+"#;
+    let result = support::typecheck(text);
+    let expected = Ok(typ("Int"));
+
+    assert_eq!(result, expected);
+}
+
+#[test]
+fn literal_pattern() {
+    let _ = env_logger::init();
+
+    let text = r#"
+match "abc" with
+| "a" -> 1
+| x -> 2
+"#;
+    let result = support::typecheck(text);
+    let expected = Ok(typ("Int"));
+
+    assert_eq!(result, expected);
+}
+
+#[test]
+fn tuple_pattern() {
+    let _ = env_logger::init();
+
+    let text = r#"
+let (x, y) = (1, "")
+in y
+"#;
+    let result = support::typecheck(text);
+    let expected = Ok(typ("String"));
+
+    assert_eq!(result, expected);
+}
+
 #[test]
 fn type_pattern() {
     let _ = env_logger::init();
@@ -609,6 +656,181 @@ t.x
     assert_eq!(result, expected);
 }
 
+#[test]
+fn polymorphic_field_access() {
+    let _ = env_logger::init();
+
+    let text = r#"
+let get_x r = r.x
+{ a = get_x { x = 1 }, b = get_x { y = "", x = 2.0 } }
+"#;
+    let result = support::typecheck(text);
+    let fields = vec![
+        Field {
+            name: intern("a"),
+            typ: typ("Int"),
+        },
+        Field {
+            name: intern("b"),
+            typ: typ("Float"),
+        },
+    ];
+    let expected = Ok(Type::record(vec![], fields));
+
+    assert_eq!(result, expected);
+}
+
+#[test]
+fn open_record_type_annotation() {
+    let _ = env_logger::init();
+
+    let text = r#"
+let get_x : { x : Int | r } -> Int = \r -> r.x
+get_x { y = "", x = 1 }
+"#;
+    let result = support::typecheck(text);
+    let expected = Ok(typ("Int"));
+
+    assert_eq!(result, expected);
+}
+
+#[test]
+fn record_update() {
+    let _ = env_logger::init();
+
+    let text = r#"
+type Test = { x: Int, y: String }
+let t: Test = { x = 1, y = "" }
+let u: Test = { t | x = 2 }
+u.y
+"#;
+    let result = support::typecheck(text);
+    let expected = Ok(typ("String"));
+
+    assert_eq!(result, expected);
+}
+
+#[test]
+fn record_extension() {
+    let _ = env_logger::init();
+
+    let text = r#"
+{ { x = 1 } | y = "" }
+"#;
+    let result = support::typecheck(text);
+    let fields = vec![
+        Field {
+            name: intern("x"),
+            typ: typ("Int"),
+        },
+        Field {
+            name: intern("y"),
+            typ: typ("String"),
+        },
+    ];
+    let expected = Ok(Type::record(vec![], fields));
+
+    assert_eq!(result, expected);
+}
+
+#[test]
+fn record_update_of_open_record() {
+    let _ = env_logger::init();
+
+    let text = r#"
+let set_y r = { r | y = 1 }
+(set_y { x = "", y = 2 }).x
+"#;
+    let result = support::typecheck(text);
+    let expected = Ok(typ("String"));
+
+    assert_eq!(result, expected);
+}
+
+#[test]
+fn implicit_argument() {
+    let _ = env_logger::init();
+
+    let text = r#"
+type Eq a = { eq: a -> a -> Int }
+let implicit eq_Int : Eq Int = { eq = \l r -> 1 }
+let implicit eq_String : Eq String = { eq = \l r -> 2 }
+let equal e : [Eq a] -> a -> a -> Int = e.eq
+equal "a" "b"
+"#;
+    let result = support::typecheck(text);
+    let expected = Ok(typ("Int"));
+
+    assert_eq!(result, expected);
+}
+
+#[test]
+fn implicit_instance_with_implicit_argument() {
+    let _ = env_logger::init();
+
+    let text = r#"
+type Eq a = { eq: a -> a -> Int }
+let implicit eq_Int : Eq Int = { eq = \l r -> 1 }
+let implicit eq_Array e : [Eq a] -> Eq (Array a) = { eq = \l r -> 2 }
+let equal e : [Eq a] -> a -> a -> Int = e.eq
+equal [1] [2]
+"#;
+    let result = support::typecheck(text);
+    let expected = Ok(typ("Int"));
+
+    assert_eq!(result, expected);
+}
+
+#[test]
+fn implicit_argument_of_field() {
+    let _ = env_logger::init();
+
+    let text = r#"
+type Eq a = { eq: a -> a -> Int }
+let implicit eq_Int : Eq Int = { eq = \l r -> 1 }
+let equal e : [Eq a] -> a -> a -> Int = e.eq
+let m = { equal }
+m.equal 1 2
+"#;
+    let result = support::typecheck(text);
+    let expected = Ok(typ("Int"));
+
+    assert_eq!(result, expected);
+}
+
+#[test]
+fn implicit_argument_of_operator() {
+    let _ = env_logger::init();
+
+    let text = r#"
+type Eq a = { eq: a -> a -> Int }
+let implicit eq_Int : Eq Int = { eq = \l r -> 1 }
+let (===) e : [Eq a] -> a -> a -> Int = e.eq
+1 === 2
+"#;
+    let result = support::typecheck(text);
+    let expected = Ok(typ("Int"));
+
+    assert_eq!(result, expected);
+}
+
+#[test]
+fn implicit_argument_forwarded() {
+    let _ = env_logger::init();
+
+    let text = r#"
+type Eq a = { eq: a -> a -> Int }
+let implicit eq_Int : Eq Int = { eq = \l r -> 1 }
+let equal e : [Eq a] -> a -> a -> Int = e.eq
+let equal2 e x y : [Eq a] -> a -> a -> Int = equal x y
+equal2 1 2
+"#;
+    let result = support::typecheck(text);
+    let expected = Ok(typ("Int"));
+
+    assert_eq!(result, expected);
+}
+
 #[test]
 fn unify_equal_hkt_aliases() {
     let _ = env_logger::init();
@@ -734,6 +956,64 @@ in f "123"
                });
 }
 
+#[test]
+fn do_expression() {
+    let _ = env_logger::init();
+
+    let text = r#"
+type Option a = | None | Some a
+type Monad m = { flat_map : (a -> m b) -> m a -> m b }
+let implicit monad_Option : Monad Option = {
+    flat_map = \f m ->
+        match m with
+        | Some x -> f x
+        | None -> None
+}
+let result =
+    do x = Some 1
+    do y = Some "a"
+    Some y
+match result with
+| Some y -> y
+| None -> ""
+"#;
+    let result = support::typecheck(text);
+    let expected = Ok(typ("String"));
+
+    assert_eq!(result, expected);
+}
+
+#[test]
+fn do_bind_error_span() {
+    let _ = env_logger::init();
+
+    let text = r#"
+type Option a = | None | Some a
+type Monad m = { flat_map : (a -> m b) -> m a -> m b }
+let implicit monad_Option : Monad Option = { flat_map = \f m -> None }
+do x = 1
+Some x
+"#;
+    let result = support::typecheck(text);
+
+    assert!(result.is_err());
+    let err = result.unwrap_err();
+    assert_eq!(err.errors.len(), 1);
+    assert_eq!(err.errors[0].span,
+               Span {
+                   start: Location {
+                       line: 5,
+                       column: CharPos(1),
+                       absolute: BytePos(0),
+                   },
+                   end: Location {
+                       line: 5,
+                       column: CharPos(9),
+                       absolute: BytePos(0),
+                   },
+               });
+}
+
 /// Test that overload resolution selects the closest implementation that matches even if another
 /// overload has a better match. If this wasn't the case it would be possible to get diffferent
 /// selection depending on the order that types are infered.
@@ -841,3 +1121,200 @@ make
     let result = support::typecheck(text);
     assert!(result.is_ok(), "{}", result.unwrap_err());
 }
+
+#[test]
+fn hole_reports_expected_type_and_candidates() {
+    let _ = ::env_logger::init();
+    let text = r#"
+let x = 1
+let y = "a"
+let f z = z #Int+ x
+f _x
+"#;
+    let (result, holes) = support::typecheck_holes(text);
+
+    assert_eq!(result, Ok(typ("Int")));
+    assert_eq!(holes.len(), 1);
+    let hole = &holes[0];
+    assert_eq!(hole.value.typ, typ("Int"));
+    assert_eq!(hole.span.start,
+               Location {
+                   line: 5,
+                   column: CharPos(3),
+                   absolute: BytePos(0),
+               });
+    let candidates = hole.value
+        .candidates
+        .iter()
+        .map(|candidate| candidate.0.declared_name())
+        .collect::<Vec<_>>();
+    assert_eq!(candidates, ["x"]);
+}
+
+#[test]
+fn underscore_identifier_of_a_binding_is_not_a_hole() {
+    let _ = ::env_logger::init();
+    let text = r#"
+let _x = 1
+_x #Int+ 2
+"#;
+    let (result, holes) = support::typecheck_holes(text);
+
+    assert_eq!(result, Ok(typ("Int")));
+    assert_eq!(holes.len(), 0);
+}
+
+#[test]
+fn functions_on_references_are_generalized() {
+    let _ = ::env_logger::init();
+    let text = r#"
+let get r = load r
+let a : Int = get (ref 1)
+let b : String = get (ref "")
+b
+"#;
+    let result = support::typecheck(text);
+
+    assert_eq!(result, Ok(typ("String")));
+}
+
+#[test]
+fn functions_creating_references_are_generalized() {
+    let _ = ::env_logger::init();
+    let text = r#"
+let mk x = ref x
+in (mk 1, mk "a")
+"#;
+    let result = support::typecheck(text);
+
+    assert!(result.is_ok(), "{}", result.unwrap_err());
+}
+
+#[test]
+fn forall_argument_can_be_used_at_different_types() {
+    let _ = ::env_logger::init();
+    let text = r#"
+let test_fn f : (forall a . a -> ()) -> () =
+    let x = f 2.0
+    f ""
+test_fn (\x -> ())
+"#;
+    let result = support::typecheck(text);
+
+    assert_eq!(result, Ok(Type::unit()));
+}
+
+#[test]
+fn forall_record_field_stays_polymorphic() {
+    let _ = ::env_logger::init();
+    let text = r#"
+type Option a = | None | Some a
+type Monad m = {
+    flat_map : forall a b . (a -> m b) -> m a -> m b,
+    pure : forall a . a -> m a
+}
+let monad_Option : Monad Option = {
+    flat_map = \f m ->
+        match m with
+        | Some x -> f x
+        | None -> None,
+    pure = \x -> Some x
+}
+let use_monad monad : Monad Option -> Option String =
+    let x = monad.pure 1
+    monad.flat_map (\y -> monad.pure "") (monad.pure 2.0)
+use_monad monad_Option
+"#;
+    let result = support::typecheck(text);
+
+    assert!(result.is_ok(), "{}", result.unwrap_err());
+}
+
+#[test]
+fn forall_in_record_argument() {
+    let _ = ::env_logger::init();
+    let text = r#"
+let f r : { id : forall a . a -> a } -> { x : Int, y : String } = { x = r.id 1, y = r.id "" }
+(f { id = \x -> x }).y
+"#;
+    let result = support::typecheck(text);
+
+    assert_eq!(result, Ok(typ("String")));
+}
+
+#[test]
+fn annotation_hides_record_fields() {
+    let _ = ::env_logger::init();
+    let text = r#"
+let m = { x = 1, y = "" } : { y : String }
+m
+"#;
+    let result = support::typecheck(text);
+
+    let expected = Type::record(vec![],
+                                vec![Field {
+                                         name: intern("y"),
+                                         typ: typ("String"),
+                                     }]);
+    assert_eq!(result, Ok(expected));
+}
+
+#[test]
+fn annotation_with_type_definition() {
+    let _ = ::env_logger::init();
+    let text = r#"
+let m =
+    type T = Int
+    let make x : Int -> T = x
+    { T, make } : { type T = Int, make : Int -> T }
+m.make 1 #Int+ 1
+"#;
+    let result = support::typecheck(text);
+
+    assert_eq!(result, Ok(typ("Int")));
+}
+
+#[test]
+fn abstract_type_is_used_through_its_module() {
+    let _ = ::env_logger::init();
+    let text = r#"
+let m =
+    type T = Int
+    let make x : Int -> T = x
+    let get t : T -> Int = t
+    { T, make, get } : { type T, make : Int -> T, get : T -> Int }
+let { T, make, get } = m
+let t : T = make 1
+get t
+"#;
+    let result = support::typecheck(text);
+
+    assert_eq!(result, Ok(typ("Int")));
+}
+
+#[test]
+fn kind_annotation_on_unused_parameter() {
+    let _ = ::env_logger::init();
+    let text = r#"
+type Proxy (f : Type -> Type) = | Proxy
+type Option a = | None | Some a
+let x : Proxy Option = Proxy
+x
+"#;
+    let result = support::typecheck(text);
+
+    assert_pass!(result);
+}
+
+#[test]
+fn kind_annotation_in_forall() {
+    let _ = ::env_logger::init();
+    let text = r#"
+type Option a = | None | Some a
+let f x : (forall (m : Type -> Type) . m Int -> m Int) -> Option Int = x (Some 1)
+f (\y -> y)
+"#;
+    let result = support::typecheck(text);
+
+    assert_pass!(result);
+}
@@ -1,7 +1,10 @@
 use base::ast;
+use base::metadata::{Metadata, MetadataEnv};
+use base::pos::Spanned;
 use base::symbol::{Symbols, SymbolModule, Symbol, SymbolRef};
-use base::types::{Alias, Generic, Kind, Type, KindEnv};
+use base::types::{Alias, AliasData, Generic, Kind, Type, KindEnv};
 use base::types::{TcIdent, TcType, TypeEnv, PrimitiveEnv, RcKind};
+use check::lint::Warning;
 use check::typecheck::{self, Typecheck};
 use parser;
 
@@ -52,6 +55,9 @@ pub fn typecheck(text: &str) -> Result<TcType, typecheck::Error> {
 
 pub struct MockEnv {
     bool: Alias<Symbol, TcType>,
+    reference: Alias<Symbol, TcType>,
+    ref_fn: TcType,
+    load_fn: TcType,
 }
 
 impl MockEnv {
@@ -62,8 +68,25 @@ impl MockEnv {
         let bool_sym = interner.symbol("Bool");
         let bool_ty = Type::app(Type::id(bool_sym.clone()), vec![]);
 
+        // A mutable `Ref a` type together with `ref` and `load` functions mirroring the ones
+        // defined by the vm
+        let ref_sym = interner.symbol("Ref");
+        let a = Generic {
+            kind: Kind::typ(),
+            id: interner.symbol("a"),
+        };
+        let ref_ty: TcType = Type::app(Type::id(ref_sym.clone()),
+                                       vec![Type::generic(a.clone())]);
+
         MockEnv {
             bool: Alias::new(bool_sym, vec![], bool_ty),
+            reference: Alias::from(AliasData {
+                name: ref_sym,
+                args: vec![a.clone()],
+                typ: None,
+            }),
+            ref_fn: Type::function(vec![Type::generic(a.clone())], ref_ty.clone()),
+            load_fn: Type::function(vec![ref_ty], Type::generic(a)),
         }
     }
 }
@@ -72,6 +95,7 @@ impl KindEnv for MockEnv {
     fn find_kind(&self, id: &SymbolRef) -> Option<RcKind> {
         match id.as_ref() {
             "Bool" => Some(Kind::typ()),
+            "Ref" => Some(Kind::function(Kind::typ(), Kind::typ())),
             _ => None,
         }
     }
@@ -81,6 +105,8 @@ impl TypeEnv for MockEnv {
     fn find_type(&self, id: &SymbolRef) -> Option<&TcType> {
         match id.as_ref() {
             "False" | "True" => Some(&self.bool.typ.as_ref().unwrap()),
+            "ref" => Some(&self.ref_fn),
+            "load" => Some(&self.load_fn),
             _ => None,
         }
     }
@@ -88,6 +114,7 @@ impl TypeEnv for MockEnv {
     fn find_type_info(&self, id: &SymbolRef) -> Option<&Alias<Symbol, TcType>> {
         match id.as_ref() {
             "Bool" => Some(&self.bool),
+            "Ref" => Some(&self.reference),
             _ => None,
         }
     }
@@ -97,13 +124,33 @@ impl TypeEnv for MockEnv {
     }
 }
 
+impl MetadataEnv for MockEnv {
+    fn get_metadata(&self, _id: &Symbol) -> Option<&Metadata> {
+        None
+    }
+}
+
 impl PrimitiveEnv for MockEnv {
     fn get_bool(&self) -> &TcType {
         self.bool.typ.as_ref().unwrap()
     }
+
+    fn is_mutable_type(&self, id: &SymbolRef) -> bool {
+        *self.reference.name == *id
+    }
 }
 
 pub fn typecheck_expr(text: &str) -> (ast::LExpr<TcIdent>, Result<TcType, typecheck::Error>) {
+    let (expr, result, _) = typecheck_expr_warnings(text);
+    (expr, result)
+}
+
+/// Typechecks `text`, also returning the warnings which were found by the typechecker
+#[allow(dead_code)]
+pub fn typecheck_expr_warnings(text: &str)
+                               -> (ast::LExpr<TcIdent>,
+                                   Result<TcType, typecheck::Error>,
+                                   Vec<Spanned<Warning<Symbol>>>) {
     let mut expr = parse_new(text).unwrap_or_else(|(_, err)| panic!("{}", err));
 
     let env = MockEnv::new();
@@ -112,8 +159,9 @@ pub fn typecheck_expr(text: &str) -> (ast::LExpr<TcIdent>, Result<TcType, typech
     let mut tc = Typecheck::new("test".into(), &mut interner, &env);
 
     let result = tc.typecheck_expr(&mut expr);
+    let warnings = tc.warnings().to_vec();
 
-    (expr, result)
+    (expr, result, warnings)
 }
 
 #[allow(dead_code)]
@@ -134,6 +182,22 @@ pub fn typecheck_partial_expr(text: &str) -> (ast::LExpr<TcIdent>, Result<TcType
     (expr, result)
 }
 
+/// Typechecks `text` returning the holes which were found in it
+#[allow(dead_code)]
+pub fn typecheck_holes(text: &str)
+                       -> (Result<TcType, typecheck::Error>, Vec<Spanned<typecheck::Hole<Symbol>>>) {
+    let mut expr = parse_new(text).unwrap_or_else(|(_, err)| panic!("{}", err));
+
+    let env = MockEnv::new();
+    let interner = get_local_interner();
+    let mut interner = interner.borrow_mut();
+    let mut tc = Typecheck::new("test".into(), &mut interner, &env);
+
+    let result = tc.typecheck_expr(&mut expr);
+
+    (result, tc.holes().to_vec())
+}
+
 #[allow(dead_code)]
 pub fn typ(s: &str) -> TcType {
     assert!(s.len() != 0);
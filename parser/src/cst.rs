@@ -0,0 +1,250 @@
+//! A lossless representation of gluon source code for tools such as formatters, refactoring tools
+//! and syntax highlighters which need more than the AST. The source is split into the tokens read
+//! by the lexer and the whitespace and comments (the trivia) between them so that every byte of
+//! the source belongs to exactly one token or trivia.
+//!
+//! Unlike the tokens which the parser sees, no tokens are inserted by the layout rules. Since the
+//! spans stored in the AST contain byte offsets the tokens of any expression can be found with
+//! `SyntaxTree::expr_tokens`.
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+
+use base::ast::{AstId, EmptyEnv, IdentEnv, LExpr};
+use base::pos::{BytePos, Location, Span};
+use base::source::SourceMap;
+
+use combine::primitives::{Error as CombineError, Info};
+use combine::ParseError;
+
+use lexer::{CommentKind, Lexer, PComment, Token};
+use Error;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum TriviaKind {
+    Whitespace,
+    Comment(CommentKind),
+}
+
+/// Whitespace or a comment
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Trivia {
+    pub kind: TriviaKind,
+    pub span: Span,
+}
+
+/// A token together with the trivia between it and the previous token
+#[derive(Clone, Debug, PartialEq)]
+pub struct SyntaxToken {
+    pub token: Token<String>,
+    pub span: Span,
+    pub leading_trivia: Vec<Trivia>,
+}
+
+/// The tokens and trivia of a source string
+#[derive(Clone, Debug)]
+pub struct SyntaxTree {
+    source: SourceMap,
+    tokens: Vec<SyntaxToken>,
+    /// The trivia after the last token
+    trailing_trivia: Vec<Trivia>,
+}
+
+impl SyntaxTree {
+    /// Splits `input` into tokens and trivia. Returns an error if `input` contains something
+    /// which is not a token, whitespace or comment such as an unterminated string literal.
+    pub fn new(input: &str) -> Result<SyntaxTree, Error> {
+        let source = SourceMap::new(input);
+        let (tokens, comments) = {
+            let mut env = EmptyEnv::<String>::new();
+            let make_ident = Rc::new(RefCell::new(&mut env as &mut IdentEnv<Ident = String>));
+            let mut lexer = Lexer::<&str, &mut IdentEnv<Ident = String>>::new(input, make_ident);
+            let comments = lexer.record_comments();
+            let mut tokens = Vec::new();
+            loop {
+                let token = lexer.next_token();
+                match token.token {
+                    // Characters which can't start a token are lexed as `EOF`
+                    Token::EOF if token.location != token.end => {
+                        let c = input[token.location.absolute.to_usize()..]
+                            .chars()
+                            .next()
+                            .unwrap_or(' ');
+                        return Err(error(token.location,
+                                         Info::Owned(format!("Unexpected character `{}`", c))));
+                    }
+                    // The lexer emits `CloseBlock` once it can't read any more tokens. If that is
+                    // due to an error the source which it did not read is found below
+                    Token::EOF | Token::CloseBlock => break,
+                    _ => tokens.push(token),
+                }
+            }
+            let comments = comments.borrow().clone();
+            (tokens, comments)
+        };
+
+        let (tokens, trailing_trivia) = {
+            let mut builder = Builder {
+                source: &source,
+                comments: comments.into_iter().peekable(),
+                position: 0,
+            };
+            let mut syntax_tokens = Vec::with_capacity(tokens.len());
+            for token in tokens {
+                let leading_trivia = try!(builder.trivia(token.location.absolute.to_usize()));
+                builder.position = token.end.absolute.to_usize();
+                syntax_tokens.push(SyntaxToken {
+                    token: token.token,
+                    span: Span {
+                        start: token.location,
+                        end: token.end,
+                    },
+                    leading_trivia: leading_trivia,
+                });
+            }
+            (syntax_tokens, try!(builder.trivia(input.len())))
+        };
+        Ok(SyntaxTree {
+            source: source,
+            tokens: tokens,
+            trailing_trivia: trailing_trivia,
+        })
+    }
+
+    pub fn source(&self) -> &str {
+        self.source.source()
+    }
+
+    pub fn tokens(&self) -> &[SyntaxToken] {
+        &self.tokens
+    }
+
+    /// Returns the trivia after the last token
+    pub fn trailing_trivia(&self) -> &[Trivia] {
+        &self.trailing_trivia
+    }
+
+    /// Returns the trivia in the order they appear in the source
+    pub fn trivia<'a>(&'a self) -> Box<Iterator<Item = &'a Trivia> + 'a> {
+        Box::new(self.tokens
+            .iter()
+            .flat_map(|token| token.leading_trivia.iter())
+            .chain(self.trailing_trivia.iter()))
+    }
+
+    /// Returns the source text which `span` covers
+    pub fn text(&self, span: Span) -> &str {
+        &self.source()[span.start.absolute.to_usize()..span.end.absolute.to_usize()]
+    }
+
+    /// Returns the tokens which are inside `span`
+    pub fn span_tokens(&self, span: Span) -> &[SyntaxToken] {
+        let start = self.tokens
+            .iter()
+            .position(|token| token.span.start.absolute >= span.start.absolute)
+            .unwrap_or(self.tokens.len());
+        let len = self.tokens[start..]
+            .iter()
+            .take_while(|token| token.span.end.absolute <= span.end.absolute)
+            .count();
+        &self.tokens[start..start + len]
+    }
+
+    /// Returns the tokens which `expr` was parsed from. `expr` must have been parsed from the same
+    /// source as the tree.
+    pub fn expr_tokens<Id>(&self, expr: &LExpr<Id>) -> &[SyntaxToken]
+        where Id: AstId
+    {
+        self.span_tokens(expr.span)
+    }
+
+    /// Returns the token which contains the byte offset `pos`
+    pub fn token_at(&self, pos: BytePos) -> Option<&SyntaxToken> {
+        self.tokens
+            .iter()
+            .find(|token| token.span.start.absolute <= pos && pos < token.span.end.absolute)
+    }
+}
+
+/// Writes the source which the tree was created from
+impl fmt::Display for SyntaxTree {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for token in &self.tokens {
+            for trivia in &token.leading_trivia {
+                try!(f.write_str(self.text(trivia.span)));
+            }
+            try!(f.write_str(self.text(token.span)));
+        }
+        for trivia in &self.trailing_trivia {
+            try!(f.write_str(self.text(trivia.span)));
+        }
+        Ok(())
+    }
+}
+
+struct Builder<'a, I>
+    where I: Iterator<Item = PComment>
+{
+    source: &'a SourceMap,
+    comments: ::std::iter::Peekable<I>,
+    /// The byte offset of the end of the last token or trivia
+    position: usize,
+}
+
+impl<'a, I> Builder<'a, I>
+    where I: Iterator<Item = PComment>
+{
+    /// Returns the trivia between the current position and `end`
+    fn trivia(&mut self, end: usize) -> Result<Vec<Trivia>, Error> {
+        let text = self.source.source();
+        let mut trivia = Vec::new();
+        while self.position < end {
+            let position = self.position;
+            let comment = self.comments
+                .peek()
+                .cloned()
+                .and_then(|comment| if comment.location.absolute.to_usize() == position {
+                    Some(comment)
+                } else {
+                    None
+                });
+            let (kind, trivia_end) = match comment {
+                Some(comment) => {
+                    self.comments.next();
+                    let comment_end = comment.end.absolute.to_usize();
+                    (TriviaKind::Comment(comment.kind), comment_end)
+                }
+                None => {
+                    let whitespace = text[self.position..end]
+                        .find(|c: char| !c.is_whitespace())
+                        .map_or(end, |i| self.position + i);
+                    if whitespace == self.position {
+                        let location = self.source
+                            .location(BytePos::from(self.position))
+                            .expect("Position at a character boundary");
+                        return Err(error(location, Info::Borrowed("Unexpected input")));
+                    }
+                    (TriviaKind::Whitespace, whitespace)
+                }
+            };
+            trivia.push(Trivia {
+                kind: kind,
+                span: span(self.source, self.position, trivia_end),
+            });
+            self.position = trivia_end;
+        }
+        Ok(trivia)
+    }
+}
+
+fn span(source: &SourceMap, start: usize, end: usize) -> Span {
+    source.span(BytePos::from(start), BytePos::from(end))
+        .expect("Span at character boundaries")
+}
+
+fn error(position: Location, info: Info<Token<String>, Token<String>>) -> Error {
+    ParseError {
+        position: position,
+        errors: vec![CombineError::Message(info)],
+    }
+}
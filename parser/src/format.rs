@@ -0,0 +1,1222 @@
+//! Formatting of gluon source code. `format_expr` parses an expression and prints it back with a
+//! canonical layout.
+//!
+//! Doc comments are stored in the AST and are printed together with the bindings they document.
+//! Ordinary comments and fixity declarations are dropped by the parser so they are instead taken
+//! from the `SyntaxTree` of the source and are printed in front of the binding, block expression
+//! or match alternative which follows them.
+use std::iter;
+
+use base::ast::{Alternative, Associativity, AstType, Binding, EmptyEnv, Expr, Fixity, Lambda,
+                LExpr, LPattern, LiteralEnum, Pattern, TypeBinding};
+use base::error::Errors;
+use base::pos::{Location, Span};
+use base::source::SourceMap;
+use base::types::{Alias, Field, Generic, Kind, Type};
+
+use cst::{self, SyntaxToken, SyntaxTree};
+use lexer::{Token, is_operator_char};
+use {Error, SECTION_ARGUMENT, default_fixity, parse_expr};
+
+/// Lines are kept within this many columns unless an expression cannot be split
+const WIDTH: usize = 100;
+
+const INDENT: usize = 4;
+
+macro_rules! try_opt {
+    ($e: expr) => {
+        match $e {
+            Some(x) => x,
+            None => return None,
+        }
+    }
+}
+
+/// Parses `input` as a gluon expression and prints it with a canonical layout. Returns the errors
+/// found while parsing if `input` could not be parsed.
+pub fn format_expr(input: &str) -> Result<String, Errors<Error>> {
+    let mut env = EmptyEnv::<String>::new();
+    let expr = try!(parse_expr(&mut env, input).map_err(|(_, errors)| errors));
+    let tree = match SyntaxTree::new(input) {
+        Ok(tree) => tree,
+        Err(err) => {
+            let mut errors = Errors::new();
+            errors.error(err);
+            return Err(errors);
+        }
+    };
+    let mut formatter = Formatter::new(&tree);
+    formatter.expr(&expr, 0, true);
+    formatter.newline(0);
+    formatter.trivia(None);
+    let mut out = String::from(formatter.out.trim());
+    out.push('\n');
+    Ok(out)
+}
+
+/// A part of the source which is not stored in the AST
+#[derive(Clone)]
+struct Trivia {
+    span: Span,
+    text: String,
+    kind: TriviaKind,
+}
+
+#[derive(Clone)]
+enum TriviaKind {
+    Comment,
+    /// A fixity declaration and the operators it declares
+    Fixity(Vec<(String, Fixity)>),
+}
+
+struct Formatter {
+    source: SourceMap,
+    trivia: Vec<Trivia>,
+    /// Index of the first trivia which has not been printed yet
+    next_trivia: usize,
+    /// The fixities declared by the fixity declarations which have been printed and are still in
+    /// scope
+    fixities: Vec<(String, Fixity)>,
+    /// The source line which the last printed binding or expression ended on
+    last_line: Option<u32>,
+    /// True if nothing has been printed since the start of an indented block
+    block_start: bool,
+    out: String,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Prec {
+    /// The type is not an argument of a function or type constructor
+    Top,
+    /// The type is the argument of a function
+    Function,
+    /// The type is the argument of a type constructor
+    Constructor,
+}
+
+impl Formatter {
+    fn new(tree: &SyntaxTree) -> Formatter {
+        Formatter {
+            source: SourceMap::new(tree.source()),
+            trivia: collect_trivia(tree),
+            next_trivia: 0,
+            fixities: Vec::new(),
+            last_line: None,
+            block_start: true,
+            out: String::new(),
+        }
+    }
+
+    fn write(&mut self, s: &str) {
+        if !s.trim().is_empty() {
+            self.block_start = false;
+        }
+        self.out.push_str(s);
+    }
+
+    /// Returns the column (counted from 0) which the next character is written at
+    fn column(&self) -> usize {
+        let line_start = self.out.rfind('\n').map_or(0, |i| i + 1);
+        self.out[line_start..].chars().count()
+    }
+
+    /// Returns true if only indentation has been written on the current line
+    fn at_line_start(&self) -> bool {
+        let line_start = self.out.rfind('\n').map_or(0, |i| i + 1);
+        self.out[line_start..].chars().all(|c| c == ' ')
+    }
+
+    /// Starts a new line which is indented by `indent` columns
+    fn newline(&mut self, indent: usize) {
+        if self.at_line_start() {
+            // Lines are never left with only indentation on them
+            let len = self.out.trim_right_matches(' ').len();
+            self.out.truncate(len);
+        }
+        self.out.push('\n');
+        self.out.extend(iter::repeat(' ').take(indent));
+    }
+
+    /// Starts a new line for the first expression of an indented block
+    fn indent(&mut self, indent: usize) {
+        self.newline(indent);
+        self.block_start = true;
+    }
+
+    fn fits(&self, s: &str) -> bool {
+        self.column() + s.chars().count() <= WIDTH
+    }
+
+    /// Prints the trivia which appear in the source before `location` (or all remaining trivia if
+    /// `location` is `None`), each on its own line
+    fn trivia(&mut self, location: Option<Location>) {
+        let indent = self.column();
+        while self.next_trivia < self.trivia.len() {
+            let trivia = self.trivia[self.next_trivia].clone();
+            if location.map_or(false, |location| trivia.span.start >= location) {
+                break;
+            }
+            self.next_trivia += 1;
+            let trailing = self.last_line.map_or(false, |last| trivia.span.start.line <= last);
+            match trivia.kind {
+                // Comments written after or inside the previous expression are written at the end
+                // of its last line
+                TriviaKind::Comment if trailing && self.at_line_start() && !self.block_start => {
+                    let len = self.out.trim_right().len();
+                    self.out.truncate(len);
+                    self.write("  ");
+                    self.write(&trivia.text);
+                    self.newline(indent);
+                }
+                kind => {
+                    self.separate(trivia.span.start.line);
+                    self.write(&trivia.text);
+                    self.newline(indent);
+                    if let TriviaKind::Fixity(fixities) = kind {
+                        self.fixities.extend(fixities);
+                    }
+                }
+            }
+            if !trailing {
+                self.last_line = Some(trivia.span.end.line);
+            }
+        }
+    }
+
+    /// Writes an empty line if there is an empty line before `line` in the source. Consecutive
+    /// empty lines are merged and no empty line is written at the start of a block or between
+    /// items which were written on the same line.
+    fn separate(&mut self, line: u32) {
+        let empty_before = line > 1 && self.last_line.map_or(true, |last| last < line) &&
+                           self.source.line(line - 1).map_or(false, |text| text.trim().is_empty());
+        if !empty_before || self.block_start || !self.at_line_start() {
+            return;
+        }
+        let indent = self.column();
+        let len = self.out.len() - indent;
+        if len == 0 || self.out[..len].ends_with("\n\n") {
+            return;
+        }
+        self.out.truncate(len);
+        self.out.push('\n');
+        self.out.extend(iter::repeat(' ').take(indent));
+    }
+
+    /// Prints the trivia before the expression at `location` which is about to be printed at the
+    /// start of a line
+    fn anchor(&mut self, location: Location) {
+        self.trivia(Some(location));
+        self.separate(location.line);
+    }
+
+    /// Returns the fixity of `op`
+    fn fixity(&self, op: &str) -> Fixity {
+        self.fixities
+            .iter()
+            .rev()
+            .find(|&&(ref name, _)| name == op)
+            .map_or_else(|| default_fixity(op), |&(_, fixity)| fixity)
+    }
+
+    /// Returns true if `operand` needs parentheses when it is the `side` operand of an operator
+    /// with `fixity`. `tail` is true if nothing follows the operator expression.
+    fn operand_parens(&self,
+                      operand: &LExpr<String>,
+                      fixity: Fixity,
+                      side: Associativity,
+                      tail: bool)
+                      -> bool {
+        match operand.value {
+            Expr::BinOp(_, ref op, _) => {
+                let inner = self.fixity(op);
+                inner.precedence < fixity.precedence ||
+                (inner.precedence == fixity.precedence &&
+                 (inner.associativity != side || fixity.associativity != side))
+            }
+            // `m >>= \x -> ...`
+            Expr::Lambda(_) if section(operand).is_none() => side == Associativity::Left || !tail,
+            _ => is_open(operand),
+        }
+    }
+
+    /// Returns `expr` printed on a single line or `None` if it must span multiple lines. `tail` is
+    /// true if nothing follows `expr`.
+    fn flat(&self, expr: &LExpr<String>, tail: bool) -> Option<String> {
+        let s = match expr.value {
+            Expr::Identifier(ref id) |
+            Expr::Hole(ref id) |
+            Expr::Error(ref id) => ident(id),
+            Expr::Literal(ref literal) => literal_str(self.source.source(), literal, expr.span),
+            Expr::Call(ref function, ref args) => {
+                let mut s = try_opt!(self.flat_sub(function, !is_atom(function), false));
+                for arg in args {
+                    s.push(' ');
+                    s.push_str(&try_opt!(self.flat_sub(arg, !is_atom(arg), false)));
+                }
+                s
+            }
+            Expr::IfElse(ref pred, ref if_true, ref if_false) => {
+                let mut s = format!("if {} then {}",
+                                    try_opt!(self.flat(pred, true)),
+                                    try_opt!(self.flat(if_true, true)));
+                if let Some(ref if_false) = *if_false {
+                    s.push_str(" else ");
+                    s.push_str(&try_opt!(self.flat(if_false, true)));
+                }
+                s
+            }
+            Expr::BinOp(ref lhs, ref op, ref rhs) => {
+                let fixity = self.fixity(op);
+                let lhs_parens = self.operand_parens(lhs, fixity, Associativity::Left, false);
+                let rhs_parens = self.operand_parens(rhs, fixity, Associativity::Right, tail);
+                format!("{} {} {}",
+                        try_opt!(self.flat_sub(lhs, lhs_parens, false)),
+                        infix(op),
+                        try_opt!(self.flat_sub(rhs, rhs_parens, tail)))
+            }
+            Expr::FieldAccess(ref record, ref field) => {
+                format!("{}.{}",
+                        try_opt!(self.flat_sub(record, field_access_parens(record), false)),
+                        ident(field))
+            }
+            Expr::Array(ref array) => format!("[{}]", try_opt!(self.flat_list(&array.expressions))),
+            Expr::Tuple(ref exprs) => format!("({})", try_opt!(self.flat_list(exprs))),
+            Expr::Record { ref types, ref exprs, .. } => {
+                let mut fields: Vec<_> = types.iter().map(record_type_field).collect();
+                for &(ref name, ref value) in exprs {
+                    fields.push(match *value {
+                        Some(ref value) => {
+                            format!("{} = {}", ident(name), try_opt!(self.flat(value, true)))
+                        }
+                        None => ident(name),
+                    });
+                }
+                braces(fields)
+            }
+            Expr::RecordUpdate { ref base, ref fields, .. } => {
+                let mut updates = Vec::new();
+                for &(ref name, ref value) in fields {
+                    updates.push(format!("{} = {}", ident(name), try_opt!(self.flat(value, true))));
+                }
+                format!("{{ {} | {} }}",
+                        try_opt!(self.flat_sub(base, is_open(base), true)),
+                        updates.join(", "))
+            }
+            Expr::Lambda(ref lambda) => {
+                match section(expr) {
+                    Some((Section::Left(lhs), op)) => {
+                        let parens = self.operand_parens(lhs,
+                                                         self.fixity(op),
+                                                         Associativity::Left,
+                                                         false);
+                        format!("({} {})", try_opt!(self.flat_sub(lhs, parens, false)), infix(op))
+                    }
+                    Some((Section::Right(rhs), op)) => {
+                        let parens = self.operand_parens(rhs,
+                                                         self.fixity(op),
+                                                         Associativity::Right,
+                                                         true);
+                        format!("({} {})", infix(op), try_opt!(self.flat_sub(rhs, parens, true)))
+                    }
+                    None => {
+                        format!("{} {}",
+                                lambda_head(lambda),
+                                try_opt!(self.flat(&lambda.body, true)))
+                    }
+                }
+            }
+            Expr::Annotated(ref expr, ref typ) => {
+                format!("{} : {}",
+                        try_opt!(self.flat_sub(expr, is_open(expr), false)),
+                        type_str(&typ.value, Prec::Top))
+            }
+            Expr::Match(..) |
+            Expr::Let(..) |
+            Expr::Type(..) |
+            Expr::Do(..) |
+            Expr::Block(..) => return None,
+        };
+        Some(s)
+    }
+
+    fn flat_sub(&self, expr: &LExpr<String>, parens: bool, tail: bool) -> Option<String> {
+        if parens {
+            self.flat(expr, true).map(|s| format!("({})", s))
+        } else {
+            self.flat(expr, tail)
+        }
+    }
+
+    fn flat_list(&self, exprs: &[LExpr<String>]) -> Option<String> {
+        let mut elems = Vec::new();
+        for expr in exprs {
+            elems.push(try_opt!(self.flat(expr, true)));
+        }
+        Some(elems.join(", "))
+    }
+
+    /// Prints `expr` starting at the current column. `indent` is the indentation of the current
+    /// line and `tail` is true if nothing follows `expr`.
+    fn expr(&mut self, expr: &LExpr<String>, indent: usize, tail: bool) {
+        match expr.value {
+            Expr::Let(..) |
+            Expr::Type(..) |
+            Expr::Do(..) => return self.chain(expr, tail),
+            Expr::Block(ref exprs) => return self.block(exprs),
+            _ => (),
+        }
+        if let Some(s) = self.flat(expr, tail) {
+            if self.fits(&s) || is_atom(expr) {
+                return self.write(&s);
+            }
+        }
+        match expr.value {
+            Expr::Call(ref function, ref args) => {
+                self.sub_expr(function, indent, !is_atom(function), false);
+                for arg in args {
+                    self.write(" ");
+                    self.sub_expr(arg, indent, !is_atom(arg), false);
+                }
+            }
+            Expr::IfElse(..) => self.if_else(expr),
+            Expr::Match(ref matched, ref alts) => self.match_expr(matched, alts),
+            Expr::BinOp(ref lhs, ref op, ref rhs) => self.binop(lhs, op, rhs, indent, tail),
+            Expr::FieldAccess(ref record, ref field) => {
+                self.sub_expr(record, indent, field_access_parens(record), false);
+                self.write(".");
+                self.write(&ident(field));
+            }
+            Expr::Array(ref array) => self.list("[", &array.expressions, "]", indent),
+            Expr::Tuple(ref exprs) => self.list("(", exprs, ")", indent),
+            Expr::Record { ref types, ref exprs, .. } => self.record(types, exprs, indent),
+            Expr::RecordUpdate { ref base, ref fields, .. } => {
+                self.record_update(base, fields, indent)
+            }
+            Expr::Lambda(ref lambda) => {
+                match section(expr) {
+                    Some((operand, op)) => self.section(operand, op, indent),
+                    None => {
+                        self.write(&lambda_head(lambda));
+                        self.rhs(&lambda.body, indent);
+                    }
+                }
+            }
+            Expr::Annotated(ref expr, ref typ) => {
+                self.sub_expr(expr, indent, is_open(expr), false);
+                self.write(" : ");
+                self.write(&type_str(&typ.value, Prec::Top));
+            }
+            _ => (),
+        }
+    }
+
+    /// Prints `expr`, enclosing it in parentheses if `parens` is true
+    fn sub_expr(&mut self, expr: &LExpr<String>, indent: usize, parens: bool, tail: bool) {
+        if parens {
+            self.write("(");
+            self.expr(expr, indent, true);
+            self.write(")");
+        } else {
+            self.expr(expr, indent, tail);
+        }
+    }
+
+    /// Prints the expression after a `=` or `->`. The expression is printed on the same line if it
+    /// fits or if it is an expression such as a lambda or record which can start on that line,
+    /// otherwise it is printed in an indented block below.
+    fn rhs(&mut self, expr: &LExpr<String>, indent: usize) {
+        if let Some(s) = self.flat(expr, true) {
+            if self.column() + 1 + s.chars().count() <= WIDTH {
+                self.write(" ");
+                return self.write(&s);
+            }
+        }
+        if self.hangs(expr) {
+            self.write(" ");
+            self.expr(expr, indent, true);
+        } else {
+            self.indent(indent + INDENT);
+            self.expr(expr, indent + INDENT, true);
+        }
+    }
+
+    /// Returns true if `expr` can start on the line of the `=` or `->` before it even though it
+    /// spans multiple lines
+    fn hangs(&self, expr: &LExpr<String>) -> bool {
+        match expr.value {
+            Expr::Lambda(_) => true,
+            Expr::Record { .. } |
+            Expr::Array(_) => true,
+            // `f x (\y -> ...)` and `f x { ... }`
+            Expr::Call(ref function, ref args) => {
+                let (last, init) = args.split_last().expect("Call without arguments");
+                match last.value {
+                    Expr::Lambda(_) |
+                    Expr::Record { .. } |
+                    Expr::Array(_) => {
+                        self.flat(function, false).is_some() &&
+                        init.iter().all(|arg| self.flat(arg, false).is_some())
+                    }
+                    _ => false,
+                }
+            }
+            // `m >>= \x -> ...`
+            Expr::BinOp(ref lhs, ref op, ref rhs) => {
+                match rhs.value {
+                    Expr::Lambda(_) => {
+                        let fixity = self.fixity(op);
+                        !self.operand_parens(rhs, fixity, Associativity::Right, true) &&
+                        self.flat(lhs, false).is_some()
+                    }
+                    _ => false,
+                }
+            }
+            _ => false,
+        }
+    }
+
+    /// Prints a sequence of `let`, `type` and `do` bindings followed by the expression which they
+    /// are in scope for
+    fn chain(&mut self, mut expr: &LExpr<String>, tail: bool) {
+        let column = self.column();
+        let fixities = self.fixities.len();
+        loop {
+            self.anchor(expr.span.start);
+            match expr.value {
+                Expr::Let(ref bindings, ref body) => {
+                    for (i, bind) in bindings.iter().enumerate() {
+                        if i != 0 {
+                            self.newline(column);
+                            self.anchor(bind.name.span.start);
+                        }
+                        if let Some(ref comment) = bind.comment {
+                            self.binding_comment(comment, i == 0, column);
+                        }
+                        self.write(if i == 0 { "let " } else { "and " });
+                        self.binding(bind, column);
+                        self.last_line = Some(bind.span().end.line);
+                    }
+                    expr = body;
+                }
+                Expr::Type(ref bindings, ref body) => {
+                    for (i, bind) in bindings.iter().enumerate() {
+                        if i != 0 {
+                            self.newline(column);
+                        }
+                        if let Some(ref comment) = bind.comment {
+                            self.binding_comment(comment, i == 0, column);
+                        }
+                        self.write(if i == 0 { "type " } else { "and " });
+                        self.type_binding(bind, column);
+                    }
+                    // Type bindings do not store where they end
+                    self.last_line = None;
+                    expr = body;
+                }
+                Expr::Do(ref do_expr) => {
+                    self.write("do ");
+                    self.write(&ident(&do_expr.id));
+                    self.write(" =");
+                    self.rhs(&do_expr.bound, column);
+                    self.last_line = Some(do_expr.bound.span.end.line);
+                    expr = &do_expr.body;
+                }
+                Expr::Block(ref exprs) => {
+                    self.block(exprs);
+                    break;
+                }
+                _ => {
+                    self.expr(expr, column, tail);
+                    self.last_line = Some(expr.span.end.line);
+                    break;
+                }
+            }
+            self.newline(column);
+        }
+        // Fixity declarations are only in scope for the rest of the expression they appear in
+        self.fixities.truncate(fixities);
+    }
+
+    /// Prints each expression of a block on its own line
+    fn block(&mut self, exprs: &[LExpr<String>]) {
+        let column = self.column();
+        for (i, expr) in exprs.iter().enumerate() {
+            if i != 0 {
+                self.newline(column);
+            }
+            self.anchor(expr.span.start);
+            self.expr(expr, column, true);
+            self.last_line = Some(expr.span.end.line);
+        }
+    }
+
+    /// Prints the doc comment of a `let` or `type` binding. The comment of a binding after `and`
+    /// is indented as it would otherwise end the `let` or `type` expression.
+    fn binding_comment(&mut self, comment: &str, first: bool, column: usize) {
+        if first {
+            self.doc_comment(comment, column, column);
+        } else {
+            self.write("  ");
+            self.doc_comment(comment, column + 2, column);
+        }
+    }
+
+    /// Prints `comment` as a doc comment followed by a new line indented by `next_indent`
+    fn doc_comment(&mut self, comment: &str, indent: usize, next_indent: usize) {
+        // Whitespace at the start of a line is skipped when parsing line comments and whitespace
+        // at the end of a line should not be written
+        let line_comments = comment.split('\n').all(|line| !line.is_empty() && line.trim() == line);
+        if line_comments {
+            let lines: Vec<_> = comment.split('\n').collect();
+            for (i, line) in lines.iter().enumerate() {
+                self.write("/// ");
+                self.write(line);
+                self.newline(if i + 1 == lines.len() { next_indent } else { indent });
+            }
+        } else {
+            self.write(if comment.contains('\n') { "/**\n" } else { "/** " });
+            self.write(comment);
+            self.write("*/");
+            self.newline(next_indent);
+        }
+    }
+
+    /// Prints a `let` binding without the `let` or `and` keyword
+    fn binding(&mut self, bind: &Binding<String>, indent: usize) {
+        if bind.implicit {
+            self.write("implicit ");
+        }
+        self.write(&pattern_str(self.source.source(), &bind.name, false));
+        for arg in &bind.arguments {
+            self.write(" ");
+            self.write(&ident(arg));
+        }
+        if let Some(ref typ) = bind.typ {
+            self.write(" : ");
+            self.write(&type_str(&typ.value, Prec::Top));
+        }
+        self.write(" =");
+        self.rhs(&bind.expression, indent);
+    }
+
+    /// Prints a `type` binding without the `type` or `and` keyword
+    fn type_binding(&mut self, bind: &TypeBinding<String>, indent: usize) {
+        self.write(&bind.name);
+        for arg in &bind.alias.args {
+            self.write(" ");
+            self.write(&type_param(arg));
+        }
+        self.write(" =");
+        if let Some(ref typ) = bind.alias.typ {
+            self.type_rhs(typ, indent);
+        }
+        if !bind.deriving.is_empty() {
+            self.write(" deriving (");
+            self.write(&bind.deriving.join(", "));
+            self.write(")");
+        }
+    }
+
+    /// Prints the type which a `type` binding is defined as. Variants and records are printed
+    /// with one variant or field on each line if they do not fit on the line.
+    fn type_rhs(&mut self, typ: &AstType<String>, indent: usize) {
+        let flat = type_str(typ, Prec::Top);
+        if self.column() + 1 + flat.chars().count() <= WIDTH {
+            self.write(" ");
+            return self.write(&flat);
+        }
+        match **typ {
+            Type::Variants(ref variants) => {
+                for variant in variants {
+                    self.newline(indent + INDENT);
+                    self.write(&variant_str(variant));
+                }
+            }
+            Type::Record { ref types, ref fields, ref rest } => {
+                self.write(" {");
+                let mut lines: Vec<_> = types.iter().map(|field| associated_type(field)).collect();
+                lines.extend(fields.iter().map(|field| {
+                    format!("{} : {}", ident(&field.name), type_str(&field.typ, Prec::Top))
+                }));
+                let count = lines.len();
+                for (i, line) in lines.into_iter().enumerate() {
+                    self.newline(indent + INDENT);
+                    self.write(&line);
+                    if i + 1 != count {
+                        self.write(",");
+                    }
+                }
+                if let Some(ref rest) = *rest {
+                    self.newline(indent + INDENT);
+                    self.write("| ");
+                    self.write(&type_str(rest, Prec::Top));
+                }
+                self.newline(indent);
+                self.write("}");
+            }
+            _ => {
+                self.write(" ");
+                self.write(&flat);
+            }
+        }
+    }
+
+    fn if_else(&mut self, mut expr: &LExpr<String>) {
+        let column = self.column();
+        while let Expr::IfElse(ref pred, ref if_true, ref if_false) = expr.value {
+            self.write("if ");
+            self.expr(pred, column, true);
+            self.write(" then");
+            self.indent(column + INDENT);
+            self.expr(if_true, column + INDENT, true);
+            let if_false = match *if_false {
+                Some(ref if_false) => if_false,
+                None => return,
+            };
+            self.newline(column);
+            self.write("else");
+            match if_false.value {
+                // `else if` chains are kept at the same indentation
+                Expr::IfElse(..) => {
+                    self.write(" ");
+                    expr = if_false;
+                }
+                _ => {
+                    self.indent(column + INDENT);
+                    self.expr(if_false, column + INDENT, true);
+                    return;
+                }
+            }
+        }
+    }
+
+    fn match_expr(&mut self, matched: &LExpr<String>, alts: &[Alternative<String>]) {
+        let column = self.column();
+        self.write("match ");
+        self.expr(matched, column, true);
+        self.write(" with");
+        for (i, alt) in alts.iter().enumerate() {
+            if i == 0 {
+                self.indent(column + INDENT);
+            } else {
+                self.newline(column + INDENT);
+            }
+            self.anchor(alt.pattern.span.start);
+            self.write("| ");
+            self.write(&pattern_str(self.source.source(), &alt.pattern, false));
+            if let Some(ref guard) = alt.guard {
+                self.write(" if ");
+                self.expr(guard, column + INDENT, true);
+            }
+            self.write(" ->");
+            self.rhs(&alt.expression, column + INDENT);
+            self.last_line = Some(alt.expression.span.end.line);
+        }
+    }
+
+    /// Prints an operator expression which does not fit on one line. The right operand is moved
+    /// to the next line unless it is a lambda.
+    fn binop(&mut self,
+             lhs: &LExpr<String>,
+             op: &str,
+             rhs: &LExpr<String>,
+             indent: usize,
+             tail: bool) {
+        let fixity = self.fixity(op);
+        let lhs_parens = self.operand_parens(lhs, fixity, Associativity::Left, false);
+        let rhs_parens = self.operand_parens(rhs, fixity, Associativity::Right, tail);
+        self.sub_expr(lhs, indent, lhs_parens, false);
+        let hangs = match rhs.value {
+            Expr::Lambda(_) => !rhs_parens,
+            _ => false,
+        };
+        let fits = match self.flat_sub(rhs, rhs_parens, tail) {
+            Some(s) => self.column() + infix(op).len() + 2 + s.chars().count() <= WIDTH,
+            None => false,
+        };
+        if fits || hangs {
+            self.write(" ");
+            self.write(&infix(op));
+            self.write(" ");
+            self.sub_expr(rhs, indent, rhs_parens, tail);
+        } else {
+            self.newline(indent + INDENT);
+            self.write(&infix(op));
+            self.write(" ");
+            self.sub_expr(rhs, indent + INDENT, rhs_parens, tail);
+        }
+    }
+
+    /// Prints an operator section which does not fit on one line
+    fn section(&mut self, operand: Section, op: &str, indent: usize) {
+        let fixity = self.fixity(op);
+        self.write("(");
+        match operand {
+            Section::Left(lhs) => {
+                let parens = self.operand_parens(lhs, fixity, Associativity::Left, false);
+                self.sub_expr(lhs, indent, parens, false);
+                self.write(" ");
+                self.write(&infix(op));
+            }
+            Section::Right(rhs) => {
+                let parens = self.operand_parens(rhs, fixity, Associativity::Right, true);
+                self.write(&infix(op));
+                self.write(" ");
+                self.sub_expr(rhs, indent, parens, true);
+            }
+        }
+        self.write(")");
+    }
+
+    /// Prints each expression in `exprs` on its own line between `open` and `close`
+    fn list(&mut self, open: &str, exprs: &[LExpr<String>], close: &str, indent: usize) {
+        self.write(open);
+        for (i, expr) in exprs.iter().enumerate() {
+            if i == 0 {
+                self.indent(indent + INDENT);
+            } else {
+                self.newline(indent + INDENT);
+            }
+            self.anchor(expr.span.start);
+            self.expr(expr, indent + INDENT, true);
+            if i + 1 != exprs.len() {
+                self.write(",");
+            }
+            self.last_line = Some(expr.span.end.line);
+        }
+        self.newline(indent);
+        self.write(close);
+    }
+
+    /// Prints a record expression with each field on its own line
+    fn record(&mut self,
+              types: &[(String, Option<AstType<String>>)],
+              exprs: &[(String, Option<LExpr<String>>)],
+              indent: usize) {
+        self.write("{");
+        let count = types.len() + exprs.len();
+        for (i, field) in types.iter().enumerate() {
+            if i == 0 {
+                self.indent(indent + INDENT);
+            } else {
+                self.newline(indent + INDENT);
+            }
+            self.write(&record_type_field(field));
+            if i + 1 != count {
+                self.write(",");
+            }
+        }
+        for (i, &(ref name, ref value)) in exprs.iter().enumerate() {
+            if types.len() + i == 0 {
+                self.indent(indent + INDENT);
+            } else {
+                self.newline(indent + INDENT);
+            }
+            match *value {
+                Some(ref value) => {
+                    self.anchor(value.span.start);
+                    self.write(&ident(name));
+                    self.write(" =");
+                    self.rhs(value, indent + INDENT);
+                    self.last_line = Some(value.span.end.line);
+                }
+                None => self.write(&ident(name)),
+            }
+            if types.len() + i + 1 != count {
+                self.write(",");
+            }
+        }
+        self.newline(indent);
+        self.write("}");
+    }
+
+    /// Prints a record update with each updated field on its own line
+    fn record_update(&mut self,
+                     base: &LExpr<String>,
+                     fields: &[(String, LExpr<String>)],
+                     indent: usize) {
+        self.write("{ ");
+        self.sub_expr(base, indent, is_open(base), true);
+        self.write(" |");
+        for (i, &(ref name, ref value)) in fields.iter().enumerate() {
+            if i == 0 {
+                self.indent(indent + INDENT);
+            } else {
+                self.newline(indent + INDENT);
+            }
+            self.anchor(value.span.start);
+            self.write(&ident(name));
+            self.write(" =");
+            self.rhs(value, indent + INDENT);
+            self.last_line = Some(value.span.end.line);
+            if i + 1 != fields.len() {
+                self.write(",");
+            }
+        }
+        self.newline(indent);
+        self.write("}");
+    }
+}
+
+/// Returns true if `expr` can be used as the argument of a function without parentheses
+fn is_atom(expr: &LExpr<String>) -> bool {
+    match expr.value {
+        Expr::Identifier(_) |
+        Expr::Hole(_) |
+        Expr::Error(_) |
+        Expr::Literal(_) |
+        Expr::FieldAccess(..) |
+        Expr::Array(_) |
+        Expr::Record { .. } |
+        Expr::RecordUpdate { .. } |
+        Expr::Tuple(_) => true,
+        Expr::Lambda(_) => section(expr).is_some(),
+        _ => false,
+    }
+}
+
+/// Returns true if `expr` extends as far to the right as possible when parsed so that it needs
+/// parentheses unless nothing follows it
+fn is_open(expr: &LExpr<String>) -> bool {
+    match expr.value {
+        Expr::Lambda(_) => section(expr).is_none(),
+        Expr::IfElse(..) |
+        Expr::Match(..) |
+        Expr::Let(..) |
+        Expr::Type(..) |
+        Expr::Do(..) |
+        Expr::Block(_) |
+        Expr::Annotated(..) => true,
+        _ => false,
+    }
+}
+
+fn field_access_parens(record: &LExpr<String>) -> bool {
+    match record.value {
+        // `1.x` would be read as a float
+        Expr::Literal(_) => true,
+        _ => !is_atom(record),
+    }
+}
+
+/// Returns `name` as it is written in an expression, operators are enclosed in parentheses
+fn ident(name: &str) -> String {
+    if name.starts_with(is_operator_char) {
+        format!("({})", name)
+    } else {
+        String::from(name)
+    }
+}
+
+/// Returns `op` as it is written between its operands, identifiers are enclosed in backticks
+fn infix(op: &str) -> String {
+    if op.starts_with(is_operator_char) || op.starts_with('#') {
+        String::from(op)
+    } else {
+        format!("`{}`", op)
+    }
+}
+
+/// The operand of an operator section
+enum Section<'a> {
+    /// `(expr op)`
+    Left(&'a LExpr<String>),
+    /// `(op expr)`
+    Right(&'a LExpr<String>),
+}
+
+/// Returns the operand and operator of `expr` if it is the desugared form of an operator section
+fn section(expr: &LExpr<String>) -> Option<(Section, &str)> {
+    let is_argument = |expr: &LExpr<String>| match expr.value {
+        Expr::Identifier(ref id) => id == SECTION_ARGUMENT,
+        _ => false,
+    };
+    let lambda = match expr.value {
+        Expr::Lambda(ref lambda) if lambda.arguments.len() == 1 &&
+                                    lambda.arguments[0].value == SECTION_ARGUMENT => lambda,
+        _ => return None,
+    };
+    match lambda.body.value {
+        Expr::BinOp(ref lhs, ref op, ref rhs) => {
+            match (is_argument(lhs), is_argument(rhs)) {
+                (false, true) => Some((Section::Left(lhs), op)),
+                (true, false) => Some((Section::Right(rhs), op)),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Returns `\x y ->`
+fn lambda_head(lambda: &Lambda<String>) -> String {
+    let mut s = String::from("\\");
+    let args: Vec<_> = lambda.arguments.iter().map(|arg| ident(arg)).collect();
+    s.push_str(&args.join(" "));
+    s.push_str(" ->");
+    s
+}
+
+fn braces(fields: Vec<String>) -> String {
+    if fields.is_empty() {
+        String::from("{}")
+    } else {
+        format!("{{ {} }}", fields.join(", "))
+    }
+}
+
+/// Returns the type field `T` or `T = Type` of a record expression
+fn record_type_field(field: &(String, Option<AstType<String>>)) -> String {
+    match field.1 {
+        Some(ref typ) => format!("{} = {}", field.0, type_str(typ, Prec::Top)),
+        None => field.0.clone(),
+    }
+}
+
+/// Returns `literal` as a string. Number literals are printed as they are written in `source` so
+/// that they keep their base and digit separators.
+fn literal_str(source: &str, literal: &LiteralEnum, span: Span) -> String {
+    let (start, end) = (span.start.absolute.to_usize(), span.end.absolute.to_usize());
+    match *literal {
+        LiteralEnum::Byte(_) |
+        LiteralEnum::Integer(_) |
+        LiteralEnum::Float(_) if start < end && end <= source.len() => {
+            return source[start..end].trim().to_string();
+        }
+        _ => (),
+    }
+    match *literal {
+        LiteralEnum::Byte(b) => format!("{}b", b),
+        LiteralEnum::Integer(i) => i.to_string(),
+        LiteralEnum::Float(f) => {
+            // A float without a fractional part would be read back as an integer
+            let s = f.to_string();
+            if s.contains('.') { s } else { s + ".0" }
+        }
+        LiteralEnum::String(ref s) => {
+            let mut out = String::from("\"");
+            for c in s.chars() {
+                escape(&mut out, c, '"');
+            }
+            out.push('"');
+            out
+        }
+        LiteralEnum::Char(c) => {
+            let mut out = String::from("'");
+            escape(&mut out, c, '\'');
+            out.push('\'');
+            out
+        }
+    }
+}
+
+/// Writes `c` as it is written inside a string or character literal delimited by `quote`
+fn escape(out: &mut String, c: char, quote: char) {
+    match c {
+        '\\' => out.push_str("\\\\"),
+        '\n' => out.push_str("\\n"),
+        '\r' => out.push_str("\\r"),
+        '\t' => out.push_str("\\t"),
+        '\0' => out.push_str("\\0"),
+        _ if c == quote => {
+            out.push('\\');
+            out.push(c);
+        }
+        _ if c.is_control() => out.push_str(&format!("\\u{{{:x}}}", c as u32)),
+        _ => out.push(c),
+    }
+}
+
+fn pattern_str(source: &str, pattern: &LPattern<String>, arg: bool) -> String {
+    match pattern.value {
+        Pattern::Identifier(ref id) => ident(id),
+        Pattern::Constructor(ref id, ref args) => {
+            let mut s = ident(id);
+            if args.is_empty() {
+                return s;
+            }
+            for arg in args {
+                s.push(' ');
+                s.push_str(&pattern_str(source, arg, true));
+            }
+            if arg { format!("({})", s) } else { s }
+        }
+        Pattern::Record { ref types, ref fields, .. } => {
+            let mut elems: Vec<_> = types.iter()
+                .map(|&(ref name, ref alias)| match *alias {
+                    Some(ref alias) => format!("{} = {}", name, alias),
+                    None => name.clone(),
+                })
+                .collect();
+            elems.extend(fields.iter().map(|&(ref name, ref field)| match *field {
+                Some(ref field) => {
+                    format!("{} = {}", ident(name), pattern_str(source, field, false))
+                }
+                None => ident(name),
+            }));
+            braces(elems)
+        }
+        Pattern::Tuple { ref elems, .. } => {
+            let elems: Vec<_> = elems.iter().map(|elem| pattern_str(source, elem, false)).collect();
+            format!("({})", elems.join(", "))
+        }
+        Pattern::Literal(ref literal) => literal_str(source, literal, pattern.span),
+    }
+}
+
+fn type_str(typ: &AstType<String>, prec: Prec) -> String {
+    let parens = |enclose: bool, s: String| if enclose { format!("({})", s) } else { s };
+    match **typ {
+        Type::App(ref function, ref args) => {
+            if let Some((arg, ret)) = typ.as_function() {
+                parens(prec != Prec::Top,
+                       format!("{} -> {}",
+                               type_str(arg, Prec::Function),
+                               type_str(ret, Prec::Top)))
+            } else if let Some((arg, ret)) = typ.as_implicit_function() {
+                parens(prec != Prec::Top,
+                       format!("[{}] -> {}",
+                               type_str(arg, Prec::Top),
+                               type_str(ret, Prec::Top)))
+            } else {
+                let mut s = type_str(function, Prec::Constructor);
+                for arg in args {
+                    s.push(' ');
+                    s.push_str(&type_str(arg, Prec::Constructor));
+                }
+                parens(prec == Prec::Constructor, s)
+            }
+        }
+        Type::Variants(ref variants) => {
+            let variants: Vec<_> = variants.iter().map(variant_str).collect();
+            parens(prec != Prec::Top, variants.join(" "))
+        }
+        Type::Variable(ref var) => var.to_string(),
+        Type::Generic(ref generic) => generic.id.clone(),
+        Type::Builtin(builtin) => String::from(builtin.to_str()),
+        Type::Record { ref types, ref fields, ref rest } => {
+            let mut elems: Vec<_> = types.iter().map(|field| associated_type(field)).collect();
+            elems.extend(fields.iter().map(|field| {
+                format!("{} : {}", ident(&field.name), type_str(&field.typ, Prec::Top))
+            }));
+            match *rest {
+                Some(ref rest) if elems.is_empty() => {
+                    format!("{{ | {} }}", type_str(rest, Prec::Top))
+                }
+                Some(ref rest) => {
+                    format!("{{ {} | {} }}", elems.join(", "), type_str(rest, Prec::Top))
+                }
+                None => braces(elems),
+            }
+        }
+        Type::Id(ref id) => id.clone(),
+        Type::Alias(ref alias) => alias.name.clone(),
+        Type::Forall(ref params, ref typ) => {
+            let params: Vec<_> = params.iter().map(type_param).collect();
+            parens(prec != Prec::Top,
+                   format!("forall {} . {}", params.join(" "), type_str(typ, Prec::Top)))
+        }
+    }
+}
+
+/// Returns the variant `| A Int Float` which has the constructor type `Int -> Float -> T`
+fn variant_str(variant: &(String, AstType<String>)) -> String {
+    let mut s = format!("| {}", variant.0);
+    let mut typ = &variant.1;
+    while let Some((arg, ret)) = typ.as_function() {
+        s.push(' ');
+        s.push_str(&type_str(arg, Prec::Constructor));
+        typ = ret;
+    }
+    s
+}
+
+/// Returns the associated type `T` of a record type or `type T` if it is abstract
+fn associated_type(field: &Field<String, Alias<String, AstType<String>>>) -> String {
+    match field.typ.typ {
+        Some(_) => field.typ.name.clone(),
+        None => format!("type {}", field.typ.name),
+    }
+}
+
+fn type_param(param: &Generic<String>) -> String {
+    match *param.kind {
+        Kind::Variable(_) => param.id.clone(),
+        _ => format!("({} : {})", param.id, *param.kind),
+    }
+}
+
+/// Finds the comments and fixity declarations in `tree`. Doc comments are not included as they
+/// are stored in the AST.
+fn collect_trivia(tree: &SyntaxTree) -> Vec<Trivia> {
+    let mut trivia = Vec::new();
+    let tokens = tree.tokens();
+    for (i, token) in tokens.iter().enumerate() {
+        trivia.extend(comments(tree, &token.leading_trivia));
+        if let Some(declaration) = fixity_declaration(tree, &tokens[i..]) {
+            trivia.push(declaration);
+        }
+    }
+    trivia.extend(comments(tree, tree.trailing_trivia()));
+    trivia
+}
+
+fn comments<'a>(tree: &'a SyntaxTree,
+                trivia: &'a [cst::Trivia])
+                -> Box<Iterator<Item = Trivia> + 'a> {
+    Box::new(trivia.iter()
+        .filter(|trivia| trivia.kind != cst::TriviaKind::Whitespace)
+        .map(move |trivia| {
+            Trivia {
+                span: trivia.span,
+                text: String::from(tree.text(trivia.span).trim_right()),
+                kind: TriviaKind::Comment,
+            }
+        }))
+}
+
+/// Returns the fixity declaration, `infixl 4 <|> <*>`, which starts at the first token of
+/// `tokens` (if there is one) printed in canonical form together with the fixities it declares
+fn fixity_declaration(tree: &SyntaxTree, tokens: &[SyntaxToken]) -> Option<Trivia> {
+    let (keyword, associativity) = match tokens.first().map(|token| &token.token) {
+        Some(&Token::Infixl) => ("infixl", Associativity::Left),
+        Some(&Token::Infixr) => ("infixr", Associativity::Right),
+        _ => return None,
+    };
+    let fixity = match tokens.get(1).map(|token| &token.token) {
+        Some(&Token::Integer(precedence)) => {
+            Fixity {
+                associativity: associativity,
+                precedence: precedence as i32,
+            }
+        }
+        _ => return None,
+    };
+    let ops: Vec<_> = tokens[2..]
+        .iter()
+        .take_while(|token| match token.token {
+            Token::Operator(_) => true,
+            _ => false,
+        })
+        .collect();
+    let end = match ops.last() {
+        Some(op) => op.span.end,
+        None => return None,
+    };
+    let texts: Vec<_> = ops.iter().map(|op| tree.text(op.span)).collect();
+    // Operators written as `name` are declared for `name`
+    let names = ops.iter()
+        .filter_map(|op| match op.token {
+            Token::Operator(ref name) => Some((name.clone(), fixity)),
+            _ => None,
+        })
+        .collect();
+    Some(Trivia {
+        span: Span {
+            start: tokens[0].span.start,
+            end: end,
+        },
+        text: format!("{} {} {}", keyword, fixity.precedence, texts.join(" ")),
+        kind: TriviaKind::Fixity(names),
+    })
+}
@@ -1,11 +1,13 @@
 use std::cell::RefCell;
 use std::cmp::Ordering;
+use std::error::Error as StdError;
 use std::fmt;
 use std::rc::Rc;
 
 use base::ast::*;
+use base::pos::{BytePos, CharPos, Location};
 
-use combine::primitives::{Consumed, SourcePosition, Error as CombineError};
+use combine::primitives::{Consumed, StreamOnce, Error as CombineError};
 use combine::combinator::EnvParser;
 use combine::*;
 use combine_language::{LanguageEnv, LanguageDef, Identifier};
@@ -65,6 +67,12 @@ pub enum Token<Id> {
     If,
     Then,
     Else,
+    Implicit,
+    Do,
+    Infixl,
+    Infixr,
+    Forall,
+    Deriving,
     Open(Delimiter),
     Close(Delimiter),
     Lambda,
@@ -102,6 +110,12 @@ impl<Id> fmt::Display for Token<Id> {
             If => "If",
             Then => "Then",
             Else => "Else",
+            Implicit => "Implicit",
+            Do => "Do",
+            Infixl => "Infixl",
+            Infixr => "Infixr",
+            Forall => "Forall",
+            Deriving => "Deriving",
             Open(Brace) => "OpenBrace",
             Close(Brace) => "CloseBrace",
             Open(Paren) => "OpenParen",
@@ -147,6 +161,12 @@ impl<Id> Token<Id> {
             If => If,
             Then => Then,
             Else => Else,
+            Implicit => Implicit,
+            Do => Do,
+            Infixl => Infixl,
+            Infixr => Infixr,
+            Forall => Forall,
+            Deriving => Deriving,
             Open(d) => Open(d),
             Close(d) => Close(d),
             Lambda => Lambda,
@@ -166,14 +186,66 @@ impl<Id> Token<Id> {
 
 #[derive(Clone, Debug)]
 pub struct PToken<Id> {
-    pub location: SourcePosition,
+    pub location: Location,
+    /// The location just after the token, not including any whitespace after it. Tokens inserted
+    /// by the layout rules end where they start
+    pub end: Location,
     pub token: Token<Id>,
 }
 
+impl<Id> PToken<Id> {
+    /// Creates a token which is inserted by the layout rules and therefore takes up no space in
+    /// the source
+    fn layout(location: Location, token: Token<Id>) -> PToken<Id> {
+        PToken {
+            location: location,
+            end: location,
+            token: token,
+        }
+    }
+}
+
+/// The kind of a comment which is not a doc comment
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum CommentKind {
+    /// `// ...`
+    Line,
+    /// `/* ... */`
+    Block,
+}
+
+/// A comment which the lexer skipped over
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct PComment {
+    pub location: Location,
+    pub end: Location,
+    pub kind: CommentKind,
+}
+
+/// A number literal whose value can't be represented by its type
+#[derive(Clone, Debug, PartialEq)]
+pub struct LiteralError {
+    pub message: &'static str,
+    pub location: Location,
+    pub end: Location,
+}
+
+impl fmt::Display for LiteralError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl StdError for LiteralError {
+    fn description(&self) -> &str {
+        self.message
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct Offside {
     pub context: Context,
-    pub location: SourcePosition,
+    pub location: Location,
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -194,8 +266,69 @@ pub enum Context {
     Lambda,
 }
 
+/// A stream of characters which keeps track of the location, including the byte offset, of the
+/// next character
+#[derive(Clone, Debug)]
+pub struct LocatedStream<I> {
+    input: I,
+    location: Location,
+    /// The location just after the last character which is not whitespace
+    end: Location,
+}
+
+impl<I> LocatedStream<I>
+    where I: Stream<Item = char>
+{
+    pub fn new(input: I) -> LocatedStream<I> {
+        let start = Location {
+            line: 1,
+            column: CharPos(1),
+            absolute: BytePos(0),
+        };
+        LocatedStream {
+            input: input,
+            location: start,
+            end: start,
+        }
+    }
+
+    /// Returns the location just after the last character read which is not whitespace
+    pub fn end(&self) -> Location {
+        self.end
+    }
+}
+
+impl<I> StreamOnce for LocatedStream<I>
+    where I: Stream<Item = char>
+{
+    type Item = char;
+    type Range = I::Range;
+    type Position = Location;
+
+    fn uncons(&mut self) -> Result<char, CombineError<char, I::Range>> {
+        let c = try!(self.input.uncons());
+        self.location.absolute += BytePos::from(c.len_utf8());
+        if c == '\n' {
+            self.location.line += 1;
+            self.location.column = CharPos(1);
+        } else {
+            self.location.column += CharPos(1);
+        }
+        if !c.is_whitespace() {
+            self.end = self.location;
+        }
+        Ok(c)
+    }
+
+    fn position(&self) -> Location {
+        self.location
+    }
+}
+
 /// Parser passes the environment to each parser function
-type LanguageParser<'a: 'b, 'b, I: 'b, F: 'b, T> = EnvParser<&'b Lexer<'a, I, F>, State<I>, T>;
+type LanguageParser<'a: 'b, 'b, I: 'b, F: 'b, T> = EnvParser<&'b Lexer<'a, I, F>,
+                                                              LocatedStream<I>,
+                                                              T>;
 
 pub struct Contexts {
     stack: Vec<Offside>,
@@ -250,7 +383,8 @@ impl Contexts {
     }
 }
 
-fn is_operator_char(c: char) -> bool {
+/// Returns true if `c` can be part of an operator
+pub fn is_operator_char(c: char) -> bool {
     "+-*/&|=<>".chars().any(|x| x == c)
 }
 
@@ -258,12 +392,19 @@ pub struct Lexer<'a, I, F>
     where I: Stream<Item = char>,
           F: IdentEnv
 {
-    pub env: LanguageEnv<'a, State<I>>,
+    pub env: LanguageEnv<'a, LocatedStream<I>>,
     pub make_ident: Rc<RefCell<F>>,
-    pub input: Option<State<I>>,
+    pub input: Option<LocatedStream<I>>,
     pub unprocessed_tokens: Vec<PToken<F::Ident>>,
     pub indent_levels: Contexts,
-    end_position: SourcePosition,
+    end_position: Location,
+    /// The end of the last token in the source which has been emitted at or before each emitted
+    /// token
+    token_ends: Rc<RefCell<Vec<Location>>>,
+    /// The comments which have been skipped, if they are recorded
+    comments: Option<Rc<RefCell<Vec<PComment>>>>,
+    /// Number literals which could be lexed but whose values are out of range
+    literal_errors: Rc<RefCell<Vec<LiteralError>>>,
 }
 
 impl<'a, 's, I, Id, F> Lexer<'a, I, F>
@@ -296,22 +437,57 @@ impl<'a, 's, I, Id, F> Lexer<'a, I, F>
         Lexer {
             env: env,
             make_ident: make_ident,
-            input: Some(State::new(input)),
+            input: Some(LocatedStream::new(input)),
             unprocessed_tokens: Vec::new(),
             indent_levels: Contexts { stack: Vec::new() },
-            end_position: SourcePosition {
-                column: -1,
-                line: -1,
+            end_position: Location {
+                line: 1,
+                column: CharPos(1),
+                absolute: BytePos(0),
             },
+            token_ends: Rc::new(RefCell::new(Vec::new())),
+            comments: None,
+            literal_errors: Rc::new(RefCell::new(Vec::new())),
+        }
+    }
+
+    /// Makes the lexer record the comments which it skips over and returns the comments which
+    /// are recorded. Doc comments are returned as tokens and are therefore not recorded.
+    pub fn record_comments(&mut self) -> Rc<RefCell<Vec<PComment>>> {
+        let comments = self.comments.take().unwrap_or_else(|| Rc::new(RefCell::new(Vec::new())));
+        self.comments = Some(comments.clone());
+        comments
+    }
+
+    fn comment(&self, location: Location, end: Location, kind: CommentKind) {
+        if let Some(ref comments) = self.comments {
+            comments.borrow_mut().push(PComment {
+                location: location,
+                end: end,
+                kind: kind,
+            });
         }
     }
 
+    /// Returns the positions at which the tokens emitted by the lexer end. The `n`th position is
+    /// the end of the last token before or at the `n`th emitted token which is not inserted by the
+    /// layout rules.
+    pub fn token_ends(&self) -> Rc<RefCell<Vec<Location>>> {
+        self.token_ends.clone()
+    }
+
+    /// Returns the errors for number literals which are out of range. Such literals are still
+    /// emitted as tokens so that the rest of the input can be parsed.
+    pub fn literal_errors(&self) -> Rc<RefCell<Vec<LiteralError>>> {
+        self.literal_errors.clone()
+    }
+
     fn intern(&self, s: &str) -> Id {
         self.make_ident.borrow_mut().from_str(s)
     }
 
     fn parser<T>(&'s self,
-                 parser: fn(&Lexer<'a, I, F>, State<I>) -> ParseResult<T, State<I>>)
+                 parser: fn(&Lexer<'a, I, F>, LocatedStream<I>) -> ParseResult<T, LocatedStream<I>>)
                  -> LanguageParser<'a, 's, I, F, T> {
         env_parser(self, parser)
     }
@@ -321,7 +497,7 @@ impl<'a, 's, I, Id, F> Lexer<'a, I, F>
         self.parser(Lexer::parse_op)
     }
 
-    fn parse_op(&self, input: State<I>) -> ParseResult<Id, State<I>> {
+    fn parse_op(&self, input: LocatedStream<I>) -> ParseResult<Id, LocatedStream<I>> {
         (optional(char('#').with(many(letter()))), try(self.env.op()))
             .map(|(builtin, op): (Option<String>, String)| {
                 match builtin {
@@ -339,27 +515,16 @@ impl<'a, 's, I, Id, F> Lexer<'a, I, F>
     fn ident(&'s self) -> LanguageParser<'a, 's, I, F, Token<Id>> {
         self.parser(Lexer::parse_ident)
     }
-    fn parse_ident(&self, input: State<I>) -> ParseResult<Token<Id>, State<I>> {
+    fn parse_ident(&self, input: LocatedStream<I>) -> ParseResult<Token<Id>, LocatedStream<I>> {
         self.parser(Lexer::parse_ident2)
             .map(|x| Token::Identifier(x.0, x.1))
             .parse_state(input)
     }
 
-    fn integer<'b>(&'b self) -> LanguageParser<'a, 'b, I, F, i64> {
-        self.parser(Self::integer_)
-    }
-
-    fn integer_<'b>(&'b self, input: State<I>) -> ParseResult<i64, State<I>> {
-        let (s, input) = try!(many1::<String, _>(digit()).parse_lazy(input));
-        let mut n = 0;
-        for c in s.chars() {
-            n = n * 10 + (c as i64 - '0' as i64);
-        }
-        Ok((n, input))
-    }
-
     /// Identifier parser which returns the identifier as well as the type of the identifier
-    fn parse_ident2(&self, input: State<I>) -> ParseResult<(Id, IdentType), State<I>> {
+    fn parse_ident2(&self,
+                    input: LocatedStream<I>)
+                    -> ParseResult<(Id, IdentType), LocatedStream<I>> {
         let id = self.env.identifier().map(|id| {
             let typ = if id.chars().next().unwrap().is_uppercase() {
                 IdentType::Constructor
@@ -375,7 +540,7 @@ impl<'a, 's, I, Id, F> Lexer<'a, I, F>
             .parse_state(input)
     }
 
-    fn layout_independent_token(&mut self, token: PToken<Id>) -> Result<Token<Id>, Error<Id>> {
+    fn layout_independent_token(&mut self, token: PToken<Id>) -> Result<PToken<Id>, Error<Id>> {
         layout(self, token)
     }
 
@@ -392,6 +557,12 @@ impl<'a, 's, I, Id, F> Lexer<'a, I, F>
                     "if" => Some(Token::If),
                     "then" => Some(Token::Then),
                     "else" => Some(Token::Else),
+                    "implicit" => Some(Token::Implicit),
+                    "do" => Some(Token::Do),
+                    "infixl" => Some(Token::Infixl),
+                    "infixr" => Some(Token::Infixr),
+                    "forall" => Some(Token::Forall),
+                    "deriving" => Some(Token::Deriving),
                     _ => None,
                 }
             }
@@ -410,22 +581,24 @@ impl<'a, 's, I, Id, F> Lexer<'a, I, F>
         let input = match self.input.take() {
             Some(input) => input,
             None => {
-                return PToken {
-                    location: SourcePosition {
-                        column: 1,
-                        line: ::std::i32::MAX,
-                    },
-                    token: Token::EOF,
-                }
+                let location = Location {
+                    line: ::std::u32::MAX,
+                    column: CharPos(1),
+                    absolute: self.end_position.absolute,
+                };
+                return PToken::layout(location, Token::EOF);
             }
         };
         let mut location = input.position();
         let result = self.next_token_(&mut location, input);
         match result {
             Ok((token, input)) => {
-                self.input = Some(input.into_inner());
+                let input = input.into_inner();
+                let end = input.end();
+                self.input = Some(input);
                 PToken {
                     location: location,
+                    end: end,
                     token: token,
                 }
             }
@@ -433,18 +606,15 @@ impl<'a, 's, I, Id, F> Lexer<'a, I, F>
                 let err = err.into_inner();
                 debug!("Error tokenizing: {:?}", err);
                 self.end_position = err.position;
-                PToken {
-                    location: location,
-                    token: Token::CloseBlock,
-                }
+                PToken::layout(location, Token::CloseBlock)
             }
         }
     }
 
     fn next_token_(&mut self,
-                   location: &mut SourcePosition,
-                   mut input: State<I>)
-                   -> ParseResult<Token<Id>, State<I>> {
+                   location: &mut Location,
+                   mut input: LocatedStream<I>)
+                   -> ParseResult<Token<Id>, LocatedStream<I>> {
         loop {
             // Skip all whitespace before the token
             let (_, new_input) = try!(spaces().parse_lazy(input));
@@ -488,11 +658,13 @@ impl<'a, 's, I, Id, F> Lexer<'a, I, F>
                                 try!(skip_many(satisfy(|c| c != '\n' && c != '\r'))
                                     .parse_lazy(input));
                             input = new_input.into_inner();
+                            self.comment(*location, input.position(), CommentKind::Line);
                             continue;
                         } else if s.starts_with("/*") {
                             // Skip over normal comments and try to parse a new token
                             let ((), new_input) = try!(self.skip_block_comment(input));
                             input = new_input.into_inner();
+                            self.comment(*location, input.position(), CommentKind::Block);
                             continue;
                         } else {
                             Token::Operator(op.clone())
@@ -501,23 +673,13 @@ impl<'a, 's, I, Id, F> Lexer<'a, I, F>
                 };
                 return Ok((tok, Consumed::Consumed(input)));
             } else if first.is_digit(10) {
-                let int_or_byte = self.env.lex((self.integer(), optional(char('b'))));
-                return try(int_or_byte.skip(not_followed_by(string("."))))
-                    .and_then(|(i, byte)| {
-                        if byte.is_none() {
-                            Ok(Token::Integer(i))
-                        } else {
-                            if i >= 0 && i <= 256 {
-                                Ok(Token::Byte(i as u8))
-                            } else {
-                                Err(CombineError::Message("Byte literal out of range".into()))
-                            }
-                        }
-                    })
-                    .or(self.env.float().map(Token::Float))
-                    .parse_state(input);
+                return self.env.lex(self.parser(Lexer::number)).parse_state(input);
             } else if first.is_alphabetic() || first == '_' {
-                return self.ident().map(|t| self.id_to_keyword(t)).parse_state(input);
+                let raw_string = self.env.lex(self.parser(Lexer::raw_string_literal));
+                return try(raw_string)
+                    .map(Token::String)
+                    .or(self.ident().map(|t| self.id_to_keyword(t)))
+                    .parse_state(input);
             }
 
             let tok = match first {
@@ -536,15 +698,219 @@ impl<'a, 's, I, Id, F> Lexer<'a, I, F>
                 ',' => Token::Comma,
                 '.' => Token::Dot,
                 '\\' => Token::Lambda,
-                '"' => return self.env.string_literal().map(Token::String).parse_state(input),
-                '\'' => return self.env.char_literal().map(Token::Char).parse_state(input),
+                '"' => {
+                    return self.env
+                        .lex(self.parser(Lexer::string_literal))
+                        .map(Token::String)
+                        .parse_state(input)
+                }
+                '\'' => {
+                    return self.env
+                        .lex(self.parser(Lexer::char_literal))
+                        .map(Token::Char)
+                        .parse_state(input)
+                }
+                // `elem` applies `elem` as an infix operator
+                '`' => {
+                    let rest = many::<String, _>(alpha_num().or(char('_')));
+                    let name = (letter().or(char('_')), rest)
+                        .map(|(first, rest)| {
+                            let mut name = first.to_string();
+                            name.push_str(&rest);
+                            name
+                        });
+                    return self.env
+                        .lex(between(char('`'), char('`'), name))
+                        .map(|name| Token::Operator(self.intern(&name)))
+                        .parse_state(input);
+                }
                 _ => Token::EOF,
             };
             return Ok((tok, one_char_consumed));
         }
     }
 
-    fn skip_block_comment(&self, input: State<I>) -> ParseResult<(), State<I>> {
+    /// Parses an integer, byte or float literal. Integers and bytes may be written in hexadecimal
+    /// (`0xFF`), octal (`0o17`) or binary (`0b1010`) and all literals may separate their digits
+    /// with `_`. Literals whose value can't be represented are recorded as `LiteralError`s.
+    fn number(&self, input: LocatedStream<I>) -> ParseResult<Token<Id>, LocatedStream<I>> {
+        /// Parses digits of `radix` with any `_` separators removed
+        fn digits<I>(radix: u32, input: LocatedStream<I>) -> ParseResult<String, LocatedStream<I>>
+            where I: Stream<Item = char>
+        {
+            (satisfy(|c: char| c.is_digit(radix)),
+             many::<String, _>(satisfy(|c: char| c.is_digit(radix) || c == '_')))
+                .map(|(first, rest)| {
+                    let mut digits = first.to_string();
+                    digits.extend(rest.chars().filter(|&c| c != '_'));
+                    digits
+                })
+                .parse_state(input)
+        }
+
+        let location = input.position();
+        let radix = char('x')
+            .map(|_| 16)
+            .or(char('o').map(|_| 8))
+            .or(char('b').map(|_| 2));
+        // `0b` without any binary digits after it is the byte `0`
+        let prefixed = try(char('0').with(radix).then(|radix| {
+                parser(move |input| digits(radix, input)).map(move |digits| (radix, digits, false))
+            }));
+        let exponent = try((char('e').or(char('E')),
+                            optional(char('+').or(char('-'))),
+                            parser(|input| digits(10, input))))
+            .map(|(_, sign, digits)| {
+                let mut exponent = String::from("e");
+                exponent.extend(sign);
+                exponent.push_str(&digits);
+                exponent
+            });
+        let decimal = (parser(|input| digits(10, input)),
+                       optional(try(char('.').with(parser(|input| digits(10, input))))),
+                       optional(exponent))
+            .map(|(integer, fraction, exponent)| {
+                match (fraction, exponent) {
+                    (None, None) => (10, integer, false),
+                    (fraction, exponent) => {
+                        let mut float = integer;
+                        float.push('.');
+                        float.push_str(fraction.as_ref().map_or("0", |s| &s[..]));
+                        float.push_str(exponent.as_ref().map_or("", |s| &s[..]));
+                        (10, float, true)
+                    }
+                }
+            });
+        // Only integers may be turned into bytes with a `b` suffix
+        let byte_suffix = |float: bool| {
+            parser(move |input: LocatedStream<I>| if float {
+                Ok((None, Consumed::Empty(input)))
+            } else {
+                optional(char('b')).parse_state(input)
+            })
+        };
+        let number = prefixed.or(decimal).then(|(radix, digits, float)| {
+            byte_suffix(float).map(move |byte| (radix, digits.clone(), float, byte))
+        });
+        let ((radix, digits, float, byte), input) = try!(number.parse_state(input));
+        let end = input.clone().into_inner().position();
+
+        let (token, error) = match (float, byte) {
+            (true, _) => {
+                let f = digits.parse::<f64>().unwrap_or(0.0);
+                if f.is_infinite() {
+                    (Token::Float(0.0), Some("Float literal is too large"))
+                } else {
+                    (Token::Float(f), None)
+                }
+            }
+            (false, None) => {
+                match i64::from_str_radix(&digits, radix) {
+                    Ok(i) => (Token::Integer(i), None),
+                    Err(_) => (Token::Integer(0), Some("Integer literal is too large")),
+                }
+            }
+            (false, Some(_)) => {
+                match u8::from_str_radix(&digits, radix) {
+                    Ok(b) => (Token::Byte(b), None),
+                    Err(_) => (Token::Byte(0), Some("Byte literal out of range")),
+                }
+            }
+        };
+        if let Some(message) = error {
+            self.literal_errors.borrow_mut().push(LiteralError {
+                message: message,
+                location: location,
+                end: end,
+            });
+        }
+        Ok((token, input))
+    }
+
+    /// Parses a string literal, `"abc\n"`. Line breaks in the literal are part of the string
+    /// unless they are escaped with a `\` in which case the line break and the indentation of
+    /// the next line are skipped.
+    fn string_literal(&self, input: LocatedStream<I>) -> ParseResult<String, LocatedStream<I>> {
+        let escaped = char('\\').with(self.parser(Lexer::escape));
+        let unescaped = satisfy(|c| c != '"' && c != '\\').map(Some);
+        between(char('"'), char('"'), many(escaped.or(unescaped)))
+            .map(|chars: Vec<Option<char>>| chars.into_iter().filter_map(|c| c).collect())
+            .parse_state(input)
+    }
+
+    /// Parses a character literal, `'a'`, which may contain the same escapes as a string literal
+    fn char_literal(&self, input: LocatedStream<I>) -> ParseResult<char, LocatedStream<I>> {
+        let escaped = char('\\').with(self.parser(Lexer::escape)).and_then(|c| {
+            c.ok_or_else(|| CombineError::Message("Line breaks can not be escaped here".into()))
+        });
+        let unescaped = satisfy(|c| c != '\'' && c != '\\');
+        between(char('\''), char('\''), escaped.or(unescaped)).parse_state(input)
+    }
+
+    /// Parses the part of an escape sequence after the `\`. Returns `None` for an escaped line
+    /// break.
+    fn escape(&self, input: LocatedStream<I>) -> ParseResult<Option<char>, LocatedStream<I>> {
+        // `\u{1F600}`
+        let unicode = char('u')
+            .with(between(char('{'), char('}'), many1::<String, _>(hex_digit())))
+            .and_then(|digits| {
+                let c = if digits.len() <= 6 {
+                    u32::from_str_radix(&digits, 16).ok().and_then(::std::char::from_u32)
+                } else {
+                    None
+                };
+                c.map(Some).ok_or_else(|| CombineError::Message("Invalid unicode escape".into()))
+            });
+        let line_break = optional(char('\r'))
+            .with(char('\n'))
+            .skip(skip_many(satisfy(|c| c == ' ' || c == '\t')))
+            .map(|_| None);
+        let simple = any().and_then(|c| {
+            let c = match c {
+                'n' => '\n',
+                'r' => '\r',
+                't' => '\t',
+                '0' => '\0',
+                '\\' | '"' | '\'' => c,
+                _ => return Err(CombineError::Message("Unknown escape sequence".into())),
+            };
+            Ok(Some(c))
+        });
+        unicode.or(line_break).or(simple).parse_state(input)
+    }
+
+    /// Parses a raw string, `r"..."` or `r#"..."#`, in which escapes are not processed. The string
+    /// ends at the first `"` which is followed by as many `#` as there are after the `r`.
+    fn raw_string_literal(&self, input: LocatedStream<I>) -> ParseResult<String, LocatedStream<I>> {
+        let (hashes, input) = try!((char('r'), many::<String, _>(char('#')), char('"'))
+            .map(|(_, hashes, _)| hashes.len())
+            .parse_state(input));
+        let mut raw_string = parser(|input| {
+            let mut input = Consumed::Empty(input);
+            let mut out = String::new();
+            loop {
+                let (c, rest) = try!(input.combine(|input| any().parse_state(input)));
+                input = rest;
+                if c == '"' {
+                    let end = count::<String, _>(hashes, char('#')).and_then(|found| {
+                        if found.len() == hashes {
+                            Ok(())
+                        } else {
+                            Err(CombineError::Message("Expected `#`".into()))
+                        }
+                    });
+                    match input.clone().combine(|input| try(end).parse_lazy(input)) {
+                        Ok((_, input)) => return Ok((out, input)),
+                        Err(_) => (),
+                    }
+                }
+                out.push(c);
+            }
+        });
+        input.combine(|input| raw_string.parse_state(input))
+    }
+
+    fn skip_block_comment(&self, input: LocatedStream<I>) -> ParseResult<(), LocatedStream<I>> {
         let mut block_doc_comment = parser(|input| {
             let mut input = Consumed::Empty(input);
             loop {
@@ -564,7 +930,9 @@ impl<'a, 's, I, Id, F> Lexer<'a, I, F>
         });
         block_doc_comment.parse_state(input)
     }
-    fn block_doc_comment(&self, input: State<I>) -> ParseResult<Token<Id>, State<I>> {
+    fn block_doc_comment(&self,
+                         input: LocatedStream<I>)
+                         -> ParseResult<Token<Id>, LocatedStream<I>> {
         let mut block_doc_comment = parser(|input| {
             let mut input = Consumed::Empty(input);
             let mut out = String::new();
@@ -590,14 +958,14 @@ impl<'a, 's, I, Id, F> Lexer<'a, I, F>
 
 fn layout<'a, I, Id, F>(lexer: &mut Lexer<'a, I, F>,
                         mut token: PToken<Id>)
-                        -> Result<Token<Id>, Error<Id>>
+                        -> Result<PToken<Id>, Error<Id>>
     where I: Stream<Item = char> + 'a,
           F: IdentEnv<Ident = Id>,
           Id: Clone + PartialEq + fmt::Debug,
           I::Range: fmt::Debug
 {
     if token.token == Token::EOF {
-        token.location.column = 0;
+        token.location.column = CharPos(0);
     }
     loop {
         // Retrieve the current indentation level if one exists
@@ -605,7 +973,7 @@ fn layout<'a, I, Id, F>(lexer: &mut Lexer<'a, I, F>,
             Some(offside) => offside,
             None => {
                 if token.token == Token::EOF {
-                    return Ok(token.token);
+                    return Ok(token);
                 }
                 try!(lexer.indent_levels.push(Offside {
                     context: Context::Block {
@@ -615,8 +983,9 @@ fn layout<'a, I, Id, F>(lexer: &mut Lexer<'a, I, F>,
                     location: token.location,
                 }));
                 debug!("Default block {:?}", token);
+                let location = token.location;
                 lexer.unprocessed_tokens.push(token);
-                return Ok(Token::OpenBlock);
+                return Ok(PToken::layout(location, Token::OpenBlock));
             }
         };
         debug!("--------\n{:?}\n{:?}", token, offside);
@@ -634,14 +1003,15 @@ fn layout<'a, I, Id, F>(lexer: &mut Lexer<'a, I, F>,
 
             if token.token == Token::Comma &&
                (offside.context == Context::Delimiter(Delimiter::Brace) ||
-                offside.context == Context::Delimiter(Delimiter::Bracket)) {
-                return Ok(token.token);
+                offside.context == Context::Delimiter(Delimiter::Bracket) ||
+                offside.context == Context::Delimiter(Delimiter::Paren)) {
+                return Ok(token);
             }
             lexer.indent_levels.pop();
             match (&token.token, &offside.context) {
                 (&Token::Else, &Context::If) => (),
                 (&Token::Close(close_delim), &Context::Delimiter(context_delim))
-                    if close_delim == context_delim => return Ok(token.token),
+                    if close_delim == context_delim => return Ok(token),
                 (&Token::In, &Context::Let) |
                 (&Token::In, &Context::Type) |
                 (&Token::CloseBlock, &Context::Block { .. }) => {
@@ -652,13 +1022,14 @@ fn layout<'a, I, Id, F>(lexer: &mut Lexer<'a, I, F>,
                             *emit_semi = false;
                         }
                     }
-                    return Ok(token.token);
+                    return Ok(token);
                 }
                 _ => {
                     match offside.context {
                         Context::Block { needs_close: true, .. } => {
+                            let location = token.location;
                             lexer.unprocessed_tokens.push(token);
-                            return Ok(Token::CloseBlock);
+                            return Ok(PToken::layout(location, Token::CloseBlock));
                         }
                         _ => (),
                     }
@@ -673,7 +1044,7 @@ fn layout<'a, I, Id, F>(lexer: &mut Lexer<'a, I, F>,
                     Ordering::Less => {
                         if needs_close {
                             lexer.unprocessed_tokens.push(token.clone());
-                            token.token = Token::CloseBlock;
+                            token = PToken::layout(token.location, Token::CloseBlock);
                         } else {
                             lexer.indent_levels.pop();
                         }
@@ -690,8 +1061,9 @@ fn layout<'a, I, Id, F>(lexer: &mut Lexer<'a, I, F>,
                                         *emit_semi = false;
                                     }
                                 }
+                                let location = token.location;
                                 lexer.unprocessed_tokens.push(token);
-                                return Ok(Token::Semi);
+                                return Ok(PToken::layout(location, Token::Semi));
                             }
                             Token::DocComment(_) |
                             Token::OpenBlock => (),
@@ -737,15 +1109,19 @@ fn layout<'a, I, Id, F>(lexer: &mut Lexer<'a, I, F>,
                             *emit_semi = false;
                         }
                     }
+                    let location = token.location;
                     lexer.unprocessed_tokens.push(token);
-                    return Ok(Token::In);
+                    return Ok(PToken::layout(location, Token::In));
                 }
             }
             _ => (),
         }
         // Some tokens directly inserts a new context when emitted
         let push_context = match token.token {
-            Token::Let => Some(Context::Let),
+            // `do x = action` and fixity declarations have the same layout rules as a `let` binding
+            Token::Let | Token::Do | Token::Infixl | Token::Infixr => Some(Context::Let),
+            // An `if` directly inside a match clause is a guard which has no `else` branch
+            Token::If if offside.context == Context::MatchClause => None,
             Token::If => Some(Context::If),
             Token::Type => Some(Context::Type),
             Token::Match => Some(Context::Expr),
@@ -758,15 +1134,16 @@ fn layout<'a, I, Id, F>(lexer: &mut Lexer<'a, I, F>,
                 context: context,
                 location: token.location,
             };
-            return lexer.indent_levels.push(offside).map(move |()| token.token);
+            return lexer.indent_levels.push(offside).map(move |()| token);
         }
         // For other tokens we need to scan for the next token to get its position
         match token.token {
             Token::In => {
                 lexer.indent_levels.pop();
                 if let Context::Block { needs_close: true, .. } = offside.context {
+                    let location = token.location;
                     lexer.unprocessed_tokens.push(token);
-                    return Ok(Token::CloseBlock);
+                    return Ok(PToken::layout(location, Token::CloseBlock));
                 }
             }
             Token::Equal => {
@@ -829,7 +1206,7 @@ fn layout<'a, I, Id, F>(lexer: &mut Lexer<'a, I, F>,
             Token::With => try!(scan_for_next_block(lexer, Context::MatchClause)),
             _ => (),
         }
-        return Ok(token.token);
+        return Ok(token);
     }
 }
 
@@ -845,10 +1222,7 @@ fn scan_for_next_block<'a, 's, I, Id, F>(lexer: &mut Lexer<'a, I, F>,
     let location = next.location;
     lexer.unprocessed_tokens.push(next);
     if let Context::Block { needs_close: true, .. } = context {
-        lexer.unprocessed_tokens.push(PToken {
-            location: location,
-            token: Token::OpenBlock,
-        });
+        lexer.unprocessed_tokens.push(PToken::layout(location, Token::OpenBlock));
     }
     lexer.indent_levels.push(Offside {
         context: context,
@@ -864,15 +1238,22 @@ impl<'a, I, Id, F> StreamOnce for Lexer<'a, I, F>
 {
     type Item = Token<Id>;
     type Range = Token<Id>;
-    type Position = SourcePosition;
+    type Position = Location;
 
     fn uncons(&mut self) -> Result<Token<Id>, Error<Id>> {
         let token = self.next_token();
         match self.layout_independent_token(token) {
-            Ok(Token::EOF) => Err(Error::end_of_input()),
+            Ok(PToken { token: Token::EOF, .. }) => Err(Error::end_of_input()),
             Ok(token) => {
                 debug!("Lex {:?}", token);
-                Ok(token)
+                let mut ends = self.token_ends.borrow_mut();
+                let end = if token.end != token.location {
+                    token.end
+                } else {
+                    ends.last().cloned().unwrap_or(token.location)
+                };
+                ends.push(end);
+                Ok(token.token)
             }
             Err(err) => {
                 if let Some(input) = self.input.take() {
@@ -887,7 +1268,7 @@ impl<'a, I, Id, F> StreamOnce for Lexer<'a, I, F>
         self.unprocessed_tokens
             .last()
             .map(|token| token.location.clone())
-            .or_else(|| self.input.as_ref().map(|input| input.position.clone()))
+            .or_else(|| self.input.as_ref().map(|input| input.position()))
             .unwrap_or_else(|| self.end_position.clone())
     }
 }
@@ -7,9 +7,12 @@ extern crate gluon_base as base;
 extern crate combine;
 extern crate combine_language;
 
+pub mod cst;
+pub mod format;
 pub mod lexer;
 
 use std::cell::RefCell;
+use std::error::Error as StdError;
 use std::fmt;
 use std::iter::FromIterator;
 use std::rc::Rc;
@@ -17,18 +20,20 @@ use std::rc::Rc;
 use base::ast;
 use base::ast::*;
 use base::error::Errors;
-use base::pos::{BytePos, CharPos, Located, Location, located};
-use base::types::{Type, Generic, Alias, Field, Kind, TypeVariable};
+use base::diagnostic::Diagnostic;
+use base::pos::{self, Located, Location, Span, located};
+use base::types::{Type, Generic, Alias, AliasData, Field, Kind, RcKind, TypeVariable};
 use base::symbol::{Name, Symbol, SymbolModule};
 
 use combine::primitives::{Consumed, Stream, StreamOnce, Error as CombineError, Info,
-                          BufferedStream, SourcePosition};
+                          BufferedStream};
 use combine::combinator::EnvParser;
-use combine::{between, choice, env_parser, many, many1, optional, parser, satisfy, sep_by1,
-              sep_end_by, token, try, value, ParseError, ParseResult, Parser, ParserExt};
+use combine::{between, choice, env_parser, many, many1, optional, parser, satisfy, sep_by,
+              sep_by1, sep_end_by, sep_end_by1, token, try, value, ParseError, ParseResult, Parser,
+              ParserExt};
 use combine_language::{Assoc, Fixity, expression_parser};
 
-use lexer::{Lexer, Delimiter, Token, IdentType};
+use lexer::{Lexer, Delimiter, LiteralError, Token, IdentType};
 
 pub type Error = ParseError<BufferedStream<'static,
                                            Lexer<'static,
@@ -36,24 +41,65 @@ pub type Error = ParseError<BufferedStream<'static,
                                                  &'static mut IdentEnv<Ident = String>>>>;
 
 /// Parser passes the environment to each parser function
-type LanguageParser<'b, I: 'b, F: 'b, T> = EnvParser<&'b ParserEnv<I, F>, I, T>;
+type LanguageParser<'b, 'h, I: 'b, F: 'b, T> = EnvParser<&'b ParserEnv<'h, I, F>, I, T>;
+
+/// Function which loads the module that `import "filename"` refers to and returns the fixities
+/// which the module declares
+pub type ImportFixities<'h> = FnMut(&str) -> Result<Vec<(String, ast::Fixity)>,
+                                                    Box<StdError + Send + Sync>> + 'h;
 
 /// `ParserEnv` is passed around to all individual parsers so that identifiers can always be
 /// constructed through calling `make_ident`.
-struct ParserEnv<I, F>
+struct ParserEnv<'h, I, F>
     where F: IdentEnv,
           I: Stream
 {
     empty_id: F::Ident,
     make_ident: Rc<RefCell<F>>,
     errors: RefCell<Errors<Error>>,
+    /// Fixities declared by `infixl` and `infixr` or by imported modules which are in scope,
+    /// innermost declarations last
+    fixities: RefCell<Vec<(String, ast::Fixity)>>,
+    /// Called for each `let` binding of an `import` to find the fixities of the imported module
+    import: RefCell<&'h mut ImportFixities<'h>>,
     env: ::std::marker::PhantomData<I>,
 }
 
+/// Streams which can be resynchronized after a syntax error has been found
+trait Resync: Sized {
+    /// Returns the stream at the furthest point that any parser has reached if it is ahead of
+    /// `self`
+    fn furthest(&self) -> Self;
+
+    /// Returns how many `OpenBlock` tokens the stream is currently inside
+    fn block_depth(&self) -> usize;
+
+    /// Returns the position just after the last token in the source which has been read from the
+    /// stream. Tokens inserted by the layout rules are skipped as they take up no space.
+    fn end_position(&self) -> Location;
+}
+
+type TokenStream<'a, 's, 'l, Id> = BufferedStream<'l,
+                                                  Lexer<'s,
+                                                        &'s str,
+                                                        &'a mut IdentEnv<Ident = Id>>>;
+
+/// The state of a stream just before the furthest token which has been read from it
+struct Checkpoint<'a: 'l, 's: 'l, 'l, Id: Clone + PartialEq + fmt::Debug + 'a> {
+    stream: TokenStream<'a, 's, 'l, Id>,
+    offset: usize,
+    block_depth: usize,
+}
+
 // Wrapper type to reduce typechecking times
 #[derive(Clone)]
 struct Wrapper<'a: 'l, 's: 'l, 'l, Id: Clone + PartialEq + fmt::Debug + 'a> {
-    stream: BufferedStream<'l, Lexer<'s, &'s str, &'a mut IdentEnv<Ident = Id>>>,
+    stream: TokenStream<'a, 's, 'l, Id>,
+    offset: usize,
+    block_depth: usize,
+    furthest: Rc<RefCell<Checkpoint<'a, 's, 'l, Id>>>,
+    /// The end positions recorded by the lexer, indexed by `offset`
+    token_ends: Rc<RefCell<Vec<Location>>>,
 }
 
 impl<'a, 's, 'l, Id> StreamOnce for Wrapper<'a, 's, 'l, Id>
@@ -61,10 +107,27 @@ impl<'a, 's, 'l, Id> StreamOnce for Wrapper<'a, 's, 'l, Id>
 {
     type Item = Token<Id>;
     type Range = Token<Id>;
-    type Position = SourcePosition;
+    type Position = Location;
 
     fn uncons(&mut self) -> Result<Token<Id>, ::lexer::Error<Id>> {
-        self.stream.uncons()
+        {
+            let mut furthest = self.furthest.borrow_mut();
+            if furthest.offset <= self.offset {
+                *furthest = Checkpoint {
+                    stream: self.stream.clone(),
+                    offset: self.offset,
+                    block_depth: self.block_depth,
+                };
+            }
+        }
+        let token = try!(self.stream.uncons());
+        self.offset += 1;
+        match token {
+            Token::OpenBlock => self.block_depth += 1,
+            Token::CloseBlock => self.block_depth = self.block_depth.saturating_sub(1),
+            _ => (),
+        }
+        Ok(token)
     }
 
     fn position(&self) -> Self::Position {
@@ -72,15 +135,50 @@ impl<'a, 's, 'l, Id> StreamOnce for Wrapper<'a, 's, 'l, Id>
     }
 }
 
+impl<'a, 's, 'l, Id> Resync for Wrapper<'a, 's, 'l, Id>
+    where Id: Clone + PartialEq + fmt::Debug
+{
+    fn furthest(&self) -> Self {
+        let furthest = self.furthest.borrow();
+        if furthest.offset > self.offset {
+            Wrapper {
+                stream: furthest.stream.clone(),
+                offset: furthest.offset,
+                block_depth: furthest.block_depth,
+                furthest: self.furthest.clone(),
+                token_ends: self.token_ends.clone(),
+            }
+        } else {
+            self.clone()
+        }
+    }
+
+    fn block_depth(&self) -> usize {
+        self.block_depth
+    }
+
+    fn end_position(&self) -> Location {
+        if self.offset == 0 {
+            self.position()
+        } else {
+            self.token_ends.borrow()[self.offset - 1]
+        }
+    }
+}
+
 enum LetOrType<Id: AstId> {
     Let(Vec<Binding<Id>>),
     Type(Vec<TypeBinding<Id::Untyped>>),
+    /// `do id = expr`
+    Do(Id, LExpr<Id>),
+    /// `infixl 4 <|>`, only affects how the rest of the expression is parsed
+    Fixity(Vec<(String, ast::Fixity)>),
 }
 
 macro_rules! match_parser {
     ($function: ident, $variant: ident -> $typ: ty) => {
-        fn $function(&'s self) -> LanguageParser<'s, I, F, $typ> {
-            fn inner<I, Id, F>(_: &ParserEnv<I, F>, input: I) -> ParseResult<$typ, I>
+        fn $function(&'s self) -> LanguageParser<'s, 'h, I, F, $typ> {
+            fn inner<'h, I, Id, F>(_: &ParserEnv<'h, I, F>, input: I) -> ParseResult<$typ, I>
                 where I: Stream<Item = Token<Id>>,
                       F: IdentEnv<Ident = Id>,
                       Id: AstId + Clone + PartialEq + fmt::Debug,
@@ -109,53 +207,146 @@ fn as_trait<P: Parser>(p: &mut P) -> &mut Parser<Input = P::Input, Output = P::O
     p
 }
 
-impl<'s, I, Id, F> ParserEnv<I, F>
-    where I: Stream<Item = Token<Id>, Range = Token<Id>, Position = SourcePosition>,
+/// The name of the argument of the lambdas which operator sections are desugared into. It can't
+/// be written in source code so it does not capture any variable in the section.
+pub const SECTION_ARGUMENT: &'static str = "#section";
+
+/// Returns the fixity which `op` has when no fixity has been declared for it
+fn default_fixity(op: &str) -> ast::Fixity {
+    ast::Fixity {
+        associativity: default_associativity(op),
+        precedence: default_precedence(op),
+    }
+}
+
+fn default_precedence(s: &str) -> i32 {
+    match s {
+        "*" | "/" | "%" => 7,
+        "+" | "-" => 6,
+        ":" | "++" => 5,
+        "&&" => 3,
+        "||" => 2,
+        "$" => 0,
+        "==" | "/=" | "<" | ">" | "<=" | ">=" => 4,
+        // Primitive operators starts with # and has the op at the end
+        _ if s.starts_with("#") => {
+            let op = s[1..].trim_left_matches(|c: char| c.is_alphanumeric());
+            default_precedence(op)
+        }
+        // Hack for some library operators
+        "<<" | ">>" => 9,
+        "<|" | "|>" => 0,
+        // User-defined operators
+        _ => 9,
+    }
+}
+
+fn default_associativity(i: &str) -> Associativity {
+    match i {
+        "*" | "/" | "%" | "+" | "-" | "==" | "/=" | "<" | ">" | "<=" | ">=" => Associativity::Left,
+        ":" | "++" | "&&" | "||" | "$" => Associativity::Right,
+        // Hack for some library operators
+        ">>" | "|>" => Associativity::Left,
+        "<<" | "<|" => Associativity::Right,
+        // User-defined operators
+        _ => Associativity::Left,
+    }
+}
+
+/// Returns the stream in `input` whether or not any input was consumed
+fn consumed_input<I>(input: &Consumed<I>) -> &I {
+    match *input {
+        Consumed::Consumed(ref input) |
+        Consumed::Empty(ref input) => input,
+    }
+}
+
+impl<'s, 'h, I, Id, F> ParserEnv<'h, I, F>
+    where I: Stream<Item = Token<Id>, Range = Token<Id>, Position = Location> + Resync,
           F: IdentEnv<Ident = Id>,
           Id: AstId + Clone + PartialEq + fmt::Debug,
           I::Range: fmt::Debug
 {
     fn parser<T>(&'s self,
-                 parser: fn(&ParserEnv<I, F>, I) -> ParseResult<T, I>)
-                 -> LanguageParser<'s, I, F, T> {
+                 parser: fn(&ParserEnv<'h, I, F>, I) -> ParseResult<T, I>)
+                 -> LanguageParser<'s, 'h, I, F, T> {
         env_parser(self, parser)
     }
 
-    fn precedence(&self, s: &str) -> i32 {
-        match s {
-            "*" | "/" | "%" => 7,
-            "+" | "-" => 6,
-            ":" | "++" => 5,
-            "&&" => 3,
-            "||" => 2,
-            "$" => 0,
-            "==" | "/=" | "<" | ">" | "<=" | ">=" => 4,
-            // Primitive operators starts with # and has the op at the end
-            _ if s.starts_with("#") => {
-                let op = s[1..].trim_left_matches(|c: char| c.is_alphanumeric());
-                self.precedence(op)
-            }
-            // Hack for some library operators
-            "<<" | ">>" => 9,
-            "<|" | "|>" => 0,
-            // User-defined operators
-            _ => 9,
+    /// Runs `parser` and returns its output together with the span of the tokens it consumed
+    fn spanned<P>(&self, mut parser: P, input: I) -> ParseResult<Located<P::Output>, I>
+        where P: Parser<Input = I>
+    {
+        let start = input.position();
+        let (value, input) = try!(parser.parse_state(input));
+        let end = consumed_input(&input).end_position();
+        Ok((located(pos::span(start, end), value), input))
+    }
+
+    /// Returns the innermost fixity declared for `op`
+    fn declared_fixity(&self, op: &str) -> Option<ast::Fixity> {
+        self.fixities
+            .borrow()
+            .iter()
+            .rev()
+            .find(|&&(ref name, _)| name == op)
+            .map(|&(_, fixity)| fixity)
+    }
+
+    /// Returns the fixity of `op`, preferring any fixity declaration in scope over the builtin
+    /// defaults
+    fn fixity(&self, op: &str) -> ast::Fixity {
+        self.declared_fixity(op).unwrap_or_else(|| default_fixity(op))
+    }
+
+    /// Returns the precedence and fixity of `op` as used by `expression_parser`
+    fn assoc(&self, op: &str) -> Assoc {
+        let fixity = self.fixity(op);
+        Assoc {
+            precedence: fixity.precedence,
+            fixity: match fixity.associativity {
+                Associativity::Left => Fixity::Left,
+                Associativity::Right => Fixity::Right,
+            },
         }
     }
 
-    fn fixity(&self, i: &str) -> Fixity {
-        match i {
-            "*" | "/" | "%" | "+" | "-" | "==" | "/=" | "<" | ">" | "<=" | ">=" => Fixity::Left,
-            ":" | "++" | "&&" | "||" | "$" => Fixity::Right,
-            // Hack for some library operators
-            ">>" | "|>" => Fixity::Left,
-            "<<" | "<|" => Fixity::Right,
-            // User-defined operators
-            _ => Fixity::Left,
+    /// Brings the fixities of the modules which `bindings` import through `import "filename"`
+    /// into scope. Errors from loading a module are recorded at the location of its filename.
+    fn import_fixities(&self, bindings: &[Binding<Id>]) {
+        let imports: Vec<_> = {
+            let ids = self.make_ident.borrow();
+            bindings.iter()
+                .filter_map(|bind| match bind.expression.value {
+                    Expr::Call(ref f, ref args) if args.len() == 1 => {
+                        match (&f.value, &args[0].value) {
+                            (&Expr::Identifier(ref id),
+                             &Expr::Literal(LiteralEnum::String(ref filename)))
+                                if ids.string(id) == "import" => {
+                                Some((args[0].span.start, filename.clone()))
+                            }
+                            _ => None,
+                        }
+                    }
+                    _ => None,
+                })
+                .collect()
+        };
+        for (position, filename) in imports {
+            let result = (*self.import.borrow_mut())(&filename);
+            match result {
+                Ok(fixities) => self.fixities.borrow_mut().extend(fixities),
+                Err(err) => {
+                    self.errors.borrow_mut().error(ParseError {
+                        position: position,
+                        errors: vec![CombineError::Other(err)],
+                    })
+                }
+            }
         }
     }
 
-    fn ident(&'s self) -> LanguageParser<'s, I, F, Id> {
+    fn ident(&'s self) -> LanguageParser<'s, 'h, I, F, Id> {
         self.parser(ParserEnv::<I, F>::parse_ident)
     }
     fn parse_ident(&self, input: I) -> ParseResult<Id, I> {
@@ -180,7 +371,12 @@ impl<'s, I, Id, F> ParserEnv<I, F>
             })
             .parse_state(input)
     }
-    fn ident_u(&'s self) -> LanguageParser<'s, I, F, Id::Untyped> {
+    /// Parses an identifier, recording where it was written
+    fn located_ident(&self, input: I) -> ParseResult<Located<Id>, I> {
+        self.spanned(self.ident(), input)
+    }
+
+    fn ident_u(&'s self) -> LanguageParser<'s, 'h, I, F, Id::Untyped> {
         self.parser(ParserEnv::<I, F>::parse_untyped_ident)
     }
     fn parse_untyped_ident(&self, input: I) -> ParseResult<Id::Untyped, I> {
@@ -189,7 +385,7 @@ impl<'s, I, Id, F> ParserEnv<I, F>
             .parse_state(input)
     }
 
-    fn ident_type(&'s self) -> LanguageParser<'s, I, F, AstType<Id::Untyped>> {
+    fn ident_type(&'s self) -> LanguageParser<'s, 'h, I, F, AstType<Id::Untyped>> {
         self.parser(ParserEnv::<I, F>::parse_ident_type)
     }
     fn parse_ident_type(&self, input: I) -> ParseResult<AstType<Id::Untyped>, I> {
@@ -224,10 +420,15 @@ impl<'s, I, Id, F> ParserEnv<I, F>
 
     match_parser! { doc_comment, DocComment -> String }
 
-    fn typ(&'s self) -> LanguageParser<'s, I, F, AstType<Id::Untyped>> {
+    fn typ(&'s self) -> LanguageParser<'s, 'h, I, F, AstType<Id::Untyped>> {
         self.parser(ParserEnv::<I, F>::parse_type)
     }
 
+    /// Parses a type annotation, recording where it was written
+    fn located_type(&self, input: I) -> ParseResult<LType<Id::Untyped>, I> {
+        self.spanned(self.typ(), input)
+    }
+
     fn parse_adt(&self,
                  return_type: &AstType<Id::Untyped>,
                  input: I)
@@ -242,7 +443,18 @@ impl<'s, I, Id, F> ParserEnv<I, F>
     }
 
     fn parse_type(&self, input: I) -> ParseResult<AstType<Id::Untyped>, I> {
-        (many1(self.parser(ParserEnv::<I, F>::type_arg)),
+        // `[Eq a] -> a -> Bool` is a function which takes an implicit `Eq a` argument
+        let implicit = (between(token(Token::Open(Delimiter::Bracket)),
+                                token(Token::Close(Delimiter::Bracket)),
+                                self.typ()),
+                        token(Token::RightArrow).with(self.typ()))
+            .map(|(arg, ret)| Type::implicit_function(vec![arg], ret));
+        // `forall a b . a -> b -> a` binds `a` and `b` in the type after the `.`
+        let forall = (token(Token::Forall),
+                      many1(self.parser(ParserEnv::<I, F>::type_param)),
+                      token(Token::Dot).with(self.typ()))
+            .map(|(_, params, typ): (_, Vec<_>, _)| Type::forall(params, typ));
+        let function = (many1(self.parser(ParserEnv::<I, F>::type_arg)),
          optional(token(Token::RightArrow).with(self.typ())))
             .map(|(mut arg, ret): (Vec<_>, _)| {
                 let arg = if arg.len() == 1 {
@@ -256,32 +468,42 @@ impl<'s, I, Id, F> ParserEnv<I, F>
                     Some(ret) => Type::function(vec![arg], ret),
                     None => arg,
                 }
-            })
-            .parse_state(input)
+            });
+        forall.or(implicit).or(function).parse_state(input)
     }
 
     fn record_type(&self, input: I) -> ParseResult<AstType<Id::Untyped>, I> {
-        let field = self.parser(ParserEnv::<I, F>::parse_ident2)
-            .then(|(id, typ)| {
+        // `type T` declares an abstract type `T` and `type T = X` declares that `T` is defined
+        // as `X`
+        let field = (optional(token(Token::Type)).map(|t| t.is_some()),
+                     self.parser(ParserEnv::<I, F>::parse_ident2))
+            .then(|(is_type, (id, typ))| {
                 parser(move |input| {
-                    if typ == IdentType::Constructor {
-                        value((id.clone(), None)).parse_state(input)
+                    if is_type {
+                        (many(self.parser(ParserEnv::<I, F>::type_param)),
+                         optional(token(Token::Equal).with(self.typ())))
+                            .map(|(args, definition)| (id.clone(), None, Some((args, definition))))
+                            .parse_state(input)
+                    } else if typ == IdentType::Constructor {
+                        value((id.clone(), None, None)).parse_state(input)
                     } else {
                         token(Token::Colon)
                             .with(self.typ())
-                            .map(|typ| (id.clone(), Some(typ)))
+                            .map(|typ| (id.clone(), Some(typ), None))
                             .parse_state(input)
                     }
                 })
             });
+        // `{ x : Int | r }` is a record with the field `x` and any number of other fields
+        let rest = token(Token::Pipe).with(self.typ());
         between(token(Token::Open(Delimiter::Brace)),
                 token(Token::Close(Delimiter::Brace)),
-                sep_end_by(field, token(Token::Comma)))
-            .map(|fields: Vec<(Id, _)>| {
+                (sep_end_by(field, token(Token::Comma)), optional(rest)))
+            .map(|(fields, rest): (Vec<(Id, _, Option<(Vec<Generic<Id::Untyped>>, _)>)>, _)| {
                 let mut associated = Vec::new();
                 let mut types = Vec::new();
                 let mut ids = self.make_ident.borrow_mut();
-                for (id, field) in fields {
+                for (id, field, declaration) in fields {
                     let untyped_id = id.clone().to_id();
                     match field {
                         Some(typ) => {
@@ -291,18 +513,33 @@ impl<'s, I, Id, F> ParserEnv<I, F>
                             })
                         }
                         None => {
-                            let typ = Type::id(untyped_id.clone());
                             let short_name = String::from(Name::new(ids.string(&id))
                                 .name()
                                 .as_str());
+                            let alias = match declaration {
+                                Some((args, definition)) => {
+                                    Alias::from(AliasData {
+                                        name: untyped_id,
+                                        args: args,
+                                        typ: definition,
+                                    })
+                                }
+                                None => {
+                                    let typ = Type::id(untyped_id.clone());
+                                    Alias::new(untyped_id, vec![], typ)
+                                }
+                            };
                             associated.push(Field {
                                 name: ids.from_str(&short_name).to_id(),
-                                typ: Alias::new(untyped_id, vec![], typ),
+                                typ: alias,
                             });
                         }
                     }
                 }
-                Type::record(associated, types)
+                match rest {
+                    Some(rest) => Type::poly_record(associated, types, rest),
+                    None => Type::record(associated, types),
+                }
             })
             .parse_state(input)
     }
@@ -343,46 +580,87 @@ impl<'s, I, Id, F> ParserEnv<I, F>
             .parse_state(input)
     }
 
+    /// Parses a type parameter which may be annotated with its kind, `a` or `(f : Type -> Type)`.
+    /// Parameters without an annotation are given a kind variable which is replaced when
+    /// kindchecking.
+    fn type_param(&self, input: I) -> ParseResult<Generic<Id::Untyped>, I> {
+        let annotated = between(token(Token::Open(Delimiter::Paren)),
+                                token(Token::Close(Delimiter::Paren)),
+                                (self.ident_u(), token(Token::Colon).with(self.kind())))
+            .map(|(id, kind)| {
+                Generic {
+                    kind: kind,
+                    id: id,
+                }
+            });
+        self.ident_u()
+            .map(|id| {
+                Generic {
+                    kind: Kind::variable(0),
+                    id: id,
+                }
+            })
+            .or(annotated)
+            .parse_state(input)
+    }
+
+    fn kind(&'s self) -> LanguageParser<'s, 'h, I, F, RcKind> {
+        self.parser(ParserEnv::<I, F>::parse_kind)
+    }
+
+    fn parse_kind(&self, input: I) -> ParseResult<RcKind, I> {
+        let typ = satisfy(|t: Token<Id>| {
+                match t {
+                    Token::Identifier(ref id, _) => self.make_ident.borrow().string(id) == "Type",
+                    _ => false,
+                }
+            })
+            .map(|_| Kind::typ())
+            .expected("kind");
+        let arg = typ.or(between(token(Token::Open(Delimiter::Paren)),
+                                 token(Token::Close(Delimiter::Paren)),
+                                 self.kind()));
+        (arg, optional(token(Token::RightArrow).with(self.kind())))
+            .map(|(arg, ret)| {
+                match ret {
+                    Some(ret) => Kind::function(arg, ret),
+                    None => arg,
+                }
+            })
+            .parse_state(input)
+    }
+
     fn type_binding(&self, input: I) -> ParseResult<TypeBinding<Id::Untyped>, I> {
-        (self.ident_u(), many(self.ident_u()))
-            .then(|(name, args): (Id::Untyped, Vec<Id::Untyped>)| {
-                let arg_types = args.iter()
-                    .map(|id| {
-                        Type::generic(Generic {
-                            kind: Kind::variable(0),
-                            id: id.clone(),
-                        })
-                    })
-                    .collect();
+        (self.ident_u(), many(self.parser(ParserEnv::<I, F>::type_param)))
+            .then(|(name, args): (Id::Untyped, Vec<Generic<Id::Untyped>>)| {
+                let arg_types = args.iter().cloned().map(Type::generic).collect();
                 let return_type = if args.is_empty() {
                     Type::id(name.clone())
                 } else {
                     Type::app(Type::id(name.clone()), arg_types)
                 };
-                token(Token::Equal)
+                let rhs = token(Token::Equal)
                     .with(self.typ()
-                        .or(parser(move |input| self.parse_adt(&return_type, input))))
-                    .map(move |rhs_type| {
+                        .or(parser(move |input| self.parse_adt(&return_type, input))));
+                // `deriving (Eq, Show)`
+                let deriving = token(Token::Deriving)
+                    .with(between(token(Token::Open(Delimiter::Paren)),
+                                  token(Token::Close(Delimiter::Paren)),
+                                  sep_by1(self.ident_u(), token(Token::Comma))));
+                (rhs, optional(deriving))
+                    .map(move |(rhs_type, deriving)| {
                         TypeBinding {
                             comment: None,
+                            deriving: deriving.unwrap_or_else(Vec::new),
                             name: name.clone(),
-                            alias: Alias::new(name.clone(),
-                                              args.iter()
-                                                  .map(|id| {
-                                                      Generic {
-                                                          kind: Kind::variable(0),
-                                                          id: id.clone(),
-                                                      }
-                                                  })
-                                                  .collect(),
-                                              rhs_type),
+                            alias: Alias::new(name.clone(), args.clone(), rhs_type),
                         }
                     })
             })
             .parse_state(input)
     }
 
-    fn expr(&'s self) -> LanguageParser<'s, I, F, LExpr<Id>> {
+    fn expr(&'s self) -> LanguageParser<'s, 'h, I, F, LExpr<Id>> {
         self.parser(ParserEnv::<I, F>::top_expr)
     }
 
@@ -392,7 +670,8 @@ impl<'s, I, Id, F> ParserEnv<I, F>
         (arg_expr1, many(arg_expr2))
             .map(|(f, args): (LExpr<Id>, Vec<_>)| {
                 if args.len() > 0 {
-                    located(f.location, Expr::Call(Box::new(f), args))
+                    let span = pos::span(f.span.start, args.last().unwrap().span.end);
+                    located(span, Expr::Call(Box::new(f), args))
                 } else {
                     f
                 }
@@ -403,15 +682,6 @@ impl<'s, I, Id, F> ParserEnv<I, F>
     /// Parses an expression which could be an argument to a function
     fn parse_arg(&self, input: I) -> ParseResult<LExpr<Id>, I> {
         debug!("Expr start: {:?}", input.clone().uncons());
-        let position = input.position();
-        let loc = |expr| {
-            located(Location {
-                        column: CharPos(position.column as usize),
-                        line: position.line as u32,
-                        absolute: BytePos(0),
-                    },
-                    expr)
-        };
 
         // To prevent stack overflows we push all binding groups (which are commonly deeply nested)
         // to a stack and construct the expressions afterwards
@@ -420,14 +690,29 @@ impl<'s, I, Id, F> ParserEnv<I, F>
         let mut input = input;
         let mut declaration_parser = self.parser(ParserEnv::<I, F>::type_decl)
             .or(self.parser(ParserEnv::<I, F>::let_in))
-            .map(loc);
+            .or(self.parser(ParserEnv::<I, F>::do_in))
+            .or(self.parser(ParserEnv::<I, F>::fixity_in));
+        // Fixity declarations and imported fixities are only in scope for the rest of this
+        // expression
+        let fixity_scope = self.fixities.borrow().len();
         loop {
+            let location = input.position();
             match declaration_parser.parse_lazy(input.clone()) {
                 Ok((bindings, new_input)) => {
-                    let_bindings.push(bindings);
+                    match bindings {
+                        LetOrType::Fixity(ref fixities) => {
+                            self.fixities.borrow_mut().extend(fixities.iter().cloned())
+                        }
+                        LetOrType::Let(ref bindings) => self.import_fixities(bindings),
+                        _ => (),
+                    }
+                    let_bindings.push((location, bindings));
                     input = new_input.into_inner();
                 }
-                Err(err @ Consumed::Consumed(_)) => return Err(err),
+                Err(err @ Consumed::Consumed(_)) => {
+                    self.fixities.borrow_mut().truncate(fixity_scope);
+                    return Err(err);
+                }
                 Err(Consumed::Empty(err)) => {
                     // If a let or type binding has been parsed then any kind of expression can
                     // follow
@@ -436,16 +721,21 @@ impl<'s, I, Id, F> ParserEnv<I, F>
                     } else {
                         self.expr()
                     };
-                    let (expr, new_input) = try!(expr_parser.parse_state(input)
-                        .map_err(|err2| err2.map(|err2| err.merge(err2))));
+                    let result = expr_parser.parse_state(input)
+                        .map_err(|err2| err2.map(|err2| err.merge(err2)));
+                    self.fixities.borrow_mut().truncate(fixity_scope);
+                    let (expr, new_input) = try!(result);
                     resulting_expr = expr;
                     input = new_input.into_inner();
                     break;
                 }
             }
         }
-        for Located { location, value } in let_bindings.into_iter().rev() {
-            resulting_expr = located(location,
+        for (location, value) in let_bindings.into_iter().rev() {
+            if let LetOrType::Fixity(_) = value {
+                continue;
+            }
+            resulting_expr = located(pos::span(location, resulting_expr.span.end),
                                      match value {
                                          LetOrType::Let(bindings) => {
                                              Expr::Let(bindings, Box::new(resulting_expr))
@@ -453,67 +743,79 @@ impl<'s, I, Id, F> ParserEnv<I, F>
                                          LetOrType::Type(bindings) => {
                                              Expr::Type(bindings, Box::new(resulting_expr))
                                          }
+                                         LetOrType::Do(id, bound) => {
+                                             let flat_map_id =
+                                                 self.make_ident.borrow_mut().from_str("flat_map");
+                                             Expr::Do(Do {
+                                                 id: id,
+                                                 bound: Box::new(bound),
+                                                 body: Box::new(resulting_expr),
+                                                 flat_map_id: flat_map_id,
+                                                 lambda_id: self.empty_id.clone(),
+                                             })
+                                         }
+                                         LetOrType::Fixity(_) => unreachable!(),
                                      });
         }
         Ok((resulting_expr, Consumed::Consumed(input)))
     }
 
     fn rest_expr(&self, input: I) -> ParseResult<LExpr<Id>, I> {
-        let position = input.position();
-        let loc = |expr| {
-            located(Location {
-                        column: CharPos(position.column as usize),
-                        line: position.line as u32,
-                        absolute: BytePos(0),
-                    },
-                    expr)
-        };
-        choice::<[&mut Parser<Input = I, Output = LExpr<Id>>; 12],
-                 _>([&mut parser(|input| self.if_else(input)).map(&loc),
-                     &mut self.parser(ParserEnv::<I, F>::case_of).map(&loc),
-                     &mut self.parser(ParserEnv::<I, F>::lambda).map(&loc),
+        let (expr, input) = try!(self.spanned(self.parser(ParserEnv::<I, F>::atom_expr), input));
+        let (fields, input) = try!(input.combine(|input| self.fields(input)));
+        debug!("Parsed expr {:?}", expr);
+        let expr = fields.into_iter().fold(expr, |expr, (field, end)| {
+            located(pos::span(expr.span.start, end),
+                    Expr::FieldAccess(Box::new(expr), field))
+        });
+        Ok((expr, input))
+    }
+
+    /// Parses an expression which is not followed by any field accesses
+    fn atom_expr(&self, input: I) -> ParseResult<Expr<Id>, I> {
+        choice::<[&mut Parser<Input = I, Output = Expr<Id>>; 13],
+                 _>([&mut parser(|input| self.if_else(input)),
+                     &mut self.parser(ParserEnv::<I, F>::case_of),
+                     &mut self.parser(ParserEnv::<I, F>::lambda),
                      &mut self.integer()
-                         .map(|i| loc(Expr::Literal(LiteralEnum::Integer(i)))),
+                         .map(|i| Expr::Literal(LiteralEnum::Integer(i))),
                      &mut self.byte()
-                         .map(|i| loc(Expr::Literal(LiteralEnum::Byte(i)))),
+                         .map(|i| Expr::Literal(LiteralEnum::Byte(i))),
                      &mut self.float()
-                         .map(|f| loc(Expr::Literal(LiteralEnum::Float(f)))),
+                         .map(|f| Expr::Literal(LiteralEnum::Float(f))),
                      &mut self.ident()
-                         .map(Expr::Identifier)
-                         .map(&loc),
-                     &mut self.parser(ParserEnv::<I, F>::record).map(&loc),
-                     &mut between(token(Token::Open(Delimiter::Paren)),
-                                  token(Token::Close(Delimiter::Paren)),
-                                  optional(self.expr()).map(|expr| {
-                                      match expr {
-                                          Some(expr) => expr,
-                                          None => loc(Expr::Tuple(vec![])),
-                                      }
-                                  })),
+                         .map(|id| {
+                             // `_` on its own is an anonymous hole. Identifiers such as `_x`
+                             // are holes unless they refer to a binding which the typechecker
+                             // decides
+                             if self.make_ident.borrow().string(&id) == "_" {
+                                 Expr::Hole(id)
+                             } else {
+                                 Expr::Identifier(id)
+                             }
+                         }),
+                     &mut self.parser(ParserEnv::<I, F>::record_update),
+                     &mut self.parser(ParserEnv::<I, F>::record),
+                     &mut self.parser(ParserEnv::<I, F>::paren_expr),
                      &mut self.string_literal()
-                         .map(|s| loc(Expr::Literal(LiteralEnum::String(s)))),
+                         .map(|s| Expr::Literal(LiteralEnum::String(s))),
                      &mut self.char_literal()
-                         .map(|s| loc(Expr::Literal(LiteralEnum::Char(s)))),
+                         .map(|s| Expr::Literal(LiteralEnum::Char(s))),
                      &mut between(token(Token::Open(Delimiter::Bracket)),
                                   token(Token::Close(Delimiter::Bracket)),
                                   sep_end_by(self.expr(), token(Token::Comma)))
                          .map(|exprs| {
-                             loc(Expr::Array(Array {
+                             Expr::Array(Array {
                                  id: self.empty_id.clone(),
                                  expressions: exprs,
-                             }))
+                             })
                          })])
-            .and(self.parser(Self::fields))
-            .map(|(expr, fields): (_, Vec<_>)| {
-                debug!("Parsed expr {:?}", expr);
-                fields.into_iter().fold(expr,
-                                        |expr, field| loc(Expr::FieldAccess(Box::new(expr), field)))
-            })
             .parse_state(input)
-
     }
 
-    fn fields(&self, input: I) -> ParseResult<Vec<Id>, I> {
+    /// Parses the field accesses after an expression, returning each field together with the
+    /// location just after it
+    fn fields(&self, input: I) -> ParseResult<Vec<(Id, Location)>, I> {
         let mut fields = Vec::new();
         let mut input = Consumed::Empty(input);
         loop {
@@ -523,61 +825,252 @@ impl<'s, I, Id, F> ParserEnv<I, F>
             };
             input = match input.clone().combine(|input| self.ident().parse_lazy(input)) {
                 Ok((field, input)) => {
-                    fields.push(field);
+                    let end = consumed_input(&input).end_position();
+                    fields.push((field, end));
                     input
                 }
                 Err(err) => {
                     // If not field where found after the '.' add an empty field so that running
                     // completion can be done for the attempt to access the field
+                    let end = consumed_input(&input).end_position();
                     let mut make_ident = self.make_ident.borrow_mut();
                     self.errors
                         .borrow_mut()
                         .error(static_error(&mut *make_ident, err.into_inner()));
-                    fields.push(make_ident.from_str(""));
+                    fields.push((make_ident.from_str(""), end));
                     return Ok((fields, input));
                 }
             };
         }
     }
 
+    /// Parses a parenthesized expression, a tuple or an operator section. Sections are desugared
+    /// into lambdas so `(op expr)` becomes `\x -> x op expr` and `(expr op)` becomes
+    /// `\x -> expr op x`.
+    fn paren_expr(&self, input: I) -> ParseResult<Expr<Id>, I> {
+        // The operators outside of any parentheses in the operand of a section
+        let ops = &RefCell::new(Vec::new());
+        let op = || parser(move |input| self.spanned(self.op(), input));
+        let operand = || parser(move |input| self.op_expr_(Some(ops), input));
+        let right_section = (op(), operand()).map(|(op, rhs)| {
+            self.check_section(&op.value, Associativity::Right, &ops.borrow());
+            self.section(op, None, Some(rhs))
+        });
+        // Operators which are directly followed by `)` are not parsed as binary operators so an
+        // operator after the first expression is always the end of a left section
+        let rest = (operand(), optional(op()), many(token(Token::Comma).with(self.expr())))
+            .map(|(first, op, mut exprs): (LExpr<Id>, _, Vec<_>)| {
+                match op {
+                    Some(op) => {
+                        self.check_section(&op.value, Associativity::Left, &ops.borrow());
+                        self.section(op, Some(first), None)
+                    }
+                    // A parenthesized expression spans its parentheses as well
+                    None if exprs.is_empty() => first.value,
+                    None => {
+                        exprs.insert(0, first);
+                        Expr::Tuple(exprs)
+                    }
+                }
+            });
+        between(token(Token::Open(Delimiter::Paren)),
+                token(Token::Close(Delimiter::Paren)),
+                optional(right_section.or(rest)))
+            .map(|expr| expr.unwrap_or_else(|| Expr::Tuple(Vec::new())))
+            .parse_state(input)
+    }
+
+    /// Desugars the section of `op` into a lambda which takes the missing operand
+    fn section(&self,
+               op: Located<Id>,
+               lhs: Option<LExpr<Id>>,
+               rhs: Option<LExpr<Id>>)
+               -> Expr<Id> {
+        let arg = self.make_ident.borrow_mut().from_str(SECTION_ARGUMENT);
+        let op_span = op.span;
+        let arg_expr = || located(op_span, Expr::Identifier(arg.clone()));
+        let lhs = lhs.unwrap_or_else(&arg_expr);
+        let rhs = rhs.unwrap_or_else(&arg_expr);
+        let span = pos::span(lhs.span.start, rhs.span.end);
+        Expr::Lambda(Lambda {
+            id: self.empty_id.clone(),
+            arguments: vec![located(op_span, arg.clone())],
+            body: Box::new(located(span, Expr::BinOp(Box::new(lhs), op.value, Box::new(rhs)))),
+        })
+    }
+
+    /// Records an error for each operator in `ops` which binds less tightly than `op` as the
+    /// operand of a section must be a single operand of `op`. `side` is the side of `op` which the
+    /// operand is on.
+    fn check_section(&self, op: &Id, side: Associativity, ops: &[(Id, Location)]) {
+        let ids = self.make_ident.borrow();
+        let fixity = self.fixity(ids.string(op));
+        for &(ref inner_op, position) in ops {
+            let inner = self.fixity(ids.string(inner_op));
+            let binds_tighter = inner.precedence > fixity.precedence ||
+                                (inner.precedence == fixity.precedence &&
+                                 inner.associativity == side &&
+                                 fixity.associativity == side);
+            if !binds_tighter {
+                let message = format!("`{}` binds less tightly than `{}` and must be \
+                                       parenthesized in the section",
+                                      ids.string(inner_op),
+                                      ids.string(op));
+                self.errors.borrow_mut().error(ParseError {
+                    position: position,
+                    errors: vec![CombineError::Message(Info::Owned(message))],
+                });
+            }
+        }
+    }
+
     match_parser! { op, Operator -> Id }
 
     /// Parses any sort of expression
     fn top_expr(&self, input: I) -> ParseResult<LExpr<Id>, I> {
+        self.parser(ParserEnv::<I, F>::block)
+            .or(sep_by1(self.parser(ParserEnv::<I, F>::op_expr), token(Token::Semi))
+                .map(|exprs| self.block_expr(exprs)))
+            .parse_state(input)
+    }
+
+    /// Parses an expression consisting of terms separated by binary operators
+    fn op_expr(&self, input: I) -> ParseResult<LExpr<Id>, I> {
+        self.op_expr_(None, input)
+    }
+
+    /// Parses an expression consisting of terms separated by binary operators. If `ops` is given
+    /// the operators of the expression are pushed to it together with their positions.
+    fn op_expr_(&self,
+                ops: Option<&RefCell<Vec<(Id, Location)>>>,
+                input: I)
+                -> ParseResult<LExpr<Id>, I> {
         let term = self.parser(ParserEnv::<I, F>::parse_expr);
-        let op = self.op()
-            .map(|op| {
-                let assoc = {
-                    let ids = self.make_ident.borrow();
-                    let op_str = ids.string(&op);
-                    Assoc {
-                        precedence: self.precedence(op_str),
-                        fixity: self.fixity(op_str),
-                    }
-                };
-                (op, assoc)
-            });
-        between(token(Token::OpenBlock),
-                token(Token::CloseBlock),
-                self.expr())
-            .or(sep_by1(expression_parser(term, op, |l, op, r| {
-                            let loc = l.location.clone();
-                            located(loc, Expr::BinOp(Box::new(l), op.clone(), Box::new(r)))
-                        }),
-                        token(Token::Semi))
-                .map(|mut exprs: Vec<LExpr<Id>>| {
-                    if exprs.len() == 1 {
-                        exprs.pop().unwrap()
-                    } else {
-                        located(exprs.first().expect("Expr in block").location,
-                                Expr::Block(exprs))
+        let op = parser(|input: I| {
+            let position = input.position();
+            let (op, rest) = try!(self.op().parse_state(input));
+            // `(expr op)` is a section and not the start of a binary operation
+            let mut close = token(Token::Close(Delimiter::Paren));
+            if close.parse_lazy(consumed_input(&rest).clone()).is_ok() {
+                let err = CombineError::Unexpected(Info::Token(Token::Operator(op)));
+                return Err(Consumed::Empty(ParseError::new(position, err)));
+            }
+            if let Some(ops) = ops {
+                ops.borrow_mut().push((op.clone(), position));
+            }
+            Ok((op, rest))
+        });
+        let op = op.map(|op| {
+            let assoc = {
+                let ids = self.make_ident.borrow();
+                self.assoc(ids.string(&op))
+            };
+            (op, assoc)
+        });
+        let expr = expression_parser(term, op, |l, op, r| {
+            let span = pos::span(l.span.start, r.span.end);
+            located(span, Expr::BinOp(Box::new(l), op.clone(), Box::new(r)))
+        });
+        // `expr : Type`
+        (expr, optional(token(Token::Colon).with(self.parser(ParserEnv::<I, F>::located_type))))
+            .map(|(expr, typ)| {
+                match typ {
+                    Some(typ) => {
+                        let span = pos::span(expr.span.start, typ.span.end);
+                        located(span, Expr::Annotated(Box::new(expr), typ))
                     }
-                }))
+                    None => expr,
+                }
+            })
             .parse_state(input)
     }
 
+    fn block_expr(&self, mut exprs: Vec<LExpr<Id>>) -> LExpr<Id> {
+        if exprs.len() == 1 {
+            exprs.pop().unwrap()
+        } else {
+            let span = pos::span(exprs.first().expect("Expr in block").span.start,
+                                 exprs.last().expect("Expr in block").span.end);
+            located(span, Expr::Block(exprs))
+        }
+    }
+
+    /// Parses the expressions between an `OpenBlock` and a `CloseBlock` token.
+    ///
+    /// If an expression in the block contains a syntax error the error is recorded and the
+    /// expression is replaced by `Expr::Error`. Parsing then continues after the next `Semi` in the
+    /// same block so that every syntax error in the block gets reported.
+    fn block(&self, input: I) -> ParseResult<LExpr<Id>, I> {
+        let (_, input) = try!(token(Token::OpenBlock).parse_state(input));
+        let mut input = input.into_inner();
+        let depth = input.block_depth();
+        let mut exprs = Vec::new();
+        loop {
+            let location = input.position();
+            let result = self.parser(ParserEnv::<I, F>::block)
+                .or(self.parser(ParserEnv::<I, F>::op_expr))
+                .parse_state(input.clone());
+            let separator = match result {
+                Ok((expr, rest)) => {
+                    exprs.push(expr);
+                    input = rest.into_inner();
+                    token(Token::Semi)
+                        .map(|_| false)
+                        .or(token(Token::CloseBlock).map(|_| true))
+                        .parse_state(input.clone())
+                        .map(|(end, rest)| (end, rest.into_inner()))
+                        .map_err(Consumed::into_inner)
+                }
+                Err(err) => {
+                    exprs.push(located(pos::span(location, location),
+                                       Expr::Error(self.empty_id.clone())));
+                    Err(err.into_inner())
+                }
+            };
+            let (end, rest) = match separator {
+                Ok(x) => x,
+                Err(err) => try!(self.recover(err, input, depth)),
+            };
+            input = rest;
+            if end {
+                break;
+            }
+        }
+        Ok((self.block_expr(exprs), Consumed::Consumed(input)))
+    }
+
+    /// Records `err` and skips tokens until the next `Semi` or `CloseBlock` in the block at
+    /// `depth`. Returns `true` if the end of the block was reached.
+    fn recover(&self,
+               err: ParseError<I>,
+               input: I,
+               depth: usize)
+               -> Result<(bool, I), Consumed<ParseError<I>>> {
+        {
+            let mut make_ident = self.make_ident.borrow_mut();
+            self.errors.borrow_mut().error(static_error(&mut *make_ident, err));
+        }
+        // Start skipping from where the error was found as the tokens before it may no longer be
+        // available in the stream
+        let mut input = input.furthest();
+        loop {
+            let token_depth = input.block_depth();
+            let position = input.position();
+            match input.uncons() {
+                Ok(Token::Semi) if token_depth == depth => return Ok((false, input)),
+                Ok(Token::CloseBlock) if token_depth == depth => return Ok((true, input)),
+                Ok(Token::EOF) => return Ok((true, input)),
+                Ok(_) => (),
+                Err(err) => return Err(Consumed::Consumed(ParseError::new(position, err))),
+            }
+        }
+    }
+
     fn lambda(&self, input: I) -> ParseResult<Expr<Id>, I> {
-        (token(Token::Lambda), many(self.ident()), token(Token::RightArrow), self.expr())
+        (token(Token::Lambda),
+         many(self.parser(ParserEnv::<I, F>::located_ident)),
+         token(Token::RightArrow),
+         self.expr())
             .map(|(_, args, _, expr)| {
                 Expr::Lambda(Lambda {
                     id: self.empty_id.clone(),
@@ -589,10 +1082,16 @@ impl<'s, I, Id, F> ParserEnv<I, F>
     }
 
     fn case_of(&self, input: I) -> ParseResult<Expr<Id>, I> {
-        let alt = (token(Token::Pipe), self.pattern(), token(Token::RightArrow), self.expr())
-            .map(|(_, p, _, e)| {
+        let guard = token(Token::If).with(self.expr());
+        let alt = (token(Token::Pipe),
+                   self.pattern(),
+                   optional(guard),
+                   token(Token::RightArrow),
+                   self.expr())
+            .map(|(_, p, guard, _, e)| {
                 Alternative {
                     pattern: p,
+                    guard: guard,
                     expression: e,
                 }
             });
@@ -601,52 +1100,80 @@ impl<'s, I, Id, F> ParserEnv<I, F>
             .parse_state(input)
     }
 
-    fn pattern(&'s self) -> LanguageParser<'s, I, F, LPattern<Id>> {
+    fn pattern(&'s self) -> LanguageParser<'s, 'h, I, F, LPattern<Id>> {
         self.parser(ParserEnv::<I, F>::parse_pattern)
     }
 
     fn parse_pattern(&self, input: I) -> ParseResult<LPattern<Id>, I> {
-        self.record_parser(self.ident_u(), self.ident_u(), |record| {
-            let position = input.position();
-            let location = Location {
-                column: CharPos(position.column as usize),
-                line: position.line as u32,
-                absolute: BytePos(0),
-            };
-            self.parser(ParserEnv::<I, F>::parse_ident2)
-                .then(|(id, typ)| {
-                    parser(move |input| {
-                        if typ == IdentType::Constructor {
-                            many(self.ident())
-                                .parse_state(input)
-                                .map(|(args, input)| {
-                                    (Pattern::Constructor(id.clone(), args), input)
-                                })
-                        } else {
-                            Ok((Pattern::Identifier(id.clone()), Consumed::Empty(input)))
-                        }
-                    })
-                })
-                .or(record.map(|fields: Vec<_>| {
-                    let mut types = Vec::new();
-                    let mut patterns = Vec::new();
-                    for (id, field) in fields {
-                        match field {
-                            Ok(name) => types.push((id, name)),
-                            Err(pattern) => patterns.push((id, pattern)),
-                        }
-                    }
-                    Pattern::Record {
-                        id: self.empty_id.clone(),
-                        types: types,
-                        fields: patterns,
+        let mut pattern = self.parser(ParserEnv::<I, F>::parse_ident2)
+            .then(|(id, typ)| {
+                parser(move |input| {
+                    if typ == IdentType::Constructor {
+                        many(self.parser(ParserEnv::<I, F>::pattern_arg))
+                            .parse_state(input)
+                            .map(|(args, input)| (Pattern::Constructor(id.clone(), args), input))
+                    } else {
+                        Ok((Pattern::Identifier(id.clone()), Consumed::Empty(input)))
                     }
-                }))
-                .map(|p| located(location, p))
-                .or(between(token(Token::Open(Delimiter::Paren)),
-                            token(Token::Close(Delimiter::Paren)),
-                            self.pattern()))
-                .parse_state(input)
+                })
+            });
+        parser(|input| self.spanned(&mut pattern, input))
+            .or(self.parser(ParserEnv::<I, F>::pattern_arg))
+            .parse_state(input)
+    }
+
+    /// Parses a pattern which does not need to be enclosed in parentheses when used as the argument
+    /// of a constructor pattern
+    fn pattern_arg(&self, input: I) -> ParseResult<LPattern<Id>, I> {
+        self.record_parser(self.ident_u(), self.pattern(), |record| {
+            let pattern =
+                choice::<[&mut Parser<Input = I, Output = Pattern<Id>>; 8],
+                         _>([&mut self.parser(ParserEnv::<I, F>::parse_ident2).map(|(id, typ)| {
+                                 if typ == IdentType::Constructor {
+                                     Pattern::Constructor(id, vec![])
+                                 } else {
+                                     Pattern::Identifier(id)
+                                 }
+                             }),
+                             &mut record.map(|fields: Vec<_>| {
+                                 let mut types = Vec::new();
+                                 let mut patterns = Vec::new();
+                                 for (id, field) in fields {
+                                     match field {
+                                         Ok(name) => types.push((id, name)),
+                                         Err(pattern) => patterns.push((id, pattern)),
+                                     }
+                                 }
+                                 Pattern::Record {
+                                     id: self.empty_id.clone(),
+                                     types: types,
+                                     fields: patterns,
+                                 }
+                             }),
+                             &mut between(token(Token::Open(Delimiter::Paren)),
+                                          token(Token::Close(Delimiter::Paren)),
+                                          sep_by(self.pattern(), token(Token::Comma)))
+                                 .map(|mut elems: Vec<LPattern<Id>>| {
+                                     if elems.len() == 1 {
+                                         elems.pop().unwrap().value
+                                     } else {
+                                         Pattern::Tuple {
+                                             typ: self.empty_id.clone(),
+                                             elems: elems,
+                                         }
+                                     }
+                                 }),
+                             &mut self.integer()
+                                 .map(|i| Pattern::Literal(LiteralEnum::Integer(i))),
+                             &mut self.byte()
+                                 .map(|i| Pattern::Literal(LiteralEnum::Byte(i))),
+                             &mut self.float()
+                                 .map(|f| Pattern::Literal(LiteralEnum::Float(f))),
+                             &mut self.string_literal()
+                                 .map(|s| Pattern::Literal(LiteralEnum::String(s))),
+                             &mut self.char_literal()
+                                 .map(|c| Pattern::Literal(LiteralEnum::Char(c)))]);
+            self.spanned(pattern, input)
         })
     }
 
@@ -681,20 +1208,64 @@ impl<'s, I, Id, F> ParserEnv<I, F>
             .parse_state(input)
     }
 
+    fn do_in(&self, input: I) -> ParseResult<LetOrType<Id>, I> {
+        (token(Token::Do),
+         self.ident(),
+         token(Token::Equal),
+         self.expr(),
+         token(Token::In).expected("`in` or an expression in the same column as the `do`"))
+            .map(|(_, id, _, bound, _)| LetOrType::Do(id, bound))
+            .parse_state(input)
+    }
+
+    /// Parses a fixity declaration such as `infixl 4 <|> <*>`
+    fn fixity_in(&self, input: I) -> ParseResult<LetOrType<Id>, I> {
+        let associativity = token(Token::Infixl)
+            .map(|_| Associativity::Left)
+            .or(token(Token::Infixr).map(|_| Associativity::Right));
+        (associativity,
+         self.integer(),
+         many1(self.op()),
+         token(Token::In).expected("`in` or an expression in the same column as the fixity \
+                                    declaration"))
+            .map(|(associativity, precedence, ops, _): (_, _, Vec<_>, _)| {
+                let fixity = ast::Fixity {
+                    associativity: associativity,
+                    precedence: precedence as i32,
+                };
+                let ids = self.make_ident.borrow();
+                LetOrType::Fixity(ops.iter()
+                    .map(|op| (String::from(ids.string(op)), fixity))
+                    .collect())
+            })
+            .parse_state(input)
+    }
+
     fn binding(&self, input: I) -> ParseResult<Binding<Id>, I> {
-        let (name, input) = try!(self.pattern().parse_state(input));
+        let ((implicit, name), input) = try!((optional(token(Token::Implicit)), self.pattern())
+            .parse_state(input));
         let (arguments, input) = match name.value {
             Pattern::Identifier(_) => {
-                try!(input.combine(|input| many(self.ident()).parse_state(input)))
+                try!(input.combine(|input| {
+                    many(self.parser(ParserEnv::<I, F>::located_ident)).parse_state(input)
+                }))
             }
             _ => (Vec::new(), input),
         };
-        let type_sig = token(Token::Colon).with(self.typ());
+        let type_sig = token(Token::Colon).with(self.parser(ParserEnv::<I, F>::located_type));
         let ((typ, _, e), input) = try!(input.combine(|input| {
             (optional(type_sig), token(Token::Equal), self.expr()).parse_state(input)
         }));
+        let fixity = match name.value {
+            Pattern::Identifier(ref id) => {
+                self.declared_fixity(self.make_ident.borrow().string(id))
+            }
+            _ => None,
+        };
         Ok((Binding {
             comment: None,
+            implicit: implicit.is_some(),
+            fixity: fixity,
             name: name,
             typ: typ,
             arguments: arguments,
@@ -724,6 +1295,27 @@ impl<'s, I, Id, F> ParserEnv<I, F>
         })
     }
 
+    /// Parses `{ base | field = expr, ... }`
+    fn record_update(&self, input: I) -> ParseResult<Expr<Id>, I> {
+        let field = (self.ident_u(), token(Token::Equal).with(self.expr()));
+        // The base expression needs to be parsed before it is known if this is a record update or
+        // an ordinary record expression
+        let base = try(token(Token::Open(Delimiter::Brace))
+            .with(self.expr())
+            .skip(token(Token::Pipe)));
+        (base,
+         sep_end_by1(field, token(Token::Comma)),
+         token(Token::Close(Delimiter::Brace)))
+            .map(|(base, fields, _)| {
+                Expr::RecordUpdate {
+                    typ: self.empty_id.clone(),
+                    base: Box::new(base),
+                    fields: fields,
+                }
+            })
+            .parse_state(input)
+    }
+
     fn record_parser<P1, P2, O, G, R>(&'s self, ref p1: P1, ref p2: P2, f: G) -> R
         where P1: Parser<Input = I> + Clone,
               P2: Parser<Input = I> + Clone,
@@ -769,6 +1361,24 @@ pub fn parse_tc
     parse_expr(&mut env, input)
 }
 
+/// Parses a string to an AST in the same way as `parse_tc` but calls `import` for each module
+/// which is bound by `let` so that the fixities the module declares are used in the body of the
+/// `let`
+pub fn parse_tc_with_imports
+    (symbols: &mut SymbolModule,
+     import: &mut ImportFixities,
+     input: &str)
+     -> Result<LExpr<TcIdent<Symbol>>, (Option<LExpr<TcIdent<Symbol>>>, Errors<Error>)> {
+    let mut env = ast::TcIdentEnv {
+        typ: Type::variable(TypeVariable {
+            id: 0,
+            kind: Kind::typ(),
+        }),
+        env: symbols,
+    };
+    parse_expr_with_imports(&mut env, &[], import, input)
+}
+
 #[cfg(feature = "test")]
 pub fn parse_string<'a, 's>(make_ident: &'a mut IdentEnv<Ident = String>,
                             input: &'s str)
@@ -781,24 +1391,68 @@ pub fn parse_expr<'a, 's, Id>(make_ident: &'a mut IdentEnv<Ident = Id>,
                               input: &'s str)
                               -> Result<LExpr<Id>, (Option<LExpr<Id>>, Errors<Error>)>
     where Id: AstId + Clone + PartialEq + fmt::Debug
+{
+    parse_expr_with_fixities(make_ident, &[], input)
+}
+
+/// Parses a gluon expression where the operators in `fixities` are parsed with the declared
+/// fixity unless it is shadowed by a fixity declaration in `input`
+pub fn parse_expr_with_fixities<'a, 's, Id>(make_ident: &'a mut IdentEnv<Ident = Id>,
+                                            fixities: &[(String, ast::Fixity)],
+                                            input: &'s str)
+                                            -> Result<LExpr<Id>, (Option<LExpr<Id>>, Errors<Error>)>
+    where Id: AstId + Clone + PartialEq + fmt::Debug
+{
+    parse_expr_with_imports(make_ident, fixities, &mut |_: &str| Ok(Vec::new()), input)
+}
+
+/// Parses a gluon expression in the same way as `parse_expr_with_fixities` but also calls `import`
+/// with the filename of each `import "filename"` which is bound by `let`. The fixities returned
+/// by `import` are in scope in the body of the `let`.
+pub fn parse_expr_with_imports<'a, 's, Id>(make_ident: &'a mut IdentEnv<Ident = Id>,
+                                           fixities: &[(String, ast::Fixity)],
+                                           import: &mut ImportFixities,
+                                           input: &'s str)
+                                           -> Result<LExpr<Id>, (Option<LExpr<Id>>, Errors<Error>)>
+    where Id: AstId + Clone + PartialEq + fmt::Debug
 {
     let make_ident = Rc::new(RefCell::new(make_ident));
     let lexer = Lexer::<&str, &mut IdentEnv<Ident = Id>>::new(input, make_ident.clone());
     let empty_id = make_ident.borrow_mut().from_str("");
+    let token_ends = lexer.token_ends();
+    let literal_errors = lexer.literal_errors();
     let env = ParserEnv {
         empty_id: empty_id,
         make_ident: make_ident.clone(),
         errors: RefCell::new(Errors::new()),
+        fixities: RefCell::new(fixities.to_vec()),
+        import: RefCell::new(import),
         env: ::std::marker::PhantomData,
     };
     let buffer = BufferedStream::new(lexer, 10);
-    let stream = Wrapper { stream: buffer.as_stream() };
+    let stream = Wrapper {
+        stream: buffer.as_stream(),
+        offset: 0,
+        block_depth: 0,
+        furthest: Rc::new(RefCell::new(Checkpoint {
+            stream: buffer.as_stream(),
+            offset: 0,
+            block_depth: 0,
+        })),
+        token_ends: token_ends,
+    };
 
     let result = env.expr()
         .parse(stream)
         .map(|t| t.0);
 
     let mut errors = env.errors.into_inner();
+    for err in literal_errors.borrow_mut().drain(..) {
+        errors.errors.push(ParseError {
+            position: err.location,
+            errors: vec![CombineError::Other(Box::new(err))],
+        });
+    }
     match result {
         Ok(x) => {
             if !errors.has_errors() {
@@ -814,9 +1468,59 @@ pub fn parse_expr<'a, 's, Id>(make_ident: &'a mut IdentEnv<Ident = Id>,
     }
 }
 
+/// Converts a parse error into a `Diagnostic`
+pub fn diagnostic(error: &Error) -> Diagnostic {
+    fn info(info: &Info<Token<String>, Token<String>>) -> String {
+        match *info {
+            Info::Token(ref token) |
+            Info::Range(ref token) => {
+                match *token {
+                    Token::Identifier(ref id, _) => format!("`{}`", id),
+                    ref token => format!("`{}`", token),
+                }
+            }
+            Info::Borrowed(s) => String::from(s),
+            Info::Owned(ref s) => s.clone(),
+        }
+    }
+    let location = error.position;
+    // Errors for number literals know where the literal ends
+    let end = error.errors
+        .iter()
+        .filter_map(|err| match *err {
+            CombineError::Other(ref err) => err.downcast_ref::<LiteralError>(),
+            _ => None,
+        })
+        .next()
+        .map_or(location, |err| err.end);
+    let mut messages = Vec::new();
+    let mut expected = Vec::new();
+    for err in &error.errors {
+        match *err {
+            CombineError::Unexpected(ref t) => messages.push(format!("Unexpected {}", info(t))),
+            CombineError::Expected(ref t) => expected.push(info(t)),
+            CombineError::Message(ref t) => messages.push(info(t)),
+            CombineError::Other(ref err) => messages.push(err.to_string()),
+        }
+    }
+    let mut messages = messages.into_iter();
+    let message = messages.next().unwrap_or_else(|| String::from("Parse error"));
+    let mut diagnostic = Diagnostic::error("parse_error", message).with_span(Span {
+        start: location,
+        end: end,
+    });
+    for message in messages {
+        diagnostic = diagnostic.with_note(message);
+    }
+    if !expected.is_empty() {
+        diagnostic = diagnostic.with_note(format!("Expected {}", expected.join(", ")));
+    }
+    diagnostic
+}
+
 fn static_error<I, Id>(make_ident: &mut IdentEnv<Ident = Id>, err: ParseError<I>) -> Error
     where Id: Clone + fmt::Debug + PartialEq,
-          I: Stream<Item = Token<Id>, Range = Token<Id>, Position = SourcePosition>
+          I: Stream<Item = Token<Id>, Range = Token<Id>, Position = Location>
 {
     let errors = err.errors
         .into_iter()
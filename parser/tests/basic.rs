@@ -8,8 +8,8 @@ use base::ast;
 use base::ast::*;
 use base::error::Errors;
 use base::pos::{self, BytePos, CharPos, Located, Location, Span};
-use base::types::{Type, Generic, Alias, Field, Kind};
-use parser::{parse_string, Error};
+use base::types::{Type, Generic, Alias, AliasData, Field, Kind};
+use parser::{parse_string, Error, SECTION_ARGUMENT};
 
 pub fn intern(s: &str) -> String {
     String::from(s)
@@ -32,7 +32,7 @@ fn no_loc<T>(x: T) -> Located<T> {
         absolute: BytePos(u32::max_value()),
     };
 
-    pos::located(max_loc, x)
+    pos::located(pos::span(max_loc, max_loc), x)
 }
 
 fn binop(l: PExpr, s: &str, r: PExpr) -> PExpr {
@@ -49,15 +49,21 @@ fn let_(s: &str, e: PExpr, b: PExpr) -> PExpr {
 
 fn let_a(s: &str, args: &[&str], e: PExpr, b: PExpr) -> PExpr {
     no_loc(Expr::Let(vec![Binding {
+                              implicit: false,
+                              fixity: None,
                               comment: None,
                               name: no_loc(Pattern::Identifier(intern(s))),
                               typ: None,
-                              arguments: args.iter().map(|i| intern(i)).collect(),
+                              arguments: args.iter().map(|i| no_loc(intern(i))).collect(),
                               expression: e,
                           }],
                      Box::new(b)))
 }
 
+fn id_pattern(s: &str) -> LPattern<String> {
+    no_loc(Pattern::Identifier(intern(s)))
+}
+
 fn id(s: &str) -> PExpr {
     no_loc(Expr::Identifier(intern(s)))
 }
@@ -104,6 +110,7 @@ fn case(e: PExpr, alts: Vec<(Pattern<String>, PExpr)>) -> PExpr {
                            .map(|(p, e)| {
                                Alternative {
                                    pattern: no_loc(p),
+                                   guard: None,
                                    expression: e,
                                }
                            })
@@ -113,7 +120,7 @@ fn case(e: PExpr, alts: Vec<(Pattern<String>, PExpr)>) -> PExpr {
 fn lambda(name: &str, args: Vec<String>, body: PExpr) -> PExpr {
     no_loc(Expr::Lambda(Lambda {
         id: intern(name),
-        arguments: args,
+        arguments: args.into_iter().map(no_loc).collect(),
         body: Box::new(body),
     }))
 }
@@ -123,6 +130,7 @@ fn type_decl(name: String, args: Vec<Generic<String>>, typ: AstType<String>, bod
                         comment: None,
                         name: name.clone(),
                         alias: Alias::new(name, args, typ),
+                        deriving: Vec::new(),
                     }],
                body)
 }
@@ -240,6 +248,40 @@ fn type_decl_record() {
     assert_eq!(e, type_decl(intern("Test"), vec![], record, int(1)));
 }
 
+#[test]
+fn type_decl_open_record() {
+    let _ = ::env_logger::init();
+    let e = parse_new("type Test r = { x: Int | r } in 1");
+    let record = Type::poly_record(Vec::new(), vec![field("x", typ("Int"))], typ("r"));
+    assert_eq!(e, type_decl(intern("Test"), vec![generic("r")], record, int(1)));
+}
+
+#[test]
+fn type_decl_deriving() {
+    let _ = ::env_logger::init();
+    let e = parse_new("type Test = { x: Int } deriving (Eq, Show) in 1");
+    let record = Type::record(Vec::new(), vec![field("x", typ("Int"))]);
+    let binding = TypeBinding {
+        comment: None,
+        name: intern("Test"),
+        alias: Alias::new(intern("Test"), vec![], record),
+        deriving: vec![intern("Eq"), intern("Show")],
+    };
+    assert_eq!(e, type_decls(vec![binding], int(1)));
+}
+
+#[test]
+fn type_decl_kind_annotation() {
+    let _ = ::env_logger::init();
+    let e = parse_new("type Test (f : Type -> Type) a = f a in 1");
+    let f = Generic {
+        kind: Kind::function(Kind::typ(), Kind::typ()),
+        id: intern("f"),
+    };
+    let typ = Type::app(Type::generic(f.clone()), vec![generic_ty("a")]);
+    assert_eq!(e, type_decl(intern("Test"), vec![f, generic("a")], typ, int(1)));
+}
+
 #[test]
 fn type_mutually_recursive() {
     let _ = ::env_logger::init();
@@ -260,11 +302,13 @@ fn type_mutually_recursive() {
             comment: None,
             name: intern("Test"),
             alias: Alias::new(intern("Test"), Vec::new(), test),
+            deriving: Vec::new(),
         },
         TypeBinding {
             comment: None,
             name: intern("Test2"),
             alias: Alias::new(intern("Test2"), Vec::new(), test2),
+            deriving: Vec::new(),
         },
         ];
     assert_eq!(e, type_decls(binds, int(1)));
@@ -278,6 +322,18 @@ fn field_access_test() {
                field_access(record(vec![(intern("x"), Some(int(1)))]), "x"));
 }
 
+#[test]
+fn record_update() {
+    let _ = ::env_logger::init();
+    let e = parse_new("{ r | x = 1, y = z }");
+    let expected = no_loc(Expr::RecordUpdate {
+        typ: intern(""),
+        base: Box::new(id("r")),
+        fields: vec![(intern("x"), int(1)), (intern("y"), id("z"))],
+    });
+    assert_eq!(e, expected);
+}
+
 #[test]
 fn builtin_op() {
     let _ = ::env_logger::init();
@@ -319,10 +375,91 @@ match None with
     let e = parse(text);
     assert_eq!(e,
                Ok(case(id("None"),
-                       vec![(Pattern::Constructor(intern("Some"), vec![intern("x")]), id("x")),
+                       vec![(Pattern::Constructor(intern("Some"), vec![id_pattern("x")]),
+                             id("x")),
+                            (Pattern::Constructor(intern("None"), vec![]), int(0))])));
+}
+#[test]
+fn match_guard() {
+    let _ = ::env_logger::init();
+    let text = r#"
+match x with
+    | Some y if y -> 1
+    | _ -> 0"#;
+    let e = parse(text);
+    let some = Pattern::Constructor(intern("Some"), vec![id_pattern("y")]);
+    assert_eq!(e,
+               Ok(no_loc(Expr::Match(Box::new(id("x")),
+                                     vec![Alternative {
+                                              pattern: no_loc(some),
+                                              guard: Some(id("y")),
+                                              expression: int(1),
+                                          },
+                                          Alternative {
+                                              pattern: id_pattern("_"),
+                                              guard: None,
+                                              expression: int(0),
+                                          }]))));
+}
+
+#[test]
+fn nested_pattern() {
+    let _ = ::env_logger::init();
+    let text = r#"
+match x with
+    | Some (Cons y _) -> y
+    | Some Nil -> 1
+    | None -> 0"#;
+    let e = parse(text);
+    let cons = Pattern::Constructor(intern("Cons"), vec![id_pattern("y"), id_pattern("_")]);
+    let nil = Pattern::Constructor(intern("Nil"), vec![]);
+    assert_eq!(e,
+               Ok(case(id("x"),
+                       vec![(Pattern::Constructor(intern("Some"), vec![no_loc(cons)]), id("y")),
+                            (Pattern::Constructor(intern("Some"), vec![no_loc(nil)]), int(1)),
                             (Pattern::Constructor(intern("None"), vec![]), int(0))])));
 }
 #[test]
+fn literal_pattern() {
+    let _ = ::env_logger::init();
+    let text = r#"
+match x with
+    | 1 -> "one"
+    | 'a' -> "a"
+    | "abc" -> "abc"
+    | Some 2 -> "two"
+    | _ -> "" "#;
+    let e = parse(text);
+    let string = |s: &str| no_loc(Expr::Literal(LiteralEnum::String(s.into())));
+    let two = no_loc(Pattern::Literal(LiteralEnum::Integer(2)));
+    assert_eq!(e,
+               Ok(case(id("x"),
+                       vec![(Pattern::Literal(LiteralEnum::Integer(1)), string("one")),
+                            (Pattern::Literal(LiteralEnum::Char('a')), string("a")),
+                            (Pattern::Literal(LiteralEnum::String("abc".into())), string("abc")),
+                            (Pattern::Constructor(intern("Some"), vec![two]), string("two")),
+                            (Pattern::Identifier(intern("_")), string(""))])));
+}
+#[test]
+fn tuple_pattern() {
+    let _ = ::env_logger::init();
+    let e = parse_new("match x with | (y, Some z) -> z");
+    let pattern = Pattern::Tuple {
+        typ: String::new(),
+        elems: vec![id_pattern("y"),
+                    no_loc(Pattern::Constructor(intern("Some"), vec![id_pattern("z")]))],
+    };
+    assert_eq!(e, case(id("x"), vec![(pattern, id("z"))]));
+}
+#[test]
+fn tuple_expr() {
+    let _ = ::env_logger::init();
+    let e = parse_new("(1, a, (b))");
+    assert_eq!(e, no_loc(Expr::Tuple(vec![int(1), id("a"), id("b")])));
+    let e = parse_new("(1)");
+    assert_eq!(e, int(1));
+}
+#[test]
 fn array_expr() {
     let _ = ::env_logger::init();
     let e = parse_new("[1, a]");
@@ -338,6 +475,133 @@ fn operator_expr() {
                      id("test")));
 }
 
+#[test]
+fn fixity_declaration() {
+    let _ = ::env_logger::init();
+    let text = r#"
+infixr 2 <|>
+infixl 8 <+>
+a <|> b <|> c <+> d * e
+"#;
+    let e = parse_new(text);
+    assert_eq!(e,
+               binop(id("a"),
+                     "<|>",
+                     binop(id("b"),
+                           "<|>",
+                           binop(binop(id("c"), "<+>", id("d")), "*", id("e")))));
+}
+
+#[test]
+fn fixity_declaration_scope() {
+    let _ = ::env_logger::init();
+    let text = r#"
+let x =
+    infixr 2 <|>
+    a <|> b <|> c
+x <|> y <|> z
+"#;
+    let e = parse_new(text);
+    assert_eq!(e,
+               let_("x",
+                    binop(id("a"), "<|>", binop(id("b"), "<|>", id("c"))),
+                    binop(binop(id("x"), "<|>", id("y")), "<|>", id("z"))));
+}
+
+#[test]
+fn operator_sections() {
+    let _ = ::env_logger::init();
+    let section = |body| lambda("", vec![intern(SECTION_ARGUMENT)], body);
+    let arg = || id(SECTION_ARGUMENT);
+    assert_eq!(parse_new("(+ 1)"), section(binop(arg(), "+", int(1))));
+    assert_eq!(parse_new("(1 +)"), section(binop(int(1), "+", arg())));
+    assert_eq!(parse_new("(+ 1 * 2)"),
+               section(binop(arg(), "+", binop(int(1), "*", int(2)))));
+    assert_eq!(parse_new("(1 + 2 -)"),
+               section(binop(binop(int(1), "+", int(2)), "-", arg())));
+    assert_eq!(parse_new("f (x <|)"), call(id("f"), vec![section(binop(id("x"), "<|", arg()))]));
+    // Operators in parentheses and tuples are unchanged
+    assert_eq!(parse_new("(+)"), id("+"));
+    assert_eq!(parse_new("(1 + 2)"), binop(int(1), "+", int(2)));
+    assert_eq!(parse_new("(1, 2)"), no_loc(Expr::Tuple(vec![int(1), int(2)])));
+    assert_eq!(parse_new("()"), no_loc(Expr::Tuple(vec![])));
+}
+
+#[test]
+fn operator_section_fixity() {
+    let _ = ::env_logger::init();
+    assert!(parse("(* 1 + 2)").is_err());
+    assert!(parse("(1 + 2 *)").is_err());
+    // `-` is left associative so `1 - 2` can't be the right operand of `-` without parentheses
+    assert!(parse("(- 1 - 2)").is_err());
+    assert!(parse("(* (1 + 2))").is_ok());
+    assert!(parse("infixr 5 ++\n(++ x ++ y)").is_ok());
+}
+
+#[test]
+fn imported_fixity_scope() {
+    let _ = ::env_logger::init();
+    let text = r#"
+let x =
+    let m = import "fixity"
+    a <|> b <|> c
+x <|> y <|> z
+"#;
+    let mut import = |filename: &str| {
+        assert_eq!(filename, "fixity");
+        let fixity = Fixity {
+            associativity: Associativity::Right,
+            precedence: 2,
+        };
+        Ok(vec![(String::from("<|>"), fixity)])
+    };
+    let e = parser::parse_expr_with_imports(&mut ast::EmptyEnv::new(), &[], &mut import, text)
+        .unwrap_or_else(|(_, err)| panic!("{:?}", err));
+    let import_expr = call(id("import"),
+                           vec![no_loc(Expr::Literal(LiteralEnum::String(intern("fixity"))))]);
+    assert_eq!(e,
+               let_("x",
+                    let_("m",
+                         import_expr,
+                         binop(id("a"), "<|>", binop(id("b"), "<|>", id("c")))),
+                    binop(binop(id("x"), "<|>", id("y")), "<|>", id("z"))));
+}
+
+#[test]
+fn import_error_is_reported_at_the_filename() {
+    let _ = ::env_logger::init();
+    let text = r#"
+let m = import "missing"
+a <|> b
+"#;
+    let result = parser::parse_expr_with_imports(&mut ast::EmptyEnv::new(),
+                                                 &[],
+                                                 &mut |_: &str| Err("Could not find file".into()),
+                                                 text);
+    let errors = match result {
+        Ok(expr) => panic!("Expected an error, got {:?}", expr),
+        Err((_, errors)) => errors,
+    };
+    assert_eq!(errors.errors.len(), 1);
+    assert_eq!(errors.errors[0].position, loc(2, 16));
+}
+
+#[test]
+fn backtick_infix() {
+    let _ = ::env_logger::init();
+    assert_eq!(parse_new("x `elem` xs && b"),
+               binop(binop(id("x"), "elem", id("xs")), "&&", id("b")));
+    let text = r#"
+infixr 1 `elem`
+a `elem` b `elem` c
+"#;
+    assert_eq!(parse_new(text),
+               binop(id("a"), "elem", binop(id("b"), "elem", id("c"))));
+    let section = |body| lambda("", vec![intern(SECTION_ARGUMENT)], body);
+    assert_eq!(parse_new("(`elem` xs)"),
+               section(binop(id(SECTION_ARGUMENT), "elem", id("xs"))));
+}
+
 #[test]
 fn record_trailing_comma() {
     let _ = ::env_logger::init();
@@ -360,7 +624,24 @@ fn record_pattern() {
     let pattern = Pattern::Record {
         id: String::new(),
         types: Vec::new(),
-        fields: vec![(intern("y"), None), (intern("x"), Some(intern("z")))],
+        fields: vec![(intern("y"), None), (intern("x"), Some(id_pattern("z")))],
+    };
+    assert_eq!(e, case(id("x"), vec![(pattern, id("z"))]));
+}
+#[test]
+fn nested_record_pattern() {
+    let _ = ::env_logger::init();
+    let e = parse_new("match x with | { y = Some { z } } -> z");
+    let inner = Pattern::Record {
+        id: String::new(),
+        types: Vec::new(),
+        fields: vec![(intern("z"), None)],
+    };
+    let pattern = Pattern::Record {
+        id: String::new(),
+        types: Vec::new(),
+        fields: vec![(intern("y"),
+                      Some(no_loc(Pattern::Constructor(intern("Some"), vec![no_loc(inner)]))))],
     };
     assert_eq!(e, case(id("x"), vec![(pattern, id("z"))]));
 }
@@ -370,6 +651,8 @@ fn let_pattern() {
     let e = parse_new("let {x, y} = test in x");
     assert_eq!(e,
                no_loc(Expr::Let(vec![Binding {
+                                         implicit: false,
+                                         fixity: None,
                                          comment: None,
                                          name: no_loc(Pattern::Record {
                                              id: String::new(),
@@ -408,7 +691,7 @@ fn span_identifier() {
     let _ = ::env_logger::init();
 
     let e = parse_new("test");
-    assert_eq!(e.span(&EmptyEnv::new()),
+    assert_eq!(e.span,
                Span {
                    start: loc(1, 1),
                    end: loc(1, 5),
@@ -421,7 +704,7 @@ fn span_integer() {
     let _ = ::env_logger::init();
 
     let e = parse_new("1234");
-    assert_eq!(e.span(&EmptyEnv::new()),
+    assert_eq!(e.span,
                Span {
                    start: loc(1, 1),
                    end: loc(1, 5),
@@ -433,7 +716,7 @@ fn span_call() {
     let _ = ::env_logger::init();
 
     let e = parse_new(r#" f 123 "asd" "#);
-    assert_eq!(e.span(&EmptyEnv::new()),
+    assert_eq!(e.span,
                Span {
                    start: loc(1, 2),
                    end: loc(1, 13),
@@ -449,7 +732,7 @@ match False with
     | True -> "asd"
     | False -> ""
 "#);
-    assert_eq!(e.span(&EmptyEnv::new()),
+    assert_eq!(e.span,
                Span {
                    start: loc(2, 1),
                    end: loc(4, 18),
@@ -466,7 +749,7 @@ if True then
 else
     123.45
 "#);
-    assert_eq!(e.span(&EmptyEnv::new()),
+    assert_eq!(e.span,
                Span {
                    start: loc(2, 1),
                    end: loc(5, 11),
@@ -478,13 +761,42 @@ fn span_byte() {
     let _ = ::env_logger::init();
 
     let e = parse_new(r#"124b"#);
-    assert_eq!(e.span(&EmptyEnv::new()),
+    assert_eq!(e.span,
                Span {
                    start: loc(1, 1),
                    end: loc(1, 5),
                });
 }
 
+#[test]
+fn span_byte_offsets() {
+    let _ = ::env_logger::init();
+
+    fn position(location: Location) -> (u32, usize, u32) {
+        (location.line, location.column.to_usize(), location.absolute.0)
+    }
+
+    let text = "let x : String = \"åäö\"\nx.len";
+    let e = parse_new(text);
+    assert_eq!((position(e.span.start), position(e.span.end)),
+               ((1, 1, 0), (2, 6, 31)));
+    match e.value {
+        Expr::Let(ref bindings, ref body) => {
+            let bind = &bindings[0];
+            assert_eq!(position(bind.name.span.end), (1, 6, 5));
+            let typ = bind.typ.as_ref().unwrap();
+            assert_eq!((position(typ.span.start), position(typ.span.end)),
+                       ((1, 9, 8), (1, 15, 14)));
+            assert_eq!((position(bind.expression.span.start),
+                        position(bind.expression.span.end)),
+                       ((1, 18, 17), (1, 23, 25)));
+            assert_eq!((position(body.span.start), position(body.span.end)),
+                       ((2, 1, 26), (2, 6, 31)));
+        }
+        _ => panic!("Expected let, found {:?}", e),
+    }
+}
+
 #[test]
 fn comment_on_let() {
     let _ = ::env_logger::init();
@@ -496,15 +808,53 @@ id
     let e = parse_new(text);
     assert_eq!(e,
                no_loc(Expr::Let(vec![Binding {
+                                         implicit: false,
+                                         fixity: None,
                                          comment: Some("The identity function".into()),
                                          name: no_loc(Pattern::Identifier(intern("id"))),
                                          typ: None,
-                                         arguments: vec![intern("x")],
+                                         arguments: vec![no_loc(intern("x"))],
                                          expression: id("x"),
                                      }],
                                 Box::new(id("id")))));
 }
 
+#[test]
+fn implicit_binding() {
+    let _ = ::env_logger::init();
+    let e = parse_new("let implicit f eq x : [Eq a] -> a -> a = x in f");
+    let eq = Type::app(typ("Eq"), vec![typ("a")]);
+    let sig = Type::implicit_function(vec![eq], Type::function(vec![typ("a")], typ("a")));
+    assert_eq!(e,
+               no_loc(Expr::Let(vec![Binding {
+                                         implicit: true,
+                                         comment: None,
+                                         name: no_loc(Pattern::Identifier(intern("f"))),
+                                         typ: Some(no_loc(sig)),
+                                         arguments: vec![no_loc(intern("eq")), no_loc(intern("x"))],
+                                         expression: id("x"),
+                                     }],
+                                Box::new(id("f")))));
+}
+
+#[test]
+fn do_binding() {
+    let _ = ::env_logger::init();
+    let text = r#"
+do x = f 1
+g x
+"#;
+    let e = parse_new(text);
+    assert_eq!(e,
+               no_loc(Expr::Do(Do {
+                   id: intern("x"),
+                   bound: Box::new(call(id("f"), vec![int(1)])),
+                   body: Box::new(call(id("g"), vec![id("x")])),
+                   flat_map_id: intern("flat_map"),
+                   lambda_id: intern(""),
+               })));
+}
+
 #[test]
 fn comment_on_type() {
     let _ = ::env_logger::init();
@@ -519,6 +869,7 @@ id
                                    comment: Some("Test type ".into()),
                                    name: intern("Test"),
                                    alias: Alias::new(intern("Test"), Vec::new(), typ("Int")),
+                                   deriving: Vec::new(),
                                }],
                           id("id")));
 }
@@ -542,6 +893,7 @@ id
                                          comment: Some("Test type ".into()),
                                          name: intern("Test"),
                                          alias: Alias::new(intern("Test"), Vec::new(), typ("Int")),
+                                         deriving: Vec::new(),
                                      }],
                                 id("id"))));
 }
@@ -562,6 +914,7 @@ id
                                    comment: Some("Merge\nconsecutive\nline comments.".into()),
                                    name: intern("Test"),
                                    alias: Alias::new(intern("Test"), Vec::new(), typ("Int")),
+                                   deriving: Vec::new(),
                                }],
                           id("id")));
 }
@@ -575,7 +928,7 @@ fn partial_field_access() {
     assert_eq!(e.unwrap_err().0,
                Some(Located {
                    value: Expr::FieldAccess(Box::new(id("test")), intern("")),
-                   location: loc(0, 0),
+                   span: pos::span(loc(0, 0), loc(0, 0)),
                }));
 }
 
@@ -590,11 +943,11 @@ test
     assert!(e.is_err());
     assert_eq!(e.unwrap_err().0,
                Some(Located {
-                   location: loc(0, 0),
+                   span: pos::span(loc(0, 0), loc(0, 0)),
                    value: Expr::Block(vec![Located {
                                                value: Expr::FieldAccess(Box::new(id("test")),
                                                                         intern("")),
-                                               location: loc(0, 0),
+                                               span: pos::span(loc(0, 0), loc(0, 0)),
                                            },
                                            id("test")]),
                }));
@@ -610,12 +963,188 @@ x
     let e = parse(text);
     assert_eq!(e,
                Ok(no_loc(Expr::Let(vec![Binding {
+                                            implicit: false,
+                                            fixity: None,
                                             comment: None,
                                             name: no_loc(Pattern::Identifier(intern("x"))),
-                                            typ: Some(Type::app(typ("->"),
-                                                                vec![typ("Int"), typ("Int")])),
+                                            typ: Some(no_loc(Type::app(typ("->"),
+                                                                       vec![typ("Int"),
+                                                                            typ("Int")]))),
                                             arguments: vec![],
                                             expression: id("x"),
                                         }],
                                    Box::new(id("x"))))));
 }
+
+#[test]
+fn recover_from_errors_in_block() {
+    let _ = ::env_logger::init();
+    let text = r#"
+1 +
+test2 =
+3
+"#;
+    let e = parse(text);
+    let (expr, errors) = e.unwrap_err();
+    assert_eq!(errors.errors.len(), 2, "{}", errors);
+    assert_eq!(expr,
+               Some(no_loc(Expr::Block(vec![no_loc(Expr::Error(intern(""))),
+                                            id("test2"),
+                                            int(3)]))));
+}
+
+#[test]
+fn recover_from_error_in_let_binding() {
+    let _ = ::env_logger::init();
+    let text = r#"
+let x = 1 +
+let y = 2
+y
+"#;
+    let e = parse(text);
+    let (expr, errors) = e.unwrap_err();
+    assert_eq!(errors.errors.len(), 1, "{}", errors);
+    assert_eq!(expr,
+               Some(let_("x", no_loc(Expr::Error(intern(""))), let_("y", int(2), id("y")))));
+}
+
+#[test]
+fn holes() {
+    let _ = ::env_logger::init();
+    let e = parse_new("f _ _y (\\_ -> x)");
+    assert_eq!(e,
+               call(id("f"),
+                    vec![no_loc(Expr::Hole(intern("_"))),
+                         id("_y"),
+                         lambda("", vec![intern("_")], id("x"))]));
+}
+
+#[test]
+fn forall_type() {
+    let _ = ::env_logger::init();
+    let e = parse_new("type Test = { id : forall a . a -> a, f : (forall b . b) -> Int } in 1");
+    let id_type = Type::forall(vec![generic("a")], Type::function(vec![typ("a")], typ("a")));
+    let f_type = Type::function(vec![Type::forall(vec![generic("b")], typ("b"))], typ("Int"));
+    let record = Type::record(Vec::new(), vec![field("id", id_type), field("f", f_type)]);
+    assert_eq!(e, type_decl(intern("Test"), vec![], record, int(1)));
+}
+
+#[test]
+fn annotated_expression() {
+    let _ = ::env_logger::init();
+    let e = parse_new("x : { type T, y : T }");
+    let abstract_type = Alias::from(AliasData {
+        name: intern("T"),
+        args: vec![],
+        typ: None,
+    });
+    let record = Type::record(vec![field("T", abstract_type)], vec![field("y", typ("T"))]);
+    assert_eq!(e, no_loc(Expr::Annotated(Box::new(id("x")), no_loc(record))));
+}
+
+#[test]
+fn annotated_expression_with_type_definition() {
+    let _ = ::env_logger::init();
+    let e = parse_new("x : { type T a = List a, y : T Int }");
+    let alias = Alias::from(AliasData {
+        name: intern("T"),
+        args: vec![generic("a")],
+        typ: Some(Type::app(typ("List"), vec![typ("a")])),
+    });
+    let record = Type::record(vec![field("T", alias)],
+                              vec![field("y", Type::app(typ("T"), vec![typ("Int")]))]);
+    assert_eq!(e, no_loc(Expr::Annotated(Box::new(id("x")), no_loc(record))));
+}
+
+#[test]
+fn string_escapes() {
+    let _ = ::env_logger::init();
+    let string = |s: &str| no_loc(Expr::Literal(LiteralEnum::String(s.into())));
+    assert_eq!(parse_new(r#" "\u{e9}\t\0\"\\\n\r\'" "#),
+               string("\u{e9}\t\0\"\\\n\r'"));
+    assert_eq!(parse_new(r"'\u{1F600}'"),
+               no_loc(Expr::Literal(LiteralEnum::Char('\u{1F600}'))));
+    assert_eq!(parse_new(r"'\''"), no_loc(Expr::Literal(LiteralEnum::Char('\''))));
+    assert!(parse(r#" "\q" "#).is_err());
+    assert!(parse(r#" "\u{110000}" "#).is_err());
+    assert!(parse(r#" "\u{}" "#).is_err());
+}
+
+#[test]
+fn raw_strings() {
+    let _ = ::env_logger::init();
+    let string = |s: &str| no_loc(Expr::Literal(LiteralEnum::String(s.into())));
+    assert_eq!(parse_new(r#"r"\d+\n""#), string(r"\d+\n"));
+    assert_eq!(parse_new(r###"r#"{ "key": "value" }"#"###),
+               string(r#"{ "key": "value" }"#));
+    assert_eq!(parse_new(r###"r##"a "# b"##"###), string(r##"a "# b"##));
+    // `r` is still an identifier when it does not start a raw string
+    assert_eq!(parse_new("let r = 1\nr"), let_("r", int(1), id("r")));
+}
+
+#[test]
+fn multi_line_strings() {
+    let _ = ::env_logger::init();
+    let text = "let x = \"first\n  second \\\n    third\"\nx";
+    let e = parse_new(text);
+    assert_eq!(e,
+               let_("x",
+                    no_loc(Expr::Literal(LiteralEnum::String("first\n  second third".into()))),
+                    id("x")));
+    match e.value {
+        Expr::Let(ref bindings, ref body) => {
+            let string = &bindings[0].expression;
+            assert_eq!((string.span.start, string.span.end), (loc(1, 9), loc(3, 11)));
+            assert_eq!(string.span.end.absolute, BytePos(36));
+            assert_eq!(body.span.start, loc(4, 1));
+        }
+        _ => panic!("Expected let, found {:?}", e),
+    }
+}
+
+#[test]
+fn number_literals() {
+    let _ = ::env_logger::init();
+    let literal = |literal| no_loc(Expr::Literal(literal));
+    assert_eq!(parse_new("0xFF"), int(255));
+    assert_eq!(parse_new("0x7fff_FFFF"), int(0x7fff_ffff));
+    assert_eq!(parse_new("0o17"), int(15));
+    assert_eq!(parse_new("0b1010"), int(10));
+    assert_eq!(parse_new("1_000_000"), int(1_000_000));
+    assert_eq!(parse_new("9223372036854775807"), int(i64::max_value()));
+    assert_eq!(parse_new("0x1Fb"), int(0x1fb));
+    assert_eq!(parse_new("0b"), literal(LiteralEnum::Byte(0)));
+    assert_eq!(parse_new("0b11b"), literal(LiteralEnum::Byte(3)));
+    assert_eq!(parse_new("255b"), literal(LiteralEnum::Byte(255)));
+    assert_eq!(parse_new("1_0.2_5"), literal(LiteralEnum::Float(10.25)));
+    assert_eq!(parse_new("1e3"), literal(LiteralEnum::Float(1000.0)));
+    assert_eq!(parse_new("2.5E-1"), literal(LiteralEnum::Float(0.25)));
+    assert_eq!(parse_new("1e+2"), literal(LiteralEnum::Float(100.0)));
+}
+
+#[test]
+fn number_literal_out_of_range() {
+    let _ = ::env_logger::init();
+    let errors = |input: &str| {
+        let errors = match parse(input) {
+            Ok(expr) => panic!("Expected an error, found {:?}", expr),
+            Err((Some(_), errors)) => errors,
+            Err((None, errors)) => panic!("Expected the parse to recover: {}", errors),
+        };
+        errors.errors
+            .iter()
+            .map(|err| {
+                let diagnostic = parser::diagnostic(err);
+                let span = diagnostic.span.unwrap();
+                (diagnostic.message, span.start, span.end)
+            })
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(errors("let x = 99999999999999999999\nx"),
+               vec![("Integer literal is too large".to_string(), loc(1, 9), loc(1, 29))]);
+    assert_eq!(errors("256b"),
+               vec![("Byte literal out of range".to_string(), loc(1, 1), loc(1, 5))]);
+    assert_eq!(errors("0x1_0000_0000_0000_0000 + 1e400"),
+               vec![("Integer literal is too large".to_string(), loc(1, 1), loc(1, 24)),
+                    ("Float literal is too large".to_string(), loc(1, 27), loc(1, 32))]);
+}
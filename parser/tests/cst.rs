@@ -0,0 +1,69 @@
+extern crate gluon_base as base;
+extern crate gluon_parser as parser;
+
+use base::ast::{EmptyEnv, Expr};
+use parser::cst::{SyntaxTree, TriviaKind};
+use parser::lexer::{CommentKind, IdentType, Token};
+use parser::parse_string;
+
+#[test]
+fn tree_contains_the_whole_source() {
+    let inputs = [r#"
+// A comment
+let x = 1   /* block
+comment */
+/// Doc comment
+let f y = y #Int+ x
+f "a string with // in it"
+"#,
+                  include_str!("../../std/prelude.glu"),
+                  include_str!("../../std/repl.glu")];
+    for input in &inputs {
+        let tree = SyntaxTree::new(input).unwrap();
+        assert_eq!(tree.to_string(), *input);
+    }
+}
+
+#[test]
+fn comments_are_trivia() {
+    let tree = SyntaxTree::new("let x = 1 // one\n/* two */ x").unwrap();
+    let tokens: Vec<_> = tree.tokens().iter().map(|token| token.token.clone()).collect();
+    assert_eq!(tokens,
+               vec![Token::Let,
+                    Token::Identifier("x".to_string(), IdentType::Variable),
+                    Token::Equal,
+                    Token::Integer(1),
+                    Token::Identifier("x".to_string(), IdentType::Variable)]);
+    let comments: Vec<_> = tree.trivia()
+        .filter(|trivia| trivia.kind != TriviaKind::Whitespace)
+        .map(|trivia| (trivia.kind, tree.text(trivia.span)))
+        .collect();
+    assert_eq!(comments,
+               vec![(TriviaKind::Comment(CommentKind::Line), "// one"),
+                    (TriviaKind::Comment(CommentKind::Block), "/* two */")]);
+    let last = &tree.tokens()[4];
+    assert_eq!(last.leading_trivia.len(), 5);
+}
+
+#[test]
+fn expr_tokens() {
+    let input = "let x = 1 + 2 * 3 // comment\nx";
+    let expr = parse_string(&mut EmptyEnv::new(), input)
+        .unwrap_or_else(|(_, err)| panic!("{}", err));
+    let tree = SyntaxTree::new(input).unwrap();
+    let bind = match expr.value {
+        Expr::Let(ref bindings, _) => &bindings[0],
+        _ => panic!("Expected let, found {:?}", expr),
+    };
+    let tokens: Vec<_> = tree.expr_tokens(&bind.expression)
+        .iter()
+        .map(|token| tree.text(token.span))
+        .collect();
+    assert_eq!(tokens, vec!["1", "+", "2", "*", "3"]);
+}
+
+#[test]
+fn unterminated_string_is_an_error() {
+    assert!(SyntaxTree::new("let x = \"abc\nx").is_err());
+    assert!(SyntaxTree::new("1 /* 2").is_err());
+}
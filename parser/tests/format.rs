@@ -0,0 +1,148 @@
+extern crate gluon_base as base;
+extern crate gluon_parser as parser;
+
+use base::ast::{EmptyEnv, LExpr};
+use parser::format::format_expr;
+use parser::parse_string;
+
+fn parse(text: &str) -> LExpr<String> {
+    parse_string(&mut EmptyEnv::new(), text).unwrap_or_else(|(_, err)| panic!("{}\n{}", err, text))
+}
+
+/// Checks that formatting `input` does not change what it parses to and that formatting the
+/// result again does not change it
+fn check_format(input: &str) -> String {
+    let formatted = format_expr(input).unwrap();
+    assert_eq!(parse(input), parse(&formatted));
+    assert_eq!(format_expr(&formatted).unwrap(), formatted);
+    formatted
+}
+
+#[test]
+fn canonical_layout() {
+    let input = r#"
+let   f x y=x+  y*2
+let g = \x ->
+        f   x 1
+match   g 2 with
+  | 3 -> "three"
+  |  _ ->   "other"
+"#;
+    assert_eq!(check_format(input),
+               r#"let f x y = x + y * 2
+let g = \x -> f x 1
+match g 2 with
+    | 3 -> "three"
+    | _ -> "other"
+"#);
+}
+
+#[test]
+fn parentheses_are_kept_where_needed() {
+    let formatted = check_format("let x = (1 + 2) * 3 - (4 - 5)\nf (g x) (\\y -> y) ((g x).y)");
+    assert_eq!(formatted,
+               "let x = (1 + 2) * 3 - (4 - 5)\nf (g x) (\\y -> y) (g x).y\n");
+}
+
+#[test]
+fn long_expressions_are_split() {
+    let input = r#"
+let record = { first_field = some_long_function_name 1 2 3, second_field = another_function "abc", third = [1, 2, 3] }
+if record.first_field == 1 then record.second_field else "a string which is long enough to not fit on a line"
+"#;
+    assert_eq!(check_format(input),
+               r#"let record = {
+    first_field = some_long_function_name 1 2 3,
+    second_field = another_function "abc",
+    third = [1, 2, 3]
+}
+if record.first_field == 1 then
+    record.second_field
+else
+    "a string which is long enough to not fit on a line"
+"#);
+}
+
+#[test]
+fn comments_are_kept() {
+    let input = r#"
+// The first binding
+let x = 1 // one
+
+/* Block comment */
+let y = 2
+x + y
+"#;
+    assert_eq!(check_format(input),
+               r#"// The first binding
+let x = 1  // one
+
+/* Block comment */
+let y = 2
+x + y
+"#);
+}
+
+#[test]
+fn doc_comments_and_fixity_declarations() {
+    let input = r#"
+/// Adds two numbers
+let add x y = x + y
+/** Multiplies
+two numbers */
+and mul x y = x * y
+infixr 1 <>
+let (<>) x y = x ++ y
+"a" <> "b" <> "c"
+"#;
+    check_format(input);
+}
+
+#[test]
+fn types() {
+    let input = r#"
+type Option a = | None | Some a
+type Record (f : Type -> Type) = { x : f Int, g : forall a . a -> Int, h : [Eq a] -> a -> a }
+type Long = | FirstVariantWithAVeryLongName Int | SecondVariantWithAVeryLongName String | Third Float
+1
+"#;
+    check_format(input);
+}
+
+#[test]
+fn format_std() {
+    for &(name, source) in &[("prelude", include_str!("../../std/prelude.glu")),
+                             ("map", include_str!("../../std/map.glu")),
+                             ("repl", include_str!("../../std/repl.glu")),
+                             ("state", include_str!("../../std/state.glu")),
+                             ("stream", include_str!("../../std/stream.glu")),
+                             ("string", include_str!("../../std/string.glu")),
+                             ("test", include_str!("../../std/test.glu")),
+                             ("types", include_str!("../../std/types.glu")),
+                             ("writer", include_str!("../../std/writer.glu"))] {
+        let formatted = format_expr(source).unwrap_or_else(|err| panic!("{}: {}", name, err));
+        assert!(parse(source) == parse(&formatted), "{} changed when formatted", name);
+        assert_eq!(format_expr(&formatted).unwrap(), formatted);
+    }
+}
+
+#[test]
+fn number_literals_keep_their_form() {
+    let input = "let mask = 0xFF_FF\nlet x = 1_000.5e3\nmask + 0b1010";
+    assert_eq!(check_format(input),
+               "let mask = 0xFF_FF\nlet x = 1_000.5e3\nmask + 0b1010\n");
+}
+
+#[test]
+fn operator_sections_and_backticks() {
+    let input = r#"
+let f = map (+  1) (filter (`elem`xs) ys)
+let g = (x *)
+(+ 2 * 3)  (a  `div`  b)
+"#;
+    assert_eq!(check_format(input),
+               r#"let f = map (+ 1) (filter (`elem` xs) ys)
+let g = (x *)
+(+ 2 * 3) (a `div` b)
+"#);
+}
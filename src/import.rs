@@ -8,7 +8,7 @@ use std::io::Read;
 use std::path::{Path, PathBuf};
 
 use base::ast;
-use base::metadata::Metadata;
+use base::metadata::MetadataEnv;
 use base::pos;
 use base::symbol::Symbol;
 use vm::macros::{Macro, Error as MacroError};
@@ -81,15 +81,47 @@ impl Importer for CheckImporter {
     fn import(&self, vm: &Thread, modulename: &str, input: &str) -> Result<(), MacroError> {
         use compiler_pipeline::*;
         let mut compiler = Compiler::new().implicit_prelude(modulename != "std.types");
-        let TypecheckValue(expr, typ) = try!(input.typecheck(&mut compiler, vm, modulename, input));
+        let TypecheckValue(mut expr, typ) =
+            try!(input.typecheck(&mut compiler, vm, modulename, input));
+        let metadata = ::check::metadata::metadata(&*vm.get_env(), &mut expr);
         self.0.lock().unwrap().insert(modulename.into(), expr);
-        let metadata = Metadata::default();
         // Insert a global to ensure the globals type can be looked up
         try!(vm.global_env().set_global(Symbol::new(modulename), typ, metadata, Value::Int(0)));
         Ok(())
     }
 }
 
+/// Loads the module which `import "filename"` refers to and returns the fixities which it declares
+/// for the operators it exports. This lets the parser use the fixities in the body of the `let`
+/// which binds the import.
+pub fn import_fixities(vm: &Thread,
+                       filename: &str)
+                       -> Result<Vec<(String, ast::Fixity)>, MacroError> {
+    let import = match vm.get_macros().get("import") {
+        Some(import) => import,
+        None => return Ok(Vec::new()),
+    };
+    let location = pos::Location {
+        line: 1,
+        column: pos::CharPos(1),
+        absolute: pos::BytePos(0),
+    };
+    let literal = ast::Expr::Literal(ast::LiteralEnum::String(filename.into()));
+    try!(import.expand(vm, &mut [pos::located(pos::span(location, location), literal)]));
+    let env = vm.get_env();
+    let modulename = Symbol::new(&filename_to_module(filename));
+    let fixities = match MetadataEnv::get_metadata(&*env, &modulename) {
+        Some(metadata) => {
+            metadata.module
+                .iter()
+                .filter_map(|(name, m)| m.fixity.map(|fixity| (name.clone(), fixity)))
+                .collect()
+        }
+        None => Vec::new(),
+    };
+    Ok(fixities)
+}
+
 /// Macro which rewrites occurances of `import "filename"` to a load of that file if it is not
 /// already loaded and then a global access to the loaded module
 pub struct Import<I = DefaultImporter> {
@@ -165,7 +197,7 @@ impl<I> Macro for Import<I>
                     try!(self.importer.import(vm, &modulename, file_contents));
                 }
                 // FIXME Does not handle shadowing
-                Ok(pos::located(arguments[0].location,
+                Ok(pos::located(arguments[0].span,
                                 ast::Expr::Identifier(TcIdent::new(name))))
             }
             _ => return Err(Error::String("Expected a string literal to import".into()).into()),
@@ -14,7 +14,7 @@ use vm::api::generic::{A, B};
 
 use vm::internal::Value;
 
-use super::Compiler;
+use super::{Compiler, Error};
 
 fn print_int(i: VmInt) -> IO<()> {
     print!("{}", i);
@@ -153,16 +153,25 @@ fn catch_io(vm: &Thread) -> Status {
 
 fn run_expr(expr: WithVM<RootStr>) -> IO<String> {
     let WithVM { vm, value: expr } = expr;
+    run_expr_with(vm, &expr, |err| err.to_string())
+}
+
+/// Runs `expr` and returns its value and type as a string. If running the expression fails the
+/// error is converted to a string with `show_error` and returned as an exception together with a
+/// stacktrace.
+pub fn run_expr_with<F>(vm: &Thread, expr: &str, show_error: F) -> IO<String>
+    where F: FnOnce(&Error) -> String
+{
     let mut stack = vm.current_frame();
     let frame_level = stack.stack.get_frames().len();
     drop(stack);
-    let run_result = Compiler::new().run_expr::<Generic<A>>(vm, "<top>", &expr);
+    let run_result = Compiler::new().run_expr::<Generic<A>>(vm, "<top>", expr);
     stack = vm.current_frame();
     match run_result {
         Ok((value, typ)) => IO::Value(format!("{:?} : {}", value.0, typ)),
         Err(err) => {
             let trace = stack.stacktrace(frame_level);
-            let fmt = format!("{}\n{}", err, trace);
+            let fmt = format!("{}\n{}", show_error(&err), trace);
             while stack.stack.get_frames().len() > frame_level {
                 match stack.exit_scope() {
                     Some(new_stack) => stack = new_stack,
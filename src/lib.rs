@@ -18,14 +18,19 @@ pub extern crate gluon_check as check;
 mod io;
 pub mod import;
 
+pub use io::run_expr_with;
 pub use vm::thread::{RootedThread, Thread};
 
+use std::collections::HashMap;
 use std::result::Result as StdResult;
 use std::string::String as StdString;
 use std::env;
 
 use base::ast;
-use base::error::Errors;
+use base::diagnostic::{Diagnostic, ToDiagnostic};
+use base::error::{Errors, InFile};
+use base::pos::Spanned;
+use base::snippet;
 use base::types::TcType;
 use base::symbol::{Name, NameBuf, Symbol, Symbols, SymbolModule};
 use base::metadata::Metadata;
@@ -39,6 +44,8 @@ use vm::thread::{RootedValue, ThreadInternal};
 use vm::internal::ClosureDataDef;
 use vm::macros;
 
+use check::lint::{Warning, WarningCode, WarningLevel};
+
 quick_error! {
     /// Error type wrapping all possible errors that can be generated from gluon
     #[derive(Debug)]
@@ -55,6 +62,19 @@ quick_error! {
             display("{}", err)
             from()
         }
+        /// Typed holes found when typechecking gluon code. Code containing holes can't be
+        /// compiled
+        Hole(err: ::base::error::InFile<::check::typecheck::Hole<Symbol>>) {
+            description(err.description())
+            display("{}", err)
+            from()
+        }
+        /// Warnings which the compiler was configured to report as errors
+        Warning(err: ::base::error::InFile<::check::lint::Warning<Symbol>>) {
+            description(err.description())
+            display("{}", err)
+            from()
+        }
         /// Error found when performing an IO action such as loading a file
         IO(err: ::std::io::Error) {
             description(err.description())
@@ -73,6 +93,12 @@ quick_error! {
             display("{}", err)
             from()
         }
+        /// Error found when generating the instances of `deriving` clauses
+        Derive(err: Errors<Spanned<vm::derive::Error>>) {
+            description(err.description())
+            display("{}", err)
+            from()
+        }
         /// Multiple errors where found
         Multiple(err: Errors<Error>) {
             description(err.description())
@@ -103,6 +129,43 @@ impl From<Errors<macros::Error>> for Error {
     }
 }
 
+impl Error {
+    /// Returns a structured `Diagnostic` for each of the errors contained in `self`
+    pub fn diagnostics(&self) -> Vec<Diagnostic> {
+        match *self {
+            Error::Parse(ref err) => err.errors.iter().map(parser::diagnostic).collect(),
+            Error::Typecheck(ref err) => err.diagnostics(),
+            Error::Hole(ref err) => err.diagnostics(),
+            Error::Warning(ref err) => err.diagnostics(),
+            Error::IO(ref err) => vec![Diagnostic::error("io_error", err.to_string())],
+            Error::VM(ref err) => vec![err.to_diagnostic()],
+            Error::Macro(ref err) => vec![Diagnostic::error("macro_error", err.to_string())],
+            Error::Derive(ref err) => err.errors.iter().map(|err| err.to_diagnostic()).collect(),
+            Error::Multiple(ref errors) => {
+                errors.errors.iter().flat_map(|err| err.diagnostics()).collect()
+            }
+        }
+    }
+
+    /// Renders the errors together with the source code they refer to (if it is known). If
+    /// `colors` is true the output is coloured with ANSI escape codes.
+    pub fn render(&self, colors: bool) -> StdString {
+        match *self {
+            Error::Typecheck(ref err) => err.render(colors),
+            Error::Hole(ref err) => err.render(colors),
+            Error::Warning(ref err) => err.render(colors),
+            Error::Multiple(ref errors) => {
+                errors.errors.iter().map(|err| err.render(colors)).collect()
+            }
+            _ => {
+                self.diagnostics()
+                    .iter()
+                    .map(|diagnostic| snippet::render(diagnostic, None, colors))
+                    .collect()
+            }
+        }
+    }
+}
 
 impl From<Errors<Error>> for Error {
     fn from(mut errors: Errors<Error>) -> Error {
@@ -121,6 +184,8 @@ pub type Result<T> = StdResult<T, Error>;
 pub struct Compiler {
     symbols: Symbols,
     implicit_prelude: bool,
+    warning_levels: HashMap<WarningCode, WarningLevel>,
+    warnings: Vec<InFile<Warning<Symbol>>>,
 }
 
 /// Advanced compiler pipeline which ensures that the compilation phases are run in order even if
@@ -136,7 +201,12 @@ pub mod compiler_pipeline {
     use vm::thread::{RootedValue, ThreadInternal};
     use vm::internal::ClosureDataDef;
 
-    pub struct MacroValue(pub ast::LExpr<ast::TcIdent<Symbol>>);
+    pub struct MacroValue {
+        pub expr: ast::LExpr<ast::TcIdent<Symbol>>,
+        /// The number of `let` expressions which the implicit prelude wrapped around the code
+        /// written by the user. Warnings are only reported for the code inside them.
+        pub implicit_prelude_lets: usize,
+    }
 
     pub trait MacroExpandable {
         fn expand_macro(self,
@@ -152,7 +222,11 @@ pub mod compiler_pipeline {
                         thread: &Thread,
                         file: &str)
                         -> Result<MacroValue> {
-            compiler.parse_expr(file, self)
+            // Imported modules are loaded while parsing so that the fixities they declare are
+            // known by the parser
+            compiler.parse_expr_with_imports(file,
+                                         self,
+                                         &mut |filename| import::import_fixities(thread, filename))
                 .map_err(From::from)
                 .and_then(|expr| expr.expand_macro(compiler, thread, file))
         }
@@ -164,11 +238,19 @@ pub mod compiler_pipeline {
                         thread: &Thread,
                         file: &str)
                         -> Result<MacroValue> {
-            if compiler.implicit_prelude {
-                compiler.include_implicit_prelude(file, &mut self);
-            }
+            let implicit_prelude_lets = if compiler.implicit_prelude {
+                compiler.include_implicit_prelude(file, &mut self)
+            } else {
+                0
+            };
             try!(thread.get_macros().run(thread, &mut self));
-            Ok(MacroValue(self))
+            try!(vm::derive::derive(&*thread.get_env(),
+                                    &mut SymbolModule::new(file.into(), &mut compiler.symbols),
+                                    &mut self));
+            Ok(MacroValue {
+                expr: self,
+                implicit_prelude_lets: implicit_prelude_lets,
+            })
         }
     }
 
@@ -221,8 +303,13 @@ pub mod compiler_pipeline {
                               -> Result<TypecheckValue>
             where Self: Sized
         {
-            compiler.typecheck_expr_expected(thread, file, expr_str, &mut self.0, expected_type)
-                .map(move |typ| TypecheckValue(self.0, typ))
+            compiler.typecheck_expr_expected(thread,
+                                          file,
+                                          expr_str,
+                                          &mut self.expr,
+                                          self.implicit_prelude_lets,
+                                          expected_type)
+                .map(move |typ| TypecheckValue(self.expr, typ))
         }
     }
 
@@ -343,6 +430,8 @@ impl Compiler {
         Compiler {
             symbols: Symbols::new(),
             implicit_prelude: true,
+            warning_levels: HashMap::new(),
+            warnings: Vec::new(),
         }
     }
 
@@ -353,6 +442,23 @@ impl Compiler {
         self
     }
 
+    /// Sets how warnings with the code `code` are reported (default: `WarningLevel::Warn`)
+    pub fn warning_level(mut self, code: WarningCode, level: WarningLevel) -> Compiler {
+        self.warning_levels.insert(code, level);
+        self
+    }
+
+    /// Returns the warnings found in the code which this compiler has typechecked. Warnings
+    /// which are denied are returned as errors instead.
+    pub fn warnings(&self) -> &[InFile<Warning<Symbol>>] {
+        &self.warnings
+    }
+
+    /// Removes and returns the warnings which this compiler has found
+    pub fn take_warnings(&mut self) -> Vec<InFile<Warning<Symbol>>> {
+        ::std::mem::replace(&mut self.warnings, Vec::new())
+    }
+
     /// Parse `input`, returning an expression if successful
     pub fn parse_expr(&mut self,
                       file: &str,
@@ -363,6 +469,21 @@ impl Compiler {
             .map_err(|t| t.1)))
     }
 
+    /// Parse `input`, calling `import` with the filename of each module which `input` imports
+    /// so that the fixities the module declares can be used when parsing the rest of the `let`
+    pub fn parse_expr_with_imports(&mut self,
+                                   file: &str,
+                                   input: &str,
+                                   import: &mut ::parser::ImportFixities)
+                                   -> StdResult<ast::LExpr<ast::TcIdent<Symbol>>,
+                                                Errors<::parser::Error>> {
+        Ok(try!(::parser::parse_tc_with_imports(&mut SymbolModule::new(file.into(),
+                                                                        &mut self.symbols),
+                                                import,
+                                                input)
+            .map_err(|t| t.1)))
+    }
+
     /// Parse `input`, returning an expression if successful
     pub fn parse_partial_expr(&mut self,
                               file: &str,
@@ -382,37 +503,81 @@ impl Compiler {
                           expr_str: &str,
                           expr: &mut ast::LExpr<ast::TcIdent<Symbol>>)
                           -> Result<TcType> {
-        self.typecheck_expr_expected(vm, file, expr_str, expr, None)
+        self.typecheck_expr_expected(vm, file, expr_str, expr, 0, None)
     }
 
+    /// Typechecks `expr` and lints the code inside the `implicit_prelude_lets` outermost `let`
+    /// expressions
     fn typecheck_expr_expected(&mut self,
                                vm: &Thread,
                                file: &str,
                                expr_str: &str,
                                expr: &mut ast::LExpr<ast::TcIdent<Symbol>>,
+                               implicit_prelude_lets: usize,
                                expected_type: Option<&TcType>)
                                -> Result<TcType> {
         use check::typecheck::Typecheck;
         use base::error;
-        let env = vm.get_env();
-        let mut tc = Typecheck::new(file.into(), &mut self.symbols, &*env);
-        let typ = try!(tc.typecheck_expr_expected(expr, expected_type)
-            .map_err(|err| error::InFile::new(StdString::from(file), expr_str, err)));
+        let (typ, match_warnings) = {
+            let env = vm.get_env();
+            let mut tc = Typecheck::new(file.into(), &mut self.symbols, &*env);
+            let typ = try!(tc.typecheck_expr_expected(expr, expected_type)
+                .map_err(|err| error::InFile::new(StdString::from(file), expr_str, err)));
+            if !tc.holes().is_empty() {
+                let holes = Errors { errors: tc.holes().to_vec() };
+                return Err(error::InFile::new(StdString::from(file), expr_str, holes).into());
+            }
+            (typ, tc.warnings().to_vec())
+        };
+        // Only the code which was written by the user is linted
+        try!(self.lint(file,
+                       expr_str,
+                       skip_lets(expr, implicit_prelude_lets),
+                       match_warnings));
         Ok(typ)
     }
 
+    /// Stores the warnings found in `expr` together with the warnings found while typechecking
+    /// it, returning an error if any of them are denied
+    fn lint(&mut self,
+            file: &str,
+            expr_str: &str,
+            expr: &mut ast::LExpr<ast::TcIdent<Symbol>>,
+            typecheck_warnings: Vec<Spanned<Warning<Symbol>>>)
+            -> Result<()> {
+        let mut warnings = Vec::new();
+        let mut denied = Vec::new();
+        for warning in typecheck_warnings.into_iter().chain(::check::lint::lint(expr)) {
+            let level = self.warning_levels
+                .get(&warning.value.code)
+                .cloned()
+                .unwrap_or(WarningLevel::Warn);
+            match level {
+                WarningLevel::Allow => (),
+                WarningLevel::Warn => warnings.push(warning),
+                WarningLevel::Deny => denied.push(warning),
+            }
+        }
+        if !warnings.is_empty() {
+            let warnings = Errors { errors: warnings };
+            self.warnings.push(InFile::new(StdString::from(file), expr_str, warnings));
+        }
+        if !denied.is_empty() {
+            let denied = Errors { errors: denied };
+            return Err(InFile::new(StdString::from(file), expr_str, denied).into());
+        }
+        Ok(())
+    }
+
     pub fn typecheck_str(&mut self,
                          vm: &Thread,
                          file: &str,
                          expr_str: &str,
                          expected_type: Option<&TcType>)
                          -> Result<(ast::LExpr<ast::TcIdent<Symbol>>, TcType)> {
-        let mut expr = try!(self.parse_expr(file, expr_str));
-        if self.implicit_prelude {
-            self.include_implicit_prelude(file, &mut expr);
-        }
-        try!(vm.get_macros().run(vm, &mut expr));
-        let typ = try!(self.typecheck_expr_expected(vm, file, expr_str, &mut expr, expected_type));
+        use compiler_pipeline::*;
+        let TypecheckValue(expr, typ) =
+            try!(expr_str.typecheck_expected(self, vm, file, expr_str, expected_type));
         Ok((expr, typ))
     }
 
@@ -538,12 +703,15 @@ impl Compiler {
         }
     }
 
+    /// Wraps `expr` in `let` expressions which bring the prelude into scope, returning the number
+    /// of `let` expressions
     fn include_implicit_prelude(&mut self,
                                 name: &str,
-                                expr: &mut ast::LExpr<ast::TcIdent<Symbol>>) {
+                                expr: &mut ast::LExpr<ast::TcIdent<Symbol>>)
+                                -> usize {
         use std::mem;
         if name == "std.prelude" {
-            return;
+            return 0;
         }
 
         let prelude_import = r#"
@@ -552,29 +720,53 @@ impl Compiler {
 
     let { (+), (-), (*), (/) } = __implicit_prelude.num_Int
     and { (==) } = __implicit_prelude.eq_Int
-    and { (<), (<=), (>=), (>) } = __implicit_prelude.make_Ord __implicit_prelude.ord_Int
+    and (<) : Int -> Int -> Bool = __implicit_prelude.(<)
+    and (<=) : Int -> Int -> Bool = __implicit_prelude.(<=)
+    and (>=) : Int -> Int -> Bool = __implicit_prelude.(>=)
+    and (>) : Int -> Int -> Bool = __implicit_prelude.(>)
 
     let { (+), (-), (*), (/) } = __implicit_prelude.num_Float
     and { (==) } = __implicit_prelude.eq_Float
-    and { (<), (<=), (>=), (>) } = __implicit_prelude.make_Ord __implicit_prelude.ord_Float
+    and (<) : Float -> Float -> Bool = __implicit_prelude.(<)
+    and (<=) : Float -> Float -> Bool = __implicit_prelude.(<=)
+    and (>=) : Float -> Float -> Bool = __implicit_prelude.(>=)
+    and (>) : Float -> Float -> Bool = __implicit_prelude.(>)
 
     let { (==) } = __implicit_prelude.eq_Char
-    and { (<), (<=), (>=), (>) } = __implicit_prelude.make_Ord __implicit_prelude.ord_Char
+    and (<) : Char -> Char -> Bool = __implicit_prelude.(<)
+    and (<=) : Char -> Char -> Bool = __implicit_prelude.(<=)
+    and (>=) : Char -> Char -> Bool = __implicit_prelude.(>=)
+    and (>) : Char -> Char -> Bool = __implicit_prelude.(>)
 
     in 0
     "#;
         let prelude_expr = self.parse_expr("", prelude_import).unwrap();
         let original_expr = mem::replace(expr, prelude_expr);
         fn assign_last_body(l: &mut ast::LExpr<ast::TcIdent<Symbol>>,
-                            original_expr: ast::LExpr<ast::TcIdent<Symbol>>) {
+                            original_expr: ast::LExpr<ast::TcIdent<Symbol>>)
+                            -> usize {
             match l.value {
-                ast::Expr::Let(_, ref mut e) => {
-                    assign_last_body(e, original_expr);
+                ast::Expr::Let(_, ref mut e) => 1 + assign_last_body(e, original_expr),
+                _ => {
+                    *l = original_expr;
+                    0
                 }
-                _ => *l = original_expr,
             }
         }
-        assign_last_body(expr, original_expr);
+        assign_last_body(expr, original_expr)
+    }
+}
+
+/// Returns the body of the `let` expression which is nested `lets` levels deep in `expr`
+fn skip_lets(expr: &mut ast::LExpr<ast::TcIdent<Symbol>>,
+             lets: usize)
+             -> &mut ast::LExpr<ast::TcIdent<Symbol>> {
+    if lets == 0 {
+        return expr;
+    }
+    match expr.value {
+        ast::Expr::Let(_, ref mut body) => skip_lets(body, lets - 1),
+        _ => expr,
     }
 }
 
@@ -19,9 +19,15 @@ extern crate gluon_vm as vm;
 #[cfg(not(test))]
 use std::error::Error as StdError;
 #[cfg(not(test))]
+use std::fs::File;
+#[cfg(not(test))]
+use std::io::{Read, Write};
+#[cfg(not(test))]
 use gluon::{new_vm, Compiler};
 #[cfg(not(test))]
 use clap::{Arg, App};
+#[cfg(not(test))]
+use base::ast::EmptyEnv;
 
 mod repl;
 
@@ -38,6 +44,43 @@ fn run_files<'s, I>(files: I) -> Result<(), Box<StdError + Send + Sync>>
     Ok(())
 }
 
+#[cfg(not(test))]
+fn fmt_files<'s, I>(files: I) -> Result<(), Box<StdError + Send + Sync>>
+    where I: Iterator<Item = &'s str>
+{
+    for file in files {
+        let mut source = String::new();
+        try!(try!(File::open(file)).read_to_string(&mut source));
+        let formatted = match parser::format::format_expr(&source) {
+            Ok(formatted) => formatted,
+            Err(errors) => {
+                let rendered: Vec<_> = errors.errors
+                    .iter()
+                    .map(|err| {
+                        let diagnostic = parser::diagnostic(err).in_file(file);
+                        base::snippet::render(&diagnostic, Some(&source), false)
+                    })
+                    .collect();
+                return Err(rendered.concat().into());
+            }
+        };
+        // Refuse to overwrite the file unless the formatted code parses to the same expression as
+        // the original code
+        let original = parser::parse_expr(&mut EmptyEnv::<String>::new(), &source);
+        let reparsed = parser::parse_expr(&mut EmptyEnv::<String>::new(), &formatted);
+        match (original, reparsed) {
+            (Ok(ref original), Ok(ref reparsed)) if original == reparsed => (),
+            _ => {
+                return Err(format!("Formatting `{}` would change its meaning so it was not \
+                                    written",
+                                   file)
+                    .into())
+            }
+        }
+        try!(try!(File::create(file)).write_all(formatted.as_bytes()));
+    }
+    Ok(())
+}
 
 #[cfg(all(not(test), feature = "env_logger"))]
 fn init_env_logger() {
@@ -65,13 +108,22 @@ fn main() {
                     .long("interactive")
                     .help("Starts the repl")
                     .takes_value(false))
+                .arg(Arg::with_name("FMT")
+                    .long("fmt")
+                    .help("Formats each input file in place instead of executing it")
+                    .takes_value(false))
                 .get_matches();
             if matches.is_present("REPL") {
                 if let Err(err) = repl::run() {
                     println!("{}", err);
                 }
             } else if let Some(args) = matches.values_of("INPUT") {
-                match run_files(args) {
+                let result = if matches.is_present("FMT") {
+                    fmt_files(args)
+                } else {
+                    run_files(args)
+                };
+                match result {
                     Ok(()) => (),
                     Err(msg) => println!("{}", msg),
                 }
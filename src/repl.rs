@@ -1,5 +1,7 @@
+extern crate libc;
 extern crate rustyline;
 
+use std::env;
 use std::error::Error as StdError;
 use std::fmt;
 use std::sync::Mutex;
@@ -12,7 +14,7 @@ use vm::api::{IO, Function, WithVM, VmType, Userdata};
 use vm::gc::{Gc, Traverseable};
 use vm::thread::{Thread, RootStr};
 
-use gluon::{Compiler, new_vm, RootedThread, Result as GluonResult};
+use gluon::{Compiler, new_vm, run_expr_with, RootedThread, Result as GluonResult};
 
 fn type_of_expr(args: WithVM<RootStr>) -> IO<Result<String, String>> {
     let WithVM { vm, value: args } = args;
@@ -84,9 +86,32 @@ fn find_info(args: WithVM<RootStr>) -> IO<Result<String, String>> {
     IO::Value(Ok(buffer))
 }
 
+fn eval_line(expr: WithVM<RootStr>) -> IO<String> {
+    let WithVM { vm, value: expr } = expr;
+    let colors = use_colors();
+    run_expr_with(vm, &expr, |err| err.render(colors))
+}
+
+/// Errors are coloured unless the `NO_COLOR` environment variable is set, the terminal does not
+/// support colours or the output is not written to a terminal
+fn use_colors() -> bool {
+    env::var_os("NO_COLOR").is_none() &&
+    env::var("TERM").map(|term| term != "dumb").unwrap_or(false) && is_terminal()
+}
+
+/// Returns true if both stdout and stderr are terminals
+#[cfg(unix)]
+fn is_terminal() -> bool {
+    unsafe { libc::isatty(libc::STDOUT_FILENO) != 0 && libc::isatty(libc::STDERR_FILENO) != 0 }
+}
+
+#[cfg(not(unix))]
+fn is_terminal() -> bool {
+    false
+}
+
 fn complete(thread: &Thread, name: &str, fileinput: &str, pos: usize) -> GluonResult<Vec<String>> {
     use base::pos::{BytePos, CharPos, Location};
-    use base::ast::EmptyEnv;
     use check::completion::suggest;
     use gluon::compiler_pipeline::*;
 
@@ -105,10 +130,10 @@ fn complete(thread: &Thread, name: &str, fileinput: &str, pos: usize) -> GluonRe
             Err((None, err)) => return Err(err.into()),
             Err((Some(expr), err)) => (expr, Err(err.into())),
         };
-    let MacroValue(mut expr) = try!(expr.expand_macro(&mut compiler, thread, &name));
+    let MacroValue { mut expr, .. } = try!(expr.expand_macro(&mut compiler, thread, &name));
     // Only need the typechecker to fill infer the types as best it can regardless of errors
     let _ = compiler.typecheck_expr(thread, &name, fileinput, &mut expr);
-    let suggestions = suggest(&EmptyEnv::new(), &*thread.get_env(), &expr, location);
+    let suggestions = suggest(&*thread.get_env(), &expr, location);
     Ok(suggestions.into_iter()
         .map(|ident| {
             let s: &str = ident.name.as_ref();
@@ -179,6 +204,7 @@ fn compile_repl(vm: &Thread) -> Result<(), Box<StdError + Send + Sync>> {
     )));
     try!(vm.define_global("repl_prim",
                           record!(
+        eval_line => primitive!(1 eval_line),
         type_of_expr => primitive!(1 type_of_expr),
         find_info => primitive!(1 find_info),
         find_kind => primitive!(1 find_kind)
@@ -128,3 +128,28 @@ sum_bytes [100b, 42b, 3b, 15b]
 
     assert_eq!(result, expected);
 }
+
+#[test]
+fn tuples() {
+    let _ = ::env_logger::init();
+
+    let expr = r#"
+let (x, y) = swap (1, 2)
+in (x #Int* 10, y, "a")
+"#;
+    fn swap(t: (VmInt, VmInt)) -> (VmInt, VmInt) {
+        (t.1, t.0)
+    }
+
+    let vm = make_vm();
+    vm.define_global("swap", {
+          let swap: fn(_) -> _ = swap;
+          swap
+      })
+      .unwrap();
+
+    let (result, _) = Compiler::new()
+        .run_expr::<(VmInt, VmInt, String)>(&vm, "<top>", expr)
+        .unwrap_or_else(|err| panic!("{}", err));
+    assert_eq!(result, (20, 1, "a".to_string()));
+}
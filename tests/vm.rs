@@ -9,6 +9,7 @@ use gluon::vm::internal::Value::{Float, Int};
 use gluon::vm::stack::State;
 use gluon::vm::channel::Sender;
 use gluon::vm::Error as VMError;
+use gluon::check::lint::{WarningCode, WarningLevel};
 use gluon::import::Import;
 use gluon::{Compiler, Error};
 
@@ -141,7 +142,10 @@ fn record() {
 ";
     let mut vm = make_vm();
     let value = run_expr::<Generic<A>>(&mut vm, text);
-    assert_eq!(value.0, vm.new_data(0, &mut [Int(0), Float(1.0), Value::Tag(0)]).unwrap());
+    let tag = vm.global_env().record_tag(&["x", "y", "z"]);
+    let empty_tag = vm.global_env().record_tag::<&str>(&[]);
+    assert_eq!(value.0,
+               vm.new_data(tag, &mut [Int(0), Float(1.0), Value::Tag(empty_tag)]).unwrap());
 }
 
 #[test]
@@ -154,7 +158,8 @@ add { x = 0, y = 1 } { x = 1, y = 1 }
 ";
     let mut vm = make_vm();
     let value = run_expr::<Generic<A>>(&mut vm, text);
-    assert_eq!(value.0, vm.new_data(0, &mut [Int(1), Int(2)]).unwrap());
+    let tag = vm.global_env().record_tag(&["x", "y"]);
+    assert_eq!(value.0, vm.new_data(tag, &mut [Int(1), Int(2)]).unwrap());
 }
 #[test]
 fn script() {
@@ -173,7 +178,26 @@ let { T, add, sub } = Vec
 in add { x = 10, y = 5 } { x = 1, y = 2 }
 "#;
     let value = run_expr::<Generic<A>>(&mut vm, script);
-    assert_eq!(value.0, vm.new_data(0, &mut [Int(11), Int(7)]).unwrap());
+    let tag = vm.global_env().record_tag(&["x", "y"]);
+    assert_eq!(value.0, vm.new_data(tag, &mut [Int(11), Int(7)]).unwrap());
+}
+#[test]
+fn imported_fixity() {
+    let _ = ::env_logger::init();
+    let text = r#"
+infixr 2 <->
+let (<->) l r = l #Int- r
+{ (<->) }
+"#;
+    let mut vm = make_vm();
+    load_script(&mut vm, "fixity", text).unwrap_or_else(|err| panic!("{}", err));
+
+    let script = r#"
+let { (<->) } = import "fixity"
+10 <-> 3 <-> 2
+"#;
+    let value = run_expr::<i32>(&mut vm, script);
+    assert_eq!(value, 9);
 }
 #[test]
 fn adt() {
@@ -292,6 +316,15 @@ in h 2 #Int+ g 10 3
 55i32
 }
 
+test_expr!{ operator_sections,
+r"
+let sub x y = x #Int- y in
+let twice f x = f (f x)
+in twice (#Int- 1) 10 #Int+ twice (100 #Int-) 1 #Int+ 2 `sub` 1 #Int* 3
+",
+12i32
+}
+
 test_expr!{ to_many_args_application,
 r"
 let f x = \y -> x #Int+ y in
@@ -371,6 +404,19 @@ string_prim.compare "a" "b"
 Generic::<A>::from(Value::Tag(0))
 }
 
+test_expr!{ prelude match_guard_falls_through,
+r#"
+let f x =
+    match x with
+    | Some y if 10 #Int< y -> y
+    | Some y if 5 #Int< y -> y #Int* 2
+    | Some y -> 0
+    | None -> 1
+f (Some 20) #Int+ f (Some 7) #Int+ f (Some 1) #Int+ f None
+"#,
+35i32
+}
+
 test_expr!{ prelude match_on_bool,
 r#"
 match True with
@@ -389,8 +435,16 @@ match A with
 | B -> True
 ";
     let mut vm = make_vm();
-    let result = Compiler::new().run_expr::<bool>(&mut vm, "<top>", text);
+    let mut compiler = Compiler::new();
+    let result = compiler.run_expr::<bool>(&mut vm, "<top>", text);
     assert!(result.is_err());
+    let warnings: Vec<_> = compiler.take_warnings()
+        .into_iter()
+        .flat_map(|warnings| warnings.errors().errors)
+        .map(|warning| (warning.value.code, String::from(warning.value.name.as_ref())))
+        .collect();
+    assert_eq!(warnings,
+               vec![(WarningCode::NonExhaustiveMatch, String::from("A"))]);
 }
 
 test_expr!{ record_pattern,
@@ -423,6 +477,142 @@ in match x with
 2.0f64
 }
 
+test_expr!{ nested_constructor_pattern,
+r#"
+type Option a = | None | Some a in
+type List a = | Nil | Cons a (List a) in
+match Cons (Some 1) (Cons None Nil) with
+| Cons (Some x) (Cons (Some y) _) -> x #Int+ y
+| Cons (Some x) (Cons None Nil) -> x #Int+ 10
+| _ -> 0
+"#,
+11i32
+}
+
+test_expr!{ literal_pattern,
+r#"
+let f x =
+    match x with
+    | 0 -> 10
+    | 1 -> 20
+    | y -> y
+in f 1 #Int+ f 5
+"#,
+25i32
+}
+
+test_expr!{ string_literal_pattern,
+r#"
+match { x = "abc", y = 'a' } with
+| { x = "ab", y } -> 1
+| { x = "abc", y = 'b' } -> 2
+| { x = "abc", y = 'a' } -> 3
+| _ -> 4
+"#,
+3i32
+}
+
+test_expr!{ tuple_pattern,
+r#"
+let (x, y) = (1, "abc")
+in
+match (x, (y, 2)) with
+| (a, (b, c)) -> a #Int+ string_prim.length b #Int+ c
+"#,
+6i32
+}
+
+test_expr!{ polymorphic_field_access,
+r#"
+let get_x r = r.x
+let { x } = { y = 1, x = 2 }
+get_x { x = 10 } #Int+ get_x { y = "abc", x = 20 } #Int+ get_x { x = 30, z = 1.0 } #Int+ x
+"#,
+62i32
+}
+
+test_expr!{ record_layout_changed_by_open_record,
+r#"
+let with_x r = let ignored = r.x in r
+let r = with_x { y = 1, x = 2 }
+r.y
+"#,
+1i32
+}
+
+test_expr!{ record_update,
+r#"
+let r = { x = 1, y = 2, z = 3 }
+let { x, y, z } = { r | y = 20, z = 30 }
+x #Int+ y #Int+ z #Int+ r.y
+"#,
+53i32
+}
+
+test_expr!{ record_extension,
+r#"
+let r = { { x = 1 } | y = 2 }
+r.x #Int+ r.y
+"#,
+3i32
+}
+
+test_expr!{ record_update_of_open_record_and_extension_of_empty_record,
+r#"
+let set_y r = { r | y = 1 }
+let r = set_y { x = 2, y = 10 }
+let e = { {} | z = 3 }
+r.x #Int+ r.y #Int+ e.z
+"#,
+6i32
+}
+
+test_expr!{ implicit_argument,
+r#"
+type Describe a = { describe: a -> Int }
+let implicit describe_Int : Describe Int = { describe = \x -> 10 }
+let implicit describe_Float : Describe Float = { describe = \x -> 2 }
+let describe d : [Describe a] -> a -> Int = d.describe
+describe 1.0 #Int+ describe 3
+"#,
+12i32
+}
+
+test_expr!{ implicit_instance_of_imported_module,
+r#"
+let prelude = import "std/prelude.glu"
+let { Ordering } = prelude
+match prelude.compare 2.0 1.0 with
+| GT -> if prelude.(<) 1 2 then 1 else 0
+| _ -> 0
+"#,
+1i32
+}
+
+test_expr!{ prelude do_expression,
+r#"
+let { monad_Option } = import "std/prelude.glu"
+let implicit monad : Monad Option = monad_Option
+let result =
+    do x = Some 1
+    do y = Some 2
+    Some (x + y)
+match result with
+| Some z -> z
+| None -> 0
+"#,
+3i32
+}
+
+test_expr!{ io do_expression_with_imported_monad_instance,
+r#"
+let prelude = import "std/prelude.glu"
+do x = io_pure 1
+io_pure (x #Int+ 1)
+"#,
+2i32
+}
+
 test_expr!{ record_let_adjust,
 r#"
 let x = \z -> let { x, y } = { x = 1, y = 2 } in z in
@@ -574,6 +764,61 @@ b
 true
 }
 
+test_expr!{ prelude derive_eq,
+r#"
+let { eq_Int } = import "std/prelude.glu"
+type Test a = | A a Int | B a deriving (Eq)
+let { (==) } = eq_Test eq_Int
+A 1 2 == A 1 2 && B 3 == B 3 && not (A 1 2 == A 1 3) && not (A 1 2 == B 1)
+"#,
+true
+}
+
+test_expr!{ prelude derive_ord,
+r#"
+let { Ordering } = import "std/prelude.glu"
+type Test = | A Int | B deriving (Eq, Ord)
+let { compare } = ord_Test
+let to_int o =
+    match o with
+    | LT -> 1
+    | EQ -> 10
+    | GT -> 100
+to_int (compare (A 1) (A 2)) + to_int (compare B (A 3)) + to_int (compare B B)
+"#,
+111i32
+}
+
+test_expr!{ prelude derive_show,
+r#"
+type Test = | A Int Int | B deriving (Show)
+type Record = { x : Int, y : Test } deriving (Show)
+show_Record.show { x = 1, y = A 2 3 }
+"#,
+String::from("{ x = 1, y = A (2) (3) }")
+}
+
+test_expr!{ prelude derive_uses_instances_in_scope,
+r#"
+let eq_Int = { (==) = \l r -> True }
+type Test = | A Int deriving (Eq)
+eq_Test.(==) (A 1) (A 2)
+"#,
+true
+}
+
+test_expr!{ prelude derive_is_hygienic,
+r#"
+type MyBool = | False | True
+let l = 1
+let string_prim = ()
+type Test = | A Int Int | B deriving (Eq, Show)
+let { (==) } = eq_Test
+if A 1 2 == B then "" else show_Test.show (A l 2)
+"#,
+String::from("A (1) (2)")
+}
+
 // Test that empty variants are handled correctly in arrays
 test_expr!{ array_empty_variant,
 r#"
@@ -610,7 +855,8 @@ in
 "#;
     let mut vm = make_vm();
     let result = run_expr::<Generic<A>>(&mut vm, text);
-    assert_eq!(result.0, vm.new_data(0, &mut [Int(3), Float(3.0)]).unwrap());
+    let tag = vm.global_env().record_tag(&["x", "y"]);
+    assert_eq!(result.0, vm.new_data(tag, &mut [Int(3), Float(3.0)]).unwrap());
 }
 
 test_expr!{ through_overloaded_alias,
@@ -641,6 +887,14 @@ fn run_expr_int() {
     assert_eq!(result, expected);
 }
 
+test_expr!{ sealed_module_fields_are_accessed_by_name,
+r#"
+let m = { x = 1, y = 2 } : { y : Int }
+m.y
+"#,
+2i32
+}
+
 test_expr!{ io run_expr_io,
 r#"io_flat_map (\x -> io_pure 100) (io.run_expr "io.print_int 123") "#,
 100i32
@@ -759,6 +1013,88 @@ string.slice s 1 (string.length s)
     }
 }
 
+#[test]
+fn unused_import_warning() {
+    let _ = ::env_logger::init();
+    let text = r#"
+let { id, const } = import "std/prelude.glu"
+id 1
+"#;
+    let vm = make_vm();
+    let mut compiler = Compiler::new();
+    compiler.run_expr::<i32>(&vm, "<top>", text)
+        .unwrap_or_else(|err| panic!("{}", err));
+    let warnings: Vec<_> = compiler.take_warnings()
+        .into_iter()
+        .flat_map(|warnings| warnings.errors().errors)
+        .map(|warning| (warning.value.code, String::from(warning.value.name.as_ref())))
+        .collect();
+    assert_eq!(warnings,
+               vec![(WarningCode::UnusedImport, String::from("const"))]);
+}
+
+#[test]
+fn denied_warning_is_an_error() {
+    let _ = ::env_logger::init();
+    let text = r#"
+let x = 1
+2
+"#;
+    let vm = make_vm();
+    let result = Compiler::new()
+        .warning_level(WarningCode::UnusedBinding, WarningLevel::Deny)
+        .run_expr::<i32>(&vm, "<top>", text);
+    match result {
+        Err(Error::Warning(..)) => (),
+        Err(err) => panic!("Unexpected error `{}`", err),
+        Ok(_) => panic!("Expected an error"),
+    }
+}
+
+#[test]
+fn undefined_variable_diagnostic() {
+    let _ = ::env_logger::init();
+    let text = r#"
+let x = 1
+x #Int+ y
+"#;
+    let vm = make_vm();
+    let result = Compiler::new()
+        .implicit_prelude(false)
+        .run_expr::<i32>(&vm, "test", text);
+    let diagnostics = match result {
+        Err(err) => err.diagnostics(),
+        Ok(_) => panic!("Expected an error"),
+    };
+    assert_eq!(diagnostics.len(), 1);
+    let diagnostic = &diagnostics[0];
+    assert_eq!(diagnostic.code, "undefined_variable");
+    assert_eq!(diagnostic.file, Some("test".to_string()));
+    assert_eq!(diagnostic.span.map(|span| (span.start.line, span.start.column.to_usize())),
+               Some((3, 9)));
+}
+
+#[test]
+fn derive_missing_instance_diagnostic() {
+    let _ = ::env_logger::init();
+    let text = r#"
+type Key = | Key Int
+type Test = | A Key deriving (Eq)
+1
+"#;
+    let vm = make_vm();
+    let result = Compiler::new().run_expr::<i32>(&vm, "test", text);
+    let diagnostics = match result {
+        Err(err) => err.diagnostics(),
+        Ok(_) => panic!("Expected an error"),
+    };
+    assert_eq!(diagnostics.len(), 1);
+    let diagnostic = &diagnostics[0];
+    assert_eq!(diagnostic.code, "missing_derive_instance");
+    assert_eq!(diagnostic.span.map(|span| (span.start.line, span.start.column.to_usize())),
+               Some((3, 1)));
+}
+
 #[test]
 fn out_of_memory() {
     let _ = ::env_logger::init();
@@ -952,6 +952,9 @@ impl<'vm> Getable<'vm> for RootStr<'vm> {
     }
 }
 
+/// The names of the fields of tuples, the `n`th element of a tuple is stored in the field `_n`
+const TUPLE_FIELDS: [&'static str; 7] = ["_0", "_1", "_2", "_3", "_4", "_5", "_6"];
+
 macro_rules! define_tuple {
     ($($id: ident)+) => {
         impl<$($id),+> VmType for ($($id),+)
@@ -961,12 +964,16 @@ macro_rules! define_tuple {
             type Type = ($($id::Type),+);
 
             fn make_type(vm: &Thread) -> TcType {
-                let fields = vec![$(
-                    types::Field {
-                        name: Symbol::new(stringify!($id)),
-                        typ: $id::make_type(vm),
-                    }
-                ),+];
+                let field_types = vec![$($id::make_type(vm)),+];
+                let fields = TUPLE_FIELDS.iter()
+                    .zip(field_types)
+                    .map(|(name, typ)| {
+                        types::Field {
+                            name: Symbol::new(name),
+                            typ: typ,
+                        }
+                    })
+                    .collect();
                 Type::record(Vec::new(), fields)
             }
         }
@@ -998,7 +1005,8 @@ macro_rules! define_tuple {
                 )+
                 let len = count!($($id),+);
                 let offset = stack.len() - len;
-                let value = try!(vm.new_data(0, &stack[offset..]));
+                let tag = vm.global_env().record_tag(&TUPLE_FIELDS[..len]);
+                let value = try!(vm.new_data(tag, &stack[offset..]));
                 for _ in 0..len {
                     stack.pop();
                 }
@@ -1091,12 +1099,14 @@ pub mod record {
 
     pub trait PushableFieldList<'vm>: FieldList {
         fn push(self, vm: &'vm Thread, fields: &mut Stack) -> Result<()>;
+        fn field_names(names: &mut Vec<&'static str>);
     }
 
     impl<'vm> PushableFieldList<'vm> for () {
         fn push(self, _: &'vm Thread, _: &mut Stack) -> Result<()> {
             Ok(())
         }
+        fn field_names(_: &mut Vec<&'static str>) {}
     }
 
     impl<'vm, F: Field, H: Pushable<'vm>, T> PushableFieldList<'vm> for HList<(F, H), T>
@@ -1107,6 +1117,10 @@ pub mod record {
             try!(head.push(vm, fields));
             tail.push(vm, fields)
         }
+        fn field_names(names: &mut Vec<&'static str>) {
+            names.push(F::name());
+            T::field_names(names);
+        }
     }
 
     pub trait GetableFieldList<'vm>: FieldList + Sized {
@@ -1152,7 +1166,10 @@ pub mod record {
             try!(self.fields.push(vm, stack));
             let len = HList::<(F, A), T>::len();
             let offset = stack.len() - len;
-            let value = try!(vm.new_data(0, &stack[offset..]));
+            let mut names = Vec::with_capacity(len as usize);
+            <HList<(F, A), T> as PushableFieldList<'vm>>::field_names(&mut names);
+            let tag = vm.global_env().record_tag(&names);
+            let value = try!(vm.new_data(tag, &stack[offset..]));
             for _ in 0..len {
                 stack.pop();
             }
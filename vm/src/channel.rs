@@ -187,8 +187,8 @@ fn f2<A, B, R>(f: fn(A, B) -> R) -> fn(A, B) -> R {
 }
 
 pub fn load(vm: &Thread) -> VmResult<()> {
-    let _ = vm.register_type::<Sender<A>>("Sender", &["a"]);
-    let _ = vm.register_type::<Receiver<A>>("Receiver", &["a"]);
+    let _ = vm.register_mutable_type::<Sender<A>>("Sender", &["a"]);
+    let _ = vm.register_mutable_type::<Receiver<A>>("Receiver", &["a"]);
     try!(vm.define_global("channel", f1(channel)));
     try!(vm.define_global("recv", f1(recv)));
     try!(vm.define_global("send", f2(send)));
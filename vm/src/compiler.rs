@@ -4,6 +4,7 @@ use base::ast;
 use base::instantiate;
 use base::symbol::{Symbol, SymbolRef, SymbolModule};
 use base::ast::{Typed, DisplayEnv, LExpr, Expr};
+use base::pos::Located;
 use base::types;
 use base::types::{Alias, KindEnv, TcIdent, TcType, Type, TypeEnv};
 use base::scoped_map::ScopedMap;
@@ -120,6 +121,12 @@ impl FunctionEnv {
     }
 
     fn emit_string(&mut self, s: InternedStr) {
+        let index = self.string_index(s);
+        self.emit(PushString(index));
+    }
+
+    /// Returns the index of `s` in the strings of the current function, adding it if necessary
+    fn string_index(&mut self, s: InternedStr) -> VmIndex {
         let index = match self.function.strings.iter().position(|t| *t == s) {
             Some(i) => i,
             None => {
@@ -127,7 +134,7 @@ impl FunctionEnv {
                 self.function.strings.len() - 1
             }
         };
-        self.emit(PushString(index as VmIndex));
+        index as VmIndex
     }
 
     fn upvar(&mut self, s: &Symbol) -> VmIndex {
@@ -159,24 +166,42 @@ impl FunctionEnv {
         debug!("Pop var: {:?}", x);
     }
 
-    fn pop_pattern(&mut self, pattern: &ast::Pattern<TcIdent>) -> VmIndex {
-        match *pattern {
-            ast::Pattern::Constructor(_, ref args) => {
-                for _ in 0..args.len() {
-                    self.pop_var();
-                }
-                args.len() as VmIndex
-            }
-            ast::Pattern::Record { ref fields, .. } => {
-                for _ in fields {
-                    self.pop_var();
-                }
-                fields.len() as VmIndex
+    /// Removes the variables bound by `pattern` from the scope. Returns the number of stack slots
+    /// which were used by the pattern, including the value which were matched on.
+    fn pop_pattern(&mut self, pattern: &ast::LPattern<TcIdent>) -> VmIndex {
+        match pattern.value {
+            // The matched value is bound directly to the identifier
+            ast::Pattern::Identifier(_) => {
+                self.pop_var();
+                1
             }
+            _ => 1 + self.pop_pattern_variables(pattern),
+        }
+    }
+
+    fn pop_pattern_variables(&mut self, pattern: &ast::LPattern<TcIdent>) -> VmIndex {
+        match pattern.value {
             ast::Pattern::Identifier(_) => {
                 self.pop_var();
                 1
             }
+            ast::Pattern::Constructor(_, ref args) |
+            ast::Pattern::Tuple { elems: ref args, .. } => {
+                args.iter().fold(0, |acc, arg| acc + self.pop_pattern_variables(arg))
+            }
+            ast::Pattern::Record { ref fields, .. } => {
+                fields.iter().fold(0, |acc, field| {
+                    acc +
+                    match field.1 {
+                        Some(ref pattern) => self.pop_pattern_variables(pattern),
+                        None => {
+                            self.pop_var();
+                            1
+                        }
+                    }
+                })
+            }
+            ast::Pattern::Literal(_) => 0,
         }
     }
 }
@@ -328,19 +353,28 @@ impl<'a> Compiler<'a> {
         })
     }
 
-    fn find_field(&self, typ: &TcType, field: &Symbol) -> Option<VmIndex> {
-        // Walk through all type aliases
-        match **instantiate::remove_aliases_cow(self, typ) {
-            Type::Record { ref fields, .. } => {
-                fields.iter()
-                    .position(|f| f.name.name_eq(field))
-                    .map(|i| i as VmIndex)
-            }
-            ref typ => {
-                panic!("ICE: FieldAccess on {}",
-                       types::display_type(&self.symbols, typ))
+    /// Returns the instruction which retrieves `field` from a record of type `typ`. The position
+    /// of the field is known if the record type is closed, otherwise the field has to be
+    /// retrieved by its name.
+    fn get_field(&mut self,
+                 typ: &TcType,
+                 field: &Symbol,
+                 function: &mut FunctionEnvs)
+                 -> Result<Instruction> {
+        {
+            let typ = instantiate::remove_aliases_cow(self, typ);
+            if let Type::Record { ref fields, rest: None, .. } = **typ {
+                if let Some(index) = fields.iter().position(|f| f.name.name_eq(field)) {
+                    let names = fields.iter().map(|f| f.name.as_ref()).collect::<Vec<&str>>();
+                    return Ok(GetRecordField {
+                        tag: self.vm.record_tag(&names),
+                        index: index as VmIndex,
+                    });
+                }
             }
         }
+        let name = try!(self.intern(field.as_ref()));
+        Ok(GetFieldByName(function.string_index(name)))
     }
 
     fn find_tag(&self, typ: &TcType, constructor: &Symbol) -> Option<VmTag> {
@@ -546,8 +580,8 @@ impl<'a> Compiler<'a> {
                         function.function.inner_functions.push(cf);
                     } else {
                         try!(self.compile(&bind.expression, function, false));
-                        let typ = bind.expression.env_type_of(self);
-                        self.compile_let_pattern(&bind.name, &typ, function);
+                        let value = function.stack_size();
+                        try!(self.compile_let_pattern(&bind.name, value, function));
                     }
                 }
                 return Ok(Some(body));
@@ -575,76 +609,44 @@ impl<'a> Compiler<'a> {
                 try!(self.compile(&**expr, function, false));
                 debug!("{:?} {:?}", expr, field);
                 let typ = expr.env_type_of(self);
-                debug!("FieldAccess {}", types::display_type(&self.symbols, &typ));
-                let field_index = self.find_field(&typ, field.id())
-                    .expect("ICE: Undefined field in field access");
-                function.emit(GetField(field_index));
+                let instruction = try!(self.get_field(&typ, field.id(), function));
+                function.emit(instruction);
             }
             Expr::Match(ref expr, ref alts) => {
                 try!(self.compile(&**expr, function, false));
-                // Indexes for each alternative for a successful match to the alternatives code
-                let mut start_jumps = Vec::new();
-                let typ = expr.env_type_of(self);
-                let mut catch_all = false;
-                // Emit a TestTag + Jump instuction for each alternative which jumps to the
-                // alternatives code if TestTag is sucessesful
-                for alt in alts.iter() {
-                    match alt.pattern.value {
-                        ast::Pattern::Constructor(ref id, _) => {
-                            let tag = self.find_tag(&typ, id.id())
-                                .unwrap_or_else(|| {
-                                    panic!("Could not find tag for {}::{}",
-                                           types::display_type(&self.symbols, &typ),
-                                           self.symbols.string(id.id()))
-                                });
-                            function.emit(TestTag(tag));
-                            start_jumps.push(function.function.instructions.len());
-                            function.emit(CJump(0));
-                        }
-                        ast::Pattern::Record { .. } => {
-                            catch_all = true;
-                            start_jumps.push(function.function.instructions.len());
-                        }
-                        _ => {
-                            catch_all = true;
-                            start_jumps.push(function.function.instructions.len());
-                            function.emit(Jump(0));
-                        }
-                    }
-                }
-                // Create a catch all to prevent us from running into undefined behaviour
-                if !catch_all {
-                    let error_fn = self.symbols.symbol("#error");
-                    self.load_identifier(&error_fn, function);
-                    function.emit_string(try!(self.intern("Non-exhaustive pattern")));
-                    function.emit(Call(1));
-                    // The stack has been increased by 1 here but it should not affect compiling the
-                    // alternatives
-                    function.stack_size -= 1;
-                }
+                let scrutinee = function.stack_size();
                 // Indexes for each alternative from the end of the alternatives code to code
                 // after the alternative
                 let mut end_jumps = Vec::new();
-                for (alt, &start_index) in alts.iter().zip(start_jumps.iter()) {
+                let mut catch_all = false;
+                for alt in alts.iter() {
                     self.stack_constructors.enter_scope();
-                    match alt.pattern.value {
-                        ast::Pattern::Constructor(_, ref args) => {
-                            function.function.instructions[start_index] =
-                                CJump(function.function.instructions.len() as VmIndex);
-                            function.emit(Split);
-                            for arg in args.iter() {
-                                function.push_stack_var(arg.id().clone());
-                            }
-                        }
-                        ast::Pattern::Record { .. } => {
-                            let typ = &expr.env_type_of(self);
-                            self.compile_let_pattern(&alt.pattern, typ, function);
-                        }
-                        ast::Pattern::Identifier(ref id) => {
-                            function.function.instructions[start_index] =
-                                Jump(function.function.instructions.len() as VmIndex);
-                            function.new_stack_var(id.id().clone());
+                    // Emit the tests for the pattern, jumping to the next alternative if any of
+                    // them fail
+                    let mut fail_jumps = Vec::new();
+                    try!(self.compile_pattern_tests(&alt.pattern,
+                                                    scrutinee,
+                                                    &mut Vec::new(),
+                                                    &mut fail_jumps,
+                                                    function));
+                    catch_all = fail_jumps.is_empty() && alt.guard.is_none();
+                    let stack_size = function.stack_size;
+                    try!(self.compile_let_pattern(&alt.pattern, scrutinee, function));
+                    if let Some(ref guard) = alt.guard {
+                        let bound = function.stack_size - stack_size;
+                        try!(self.compile(guard, function, false));
+                        let guard_jump = function.function.instructions.len();
+                        function.emit(CJump(0));
+                        // The guard failed so the variables bound by the pattern are popped
+                        // before continuing with the next alternative
+                        if bound != 0 {
+                            function.emit(Pop(bound));
                         }
+                        fail_jumps.push(function.function.instructions.len());
+                        function.emit(Jump(0));
+                        function.stack_size += bound;
+                        function.function.instructions[guard_jump] =
+                            CJump(function.function.instructions.len() as VmIndex);
                     }
                     try!(self.compile(&alt.expression, function, tail_position));
                     let count = function.pop_pattern(&alt.pattern);
@@ -652,6 +654,22 @@ impl<'a> Compiler<'a> {
                     function.emit(Slide(count));
                     end_jumps.push(function.function.instructions.len());
                     function.emit(Jump(0));
+                    // The next alternative starts out with only the scrutinee on the stack
+                    function.stack_size = scrutinee + 1;
+                    for &index in &fail_jumps {
+                        function.function.instructions[index] =
+                            Jump(function.function.instructions.len() as VmIndex);
+                    }
+                }
+                // Create a catch all to prevent us from running into undefined behaviour
+                if !catch_all {
+                    let error_fn = self.symbols.symbol("#error");
+                    self.load_identifier(&error_fn, function);
+                    function.emit_string(try!(self.intern("Non-exhaustive pattern")));
+                    function.emit(Call(1));
+                    // The call never returns so the stack should have the same size as after a
+                    // successful match
+                    function.stack_size = scrutinee + 1;
                 }
                 for &index in end_jumps.iter() {
                     function.function.instructions[index] =
@@ -676,6 +694,9 @@ impl<'a> Compiler<'a> {
                 function.stack_size -= vars;
                 function.function.inner_functions.push(cf);
             }
+            Expr::Do(_) => panic!("ICE: `do` expressions are desugared during typechecking"),
+            Expr::Error(_) => panic!("ICE: Expressions with parse errors should not be compiled"),
+            Expr::Hole(_) => panic!("ICE: Expressions containing holes should not be compiled"),
             Expr::Type(ref type_bindings, ref expr) => {
                 for bind in type_bindings {
                     self.stack_types.insert(bind.alias.name.clone(), bind.alias.clone());
@@ -684,6 +705,8 @@ impl<'a> Compiler<'a> {
                 }
                 return Ok(Some(expr));
             }
+            // Type annotations only affect typechecking
+            Expr::Annotated(ref expr, _) => return Ok(Some(expr)),
             Expr::Record { exprs: ref fields, .. } => {
                 for field in fields {
                     match field.1 {
@@ -691,8 +714,20 @@ impl<'a> Compiler<'a> {
                         None => self.load_identifier(&field.0, function),
                     }
                 }
+                let names = fields.iter().map(|field| field.0.as_ref()).collect::<Vec<&str>>();
                 function.emit(Construct {
-                    tag: 0,
+                    tag: self.vm.record_tag(&names),
+                    args: fields.len() as u32,
+                });
+            }
+            Expr::RecordUpdate { ref base, ref fields, .. } => {
+                try!(self.compile(base, function, false));
+                for field in fields {
+                    try!(self.compile(&field.1, function, false));
+                }
+                let names = fields.iter().map(|field| field.0.as_ref()).collect::<Vec<&str>>();
+                function.emit(UpdateRecord {
+                    tag: self.vm.record_tag(&names),
                     args: fields.len() as u32,
                 });
             }
@@ -700,8 +735,9 @@ impl<'a> Compiler<'a> {
                 for expr in exprs {
                     try!(self.compile(expr, function, false));
                 }
+                let names = (0..exprs.len()).map(ast::tuple_field_name).collect::<Vec<_>>();
                 function.emit(Construct {
-                    tag: 0,
+                    tag: self.vm.record_tag(&names),
                     args: exprs.len() as u32,
                 });
             }
@@ -717,16 +753,143 @@ impl<'a> Compiler<'a> {
         Ok(None)
     }
 
+    /// Pushes the value at `index` to the stack and retrieves the fields using the instructions in
+    /// `path`
+    fn emit_path(&self, index: VmIndex, path: &[Instruction], function: &mut FunctionEnvs) {
+        function.emit(Push(index));
+        for &instruction in path {
+            function.emit(instruction);
+        }
+    }
+
+    /// Emits a conditional jump which continues execution if the test at the top of the stack were
+    /// successful. Otherwise execution jumps to the instruction at the index stored in
+    /// `fail_jumps`, which needs to be filled in later.
+    fn emit_test_jump(&self, fail_jumps: &mut Vec<usize>, function: &mut FunctionEnvs) {
+        let index = function.function.instructions.len();
+        function.emit(CJump(index as VmIndex + 2));
+        fail_jumps.push(function.function.instructions.len());
+        function.emit(Jump(0));
+    }
+
+    /// Emits the tests needed to check that the value at `index`, after retrieving the fields in
+    /// `path`, matches `pattern`. The stack is left unchanged after the tests.
+    fn compile_pattern_tests(&mut self,
+                             pattern: &ast::LPattern<TcIdent>,
+                             index: VmIndex,
+                             path: &mut Vec<Instruction>,
+                             fail_jumps: &mut Vec<usize>,
+                             function: &mut FunctionEnvs)
+                             -> Result<()> {
+        match pattern.value {
+            ast::Pattern::Identifier(_) => (),
+            ast::Pattern::Constructor(ref id, ref args) => {
+                let typ = pattern.env_type_of(self);
+                let tag = self.find_tag(&typ, id.id())
+                    .unwrap_or_else(|| {
+                        panic!("Could not find tag for {}::{}",
+                               types::display_type(&self.symbols, &typ),
+                               self.symbols.string(id.id()))
+                    });
+                self.emit_path(index, path, function);
+                function.emit(TestTag(tag));
+                // Remove the tested value, leaving only the result of the test
+                function.emit(Slide(1));
+                self.emit_test_jump(fail_jumps, function);
+                for (i, arg) in args.iter().enumerate() {
+                    path.push(GetField(i as VmIndex));
+                    try!(self.compile_pattern_tests(arg, index, path, fail_jumps, function));
+                    path.pop();
+                }
+            }
+            ast::Pattern::Record { ref fields, .. } => {
+                let typ = pattern.env_type_of(self);
+                for field in fields {
+                    if let Some(ref field_pattern) = field.1 {
+                        let instruction = try!(self.get_field(&typ, &field.0, function));
+                        path.push(instruction);
+                        try!(self.compile_pattern_tests(field_pattern,
+                                                        index,
+                                                        path,
+                                                        fail_jumps,
+                                                        function));
+                        path.pop();
+                    }
+                }
+            }
+            ast::Pattern::Tuple { ref elems, .. } => {
+                for (i, elem) in elems.iter().enumerate() {
+                    path.push(GetField(i as VmIndex));
+                    try!(self.compile_pattern_tests(elem, index, path, fail_jumps, function));
+                    path.pop();
+                }
+            }
+            ast::Pattern::Literal(ref lit) => {
+                match *lit {
+                    ast::LiteralEnum::Integer(i) => {
+                        self.emit_path(index, path, function);
+                        function.emit(PushInt(i as isize));
+                        function.emit(IntEQ);
+                    }
+                    ast::LiteralEnum::Byte(b) => {
+                        self.emit_path(index, path, function);
+                        function.emit(PushByte(b));
+                        function.emit(ByteEQ);
+                    }
+                    ast::LiteralEnum::Float(f) => {
+                        self.emit_path(index, path, function);
+                        function.emit(PushFloat(f));
+                        function.emit(FloatEQ);
+                    }
+                    ast::LiteralEnum::Char(c) => {
+                        self.emit_path(index, path, function);
+                        function.emit(PushInt(c as isize));
+                        function.emit(IntEQ);
+                    }
+                    ast::LiteralEnum::String(ref s) => {
+                        let eq_fn = self.symbols.symbol("#string_eq");
+                        self.load_identifier(&eq_fn, function);
+                        self.emit_path(index, path, function);
+                        function.emit_string(try!(self.intern(&s)));
+                        function.emit(Call(2));
+                    }
+                }
+                self.emit_test_jump(fail_jumps, function);
+            }
+        }
+        Ok(())
+    }
+
+    /// Binds the variables in `pattern` to the value at `index`. The value is assumed to be at the
+    /// top of the stack.
     fn compile_let_pattern(&mut self,
-                           pattern: &ast::Pattern<TcIdent>,
-                           pattern_type: &TcType,
-                           function: &mut FunctionEnvs) {
-        match *pattern {
+                           pattern: &ast::LPattern<TcIdent>,
+                           index: VmIndex,
+                           function: &mut FunctionEnvs)
+                           -> Result<()> {
+        match pattern.value {
+            // Bind the value directly instead of pushing a copy of it
+            ast::Pattern::Identifier(ref name) => {
+                function.new_stack_var(name.id().clone());
+                Ok(())
+            }
+            _ => self.compile_pattern_bindings(pattern, index, &mut Vec::new(), function),
+        }
+    }
+
+    fn compile_pattern_bindings(&mut self,
+                                pattern: &ast::LPattern<TcIdent>,
+                                index: VmIndex,
+                                path: &mut Vec<Instruction>,
+                                function: &mut FunctionEnvs)
+                                -> Result<()> {
+        match pattern.value {
             ast::Pattern::Identifier(ref name) => {
+                self.emit_path(index, path, function);
                 function.new_stack_var(name.id().clone());
             }
             ast::Pattern::Record { ref types, ref fields, .. } => {
-                let typ = instantiate::remove_aliases(self, pattern_type.clone());
+                let typ = instantiate::remove_aliases(self, pattern.env_type_of(self));
                 // Insert all variant constructor into scope
                 with_pattern_types(types, &typ, |name, alias| {
                     // FIXME: Workaround so that both the types name in this module and its global
@@ -738,53 +901,40 @@ impl<'a> Compiler<'a> {
                         self.stack_constructors.insert(name.clone(), typ.clone());
                     }
                 });
-                match *typ {
-                    Type::Record { fields: ref type_fields, .. } => {
-                        if fields.len() == 0 ||
-                           (type_fields.len() > 4 && type_fields.len() / fields.len() >= 4) {
-                            // For pattern matches on large records where only a few of the fields
-                            // are used we instead emit a series of GetField instructions to avoid
-                            // pushing a lot of unnecessary fields to the stack
-                            let record_index = function.stack_size();
-                            for pattern_field in fields {
-                                let offset = type_fields.iter()
-                                    .position(|field| field.name.name_eq(&pattern_field.0))
-                                    .expect("Field to exist");
-                                function.emit(Push(record_index));
-                                function.emit(GetField(offset as VmIndex));
-                                function.new_stack_var(pattern_field.1
-                                    .as_ref()
-                                    .unwrap_or(&pattern_field.0)
-                                    .clone());
-                            }
-                        } else {
-                            function.emit(Split);
-                            for field in type_fields {
-                                let name = match fields.iter()
-                                    .find(|tup| tup.0.name_eq(&field.name)) {
-                                    Some(&(ref name, ref bind)) => {
-                                        bind.as_ref().unwrap_or(name).clone()
-                                    }
-                                    None => self.symbols.symbol(""),
-                                };
-                                function.push_stack_var(name);
-                            }
+                for field in fields {
+                    let instruction = try!(self.get_field(&typ, &field.0, function));
+                    path.push(instruction);
+                    match field.1 {
+                        Some(ref field_pattern) => {
+                            try!(self.compile_pattern_bindings(field_pattern,
+                                                               index,
+                                                               path,
+                                                               function))
+                        }
+                        None => {
+                            self.emit_path(index, path, function);
+                            function.new_stack_var(field.0.clone());
                         }
                     }
-                    _ => {
-                        panic!("Expected record, got {} at {:?}",
-                               types::display_type(&self.symbols, &typ),
-                               pattern)
-                    }
+                    path.pop();
                 }
             }
-            ast::Pattern::Constructor(..) => panic!("constructor pattern in let"),
+            ast::Pattern::Constructor(_, ref args) |
+            ast::Pattern::Tuple { elems: ref args, .. } => {
+                for (i, arg) in args.iter().enumerate() {
+                    path.push(GetField(i as VmIndex));
+                    try!(self.compile_pattern_bindings(arg, index, path, function));
+                    path.pop();
+                }
+            }
+            ast::Pattern::Literal(_) => (),
         }
+        Ok(())
     }
 
     fn compile_lambda(&mut self,
                       id: &TcIdent,
-                      arguments: &[TcIdent],
+                      arguments: &[Located<TcIdent>],
                       body: &LExpr<TcIdent>,
                       function: &mut FunctionEnvs)
                       -> Result<(VmIndex, VmIndex, CompiledFunction)> {
@@ -0,0 +1,741 @@
+//! Generation of the instance records for type declarations which have a `deriving` clause.
+//!
+//! `type Pair a = { x : a, y : Int } deriving (Eq, Show)` generates the bindings
+//!
+//! ```f#
+//! let eq_Pair eq_a : Eq a -> Eq (Pair a) = ...
+//! let show_Pair show_a : Show a -> Show (Pair a) = ...
+//! ```
+//!
+//! The instance needed for a type `T` inside the declaration is the instance argument if `T` is a
+//! type parameter, otherwise it is the binding `eq_T` (`show_T`, ...) which is in scope at the
+//! declaration or, if there is no such binding, the instance exported by the standard library.
+//! Every other name used by the generated code (`Eq`, `True`, `string_prim`, ...) refers to the
+//! definition in the standard library so the generated code can't be affected by the bindings
+//! around it. The generated code is typechecked just as if it were written by hand.
+use std::cell::RefCell;
+use std::mem;
+
+use base::ast::{self, Binding, Expr, LExpr, LPattern, MutVisitor, Pattern};
+use base::diagnostic::{Diagnostic, ToDiagnostic};
+use base::error::Errors;
+use base::fnv::FnvMap;
+use base::pos::{self, Span, Spanned};
+use base::symbol::{Name, Symbol, SymbolModule};
+use base::types::{self, BuiltinType, Generic, TcIdent, TcType, Type, TypeEnv};
+
+quick_error! {
+    /// Error type for the `deriving` clause of type declarations
+    #[derive(Debug)]
+    pub enum Error {
+        /// The instance can't be derived
+        UnknownInstance(name: String) {
+            description("unknown instance")
+            display("`{}` can't be derived, only `Eq`, `Ord` and `Show` can be derived", name)
+        }
+        /// The declared type contains a type which instances can't be generated for
+        UnsupportedType(instance: String, typ: String) {
+            description("unsupported type")
+            display("`{}` can't be derived for a type containing `{}`", instance, typ)
+        }
+        /// The declared type contains a type which has no instance in scope
+        MissingInstance(instance: String, typ: String) {
+            description("missing instance")
+            display("There is no `{}` instance for `{}` in scope", instance, typ)
+        }
+        /// A definition from the standard library which the generated code uses is not loaded
+        MissingDefinition(name: String) {
+            description("missing definition")
+            display("`{}` must be loaded to derive instances", name)
+        }
+    }
+}
+
+impl ToDiagnostic for Error {
+    fn to_diagnostic(&self) -> Diagnostic {
+        let code = match *self {
+            Error::UnknownInstance(..) => "unknown_derive",
+            Error::UnsupportedType(..) => "unsupported_derive",
+            Error::MissingInstance(..) => "missing_derive_instance",
+            Error::MissingDefinition(..) => "missing_derive_definition",
+        };
+        Diagnostic::error(code, self.to_string())
+    }
+}
+
+/// The modules of the standard library which define the types and instances used by the generated
+/// code
+const STD_MODULES: &'static [&'static str] = &["std.types", "std.prelude"];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Instance {
+    Eq,
+    Ord,
+    Show,
+}
+
+impl Instance {
+    fn from_str(name: &str) -> Option<Instance> {
+        match name {
+            "Eq" => Some(Instance::Eq),
+            "Ord" => Some(Instance::Ord),
+            "Show" => Some(Instance::Show),
+            _ => None,
+        }
+    }
+
+    fn type_name(self) -> &'static str {
+        match self {
+            Instance::Eq => "Eq",
+            Instance::Ord => "Ord",
+            Instance::Show => "Show",
+        }
+    }
+
+    /// The prefix of the bindings holding instances of this kind (`eq_Int`, `ord_Int`, ...)
+    fn prefix(self) -> &'static str {
+        match self {
+            Instance::Eq => "eq",
+            Instance::Ord => "ord",
+            Instance::Show => "show",
+        }
+    }
+
+    /// The field of the instance record which contains the function for the type
+    fn method(self) -> &'static str {
+        match self {
+            Instance::Eq => "==",
+            Instance::Ord => "compare",
+            Instance::Show => "show",
+        }
+    }
+}
+
+/// Generates the instances listed in the `deriving` clauses of every type declaration in `expr`
+/// and binds them in `let` expressions around the body of the declaration. The names of the
+/// instances are created from `symbols` so that the code after the declaration can refer to them.
+pub fn derive(env: &TypeEnv,
+              symbols: &mut SymbolModule,
+              expr: &mut LExpr<TcIdent>)
+              -> Result<(), Errors<Spanned<Error>>> {
+    let mut visitor = DeriveVisitor {
+        scope: Scope {
+            env: env,
+            values: Vec::new(),
+            types: Vec::new(),
+        },
+        symbols: symbols,
+        errors: Errors::new(),
+    };
+    visitor.visit_expr(expr);
+    if visitor.errors.has_errors() {
+        Err(visitor.errors)
+    } else {
+        Ok(())
+    }
+}
+
+/// The bindings which are in scope at a type declaration
+struct Scope<'a> {
+    env: &'a TypeEnv,
+    /// Variables bound by `let`, innermost last
+    values: Vec<Symbol>,
+    /// Types declared by `type`, innermost last
+    types: Vec<(Symbol, Option<TcType>)>,
+}
+
+impl<'a> Scope<'a> {
+    /// Returns the name and definition of the type `name` from the standard library. While the
+    /// standard library itself is compiled the type is instead found among the types in scope.
+    fn find_type(&self, name: &str) -> Result<(Symbol, Option<TcType>), Error> {
+        STD_MODULES.iter()
+            .filter_map(|module| {
+                let id = Symbol::new(&format!("{}.{}", module, name));
+                self.env.find_type_info(&id).map(|alias| (alias.name.clone(), alias.typ.clone()))
+            })
+            .next()
+            .or_else(|| self.types.iter().rev().find(|typ| typ.0.as_ref() == name).cloned())
+            .ok_or_else(|| Error::MissingDefinition(String::from(name)))
+    }
+
+    /// Returns the constructor `name` of the type `type_name`
+    fn find_constructor(&self, type_name: &str, name: &str) -> Result<Symbol, Error> {
+        let (_, typ) = try!(self.find_type(type_name));
+        typ.as_ref()
+            .and_then(|typ| match **typ {
+                Type::Variants(ref variants) => {
+                    variants.iter()
+                        .find(|variant| Name::new(variant.0.as_ref()).name().as_str() == name)
+                        .map(|variant| variant.0.clone())
+                }
+                _ => None,
+            })
+            .ok_or_else(|| Error::MissingDefinition(String::from(name)))
+    }
+
+    /// Returns the module of the standard library which exports the value `name`
+    fn find_std_value(&self, name: &str) -> Option<&'static str> {
+        STD_MODULES.iter()
+            .cloned()
+            .find(|module| {
+                self.env.find_type(&Symbol::new(module)).map_or(false, |typ| match **typ {
+                    Type::Record { ref fields, .. } => {
+                        fields.iter().any(|field| field.name.as_ref() == name)
+                    }
+                    _ => false,
+                })
+            })
+    }
+}
+
+struct DeriveVisitor<'a, 'b: 'a> {
+    scope: Scope<'a>,
+    symbols: &'a mut SymbolModule<'b>,
+    errors: Errors<Spanned<Error>>,
+}
+
+impl<'a, 'b> DeriveVisitor<'a, 'b> {
+    fn derive_type(&mut self,
+                   bindings: &[ast::TypeBinding<Symbol>],
+                   span: Span,
+                   body: &mut LExpr<TcIdent>)
+                   -> Result<(), Error> {
+        let scope = self.scope.values.len();
+        let instances = self.derive_instances(bindings, span);
+        self.scope.values.truncate(scope);
+        for binding in try!(instances).into_iter().rev() {
+            let inner = mem::replace(body, pos::located(span, Expr::Tuple(vec![])));
+            *body = pos::located(pos::span(span.start, inner.span.end),
+                                 Expr::Let(vec![binding], Box::new(inner)));
+        }
+        Ok(())
+    }
+
+    fn derive_instances(&mut self,
+                        bindings: &[ast::TypeBinding<Symbol>],
+                        span: Span)
+                        -> Result<Vec<Binding<TcIdent>>, Error> {
+        let mut instances = Vec::new();
+        for bind in bindings {
+            for name in &bind.deriving {
+                let instance = try!(Instance::from_str(name.as_ref())
+                    .ok_or_else(|| Error::UnknownInstance(String::from(name.as_ref()))));
+                let binding_name = self.symbols
+                    .symbol(instance_name(instance, Name::new(bind.name.as_ref()).name().as_str()));
+                let binding = {
+                    let deriver = Deriver {
+                        instance: instance,
+                        span: span,
+                        name: &bind.name,
+                        binding_name: &binding_name,
+                        args: &bind.alias.args,
+                        scope: &self.scope,
+                        locals: RefCell::new(FnvMap::default()),
+                    };
+                    let typ = bind.alias.typ.as_ref().expect("Type binding has a type");
+                    try!(deriver.binding(typ))
+                };
+                // Later instances (`Ord`) may use the instances derived before them (`Eq`)
+                self.scope.values.push(binding_name);
+                instances.push(binding);
+            }
+        }
+        Ok(instances)
+    }
+}
+
+impl<'a, 'b> MutVisitor for DeriveVisitor<'a, 'b> {
+    type T = TcIdent;
+
+    fn visit_expr(&mut self, expr: &mut LExpr<TcIdent>) {
+        match expr.value {
+            Expr::Let(ref mut bindings, ref mut body) => {
+                let scope = self.scope.values.len();
+                for bind in bindings.iter() {
+                    pattern_variables(&bind.name, &mut self.scope.values);
+                }
+                for bind in bindings {
+                    self.visit_expr(&mut bind.expression);
+                }
+                self.visit_expr(body);
+                self.scope.values.truncate(scope);
+            }
+            Expr::Type(ref bindings, ref mut body) => {
+                let scope = self.scope.types.len();
+                self.scope.types.extend(bindings.iter()
+                    .map(|bind| (bind.name.clone(), bind.alias.typ.clone())));
+                // Errors are reported at the declaration which is the part of the expression
+                // before the body
+                let span = pos::span(expr.span.start, body.span.start);
+                if let Err(err) = self.derive_type(bindings, span, body) {
+                    self.errors.error(pos::spanned(span, err));
+                }
+                self.visit_expr(body);
+                self.scope.types.truncate(scope);
+            }
+            _ => ast::walk_mut_expr(self, expr),
+        }
+    }
+}
+
+/// Adds the variables bound by `pattern` to `variables`
+fn pattern_variables(pattern: &LPattern<TcIdent>, variables: &mut Vec<Symbol>) {
+    match pattern.value {
+        Pattern::Identifier(ref id) => variables.push(id.name.clone()),
+        Pattern::Record { ref fields, .. } => {
+            for &(ref name, ref pattern) in fields {
+                match *pattern {
+                    Some(ref pattern) => pattern_variables(pattern, variables),
+                    None => variables.push(name.clone()),
+                }
+            }
+        }
+        Pattern::Tuple { ref elems, .. } |
+        Pattern::Constructor(_, ref elems) => {
+            for elem in elems {
+                pattern_variables(elem, variables);
+            }
+        }
+        Pattern::Literal(_) => (),
+    }
+}
+
+struct Deriver<'a, 'b: 'a> {
+    instance: Instance,
+    span: Span,
+    /// The name of the type which the instance is derived for
+    name: &'a Symbol,
+    /// The name of the binding holding the derived instance
+    binding_name: &'a Symbol,
+    /// The type parameters of the type
+    args: &'a [Generic<Symbol>],
+    scope: &'a Scope<'b>,
+    /// The variables which only exist in the generated code. Each of them is a new symbol so they
+    /// can't shadow or be shadowed by any variable written by the user.
+    locals: RefCell<FnvMap<String, Symbol>>,
+}
+
+impl<'a, 'b> Deriver<'a, 'b> {
+    fn located<T>(&self, value: T) -> pos::Located<T> {
+        pos::located(self.span, value)
+    }
+
+    fn local(&self, name: &str) -> Symbol {
+        self.locals
+            .borrow_mut()
+            .entry(String::from(name))
+            .or_insert_with(|| Symbol::new(name))
+            .clone()
+    }
+
+    fn ident(&self, id: Symbol) -> LExpr<TcIdent> {
+        self.located(Expr::Identifier(TcIdent::new(id)))
+    }
+
+    fn local_ident(&self, name: &str) -> LExpr<TcIdent> {
+        self.ident(self.local(name))
+    }
+
+    fn call(&self, function: LExpr<TcIdent>, args: Vec<LExpr<TcIdent>>) -> LExpr<TcIdent> {
+        if args.is_empty() {
+            function
+        } else {
+            self.located(Expr::Call(Box::new(function), args))
+        }
+    }
+
+    fn field(&self, expr: LExpr<TcIdent>, field: &str) -> LExpr<TcIdent> {
+        self.located(Expr::FieldAccess(Box::new(expr), TcIdent::new(Symbol::new(field))))
+    }
+
+    fn string(&self, s: &str) -> LExpr<TcIdent> {
+        self.located(Expr::Literal(ast::LiteralEnum::String(String::from(s))))
+    }
+
+    fn pattern(&self, id: Symbol) -> LPattern<TcIdent> {
+        self.located(Pattern::Identifier(TcIdent::new(id)))
+    }
+
+    fn alternative(&self,
+                   pattern: LPattern<TcIdent>,
+                   expr: LExpr<TcIdent>)
+                   -> ast::Alternative<TcIdent> {
+        ast::Alternative {
+            pattern: pattern,
+            guard: None,
+            expression: expr,
+        }
+    }
+
+    fn let_(&self,
+            name: &str,
+            arguments: &[&str],
+            expr: LExpr<TcIdent>,
+            body: LExpr<TcIdent>)
+            -> LExpr<TcIdent> {
+        let binding = Binding {
+            comment: None,
+            implicit: false,
+            fixity: None,
+            name: self.pattern(self.local(name)),
+            typ: None,
+            arguments: arguments.iter()
+                .map(|arg| self.located(TcIdent::new(self.local(arg))))
+                .collect(),
+            expression: expr,
+        };
+        self.located(Expr::Let(vec![binding], Box::new(body)))
+    }
+
+    fn record(&self, fields: Vec<(&str, LExpr<TcIdent>)>) -> LExpr<TcIdent> {
+        self.located(Expr::Record {
+            typ: TcIdent::new(Symbol::new("")),
+            types: Vec::new(),
+            exprs: fields.into_iter()
+                .map(|(name, expr)| (Symbol::new(name), Some(expr)))
+                .collect(),
+        })
+    }
+
+    /// Returns the constructor `name` of the type `type_name` from the standard library
+    fn constructor(&self, type_name: &str, name: &str) -> Result<Symbol, Error> {
+        self.scope.find_constructor(type_name, name)
+    }
+
+    /// Returns the global `name`
+    fn global(&self, name: &str) -> Result<LExpr<TcIdent>, Error> {
+        // A new symbol can only refer to the global as it is not equal to any local variable
+        let id = Symbol::new(name);
+        if self.scope.env.find_type(&id).is_some() {
+            Ok(self.ident(id))
+        } else {
+            Err(Error::MissingDefinition(String::from(name)))
+        }
+    }
+
+    /// Returns the `instance` instance for the type `name` which is either bound in the scope of
+    /// the declaration or exported by the standard library
+    fn named_instance(&self, instance: Instance, name: &str) -> Result<LExpr<TcIdent>, Error> {
+        let binding = instance_name(instance, name);
+        if let Some(id) = self.scope.values.iter().rev().find(|id| id.as_ref() == binding) {
+            return Ok(self.ident(id.clone()));
+        }
+        match self.scope.find_std_value(&binding) {
+            Some(module) => Ok(self.field(self.ident(Symbol::new(module)), &binding)),
+            None => {
+                Err(Error::MissingInstance(String::from(instance.type_name()), String::from(name)))
+            }
+        }
+    }
+
+    /// Returns the binding `let eq_T eq_a ... : Eq a -> ... -> Eq (T a ...) = ...`
+    fn binding(&self, typ: &TcType) -> Result<Binding<TcIdent>, Error> {
+        let (instance_type_name, _) = try!(self.scope.find_type(self.instance.type_name()));
+        let instance_type = |typ: TcType| -> TcType {
+            Type::app(Type::id(instance_type_name.clone()), vec![typ])
+        };
+        let generics: Vec<TcType> = self.args.iter().cloned().map(Type::generic).collect();
+        let signature = Type::function(self.args
+                                           .iter()
+                                           .cloned()
+                                           .map(|arg| instance_type(Type::generic(arg)))
+                                           .collect(),
+                                       instance_type(Type::app(Type::id(self.name.clone()),
+                                                               generics)));
+        let function = match self.instance {
+            Instance::Eq | Instance::Ord => {
+                let body = try!(self.compare(typ));
+                let instance = try!(self.self_instance());
+                self.let_(method_name(self.instance), &["l", "r"], body, instance)
+            }
+            Instance::Show => {
+                let body = try!(self.show(typ));
+                let instance = try!(self.self_instance());
+                self.let_(method_name(self.instance), &["x"], body, instance)
+            }
+        };
+        Ok(Binding {
+            comment: None,
+            implicit: false,
+            fixity: None,
+            name: self.pattern(self.binding_name.clone()),
+            typ: Some(self.located(signature)),
+            arguments: self.args
+                .iter()
+                .map(|arg| {
+                    let id = self.local(&instance_name(self.instance, arg.id.as_ref()));
+                    self.located(TcIdent::new(id))
+                })
+                .collect(),
+            expression: function,
+        })
+    }
+
+    /// The instance record for the type being derived, built from the local function which
+    /// implements it
+    fn self_instance(&self) -> Result<LExpr<TcIdent>, Error> {
+        let method = self.local_ident(method_name(self.instance));
+        match self.instance {
+            Instance::Eq | Instance::Show => {
+                Ok(self.record(vec![(self.instance.method(), method)]))
+            }
+            Instance::Ord => {
+                // `Ord` contains the `Eq` instance of the type which must have been defined
+                // already
+                let name = Name::new(self.name.as_ref()).name().as_str();
+                let eq = try!(self.named_instance(Instance::Eq, name));
+                let eq_args = self.args
+                    .iter()
+                    .map(|arg| {
+                        let ord = self.local_ident(&instance_name(Instance::Ord, arg.id.as_ref()));
+                        self.field(ord, "eq")
+                    })
+                    .collect();
+                Ok(self.record(vec![("eq", self.call(eq, eq_args)), ("compare", method)]))
+            }
+        }
+    }
+
+    /// Returns true if `typ` is the type which is being derived
+    fn is_self(&self, typ: &TcType) -> bool {
+        match **typ {
+            Type::Id(ref id) => self.args.is_empty() && id == self.name,
+            Type::App(ref f, ref args) => {
+                type_name(f).map_or(false, |name| name == self.name.as_ref()) &&
+                args.len() == self.args.len() &&
+                args.iter().zip(self.args).all(|(arg, param)| match **arg {
+                    Type::Generic(ref gen) => gen.id == param.id,
+                    _ => false,
+                })
+            }
+            _ => false,
+        }
+    }
+
+    /// Returns an expression evaluating to the instance record for `typ`
+    fn instance(&self, typ: &TcType) -> Result<LExpr<TcIdent>, Error> {
+        if self.is_self(typ) {
+            return self.self_instance();
+        }
+        match **typ {
+            Type::Generic(ref gen) => {
+                Ok(self.local_ident(&instance_name(self.instance, gen.id.as_ref())))
+            }
+            Type::App(ref f, ref args) => {
+                let f = try!(self.instance(f));
+                let args = try!(args.iter().map(|arg| self.instance(arg)).collect());
+                Ok(self.call(f, args))
+            }
+            _ => {
+                match type_name(typ) {
+                    Some(name) => self.named_instance(self.instance, name),
+                    None => Err(self.unsupported(typ)),
+                }
+            }
+        }
+    }
+
+    /// Returns an expression evaluating to the function which implements the instance for `typ`
+    fn method(&self, typ: &TcType) -> Result<LExpr<TcIdent>, Error> {
+        if self.is_self(typ) {
+            Ok(self.local_ident(method_name(self.instance)))
+        } else {
+            let instance = try!(self.instance(typ));
+            Ok(self.field(instance, self.instance.method()))
+        }
+    }
+
+    fn unsupported(&self, typ: &TcType) -> Error {
+        Error::UnsupportedType(String::from(self.instance.type_name()), format!("{}", typ))
+    }
+
+    /// The body of `==` or `compare` which compares `l` and `r`
+    fn compare(&self, typ: &TcType) -> Result<LExpr<TcIdent>, Error> {
+        match **typ {
+            Type::Variants(ref variants) => {
+                let mut alternatives = Vec::new();
+                for (i, &(ref l_name, ref l_typ)) in variants.iter().enumerate() {
+                    let l_args: Vec<_> = types::arg_iter(l_typ).collect();
+                    let mut inner = Vec::new();
+                    for (j, &(ref r_name, ref r_typ)) in variants.iter().enumerate() {
+                        let r_args = types::arg_iter(r_typ).count();
+                        let expr = if i == j {
+                            let comparisons = try!(l_args.iter()
+                                .enumerate()
+                                .map(|(k, typ)| {
+                                    let method = try!(self.method(typ));
+                                    let l = self.local_ident(&format!("l{}", k));
+                                    let r = self.local_ident(&format!("r{}", k));
+                                    Ok(self.call(method, vec![l, r]))
+                                })
+                                .collect::<Result<Vec<_>, Error>>());
+                            try!(self.combine(comparisons))
+                        } else {
+                            let constructor = match self.instance {
+                                Instance::Eq => try!(self.constructor("Bool", "False")),
+                                _ => {
+                                    try!(self.constructor("Ordering",
+                                                          if i < j { "LT" } else { "GT" }))
+                                }
+                            };
+                            self.ident(constructor)
+                        };
+                        inner.push(self.alternative(self.constructor_pattern(r_name, "r", r_args),
+                                                    expr));
+                    }
+                    let expr = self.located(Expr::Match(Box::new(self.local_ident("r")), inner));
+                    alternatives.push(self.alternative(self.constructor_pattern(l_name,
+                                                                                "l",
+                                                                                l_args.len()),
+                                                       expr));
+                }
+                Ok(self.located(Expr::Match(Box::new(self.local_ident("l")), alternatives)))
+            }
+            Type::Record { ref fields, .. } => {
+                let comparisons = try!(fields.iter()
+                    .map(|field| {
+                        let method = try!(self.method(&field.typ));
+                        let l = self.field(self.local_ident("l"), field.name.as_ref());
+                        let r = self.field(self.local_ident("r"), field.name.as_ref());
+                        Ok(self.call(method, vec![l, r]))
+                    })
+                    .collect::<Result<Vec<_>, Error>>());
+                self.combine(comparisons)
+            }
+            _ => {
+                let method = try!(self.method(typ));
+                Ok(self.call(method, vec![self.local_ident("l"), self.local_ident("r")]))
+            }
+        }
+    }
+
+    /// Combines the comparisons of each field of a value into a comparison of the whole value
+    fn combine(&self, mut comparisons: Vec<LExpr<TcIdent>>) -> Result<LExpr<TcIdent>, Error> {
+        let last = match comparisons.pop() {
+            Some(last) => last,
+            None => {
+                let constructor = match self.instance {
+                    Instance::Eq => try!(self.constructor("Bool", "True")),
+                    _ => try!(self.constructor("Ordering", "EQ")),
+                };
+                return Ok(self.ident(constructor));
+            }
+        };
+        // `False` ends the comparison for `Eq` while `EQ` continues it for `Ord`
+        let constructor = match self.instance {
+            Instance::Eq => try!(self.constructor("Bool", "False")),
+            _ => try!(self.constructor("Ordering", "EQ")),
+        };
+        Ok(comparisons.into_iter().rev().fold(last, |rest, comparison| {
+            match self.instance {
+                Instance::Eq => {
+                    self.located(Expr::IfElse(Box::new(comparison),
+                                              Box::new(rest),
+                                              Some(Box::new(self.ident(constructor.clone())))))
+                }
+                // Later fields are only compared if the earlier fields are equal
+                _ => {
+                    let alternatives = vec![
+                        self.alternative(self.located(Pattern::Constructor(
+                                             TcIdent::new(constructor.clone()), vec![])),
+                                         rest),
+                        self.alternative(self.pattern(self.local("ordering")),
+                                         self.local_ident("ordering")),
+                    ];
+                    self.located(Expr::Match(Box::new(comparison), alternatives))
+                }
+            }
+        }))
+    }
+
+    fn constructor_pattern(&self, name: &Symbol, prefix: &str, args: usize) -> LPattern<TcIdent> {
+        let args = (0..args)
+            .map(|i| self.pattern(self.local(&format!("{}{}", prefix, i))))
+            .collect();
+        self.located(Pattern::Constructor(TcIdent::new(name.clone()), args))
+    }
+
+    /// The body of `show` which shows `x`
+    fn show(&self, typ: &TcType) -> Result<LExpr<TcIdent>, Error> {
+        match **typ {
+            Type::Variants(ref variants) => {
+                let alternatives = try!(variants.iter()
+                    .map(|&(ref name, ref typ)| {
+                        let args: Vec<_> = types::arg_iter(typ).collect();
+                        let mut parts = vec![self.string(name.as_ref())];
+                        for (i, typ) in args.iter().enumerate() {
+                            let method = try!(self.method(typ));
+                            let x = self.local_ident(&format!("x{}", i));
+                            parts.push(self.string(" ("));
+                            parts.push(self.call(method, vec![x]));
+                            parts.push(self.string(")"));
+                        }
+                        let pattern = self.constructor_pattern(name, "x", args.len());
+                        Ok(self.alternative(pattern, try!(self.concat(parts))))
+                    })
+                    .collect::<Result<Vec<_>, Error>>());
+                Ok(self.located(Expr::Match(Box::new(self.local_ident("x")), alternatives)))
+            }
+            Type::Record { ref fields, .. } => {
+                if fields.is_empty() {
+                    return Ok(self.string("{}"));
+                }
+                let mut parts = Vec::new();
+                for (i, field) in fields.iter().enumerate() {
+                    let separator = if i == 0 { "{ " } else { ", " };
+                    let method = try!(self.method(&field.typ));
+                    parts.push(self.string(&format!("{}{} = ", separator, field.name)));
+                    let value = self.field(self.local_ident("x"), field.name.as_ref());
+                    parts.push(self.call(method, vec![value]));
+                }
+                parts.push(self.string(" }"));
+                self.concat(parts)
+            }
+            _ => {
+                let method = try!(self.method(typ));
+                Ok(self.call(method, vec![self.local_ident("x")]))
+            }
+        }
+    }
+
+    /// Appends the strings in `parts`
+    fn concat(&self, mut parts: Vec<LExpr<TcIdent>>) -> Result<LExpr<TcIdent>, Error> {
+        let last = parts.pop().expect("At least one string");
+        if parts.is_empty() {
+            return Ok(last);
+        }
+        let string_prim = try!(self.global("string_prim"));
+        Ok(parts.into_iter().rev().fold(last, |rest, part| {
+            let append = self.field(string_prim.clone(), "append");
+            self.call(append, vec![part, rest])
+        }))
+    }
+}
+
+/// The name of the binding holding the instance for the type `name`
+fn instance_name(instance: Instance, name: &str) -> String {
+    format!("{}_{}", instance.prefix(), name)
+}
+
+/// The name of the local function which implements the instance
+fn method_name(instance: Instance) -> &'static str {
+    match instance {
+        Instance::Eq => "eq",
+        Instance::Ord => "compare",
+        Instance::Show => "show",
+    }
+}
+
+/// Returns the name of a type which is referred to by name
+fn type_name(typ: &TcType) -> Option<&str> {
+    match **typ {
+        Type::Id(ref id) => Some(Name::new(id.as_ref()).name().as_str()),
+        Type::Alias(ref alias) => Some(Name::new(alias.name.as_ref()).name().as_str()),
+        Type::Builtin(BuiltinType::Unit) => Some("Unit"),
+        Type::Builtin(BuiltinType::Function) |
+        Type::Builtin(BuiltinType::ImplicitFunction) |
+        Type::Builtin(BuiltinType::Error) => None,
+        Type::Builtin(builtin) => Some(builtin.to_str()),
+        _ => None,
+    }
+}
@@ -15,6 +15,7 @@ extern crate gluon_base as base;
 pub mod api;
 pub mod channel;
 pub mod compiler;
+pub mod derive;
 pub mod gc;
 pub mod macros;
 pub mod thread;
@@ -30,6 +31,7 @@ mod vm;
 
 use api::ValueRef;
 use value::Value;
+use base::diagnostic::{Diagnostic, ToDiagnostic};
 use base::types::TcType;
 use base::symbol::Symbol;
 
@@ -89,6 +91,28 @@ quick_error! {
     }
 }
 
+impl ToDiagnostic for Error {
+    fn to_diagnostic(&self) -> Diagnostic {
+        match *self {
+            Error::Yield {} => Diagnostic::error("yield", "The thread yielded"),
+            Error::Dead {} => Diagnostic::error("dead_thread", "The thread is dead"),
+            _ => {
+                let code = match *self {
+                    Error::UndefinedBinding(..) => "undefined_binding",
+                    Error::UndefinedField(..) => "undefined_field",
+                    Error::TypeAlreadyExists(..) => "type_already_exists",
+                    Error::GlobalAlreadyExists(..) => "global_already_exists",
+                    Error::MetadataDoesNotExist(..) => "metadata_does_not_exist",
+                    Error::WrongType(..) => "wrong_type",
+                    Error::OutOfMemory { .. } => "out_of_memory",
+                    _ => "vm_error",
+                };
+                Diagnostic::error(code, self.to_string())
+            }
+        }
+    }
+}
+
 /// Internal types and functions exposed to the main `gluon` crate
 pub mod internal {
     pub use value::{Value, ClosureDataDef};
@@ -111,7 +111,7 @@ impl<'a> MutVisitor for MacroExpander<'a> {
             _ => None,
         };
         if let Some(mut e) = replacement {
-            e.location = expr.location;
+            e.span = expr.span;
             *expr = e;
         }
         ast::walk_mut_expr(self, expr);
@@ -290,6 +290,7 @@ pub fn load(vm: &Thread) -> Result<()> {
 
     try!(vm.define_global("#error",
                           primitive::<fn(StdString) -> A>("#error", prim::error)));
+    try!(vm.define_global("#string_eq", primitive!(2 <str as PartialEq>::eq)));
     try!(vm.define_global("error",
                           primitive::<fn(StdString) -> A>("error", prim::error)));
     try!(vm.define_global("trace", primitive!(1 prim::trace)));
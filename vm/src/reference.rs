@@ -76,7 +76,7 @@ fn f2<A, B, R>(f: fn(A, B) -> R) -> fn(A, B) -> R {
 }
 
 pub fn load(vm: &Thread) -> Result<()> {
-    let _ = vm.register_type::<Reference<A>>("Ref", &["a"]);
+    let _ = vm.register_mutable_type::<Reference<A>>("Ref", &["a"]);
     try!(vm.define_global("<-", f2(set)));
     try!(vm.define_global("load", f1(get)));
     try!(vm.define_global("ref", f1(make_ref)));
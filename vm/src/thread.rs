@@ -385,6 +385,15 @@ impl Thread {
         self.global_env().register_type::<T>(name, args)
     }
 
+    /// Registers the type `T` as being a mutable gluon type called `name` with generic arguments
+    /// `args`. See `GlobalVmState::register_mutable_type`
+    pub fn register_mutable_type<T: ?Sized + Any>(&self,
+                                                   name: &str,
+                                                   args: &[&str])
+                                                   -> Result<TcType> {
+        self.global_env().register_mutable_type::<T>(name, args)
+    }
+
     /// Locks and retrieves the global environment of the vm
     pub fn get_env<'b>(&'b self) -> RwLockReadGuard<'b, VmEnv> {
         self.global_env().get_env()
@@ -985,6 +994,92 @@ impl<'b> Context<'b> {
                         x => return Err(Error::Message(format!("GetField on {:?}", x))),
                     }
                 }
+                GetFieldByName(i) => {
+                    let field = function.strings[i as usize];
+                    match self.stack.pop() {
+                        Data(data) => {
+                            let index = match self.thread
+                                .global_env()
+                                .record_field_index(data.tag, &*field) {
+                                Some(index) => index,
+                                None => {
+                                    return Err(Error::Message(format!("Record does not have \
+                                                                       the field `{}`",
+                                                                      &field[..])))
+                                }
+                            };
+                            let v = data.fields[index as usize];
+                            self.stack.push(v);
+                        }
+                        x => {
+                            return Err(Error::Message(format!("GetFieldByName on {:?}", x)))
+                        }
+                    }
+                }
+                GetRecordField { tag, index } => {
+                    match self.stack.pop() {
+                        Data(data) => {
+                            let index = if data.tag == tag {
+                                Some(index)
+                            } else {
+                                let global = self.thread.global_env();
+                                global.record_field_name(tag, index)
+                                    .and_then(|field| global.record_field_index(data.tag, &field))
+                            };
+                            let index = match index {
+                                Some(index) => index,
+                                None => {
+                                    return Err(Error::Message(format!("GetRecordField on a \
+                                                                       record which does not \
+                                                                       match the tag {}",
+                                                                      tag)))
+                                }
+                            };
+                            let v = data.fields[index as usize];
+                            self.stack.push(v);
+                        }
+                        x => {
+                            return Err(Error::Message(format!("GetRecordField on {:?}", x)))
+                        }
+                    }
+                }
+                UpdateRecord { tag: fields_tag, args } => {
+                    // Keep the record and the values on the stack while allocating so that they
+                    // are not collected
+                    let (tag, fields) = {
+                        let len = self.stack.len();
+                        let (record_tag, mut fields) = match self.stack[len - args - 1] {
+                            Data(data) => (data.tag, data.fields.iter().cloned().collect()),
+                            // `{}` is the only record without any fields and it is represented as
+                            // a tag
+                            Value::Tag(_) => (0, Vec::new()),
+                            x => return Err(Error::Message(format!("UpdateRecord on {:?}", x))),
+                        };
+                        let (tag, positions) = self.thread
+                            .global_env()
+                            .update_record_tag(record_tag, fields_tag);
+                        for (value, position) in self.stack[len - args..].iter().zip(positions) {
+                            let position = position as usize;
+                            if position < fields.len() {
+                                fields[position] = *value;
+                            } else {
+                                fields.push(*value);
+                            }
+                        }
+                        (tag, fields)
+                    };
+                    let d = try!(alloc(&mut self.gc,
+                                       self.thread,
+                                       &self.stack.stack,
+                                       Def {
+                                           tag: tag,
+                                           elems: &fields,
+                                       }));
+                    for _ in 0..(args + 1) {
+                        self.stack.pop();
+                    }
+                    self.stack.push(Data(d));
+                }
                 TestTag(tag) => {
                     let data_tag = match self.stack.top() {
                         Data(ref data) => data.tag,
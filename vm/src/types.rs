@@ -49,6 +49,26 @@ pub enum Instruction {
     /// Retrieves the field at `index` of an object at the top of the stack. The result of the
     /// field access replaces the object on the stack.
     GetField(VmIndex),
+    /// Retrieves the field named by the string at `index` of the record at the top of the stack.
+    /// Used when the position of the field is not known at compile time. The result of the field
+    /// access replaces the record on the stack.
+    GetFieldByName(VmIndex),
+    /// Retrieves the field at `index` of a record which is expected to be tagged with `tag`, the
+    /// tag of the closed record type which the record was typechecked as. A record which has been
+    /// passed through an open record type may have its fields in another order so if the tags
+    /// differ the field is looked up by the name it has in `tag` instead. The result of the field
+    /// access replaces the record on the stack.
+    GetRecordField {
+        tag: VmTag,
+        index: VmIndex,
+    },
+    /// Replaces the record below the `args` values at the top of the stack with a copy of it where
+    /// the fields of the record shape `tag` are set to those values (fields which the record does
+    /// not have are added). Pops the values.
+    UpdateRecord {
+        tag: VmTag,
+        args: VmIndex,
+    },
     /// Splits a object, pushing all contained values to the stack.
     Split,
     /// Tests if the value at the top of the stack is tagged with `tag`. Pushes `True` if the tag
@@ -117,7 +137,8 @@ impl Instruction {
             TailCall(n) => -(n as i32),
             Construct { args: n, .. } |
             ConstructArray(n) => 1 - n as i32,
-            GetField(_) => 0,
+            GetField(_) | GetFieldByName(_) | GetRecordField { .. } => 0,
+            UpdateRecord { args, .. } => -(args as i32),
             // The number of added stack slots are handled separately as the type is needed to
             // calculate the number of slots needed
             Split => -1,
@@ -81,6 +81,7 @@ pub struct GlobalVmState {
     generics: RwLock<FnvMap<StdString, TcType>>,
     typeids: RwLock<FnvMap<TypeId, TcType>>,
     interner: RwLock<Interner>,
+    record_shapes: RwLock<RecordShapes>,
     macros: MacroEnv,
     // FIXME These fields should not be public
     pub gc: Mutex<Gc>,
@@ -101,12 +102,34 @@ impl Traverseable for GlobalVmState {
     }
 }
 
+/// Assigns a unique tag to the field names of each layout that record values are created with.
+/// Since a value of an open record type can have its fields in any order the tag of the value is
+/// used to find the position of a field when it is accessed.
+#[derive(Debug, Default)]
+struct RecordShapes {
+    tags: FnvMap<Vec<StdString>, VmTag>,
+    fields: Vec<Vec<StdString>>,
+}
+
+impl RecordShapes {
+    /// Creates the shapes with tag 0 reserved for the record without any fields. `{}` does not
+    /// need to be allocated so it is represented as `Value::Tag(0)`.
+    fn new() -> RecordShapes {
+        let mut shapes = RecordShapes::default();
+        shapes.tags.insert(Vec::new(), 0);
+        shapes.fields.push(Vec::new());
+        shapes
+    }
+}
+
 /// A borrowed structure which implements `CompilerEnv`, `TypeEnv` and `KindEnv` allowing the
 /// typechecker and compiler to lookup things in the virtual machine.
 #[derive(Debug)]
 pub struct VmEnv {
     pub type_infos: TypeInfos,
     pub globals: FnvMap<StdString, Global>,
+    /// The symbols of the registered types which are mutable
+    pub mutable_types: Vec<Symbol>,
 }
 
 impl CompilerEnv for VmEnv {
@@ -152,6 +175,21 @@ impl TypeEnv for VmEnv {
     fn find_type_info(&self, id: &SymbolRef) -> Option<&Alias<Symbol, TcType>> {
         self.type_infos
             .find_type_info(id)
+            .or_else(|| {
+                // Types declared in a module can be found through the module which declares
+                // them (`std.prelude.Monad`)
+                let name = Name::new(AsRef::<str>::as_ref(id));
+                self.globals
+                    .get(name.module().as_str())
+                    .and_then(|global| match *global.typ {
+                        Type::Record { ref types, .. } => {
+                            types.iter()
+                                .find(|field| field.name.as_ref() == name.name().as_str())
+                                .map(|field| &field.typ)
+                        }
+                        _ => None,
+                    })
+            })
     }
     fn find_record(&self, fields: &[Symbol]) -> Option<(&TcType, &TcType)> {
         self.type_infos.find_record(fields)
@@ -168,6 +206,10 @@ impl PrimitiveEnv for VmEnv {
             })
             .expect("std.types.Bool")
     }
+
+    fn is_mutable_type(&self, id: &SymbolRef) -> bool {
+        self.mutable_types.iter().any(|typ| **typ == *id)
+    }
 }
 
 impl MetadataEnv for VmEnv {
@@ -321,10 +363,12 @@ impl GlobalVmState {
             env: RwLock::new(VmEnv {
                 globals: FnvMap::default(),
                 type_infos: TypeInfos::new(),
+                mutable_types: Vec::new(),
             }),
             generics: RwLock::new(FnvMap::default()),
             typeids: RwLock::new(FnvMap::default()),
             interner: RwLock::new(Interner::new()),
+            record_shapes: RwLock::new(RecordShapes::new()),
             gc: Mutex::new(Gc::new(0, usize::MAX)),
             macros: MacroEnv::new(),
             generation_0_threads: RwLock::new(Vec::new()),
@@ -450,6 +494,20 @@ impl GlobalVmState {
         }
     }
 
+    /// Registers `T` in the same way as `register_type` but marks the type as mutable. The
+    /// typechecker will never generalize the type variables of a mutable value as that would
+    /// allow the value to be written and read at different types
+    pub fn register_mutable_type<T: ?Sized + Any>(&self,
+                                                   name: &str,
+                                                   args: &[&str])
+                                                   -> Result<TcType> {
+        let typ = try!(self.register_type::<T>(name, args));
+        let mut env = self.env.write().unwrap();
+        let id = env.type_infos.id_to_type[name].name.clone();
+        env.mutable_types.push(id);
+        Ok(typ)
+    }
+
     pub fn get_macros(&self) -> &MacroEnv {
         &self.macros
     }
@@ -458,6 +516,68 @@ impl GlobalVmState {
         self.interner.write().unwrap().intern(&mut *self.gc.lock().unwrap(), s)
     }
 
+    /// Returns the tag which records with the fields `fields` (in that order) are tagged with
+    pub fn record_tag<S>(&self, fields: &[S]) -> VmTag
+        where S: AsRef<str>
+    {
+        let fields: Vec<StdString> = fields.iter().map(|field| field.as_ref().into()).collect();
+        if let Some(&tag) = self.record_shapes.read().unwrap().tags.get(&fields) {
+            return tag;
+        }
+        let mut shapes = self.record_shapes.write().unwrap();
+        let RecordShapes { ref mut tags, fields: ref mut shape_fields } = *shapes;
+        *tags.entry(fields.clone()).or_insert_with(|| {
+            shape_fields.push(fields);
+            (shape_fields.len() - 1) as VmTag
+        })
+    }
+
+    /// Returns the position of `field` in records tagged with `tag`
+    pub fn record_field_index(&self, tag: VmTag, field: &str) -> Option<VmIndex> {
+        self.record_shapes
+            .read()
+            .unwrap()
+            .fields
+            .get(tag as usize)
+            .and_then(|fields| fields.iter().position(|f| f == field))
+            .map(|index| index as VmIndex)
+    }
+
+    /// Returns the name of the field at `index` in records tagged with `tag`
+    pub fn record_field_name(&self, tag: VmTag, index: VmIndex) -> Option<StdString> {
+        self.record_shapes
+            .read()
+            .unwrap()
+            .fields
+            .get(tag as usize)
+            .and_then(|fields| fields.get(index as usize))
+            .cloned()
+    }
+
+    /// Returns the tag of records tagged with `tag` once the fields of the shape `fields_tag` have
+    /// been set, together with the position of each of those fields in the updated record. Fields
+    /// which the record does not have are added after its other fields.
+    pub fn update_record_tag(&self, tag: VmTag, fields_tag: VmTag) -> (VmTag, Vec<VmIndex>) {
+        let (mut fields, updated) = {
+            let shapes = self.record_shapes.read().unwrap();
+            let get = |tag: VmTag| shapes.fields.get(tag as usize).cloned().unwrap_or_else(Vec::new);
+            (get(tag), get(fields_tag))
+        };
+        let positions = updated.into_iter()
+            .map(|field| {
+                let position = match fields.iter().position(|f| *f == field) {
+                    Some(position) => position,
+                    None => {
+                        fields.push(field);
+                        fields.len() - 1
+                    }
+                };
+                position as VmIndex
+            })
+            .collect();
+        (self.record_tag(&fields), positions)
+    }
+
     /// Returns a borrowed structure which implements `CompilerEnv`
     pub fn get_env<'b>(&'b self) -> RwLockReadGuard<'b, VmEnv> {
         self.env.read().unwrap()